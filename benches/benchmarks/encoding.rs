@@ -21,11 +21,12 @@ use rand::{rngs::StdRng, RngCore, SeedableRng};
 
 use merkledb::{
     crypto::{self, hash, Hash},
-    impl_object_hash_for_binary_value, BinaryKey, BinaryValue, ObjectHash,
+    impl_object_hash_for_binary_value, BinaryKey, BinaryValue, HashingBuffer, ObjectHash,
 };
 
 const CHUNK_SIZE: usize = 64;
 const SEED: [u8; 32] = [100; 32];
+const HASHED_VALUES_COUNT: usize = 1_000;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 struct SimpleData {
@@ -45,6 +46,15 @@ impl BinaryValue for SimpleData {
         buffer
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        let mut bytes = [0; 8];
+        LittleEndian::write_u16(&mut bytes[0..2], self.id);
+        LittleEndian::write_i16(&mut bytes[2..4], self.class);
+        LittleEndian::write_i32(&mut bytes[4..8], self.value);
+        buffer.extend_from_slice(&bytes);
+        buffer.extend_from_slice(self.hash.as_ref());
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         let bytes = bytes.as_ref();
         let id = LittleEndian::read_u16(&bytes[0..2]);
@@ -172,6 +182,29 @@ where
             b.iter_with_setup(f, |data| black_box(data.object_hash()));
         },
     );
+    c.bench_function(
+        &format!("encoding/{}/hash_batch/to_bytes", name),
+        move |b: &mut Bencher<'_>| {
+            let values: Vec<_> = (0..HASHED_VALUES_COUNT).map(|_| f()).collect();
+            b.iter(|| {
+                for value in &values {
+                    black_box(hash(&value.to_bytes()));
+                }
+            });
+        },
+    );
+    c.bench_function(
+        &format!("encoding/{}/hash_batch/buffer", name),
+        move |b: &mut Bencher<'_>| {
+            let values: Vec<_> = (0..HASHED_VALUES_COUNT).map(|_| f()).collect();
+            let mut buffer = HashingBuffer::new();
+            b.iter(|| {
+                for value in &values {
+                    black_box(buffer.hash_value(value));
+                }
+            });
+        },
+    );
 }
 
 fn bench_binary_key_concat(b: &mut Bencher<'_>) {
@@ -0,0 +1,57 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{black_box, Criterion, Throughput};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+use merkledb::crypto::{self, hash, hash_batch};
+
+const SEED: [u8; 32] = [100; 32];
+const LEAVES_COUNT: usize = 4_096;
+const LEAF_SIZES: [usize; 3] = [64, 1_024, 16_384];
+
+fn gen_leaves(leaf_size: usize) -> Vec<Vec<u8>> {
+    let mut rng = StdRng::from_seed(SEED);
+    (0..LEAVES_COUNT)
+        .map(|_| {
+            let mut leaf = vec![0; leaf_size];
+            rng.fill_bytes(&mut leaf);
+            leaf
+        })
+        .collect()
+}
+
+/// Compares sequential hashing of leaves with `hash_batch`. The speedup is only observable
+/// if the benchmarks are run with the `parallel` feature.
+pub fn bench_hashing(c: &mut Criterion) {
+    crypto::init();
+
+    for &leaf_size in &LEAF_SIZES {
+        let leaves = gen_leaves(leaf_size);
+        let chunks: Vec<&[u8]> = leaves.iter().map(Vec::as_slice).collect();
+
+        let mut group = c.benchmark_group(format!("hashing/leaves/{}", leaf_size));
+        group.bench_function("sequential", |b| {
+            b.iter(|| {
+                let hashes: Vec<_> = chunks.iter().map(|chunk| hash(chunk)).collect();
+                black_box(hashes)
+            });
+        });
+        group.bench_function("hash_batch", |b| {
+            b.iter(|| black_box(hash_batch(&chunks)));
+        });
+        group.throughput(Throughput::Bytes((LEAVES_COUNT * leaf_size) as u64));
+        group.finish();
+    }
+}
@@ -16,6 +16,7 @@ use merkledb::{Database, DbOptions, Fork, Patch, Result, RocksDB, Snapshot};
 use tempfile::{tempdir, TempDir};
 
 pub mod encoding;
+pub mod hashing;
 pub mod schema_patterns;
 pub mod storage;
 
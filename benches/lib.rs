@@ -15,7 +15,8 @@
 use criterion::{criterion_group, criterion_main};
 
 use crate::benchmarks::{
-    encoding::bench_encoding, schema_patterns::bench_schema_patterns, storage::bench_storage,
+    encoding::bench_encoding, hashing::bench_hashing, schema_patterns::bench_schema_patterns,
+    storage::bench_storage,
 };
 
 mod benchmarks;
@@ -24,6 +25,7 @@ criterion_group!(
     benches,
     bench_storage,
     bench_encoding,
+    bench_hashing,
     bench_schema_patterns,
 );
 criterion_main!(benches);
@@ -33,6 +33,18 @@ impl FromDeriveInput for BinaryValueStruct {
         let attrs = find_meta_attrs("binary_value", &input.attrs)
             .map(|meta| BinaryValueAttrs::from_nested_meta(&meta))
             .unwrap_or_else(|| Ok(BinaryValueAttrs::default()))?;
+        if attrs.upgrade.is_some() && attrs.version.is_none() {
+            let msg = "#[binary_value(upgrade = ...)] requires #[binary_value(version = ...)]";
+            return Err(darling::Error::custom(msg));
+        }
+        if attrs.legacy.is_some() && attrs.version.is_none() {
+            let msg = "#[binary_value(legacy = ...)] requires #[binary_value(version = ...)]";
+            return Err(darling::Error::custom(msg));
+        }
+        if attrs.version == Some(0) {
+            let msg = "#[binary_value(version = 0)] is reserved for unversioned values";
+            return Err(darling::Error::custom(msg));
+        }
 
         Ok(Self {
             ident: input.ident.clone(),
@@ -73,22 +85,127 @@ impl FromMeta for Codec {
 struct BinaryValueAttrs {
     #[darling(default)]
     codec: Codec,
+    /// Version of the serialization format written as the leading byte.
+    #[darling(default)]
+    version: Option<u8>,
+    /// Function decoding values serialized with older versions.
+    #[darling(default)]
+    upgrade: Option<syn::Path>,
+    /// Function decoding values serialized before versioning was enabled.
+    #[darling(default)]
+    legacy: Option<syn::Path>,
 }
 
-#[derive(Debug, FromDeriveInput)]
+/// Byte preceding the version in values of types with the `legacy` attribute. The byte
+/// cannot start a Protobuf message since it encodes the invalid wire type 7.
+const LEGACY_MARKER: u8 = 0xff;
+
+#[derive(Debug)]
 struct ObjectHashStruct {
     ident: Ident,
+    /// Tagged fields together with their indexes in the struct, sorted by tag.
+    tagged_fields: Vec<(usize, ObjectHashField)>,
+}
+
+#[derive(Debug, Default, FromMeta)]
+struct ObjectHashFieldAttrs {
+    #[darling(default)]
+    tag: Option<u32>,
+}
+
+#[derive(Debug)]
+struct ObjectHashField {
+    span: Span,
+    ident: Option<Ident>,
+    tag: Option<u32>,
+}
+
+impl FromField for ObjectHashField {
+    fn from_field(field: &syn::Field) -> darling::Result<Self> {
+        let attrs = find_meta_attrs("object_hash", &field.attrs)
+            .map(|meta| ObjectHashFieldAttrs::from_nested_meta(&meta))
+            .unwrap_or_else(|| Ok(ObjectHashFieldAttrs::default()))?;
+
+        Ok(Self {
+            span: field.span(),
+            ident: field.ident.clone(),
+            tag: attrs.tag,
+        })
+    }
+}
+
+impl FromDeriveInput for ObjectHashStruct {
+    fn from_derive_input(input: &DeriveInput) -> darling::Result<Self> {
+        let fields = match &input.data {
+            Data::Struct(DataStruct { fields, .. }) => Fields::try_from(fields)?.fields,
+            _ => vec![],
+        };
+
+        let mut tagged_fields = vec![];
+        if fields.iter().any(|field| field.tag.is_some()) {
+            let mut tags = HashSet::new();
+            for (i, field) in fields.into_iter().enumerate() {
+                let tag = field.tag.ok_or_else(|| {
+                    let msg = "All fields must have #[object_hash(tag = ...)] \
+                               if at least one field is tagged";
+                    darling::Error::custom(msg).with_span(&field.span)
+                })?;
+                if !tags.insert(tag) {
+                    let e = darling::Error::custom("Duplicate field tag");
+                    return Err(e.with_span(&field.span));
+                }
+                tagged_fields.push((i, field));
+            }
+            tagged_fields.sort_by_key(|(_, field)| field.tag);
+        }
+
+        Ok(Self {
+            ident: input.ident.clone(),
+            tagged_fields,
+        })
+    }
 }
 
 impl ObjectHashStruct {
-    pub fn implement_object_hash(&self) -> impl ToTokens {
+    fn implement_tagged_object_hash(&self) -> impl ToTokens {
         let name = &self.ident;
+        // Fields are hashed in the order of their tags, so the hash does not depend
+        // on the order or names of fields in the source.
+        let updates = self.tagged_fields.iter().map(|(i, field)| {
+            let tag = field.tag.unwrap();
+            let ident = if let Some(ref ident) = field.ident {
+                quote!(#ident)
+            } else {
+                let field_index = syn::Index::from(*i);
+                quote!(#field_index)
+            };
+            quote! {
+                .update(&#tag.to_le_bytes())
+                .update(merkledb::ObjectHash::object_hash(&self.#ident).as_ref())
+            }
+        });
 
         quote! {
             impl merkledb::ObjectHash for #name {
                 fn object_hash(&self) -> merkledb::_reexports::Hash {
-                    let bytes = merkledb::BinaryValue::to_bytes(self);
-                    merkledb::_reexports::hash(&bytes)
+                    merkledb::_reexports::HashStream::new()
+                        #(#updates)*
+                        .hash()
+                }
+            }
+        }
+    }
+
+    pub fn implement_object_hash(&self) -> impl ToTokens {
+        if !self.tagged_fields.is_empty() {
+            return self.implement_tagged_object_hash().into_token_stream();
+        }
+
+        let name = &self.ident;
+        quote! {
+            impl merkledb::ObjectHash for #name {
+                fn object_hash(&self) -> merkledb::_reexports::Hash {
+                    merkledb::HashingBuffer::hash_thread_local(self)
                 }
             }
         }
@@ -96,58 +213,150 @@ impl ObjectHashStruct {
 }
 
 impl BinaryValueStruct {
-    fn implement_binary_value_from_pb(&self) -> proc_macro2::TokenStream {
+    /// Returns expressions serializing `self` into a `Vec<u8>`, appending serialized `self`
+    /// to the `buffer: &mut Vec<u8>` variable and deserializing `Self` from the `bytes: &[u8]`
+    /// variable, respectively.
+    fn codec(
+        &self,
+    ) -> (
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+        proc_macro2::TokenStream,
+    ) {
         let name = &self.ident;
 
-        quote! {
-            impl merkledb::BinaryValue for #name {
-                fn to_bytes(&self) -> Vec<u8> {
+        match self.attrs.codec {
+            Codec::Protobuf => {
+                let serialize = quote! {{
                     use protobuf::Message as _;
                     // This trait assumes that we work with trusted data so we can unwrap here.
                     exonum_proto::ProtobufConvert::to_pb(self).write_to_bytes().expect(
                         concat!("Failed to serialize `BinaryValue` for ", stringify!(#name))
                     )
-                }
-
-                fn from_bytes(
-                    value: std::borrow::Cow<[u8]>,
-                ) -> std::result::Result<Self, merkledb::_reexports::Error> {
+                }};
+                let serialize_into = quote! {{
+                    use protobuf::Message as _;
+                    exonum_proto::ProtobufConvert::to_pb(self).write_to_vec(buffer).expect(
+                        concat!("Failed to serialize `BinaryValue` for ", stringify!(#name))
+                    );
+                }};
+                let deserialize = quote! {{
                     use protobuf::Message as _;
 
                     let mut block = <Self as exonum_proto::ProtobufConvert>::ProtoStruct::new();
-                    block.merge_from_bytes(value.as_ref())?;
+                    block.merge_from_bytes(bytes)?;
                     exonum_proto::ProtobufConvert::from_pb(block)
-                }
+                }};
+                (serialize, serialize_into, deserialize)
+            }
+
+            Codec::Bincode => {
+                let serialize = quote! {
+                    bincode::serialize(self).expect(
+                        concat!("Failed to serialize `BinaryValue` for ", stringify!(#name))
+                    )
+                };
+                let serialize_into = quote! {
+                    bincode::serialize_into(&mut *buffer, self).expect(
+                        concat!("Failed to serialize `BinaryValue` for ", stringify!(#name))
+                    );
+                };
+                let deserialize = quote! {
+                    bincode::deserialize(bytes).map_err(From::from)
+                };
+                (serialize, serialize_into, deserialize)
             }
         }
     }
 
-    fn implement_binary_value_from_bincode(&self) -> proc_macro2::TokenStream {
+    fn implement_binary_value(&self) -> impl ToTokens {
         let name = &self.ident;
+        let (serialize, serialize_into, deserialize) = self.codec();
+
+        let (to_bytes, write_bytes, from_bytes) = if let Some(version) = self.attrs.version {
+            // Values serialized with older versions are either upgraded or rejected.
+            let upgrade_arm = self.attrs.upgrade.as_ref().map(|upgrade| {
+                quote! {
+                    old_version if old_version > 0 && old_version < #version => {
+                        #upgrade(old_version, bytes)
+                    }
+                }
+            });
+            // If the type opts into decoding values serialized before versioning was enabled,
+            // the version is preceded by the marker byte, and values without the marker
+            // are passed to the legacy function as a whole.
+            let (header, decode) = if let Some(legacy) = &self.attrs.legacy {
+                let decode = quote! {
+                    let value: &[u8] = value.as_ref();
+                    match value.split_first() {
+                        Some((&#LEGACY_MARKER, versioned)) => decode_versioned(versioned),
+                        _ => #legacy(value),
+                    }
+                };
+                (quote!(#LEGACY_MARKER, #version), decode)
+            } else {
+                (quote!(#version), quote!(decode_versioned(value.as_ref())))
+            };
+
+            let to_bytes = quote! {
+                let mut buffer = vec![#header];
+                #serialize_into
+                buffer
+            };
+            let write_bytes = quote! {
+                buffer.extend_from_slice(&[#header]);
+                #serialize_into
+            };
+            let from_bytes = quote! {
+                let decode_versioned = |value: &[u8]| -> std::result::Result<
+                    Self,
+                    merkledb::_reexports::Error,
+                > {
+                    let (&version, bytes) = value.split_first().ok_or_else(|| {
+                        merkledb::_reexports::Error::msg(
+                            concat!("Missing version of `BinaryValue` for ", stringify!(#name))
+                        )
+                    })?;
+                    match version {
+                        #version => #deserialize,
+                        #upgrade_arm
+                        other => Err(merkledb::_reexports::Error::msg(format!(
+                            "Unsupported version {} of `BinaryValue` for {} (current version is {})",
+                            other,
+                            stringify!(#name),
+                            #version,
+                        ))),
+                    }
+                };
+                #decode
+            };
+            (to_bytes, write_bytes, from_bytes)
+        } else {
+            let from_bytes = quote! {
+                let bytes: &[u8] = value.as_ref();
+                #deserialize
+            };
+            (serialize, serialize_into, from_bytes)
+        };
 
         quote! {
             impl merkledb::BinaryValue for #name {
                 fn to_bytes(&self) -> std::vec::Vec<u8> {
-                    bincode::serialize(self).expect(
-                        concat!("Failed to serialize `BinaryValue` for ", stringify!(#name))
-                    )
+                    #to_bytes
+                }
+
+                fn write_bytes(&self, buffer: &mut std::vec::Vec<u8>) {
+                    #write_bytes
                 }
 
                 fn from_bytes(
                     value: std::borrow::Cow<[u8]>,
                 ) -> std::result::Result<Self, merkledb::_reexports::Error> {
-                    bincode::deserialize(value.as_ref()).map_err(From::from)
+                    #from_bytes
                 }
             }
         }
     }
-
-    fn implement_binary_value(&self) -> impl ToTokens {
-        match self.attrs.codec {
-            Codec::Protobuf => self.implement_binary_value_from_pb(),
-            Codec::Bincode => self.implement_binary_value_from_bincode(),
-        }
-    }
 }
 
 impl ToTokens for BinaryValueStruct {
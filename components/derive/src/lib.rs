@@ -51,6 +51,58 @@ use syn::{Attribute, NestedMeta};
 /// Selects the serialization codec to use. Allowed values are `protobuf` (used by default)
 /// and `bincode`.
 ///
+/// ## `version`
+///
+/// ```text
+/// #[binary_value(version = 2)]
+/// ```
+///
+/// Prepends the serialized value with a byte containing the specified version of
+/// the serialization format. Deserialization fails for values with other versions unless
+/// the `upgrade` attribute is specified. The version must be positive. The version byte
+/// cannot be distinguished from the serialized data, so versioning should be enabled before
+/// any values of the type are stored, unless the `legacy` attribute is specified.
+///
+/// ## `upgrade`
+///
+/// ```text
+/// #[binary_value(version = 2, upgrade = "path::to::function")]
+/// ```
+///
+/// Specifies a function used to deserialize values with versions lower than the current
+/// `version`, which allows to evolve the schema of stored values without migrating
+/// the stored data. The function has the following signature:
+///
+/// ```text
+/// fn upgrade(old_version: u8, bytes: &[u8]) -> Result<Self, anyhow::Error>
+/// ```
+///
+/// where `bytes` is the serialized value without the version byte. Values with versions
+/// higher than the current one are still rejected.
+///
+/// ## `legacy`
+///
+/// ```text
+/// #[binary_value(version = 2, legacy = "path::to::function")]
+/// ```
+///
+/// Specifies a function used to deserialize values stored before versioning was enabled,
+/// which do not have the version byte. The function has the following signature:
+///
+/// ```text
+/// fn legacy(bytes: &[u8]) -> Result<Self, anyhow::Error>
+/// ```
+///
+/// where `bytes` is the entire serialized value. Since the version byte cannot be reliably
+/// distinguished from unversioned data, versioned values of such types are prepended with
+/// the `0xff` marker byte before the version byte, and all values not starting with
+/// the marker are passed to the function. The marker cannot start a Protobuf message;
+/// with other codecs, the unversioned layout must not start with the `0xff` byte.
+///
+/// The attribute changes the format of versioned values, so it should be specified
+/// when versioning is enabled for a type with stored unversioned values, and retained
+/// in the following versions of the type.
+///
 /// # Examples
 ///
 /// With Protobuf serialization:
@@ -86,6 +138,30 @@ use syn::{Attribute, NestedMeta};
 /// };
 /// let bytes = wallet.to_bytes();
 /// ```
+///
+/// With versioning:
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct WalletV1 {
+///     balance: u64,
+/// }
+///
+/// #[derive(Clone, Debug, Serialize, Deserialize, BinaryValue)]
+/// #[binary_value(codec = "bincode", version = 2, upgrade = "Wallet::upgrade")]
+/// pub struct Wallet {
+///     pub balance: u64,
+///     pub frozen: bool,
+/// }
+///
+/// impl Wallet {
+///     fn upgrade(old_version: u8, bytes: &[u8]) -> anyhow::Result<Self> {
+///         anyhow::ensure!(old_version == 1, "Unknown version {}", old_version);
+///         let WalletV1 { balance } = bincode::deserialize(bytes)?;
+///         Ok(Self { balance, frozen: false })
+///     }
+/// }
+/// ```
 #[proc_macro_derive(BinaryValue, attributes(binary_value))]
 pub fn binary_value(input: TokenStream) -> TokenStream {
     db_traits::impl_binary_value(input)
@@ -111,7 +187,26 @@ pub fn binary_value(input: TokenStream) -> TokenStream {
 /// };
 /// let hash = wallet.object_hash();
 /// ```
-#[proc_macro_derive(ObjectHash)]
+///
+/// # Tagged fields
+///
+/// Alternatively, each field of a struct may be annotated with a numeric tag via
+/// `#[object_hash(tag = ...)]`. In this case, the hash is computed from the tags and
+/// object hashes of the fields, taken in the ascending order of tags; the `BinaryValue`
+/// implementation is not used. Thus, fields may be reordered or renamed without changing
+/// the hash as long as their tags stay the same. If at least one field is tagged, all fields
+/// must be tagged, and tags must be unique.
+///
+/// ```ignore
+/// #[derive(ObjectHash)]
+/// pub struct Wallet {
+///     #[object_hash(tag = 2)]
+///     pub balance: u64,
+///     #[object_hash(tag = 1)]
+///     pub pub_key: PublicKey,
+/// }
+/// ```
+#[proc_macro_derive(ObjectHash, attributes(object_hash))]
 pub fn object_hash(input: TokenStream) -> TokenStream {
     db_traits::impl_object_hash(input)
 }
@@ -283,6 +283,19 @@ pub enum AccessErrorKind {
     #[error("Invalid tombstone location. Tombstones can only be created in migrations")]
     InvalidTombstone,
 
+    /// Merkelized index was created with a different node hasher.
+    #[error(
+        "Wrong node hasher: expected hasher with ID {}, but the index uses hasher with ID {}",
+        expected,
+        actual
+    )]
+    WrongNodeHasher {
+        /// Identifier of the expected node hasher.
+        expected: u32,
+        /// Identifier of the node hasher used by the index.
+        actual: u32,
+    },
+
     /// Custom error.
     #[error("{0}")]
     Custom(#[source] anyhow::Error),
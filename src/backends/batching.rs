@@ -0,0 +1,448 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Database wrapper coalescing small patches into larger batches.
+
+use std::{
+    collections::HashMap,
+    fmt, mem,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    db::{SharedChangesSnapshot, ViewChanges},
+    ChangesSink, Database, Patch, ResolvedAddress, Result, Snapshot,
+};
+
+/// Changes buffered by `BatchingDatabase`, but not yet written to the underlying database.
+#[derive(Default)]
+struct PendingChanges {
+    /// Buffered changes. The changes are shared with the snapshots created from
+    /// the wrapper and are copied on write if they are still used by a snapshot.
+    changes: Arc<HashMap<ResolvedAddress, ViewChanges>>,
+    /// Number of buffered key changes. Repeated changes of the same key are counted
+    /// separately, so this is an upper bound on the actual batch size.
+    len: usize,
+}
+
+impl PendingChanges {
+    fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn push(&mut self, patch: Patch) {
+        let changes = Arc::make_mut(&mut self.changes);
+        self.len += extend_changes(changes, patch);
+    }
+}
+
+/// Extends `changes` with the changes from the `patch`, returning the number of key changes
+/// in the patch.
+fn extend_changes(changes: &mut HashMap<ResolvedAddress, ViewChanges>, patch: Patch) -> usize {
+    let mut len = 0;
+    for (address, view_changes) in patch.into_changes() {
+        len += view_changes.data.len();
+        changes.entry(address).or_default().extend(view_changes);
+    }
+    len
+}
+
+/// Streams `changes` to the `sink`. Cleared views are cleared before changing their entries.
+fn stream_changes(
+    changes: &HashMap<ResolvedAddress, ViewChanges>,
+    sink: &mut dyn ChangesSink,
+) -> Result<()> {
+    for (address, view_changes) in changes {
+        if view_changes.is_cleared() {
+            sink.clear(address)?;
+        }
+        for (key, change) in &view_changes.data {
+            sink.change(address, key, change)?;
+        }
+    }
+    Ok(())
+}
+
+/// Database wrapper that buffers merged patches and writes them to the underlying
+/// database together.
+///
+/// Merging many small patches one by one may be expensive for persistent backends,
+/// since each merge results in a separate write to the disk. `BatchingDatabase` coalesces
+/// such patches in memory and flushes them to the underlying database in a single write
+/// once the number of buffered changes reaches `max_changes`. The buffer is not flushed
+/// based on time; if the wrapper may stay idle with a non-empty buffer, call [`flush`]
+/// periodically to bound the delay. The buffer is flushed automatically when the wrapper
+/// is dropped.
+///
+/// The buffered changes are streamed to the underlying database with
+/// [`Database::merge_streamed`]. If writing to the underlying database fails, the buffered
+/// changes are retained, and the failed call does not add its patch to the buffer,
+/// in accordance with the [`Database`] contract.
+///
+/// Snapshots and forks created from the wrapper see buffered changes as if they were
+/// already merged into the underlying database. [`merge_sync`] writes the buffered changes
+/// and then merges the supplied patch with fsync. For backends persisting earlier writes
+/// together with a synced one, such as `RocksDB` with its write-ahead log, this makes
+/// the buffered changes durable as well.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::CopyAccessExt, BatchingDatabase, Database, TemporaryDB};
+///
+/// let db = BatchingDatabase::new(TemporaryDB::new(), 1_000);
+/// let fork = db.fork();
+/// fork.get_list("list").push(1_u32);
+/// db.merge(fork.into_patch()).unwrap();
+///
+/// // The change is not written to `TemporaryDB` yet, but is visible nonetheless.
+/// assert!(db.pending_changes() > 0);
+/// assert_eq!(db.snapshot().get_list::<_, u32>("list").len(), 1);
+///
+/// db.flush().unwrap();
+/// assert_eq!(db.pending_changes(), 0);
+/// assert_eq!(db.inner().snapshot().get_list::<_, u32>("list").len(), 1);
+/// ```
+///
+/// [`flush`]: #method.flush
+/// [`Database`]: trait.Database.html
+/// [`Database::merge_streamed`]: trait.Database.html#method.merge_streamed
+/// [`merge_sync`]: trait.Database.html#tymethod.merge_sync
+pub struct BatchingDatabase<D: Database> {
+    inner: D,
+    pending: Mutex<PendingChanges>,
+    max_changes: usize,
+}
+
+impl<D: Database> fmt::Debug for BatchingDatabase<D> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("BatchingDatabase")
+            .field("max_changes", &self.max_changes)
+            .field("pending_changes", &self.pending_changes())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D: Database> BatchingDatabase<D> {
+    /// Wraps the specified database. Buffered patches are flushed once they contain
+    /// at least `max_changes` key changes.
+    pub fn new(inner: D, max_changes: usize) -> Self {
+        Self {
+            inner,
+            pending: Mutex::new(PendingChanges::default()),
+            max_changes,
+        }
+    }
+
+    /// Returns a reference to the underlying database. Buffered changes are not visible
+    /// via this reference until they are flushed.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Returns the number of key changes buffered, but not yet written to the underlying
+    /// database.
+    pub fn pending_changes(&self) -> usize {
+        self.lock().len
+    }
+
+    /// Writes all buffered changes to the underlying database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to the underlying database fails. In this case,
+    /// the changes remain buffered.
+    pub fn flush(&self) -> Result<()> {
+        let mut pending = self.lock();
+        if pending.is_empty() {
+            Ok(())
+        } else {
+            self.flush_locked(&mut pending, None)
+        }
+    }
+
+    /// Writes the buffered changes together with the optional `patch` to the underlying database.
+    /// The lock on the buffer is held during the write, so that concurrently created snapshots
+    /// do not miss the changes being written. The buffer is restored if the write fails.
+    fn flush_locked(&self, pending: &mut PendingChanges, patch: Option<Patch>) -> Result<()> {
+        // The buffered changes are streamed by reference rather than moved into a patch,
+        // so that they are not copied and can be restored if the write fails.
+        let buffered = mem::take(pending);
+        let patch_changes = patch.map(Patch::into_changes);
+        let res = self.inner.merge_streamed(&mut |sink| {
+            stream_changes(&buffered.changes, sink)?;
+            match &patch_changes {
+                Some(changes) => stream_changes(changes, sink),
+                None => Ok(()),
+            }
+        });
+        if res.is_err() {
+            *pending = buffered;
+        }
+        res
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PendingChanges> {
+        self.pending
+            .lock()
+            .expect("`BatchingDatabase` buffer is poisoned")
+    }
+}
+
+impl<D: Database> Database for BatchingDatabase<D> {
+    fn snapshot(&self) -> Box<dyn Snapshot> {
+        let pending = self.lock();
+        let snapshot = self.inner.snapshot();
+        if pending.is_empty() {
+            snapshot
+        } else {
+            let changes = Arc::clone(&pending.changes);
+            Box::new(SharedChangesSnapshot::new(snapshot, changes))
+        }
+    }
+
+    fn merge(&self, patch: Patch) -> Result<()> {
+        let mut pending = self.lock();
+        if pending.len + patch.changes_count() >= self.max_changes {
+            self.flush_locked(&mut pending, Some(patch))
+        } else {
+            pending.push(patch);
+            Ok(())
+        }
+    }
+
+    fn merge_sync(&self, patch: Patch) -> Result<()> {
+        let mut pending = self.lock();
+        if !pending.is_empty() {
+            self.flush_locked(&mut pending, None)?;
+        }
+        self.inner.merge_sync(patch)
+    }
+
+    /// Returns the estimate for the underlying database; pending changes are not counted.
+    fn approximate_size(&self) -> u64 {
+        self.inner.approximate_size()
+    }
+
+    /// Returns the estimate for the underlying database; pending changes are not counted.
+    fn approximate_size_at(&self, addr: &ResolvedAddress) -> u64 {
+        self.inner.approximate_size_at(addr)
+    }
+}
+
+impl<D: Database> Drop for BatchingDatabase<D> {
+    fn drop(&mut self) {
+        // Errors cannot be propagated from `drop`; call `flush()` explicitly to handle them.
+        match self.pending.get_mut() {
+            Ok(pending) if !pending.is_empty() => {
+                let changes = mem::take(Arc::make_mut(&mut pending.changes));
+                let patch = Patch::from_changes(self.inner.snapshot(), changes);
+                self.inner.merge(patch).ok();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[allow(clippy::use_self)] // false positive
+impl<D: Database> From<BatchingDatabase<D>> for Arc<dyn Database> {
+    fn from(db: BatchingDatabase<D>) -> Self {
+        Arc::new(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::CopyAccessExt, DbOptions, Error, ObjectHash, RocksDB, SystemSchema, TemporaryDB,
+    };
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn buffered_writes_are_visible_and_persisted() {
+        let db = BatchingDatabase::new(TemporaryDB::new(), 100);
+        for i in 0_u32..3 {
+            let fork = db.fork();
+            fork.get_proof_list("list").push(i);
+            fork.get_map("map").put(&i, i.to_string());
+            db.merge(fork.into_patch()).unwrap();
+        }
+
+        // Nothing is written to the underlying database yet.
+        assert!(db.pending_changes() > 0);
+        assert!(db
+            .inner()
+            .snapshot()
+            .get_map::<_, u32, String>("map")
+            .get(&0)
+            .is_none());
+
+        let snapshot = db.snapshot();
+        let list = snapshot.get_proof_list::<_, u32>("list");
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+        let map = snapshot.get_map::<_, u32, String>("map");
+        assert_eq!(map.get(&2).unwrap(), "2");
+        let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+        assert_eq!(aggregator.get("list").unwrap(), list.object_hash());
+
+        // Enough changes trigger an automatic flush.
+        let fork = db.fork();
+        {
+            let mut map = fork.get_map::<_, u32, String>("map");
+            for i in 3..100 {
+                map.put(&i, i.to_string());
+            }
+            map.remove(&0);
+        }
+        db.merge(fork.into_patch()).unwrap();
+        assert_eq!(db.pending_changes(), 0);
+
+        let snapshot = db.inner().snapshot();
+        let map = snapshot.get_map::<_, u32, String>("map");
+        assert_eq!(map.keys().collect::<Vec<_>>(), (1..100).collect::<Vec<_>>());
+        assert_eq!(snapshot.get_proof_list::<_, u32>("list").len(), 3);
+        let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+        assert_eq!(
+            aggregator.get("list").unwrap(),
+            snapshot.get_proof_list::<_, u32>("list").object_hash()
+        );
+    }
+
+    #[test]
+    fn clearing_views_in_buffered_patches() {
+        let db = BatchingDatabase::new(TemporaryDB::new(), 1_000);
+        let fork = db.fork();
+        fork.get_list("list").extend(vec![1_u32, 2, 3]);
+        db.merge(fork.into_patch()).unwrap();
+
+        let fork = db.fork();
+        {
+            let mut list = fork.get_list::<_, u32>("list");
+            list.clear();
+            list.push(4);
+        }
+        db.merge(fork.into_patch()).unwrap();
+
+        let list = db
+            .snapshot()
+            .get_list::<_, u32>("list")
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(list, vec![4]);
+        db.flush().unwrap();
+        let snapshot = db.inner().snapshot();
+        let stored = snapshot
+            .get_list::<_, u32>("list")
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(stored, vec![4]);
+    }
+
+    /// Database failing all writes while `is_failing` is set.
+    struct FailingDB {
+        inner: TemporaryDB,
+        is_failing: AtomicBool,
+    }
+
+    impl Database for FailingDB {
+        fn snapshot(&self) -> Box<dyn Snapshot> {
+            self.inner.snapshot()
+        }
+
+        fn merge(&self, patch: Patch) -> Result<()> {
+            if self.is_failing.load(Ordering::SeqCst) {
+                Err(Error::new("write failed"))
+            } else {
+                self.inner.merge(patch)
+            }
+        }
+
+        fn merge_sync(&self, patch: Patch) -> Result<()> {
+            self.merge(patch)
+        }
+
+        fn merge_streamed(
+            &self,
+            source: &mut dyn FnMut(&mut dyn ChangesSink) -> Result<()>,
+        ) -> Result<()> {
+            if self.is_failing.load(Ordering::SeqCst) {
+                Err(Error::new("write failed"))
+            } else {
+                self.inner.merge_streamed(source)
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_is_retained_on_failed_flush() {
+        let inner = FailingDB {
+            inner: TemporaryDB::new(),
+            is_failing: AtomicBool::new(true),
+        };
+        let db = BatchingDatabase::new(inner, 1_000);
+        let fork = db.fork();
+        fork.get_entry("first").set(1_u8);
+        db.merge(fork.into_patch()).unwrap();
+        let pending_changes = db.pending_changes();
+        let snapshot = db.snapshot();
+
+        db.flush().unwrap_err();
+        assert_eq!(db.pending_changes(), pending_changes);
+        // A failed sync merge does not buffer its patch.
+        let fork = db.fork();
+        fork.get_entry("second").set(2_u8);
+        db.merge_sync(fork.into_patch()).unwrap_err();
+        assert_eq!(db.pending_changes(), pending_changes);
+        assert!(db.snapshot().get_entry::<_, u8>("second").get().is_none());
+
+        db.inner().is_failing.store(false, Ordering::SeqCst);
+        db.flush().unwrap();
+        assert_eq!(db.pending_changes(), 0);
+        let stored = db.inner().snapshot();
+        assert_eq!(stored.get_entry("first").get(), Some(1_u8));
+        // Snapshots created before the flush are unaffected by it.
+        assert_eq!(snapshot.get_entry("first").get(), Some(1_u8));
+    }
+
+    #[test]
+    fn buffer_is_flushed_on_sync_merge_and_drop() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let options = DbOptions::default();
+        let db = RocksDB::open(&dir, &options).unwrap();
+        let db = BatchingDatabase::new(db, 1_000);
+        let fork = db.fork();
+        fork.get_entry("first").set(1_u8);
+        db.merge(fork.into_patch()).unwrap();
+        let fork = db.fork();
+        fork.get_entry("second").set(2_u8);
+        db.merge_sync(fork.into_patch()).unwrap();
+        assert_eq!(db.pending_changes(), 0);
+        let snapshot = db.inner().snapshot();
+        assert_eq!(snapshot.get_entry("first").get(), Some(1_u8));
+        assert_eq!(snapshot.get_entry("second").get(), Some(2_u8));
+
+        let fork = db.fork();
+        fork.get_entry("third").set(3_u8);
+        db.merge(fork.into_patch()).unwrap();
+        let snapshot = db.inner().snapshot();
+        assert!(snapshot.get_entry::<_, u8>("third").get().is_none());
+        drop(db);
+
+        let db = RocksDB::open(&dir, &options).unwrap();
+        assert_eq!(db.snapshot().get_entry("third").get(), Some(3_u8));
+    }
+}
@@ -18,14 +18,16 @@ pub use rocksdb::{BlockBasedOptions as RocksBlockOptions, WriteOptions as RocksD
 
 use crossbeam::sync::{ShardedLock, ShardedLockReadGuard};
 use rocksdb::{
-    self, checkpoint::Checkpoint, ColumnFamily, DBIterator, Options as RocksDbOptions, WriteBatch,
+    self, checkpoint::Checkpoint, ColumnFamily, ColumnFamilyDescriptor, DBIterator,
+    Options as RocksDbOptions, WriteBatch,
 };
 use smallvec::SmallVec;
-use std::{fmt, iter::Peekable, mem, path::Path, sync::Arc};
+use std::{collections::HashMap, fmt, iter::Peekable, mem, path::Path, sync::Arc};
 
 use crate::{
     db::{check_database, Change},
-    Database, DbOptions, Iter, Iterator, Patch, ResolvedAddress, Snapshot,
+    ChangesSink, ColumnFamilyOptions, Database, DbOptions, Iter, Iterator, Patch, ResolvedAddress,
+    Snapshot,
 };
 
 /// Size of a byte representation of an index ID, which is used to prefix index keys
@@ -40,7 +42,11 @@ pub const ID_SIZE: usize = mem::size_of::<u64>();
 /// use different databases.
 pub struct RocksDB {
     db: Arc<ShardedLock<rocksdb::DB>>,
+    /// Names of the column families opened in `db`. `rocksdb::DB` does not expose them,
+    /// so they are tracked separately.
+    cf_names: ShardedLock<Vec<String>>,
     options: DbOptions,
+    cf_options: HashMap<String, ColumnFamilyOptions>,
 }
 
 impl From<DbOptions> for RocksDbOptions {
@@ -60,6 +66,31 @@ impl From<&DbOptions> for RocksDbOptions {
     }
 }
 
+/// Builds `RocksDB` options for a column family by applying column family-specific overrides
+/// on top of the database-wide options.
+fn column_family_options(
+    db_options: &DbOptions,
+    cf_options: Option<&ColumnFamilyOptions>,
+) -> RocksDbOptions {
+    let mut options = RocksDbOptions::from(db_options);
+    // Setting the table factory replaces the previously set one, so all table settings
+    // are collected into a single `BlockBasedOptions` instance.
+    let mut block_options = RocksBlockOptions::default();
+    if let Some(cf_options) = cf_options {
+        if let Some(compression_type) = cf_options.compression_type {
+            options.set_compression_type(compression_type.into());
+        }
+        if let Some(block_size) = cf_options.block_size {
+            block_options.set_block_size(block_size);
+        }
+        if let Some(compaction_style) = cf_options.compaction_style {
+            options.set_compaction_style(compaction_style.into());
+        }
+    }
+    options.set_block_based_table_factory(&block_options);
+    options
+}
+
 /// A snapshot of a `RocksDB`.
 pub struct RocksDBSnapshot {
     snapshot: rocksdb::Snapshot<'static>,
@@ -82,17 +113,58 @@ impl RocksDB {
     /// `create_if_missing` is switched on in `DbOptions`, a new database will
     /// be created at the indicated path.
     pub fn open<P: AsRef<Path>>(path: P, options: &DbOptions) -> crate::Result<Self> {
-        let inner = {
+        Self::open_with(path, options, HashMap::new())
+    }
+
+    /// Opens a database stored at the specified path with the specified options and
+    /// per-index options for column families.
+    ///
+    /// `cf_options` maps names of column families to their options. A column family
+    /// has the same name as the index stored in it; all indexes in a group share
+    /// the column family named after the group. Column families not mentioned
+    /// in `cf_options` use the database-wide settings from `options`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::{access::CopyAccessExt, ColumnFamilyOptions, CompressionType, Database, DbOptions, RocksDB};
+    /// # use std::collections::HashMap;
+    /// # use tempfile::TempDir;
+    /// let dir = TempDir::new().unwrap();
+    /// let mut blocks_options = ColumnFamilyOptions::default();
+    /// blocks_options.compression_type = Some(CompressionType::Lz4);
+    /// blocks_options.block_size = Some(64 * 1_024);
+    /// let mut cf_options = HashMap::new();
+    /// cf_options.insert("blocks".to_owned(), blocks_options);
+    ///
+    /// let db = RocksDB::open_with(&dir, &DbOptions::default(), cf_options).unwrap();
+    /// let fork = db.fork();
+    /// fork.get_list("blocks").push(1_u64);
+    /// db.merge(fork.into_patch()).unwrap();
+    /// ```
+    pub fn open_with<P: AsRef<Path>>(
+        path: P,
+        options: &DbOptions,
+        cf_options: HashMap<String, ColumnFamilyOptions>,
+    ) -> crate::Result<Self> {
+        let (inner, cf_names) = {
             if let Ok(names) = rocksdb::DB::list_cf(&RocksDbOptions::default(), &path) {
-                let cf_names = names.iter().map(String::as_str).collect::<Vec<_>>();
-                rocksdb::DB::open_cf(&options.into(), path, cf_names)?
+                let descriptors = names.iter().map(|name| {
+                    let cf_opts = column_family_options(options, cf_options.get(name));
+                    ColumnFamilyDescriptor::new(name, cf_opts)
+                });
+                let inner = rocksdb::DB::open_cf_descriptors(&options.into(), path, descriptors)?;
+                (inner, names)
             } else {
-                rocksdb::DB::open(&options.into(), path)?
+                let inner = rocksdb::DB::open(&options.into(), path)?;
+                (inner, vec![rocksdb::DEFAULT_COLUMN_FAMILY_NAME.to_owned()])
             }
         };
         let mut db = Self {
             db: Arc::new(ShardedLock::new(inner)),
+            cf_names: ShardedLock::new(cf_names),
             options: *options,
+            cf_options,
         };
         check_database(&mut db)?;
         Ok(db)
@@ -111,22 +183,60 @@ impl RocksDB {
         Ok(())
     }
 
+    /// Flushes memtables of all column families to disk and compacts the entire key range
+    /// of each column family. See [`RocksDB` docs] for details.
+    ///
+    /// Compaction does not affect snapshots: data visible to a snapshot is retained
+    /// until the snapshot is dropped.
+    ///
+    /// [`RocksDB` docs]: https://github.com/facebook/rocksdb/wiki/Manual-Compaction
+    pub fn compact(&self) -> crate::Result<()> {
+        let db = self.get_lock_guard();
+        let cf_names = self.get_cf_names();
+        for cf in cf_names.iter().filter_map(|name| db.cf_handle(name)) {
+            db.flush_cf(cf)?;
+            db.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+        }
+        Ok(())
+    }
+
     fn cf_exists(&self, cf_name: &str) -> bool {
         self.get_lock_guard().cf_handle(cf_name).is_some()
     }
 
+    fn ensure_cf_exists(&self, cf_name: &str) -> crate::Result<()> {
+        if self.cf_exists(cf_name) {
+            Ok(())
+        } else {
+            self.create_cf(cf_name)
+        }
+    }
+
     fn create_cf(&self, cf_name: &str) -> crate::Result<()> {
-        self.db
+        let mut db = self.db.write().expect("Couldn't get write lock to DB");
+        db.create_cf(
+            cf_name,
+            &column_family_options(&self.options, self.cf_options.get(cf_name)),
+        )?;
+        self.cf_names
             .write()
-            .expect("Couldn't get write lock to DB")
-            .create_cf(cf_name, &self.options.into())
-            .map_err(Into::into)
+            .expect("Couldn't get write lock to column family names")
+            .push(cf_name.to_owned());
+        Ok(())
     }
 
     pub(super) fn get_lock_guard(&self) -> ShardedLockReadGuard<'_, rocksdb::DB> {
         self.db.read().expect("Couldn't get read lock to DB")
     }
 
+    /// Returns names of the open column families. Should be called after locking `db`
+    /// to keep the lock order consistent with `create_cf`.
+    fn get_cf_names(&self) -> ShardedLockReadGuard<'_, Vec<String>> {
+        self.cf_names
+            .read()
+            .expect("Couldn't get read lock to column family names")
+    }
+
     /// Clears the column family completely, removing all keys from it.
     pub(super) fn clear_column_family(&self, batch: &mut WriteBatch, cf: &ColumnFamily) {
         /// Some lexicographically large key.
@@ -156,9 +266,7 @@ impl RocksDB {
     fn do_merge(&self, patch: Patch, w_opts: &RocksDBWriteOptions) -> crate::Result<()> {
         let mut batch = WriteBatch::default();
         for (resolved, changes) in patch.into_changes() {
-            if !self.cf_exists(&resolved.name) {
-                self.create_cf(&resolved.name)?;
-            }
+            self.ensure_cf_exists(&resolved.name)?;
 
             let db_reader = self.get_lock_guard();
             let cf = db_reader.cf_handle(&resolved.name).unwrap();
@@ -252,6 +360,39 @@ impl RocksDBSnapshot {
     }
 }
 
+/// Sink writing changes streamed to `RocksDB` into a `WriteBatch`.
+struct BatchSink<'a> {
+    db: &'a RocksDB,
+    batch: WriteBatch,
+}
+
+impl ChangesSink for BatchSink<'_> {
+    fn clear(&mut self, address: &ResolvedAddress) -> crate::Result<()> {
+        self.db.ensure_cf_exists(&address.name)?;
+        let db_reader = self.db.get_lock_guard();
+        let cf = db_reader.cf_handle(&address.name).unwrap();
+        self.db.clear_prefix(&mut self.batch, cf, address);
+        Ok(())
+    }
+
+    fn change(
+        &mut self,
+        address: &ResolvedAddress,
+        key: &[u8],
+        change: &Change,
+    ) -> crate::Result<()> {
+        self.db.ensure_cf_exists(&address.name)?;
+        let db_reader = self.db.get_lock_guard();
+        let cf = db_reader.cf_handle(&address.name).unwrap();
+        let key = address.keyed(key);
+        match change {
+            Change::Put(value) => self.batch.put_cf(cf, &key, value),
+            Change::Delete => self.batch.delete_cf(cf, &key),
+        }
+        Ok(())
+    }
+}
+
 impl Database for RocksDB {
     fn snapshot(&self) -> Box<dyn Snapshot> {
         Box::new(self.rocksdb_snapshot())
@@ -259,13 +400,51 @@ impl Database for RocksDB {
 
     fn merge(&self, patch: Patch) -> crate::Result<()> {
         let w_opts = RocksDBWriteOptions::default();
-        self.do_merge(patch, &w_opts)
+        traced!("merge", changes = patch.changes_count(); self.do_merge(patch, &w_opts))
     }
 
     fn merge_sync(&self, patch: Patch) -> crate::Result<()> {
         let mut w_opts = RocksDBWriteOptions::default();
         w_opts.set_sync(true);
-        self.do_merge(patch, &w_opts)
+        traced!(
+            "merge_sync",
+            changes = patch.changes_count();
+            self.do_merge(patch, &w_opts)
+        )
+    }
+
+    /// Writes the streamed changes directly to a `WriteBatch`, which is written
+    /// to the database once the `source` succeeds.
+    fn merge_streamed(
+        &self,
+        source: &mut dyn FnMut(&mut dyn ChangesSink) -> crate::Result<()>,
+    ) -> crate::Result<()> {
+        let mut sink = BatchSink {
+            db: self,
+            batch: WriteBatch::default(),
+        };
+        source(&mut sink)?;
+        self.get_lock_guard()
+            .write_opt(sink.batch, &RocksDBWriteOptions::default())
+            .map_err(Into::into)
+    }
+
+    fn approximate_size(&self) -> u64 {
+        let db = self.get_lock_guard();
+        self.get_cf_names()
+            .iter()
+            .filter_map(|name| db.cf_handle(name))
+            .map(|cf| approximate_cf_size(&db, cf))
+            .sum()
+    }
+
+    /// Returns the estimate for the column family storing the index. Indexes in the same group
+    /// share a column family, so all members of a group get the same value, which covers
+    /// the whole group.
+    fn approximate_size_at(&self, addr: &ResolvedAddress) -> u64 {
+        let db = self.get_lock_guard();
+        db.cf_handle(&addr.name)
+            .map_or(0, |cf| approximate_cf_size(&db, cf))
     }
 }
 
@@ -347,6 +526,21 @@ impl fmt::Debug for RocksDBSnapshot {
     }
 }
 
+/// Estimates the size of a column family as the sum of the estimated size of live data
+/// in SST files and the size of memtables. Both values are maintained by RocksDB, so
+/// the estimation does not scan the data.
+fn approximate_cf_size(db: &rocksdb::DB, cf: &ColumnFamily) -> u64 {
+    const SIZE_PROPERTIES: [&str; 2] = [
+        "rocksdb.estimate-live-data-size",
+        "rocksdb.cur-size-all-mem-tables",
+    ];
+
+    SIZE_PROPERTIES
+        .iter()
+        .filter_map(|property| db.property_int_value_cf(cf, property).ok().flatten())
+        .sum()
+}
+
 /// Generates the sequence of bytes lexicographically following the provided one. Assumes that
 /// the provided sequence is less than `[u8::max_value(); ID_SIZE]`.
 pub fn next_id_bytes(id_bytes: [u8; ID_SIZE]) -> [u8; ID_SIZE] {
@@ -70,19 +70,7 @@ impl TemporaryDB {
         Ok(())
     }
 
-    fn temporary_snapshot(&self) -> TemporarySnapshot {
-        TemporarySnapshot {
-            snapshot: self.inner.read().expect("Couldn't get read lock").clone(),
-        }
-    }
-}
-
-impl Database for TemporaryDB {
-    fn snapshot(&self) -> Box<dyn Snapshot> {
-        Box::new(self.temporary_snapshot())
-    }
-
-    fn merge(&self, patch: Patch) -> Result<()> {
+    fn do_merge(&self, patch: Patch) {
         let mut inner = self.inner.write().expect("Couldn't get write lock");
         for (resolved, changes) in patch.into_changes() {
             if !inner.contains_key(&resolved) {
@@ -130,12 +118,47 @@ impl Database for TemporaryDB {
                 }
             }
         }
+    }
+
+    fn temporary_snapshot(&self) -> TemporarySnapshot {
+        TemporarySnapshot {
+            snapshot: self.inner.read().expect("Couldn't get read lock").clone(),
+        }
+    }
+}
+
+impl Database for TemporaryDB {
+    fn snapshot(&self) -> Box<dyn Snapshot> {
+        Box::new(self.temporary_snapshot())
+    }
+
+    fn merge(&self, patch: Patch) -> Result<()> {
+        traced!("merge", changes = patch.changes_count(); self.do_merge(patch));
         Ok(())
     }
 
     fn merge_sync(&self, patch: Patch) -> Result<()> {
         self.merge(patch)
     }
+
+    fn approximate_size(&self) -> u64 {
+        let inner = self.inner.read().expect("Couldn't get read lock");
+        inner
+            .values()
+            .flat_map(BTreeMap::iter)
+            .map(|(key, value)| (key.len() + value.len()) as u64)
+            .sum()
+    }
+
+    fn approximate_size_at(&self, addr: &ResolvedAddress) -> u64 {
+        let inner = self.inner.read().expect("Couldn't get read lock");
+        inner.get(addr).map_or(0, |collection| {
+            collection
+                .iter()
+                .map(|(key, value)| (key.len() + value.len()) as u64)
+                .sum()
+        })
+    }
 }
 
 impl<'a> DbIterator for TemporaryDBIterator<'a> {
@@ -0,0 +1,382 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pluggable serialization codecs for index values.
+//!
+//! Indexes store values implementing [`BinaryValue`]. Instead of implementing this trait
+//! manually, a value can be wrapped into [`Coded`], which serializes the value with
+//! the chosen [`Codec`]. Thus, the codec becomes a part of the index type, e.g.,
+//! `MapIndex<_, K, Coded<V, JsonCodec>>`, and can be chosen per index, trading space
+//! for debuggability.
+//!
+//! The codec is attached to the value type rather than being a separate type parameter
+//! of the indexes (as in `MapIndex<K, V, C: Codec>`). This way, codecs work with every index
+//! type, including Merkelized indexes, proofs and iterators, which all rely on `BinaryValue`,
+//! and indexes with values implementing `BinaryValue` directly keep their types and storage
+//! format.
+//!
+//! Each codec has a unique [tag](trait.Codec.html#associatedconstant.TAG), which is stored
+//! as the first byte of each serialized value. Reading a value with a codec different from
+//! the one it was written with results in an error rather than garbage data.
+//!
+//! Codecs are enabled with the corresponding crate features:
+//!
+//! | Codec | Feature | Tag | Value requirements |
+//! |-------|---------|-----|--------------------|
+//! | [`BincodeCodec`] | `bincode-codec` | 1 | `Serialize + DeserializeOwned` |
+//! | [`JsonCodec`] | `json-codec` | 2 | `Serialize + DeserializeOwned` |
+//! | [`ProtobufCodec`] | `protobuf-codec` | 3 | `prost::Message + Default` |
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(feature = "json-codec")] {
+//! use merkledb::{access::CopyAccessExt, codec::{Coded, JsonCodec}, TemporaryDB, Database};
+//! use serde_derive::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+//! struct Wallet {
+//!     owner: String,
+//!     balance: u64,
+//! }
+//!
+//! let db = TemporaryDB::new();
+//! let fork = db.fork();
+//! let mut wallets = fork.get_map::<_, str, Coded<Wallet, JsonCodec>>("wallets");
+//! let wallet = Wallet { owner: "Alice".to_owned(), balance: 100 };
+//! wallets.put("alice", Coded::new(wallet.clone()));
+//! assert_eq!(wallets.get("alice").unwrap().into_inner(), wallet);
+//! # }
+//! ```
+//!
+//! [`BinaryValue`]: ../trait.BinaryValue.html
+//! [`Coded`]: struct.Coded.html
+//! [`Codec`]: trait.Codec.html
+//! [`BincodeCodec`]: struct.BincodeCodec.html
+//! [`JsonCodec`]: struct.JsonCodec.html
+//! [`ProtobufCodec`]: struct.ProtobufCodec.html
+
+use anyhow::{ensure, format_err};
+
+use std::{borrow::Cow, fmt, marker::PhantomData, ops::Deref};
+
+use crate::{crypto::Hash, BinaryValue, ObjectHash};
+
+/// Serialization codec for values of type `V`.
+pub trait Codec<V> {
+    /// Tag of the codec, which is stored together with the encoded values.
+    /// Tags must be unique among codecs.
+    const TAG: u8;
+
+    /// Encodes the value.
+    fn encode(value: &V) -> Vec<u8>;
+
+    /// Decodes the value from bytes.
+    fn decode(bytes: &[u8]) -> anyhow::Result<V>;
+}
+
+/// Codec based on `bincode`.
+#[cfg(feature = "bincode-codec")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode-codec")]
+impl<V> Codec<V> for BincodeCodec
+where
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    const TAG: u8 = 1;
+
+    fn encode(value: &V) -> Vec<u8> {
+        bincode::serialize(value).expect("cannot serialize value with `bincode`")
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<V> {
+        bincode::deserialize(bytes).map_err(From::from)
+    }
+}
+
+/// Codec based on JSON. JSON-encoded values take more space than binary ones,
+/// but are human-readable when inspecting the storage.
+#[cfg(feature = "json-codec")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json-codec")]
+impl<V> Codec<V> for JsonCodec
+where
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    const TAG: u8 = 2;
+
+    fn encode(value: &V) -> Vec<u8> {
+        serde_json::to_vec(value).expect("cannot serialize value to JSON")
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<V> {
+        serde_json::from_slice(bytes).map_err(From::from)
+    }
+}
+
+/// Codec based on Protocol Buffers, for types implementing `prost::Message`.
+#[cfg(feature = "protobuf-codec")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProtobufCodec;
+
+#[cfg(feature = "protobuf-codec")]
+impl<V> Codec<V> for ProtobufCodec
+where
+    V: prost::Message + Default,
+{
+    const TAG: u8 = 3;
+
+    fn encode(value: &V) -> Vec<u8> {
+        value.encode_to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<V> {
+        V::decode(bytes).map_err(From::from)
+    }
+}
+
+/// Value serialized with the codec `C` when stored in an index.
+///
+/// The serialized form of the value is the codec tag followed by the value encoded
+/// with the codec. See [the module docs](index.html) for details.
+pub struct Coded<V, C> {
+    value: V,
+    _codec: PhantomData<C>,
+}
+
+impl<V, C> Coded<V, C> {
+    /// Wraps the value.
+    pub fn new(value: V) -> Self {
+        Self {
+            value,
+            _codec: PhantomData,
+        }
+    }
+
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> V {
+        self.value
+    }
+}
+
+impl<V, C> From<V> for Coded<V, C> {
+    fn from(value: V) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<V, C> Deref for Coded<V, C> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.value
+    }
+}
+
+impl<V: fmt::Debug, C> fmt::Debug for Coded<V, C> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_tuple("Coded").field(&self.value).finish()
+    }
+}
+
+impl<V: Clone, C> Clone for Coded<V, C> {
+    fn clone(&self) -> Self {
+        Self::new(self.value.clone())
+    }
+}
+
+impl<V: PartialEq, C> PartialEq for Coded<V, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<V: Eq, C> Eq for Coded<V, C> {}
+
+impl<V, C: Codec<V>> BinaryValue for Coded<V, C> {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![C::TAG];
+        bytes.extend_from_slice(&C::encode(&self.value));
+        bytes
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
+        let (&tag, encoded) = bytes
+            .split_first()
+            .ok_or_else(|| format_err!("Coded value is empty"))?;
+        ensure!(
+            tag == C::TAG,
+            "Value is encoded with the codec with tag {}, while the expected tag is {}",
+            tag,
+            C::TAG
+        );
+        C::decode(encoded).map(Self::new)
+    }
+}
+
+impl<V, C: Codec<V>> ObjectHash for Coded<V, C> {
+    fn object_hash(&self) -> Hash {
+        crate::crypto::hash(&self.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{access::CopyAccessExt, Database, TemporaryDB};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Wallet {
+        owner: String,
+        balance: u64,
+    }
+
+    /// Codec used to check tag mismatches.
+    struct OtherCodec;
+
+    impl Codec<Wallet> for OtherCodec {
+        const TAG: u8 = 100;
+
+        fn encode(value: &Wallet) -> Vec<u8> {
+            value.owner.as_bytes().to_vec()
+        }
+
+        fn decode(bytes: &[u8]) -> anyhow::Result<Wallet> {
+            let owner = String::from_utf8(bytes.to_vec())?;
+            Ok(Wallet { owner, balance: 0 })
+        }
+    }
+
+    /// Codec not depending on any serialization crate, which allows to test `Coded`
+    /// with the default features.
+    struct DummyCodec;
+
+    impl Codec<Wallet> for DummyCodec {
+        const TAG: u8 = 200;
+
+        fn encode(value: &Wallet) -> Vec<u8> {
+            let mut bytes = value.balance.to_le_bytes().to_vec();
+            bytes.extend_from_slice(value.owner.as_bytes());
+            bytes
+        }
+
+        fn decode(bytes: &[u8]) -> anyhow::Result<Wallet> {
+            ensure!(bytes.len() >= 8, "Encoded wallet is too short");
+            let (balance, owner) = bytes.split_at(8);
+            let mut balance_bytes = [0; 8];
+            balance_bytes.copy_from_slice(balance);
+            Ok(Wallet {
+                owner: String::from_utf8(owner.to_vec())?,
+                balance: u64::from_le_bytes(balance_bytes),
+            })
+        }
+    }
+
+    fn check_codec<C: Codec<Wallet>>() {
+        let wallet = Wallet {
+            owner: "Alice".to_owned(),
+            balance: 100,
+        };
+
+        let value = Coded::<_, C>::new(wallet.clone());
+        let bytes = value.to_bytes();
+        assert_eq!(bytes[0], C::TAG);
+        let restored = Coded::<Wallet, C>::from_bytes(bytes.as_slice().into()).unwrap();
+        assert_eq!(restored.into_inner(), wallet);
+
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        fork.get_map::<_, str, Coded<Wallet, C>>("wallets")
+            .put("alice", value.clone());
+        fork.get_proof_entry::<_, Coded<Wallet, C>>("entry")
+            .set(value);
+        db.merge(fork.into_patch()).unwrap();
+
+        let snapshot = db.snapshot();
+        let wallets = snapshot.get_map::<_, str, Coded<Wallet, C>>("wallets");
+        assert_eq!(*wallets.get("alice").unwrap(), wallet);
+        let entry = snapshot.get_proof_entry::<_, Coded<Wallet, C>>("entry");
+        assert_eq!(entry.get().unwrap().balance, 100);
+
+        // Values written with one codec cannot be read with another one.
+        let err = Coded::<Wallet, OtherCodec>::from_bytes(bytes.into()).unwrap_err();
+        assert!(err.to_string().contains("expected tag is 100"), "{}", err);
+    }
+
+    #[test]
+    fn empty_coded_value() {
+        let err = Coded::<Wallet, OtherCodec>::from_bytes(vec![].into()).unwrap_err();
+        assert!(err.to_string().contains("empty"), "{}", err);
+    }
+
+    #[test]
+    fn dummy_codec() {
+        check_codec::<DummyCodec>();
+
+        let err =
+            Coded::<Wallet, DummyCodec>::from_bytes(vec![DummyCodec::TAG, 1].into()).unwrap_err();
+        assert!(err.to_string().contains("too short"), "{}", err);
+    }
+
+    #[cfg(feature = "bincode-codec")]
+    #[test]
+    fn bincode_codec() {
+        check_codec::<BincodeCodec>();
+    }
+
+    #[cfg(feature = "json-codec")]
+    #[test]
+    fn json_codec() {
+        check_codec::<JsonCodec>();
+
+        // JSON-encoded values are human-readable.
+        let wallet = Wallet {
+            owner: "Bob".to_owned(),
+            balance: 5,
+        };
+        let bytes = Coded::<_, JsonCodec>::new(wallet).to_bytes();
+        assert_eq!(&bytes[1..], br#"{"owner":"Bob","balance":5}"#);
+    }
+
+    #[cfg(feature = "protobuf-codec")]
+    #[test]
+    fn protobuf_codec() {
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct Point {
+            #[prost(int64, tag = "1")]
+            x: i64,
+            #[prost(int64, tag = "2")]
+            y: i64,
+        }
+
+        let point = Point { x: 1, y: -2 };
+        let value = Coded::<_, ProtobufCodec>::new(point.clone());
+        let bytes = value.to_bytes();
+        assert_eq!(bytes[0], 3);
+        let restored = Coded::<Point, ProtobufCodec>::from_bytes(bytes.into()).unwrap();
+        assert_eq!(restored.into_inner(), point);
+
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut points = fork.get_map::<_, u8, Coded<Point, ProtobufCodec>>("points");
+        points.put(&1, Coded::new(point.clone()));
+        assert_eq!(points.get(&1).unwrap().into_inner(), point);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cancellable hashing of large inputs on the `tokio` runtime.
+
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::sync::CancellationToken;
+
+use std::io;
+
+use super::{Hash, HashStream};
+
+/// Size of the chunks in which the data is read and hashed. The task yields
+/// to the executor after each chunk.
+const CHUNK_SIZE: usize = 64 * 1_024;
+
+/// Error returned by [`hash_async`].
+///
+/// [`hash_async`]: fn.hash_async.html
+#[derive(Debug, Error)]
+pub enum AsyncHashError {
+    /// Hashing was cancelled via the cancellation token before the input was exhausted.
+    #[error("hashing was cancelled")]
+    Cancelled,
+    /// Reading the input failed.
+    #[error("cannot read hashed data: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Calculates the hash of the data read from an asynchronous reader, the same as
+/// [`hash`] of the entire data.
+///
+/// The data is read and hashed in chunks, and the task yields to the executor between
+/// chunks, so hashing a large input does not starve other tasks on the runtime.
+/// Cancellation is checked before each chunk, and a pending read is aborted as soon
+/// as `cancel_token` is cancelled. A hash is only returned if the entire input
+/// has been processed; neither a cancelled nor a failed call produces a hash
+/// of the partially read data.
+///
+/// # Errors
+///
+/// Returns [`AsyncHashError::Cancelled`] if `cancel_token` is cancelled before the input
+/// is exhausted, and [`AsyncHashError::Io`] if reading from `reader` fails.
+///
+/// # Examples
+///
+/// ```
+/// # use merkledb::crypto::{hash, hash_async};
+/// # use tokio_util::sync::CancellationToken;
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let data = vec![1_u8; 100_000];
+/// let cancel_token = CancellationToken::new();
+/// let hash_value = hash_async(&data[..], &cancel_token).await.unwrap();
+/// assert_eq!(hash_value, hash(&data));
+/// # }
+/// ```
+///
+/// [`hash`]: fn.hash.html
+/// [`AsyncHashError::Cancelled`]: enum.AsyncHashError.html#variant.Cancelled
+/// [`AsyncHashError::Io`]: enum.AsyncHashError.html#variant.Io
+pub async fn hash_async<R: AsyncRead + Unpin>(
+    mut reader: R,
+    cancel_token: &CancellationToken,
+) -> Result<Hash, AsyncHashError> {
+    let mut stream = HashStream::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let bytes_read = tokio::select! {
+            // Check cancellation first, so that a reader which is always ready
+            // cannot postpone it.
+            biased;
+            () = cancel_token.cancelled() => return Err(AsyncHashError::Cancelled),
+            bytes_read = reader.read(&mut buffer) => bytes_read?,
+        };
+        if bytes_read == 0 {
+            return Ok(stream.hash());
+        }
+        stream = stream.update(&buffer[..bytes_read]);
+        tokio::task::yield_now().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncRead, ReadBuf};
+    use tokio_util::sync::CancellationToken;
+
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use super::{hash_async, AsyncHashError, CHUNK_SIZE};
+    use crate::crypto::hash;
+
+    /// Infinite reader cancelling the token on the specified read.
+    struct CancellingReader {
+        reads: usize,
+        cancel_on_read: usize,
+        cancel_token: CancellationToken,
+    }
+
+    impl AsyncRead for CancellingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.reads += 1;
+            if self.reads == self.cancel_on_read {
+                self.cancel_token.cancel();
+            }
+            let unfilled = buf.initialize_unfilled();
+            unfilled.fill(1);
+            let len = unfilled.len();
+            buf.advance(len);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn hash_async_matches_hash() {
+        // Data spans several chunks.
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE + 5).map(|i| i as u8).collect();
+        let cancel_token = CancellationToken::new();
+        let hash_value = hash_async(&data[..], &cancel_token).await.unwrap();
+        assert_eq!(hash_value, hash(&data));
+
+        let empty_hash = hash_async(&[][..], &cancel_token).await.unwrap();
+        assert_eq!(empty_hash, hash(&[]));
+    }
+
+    #[tokio::test]
+    async fn cancelling_mid_hash() {
+        let cancel_token = CancellationToken::new();
+        let mut reader = CancellingReader {
+            reads: 0,
+            cancel_on_read: 3,
+            cancel_token: cancel_token.clone(),
+        };
+        // The reader never ends, so the call can only finish due to cancellation.
+        let err = hash_async(&mut reader, &cancel_token).await.unwrap_err();
+        assert!(matches!(err, AsyncHashError::Cancelled));
+        assert_eq!(reader.reads, 3);
+
+        // An already cancelled token aborts hashing before reading any data.
+        let err = hash_async(&[1_u8; 10][..], &cancel_token)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AsyncHashError::Cancelled));
+    }
+}
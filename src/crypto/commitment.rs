@@ -0,0 +1,153 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Salted commitments to streamed data.
+
+use std::io::{self, Read};
+
+use super::{crypto_impl, verify_hash_eq, Hash, HashStream};
+
+/// Number of bytes in a `Salt`.
+pub const SALT_SIZE: usize = 32;
+
+/// Size of the buffer used to read data being committed to.
+const BUFFER_SIZE: usize = 8_192;
+
+/// Random salt used to make commitments hiding.
+///
+/// A commitment reveals nothing about the committed data as long as the salt is kept secret;
+/// the salt is disclosed together with the data to open the commitment. A fresh salt must be
+/// generated for each commitment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Salt([u8; SALT_SIZE]);
+
+impl Salt {
+    /// Creates a salt from the specified bytes.
+    pub fn new(bytes: [u8; SALT_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    /// Generates a random salt using the cryptographically secure generator
+    /// of the crypto backend.
+    pub fn generate() -> Self {
+        let mut bytes = [0; SALT_SIZE];
+        crypto_impl::fill_random(&mut bytes);
+        Self(bytes)
+    }
+
+    /// Copies bytes from this salt.
+    pub fn as_bytes(&self) -> [u8; SALT_SIZE] {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for Salt {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Commitment scheme for files and other large data read from a stream.
+///
+/// The commitment is the hash of the salt followed by the data. The data is fed to
+/// a [`HashStream`] in chunks, so it is never loaded into memory as a whole.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::crypto::{FileCommitment, Salt};
+/// use std::io::Cursor;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let data = vec![1_u8; 100_000];
+/// let salt = Salt::generate();
+/// let commitment = FileCommitment::commit_reader(Cursor::new(&data), &salt)?;
+/// // Later, the data and the salt are disclosed to open the commitment.
+/// assert!(FileCommitment::verify_reader(Cursor::new(&data), &salt, &commitment)?);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`HashStream`]: struct.HashStream.html
+#[derive(Debug)]
+pub struct FileCommitment;
+
+impl FileCommitment {
+    /// Computes a commitment to the data read from `reader` until EOF.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from `reader` fails.
+    pub fn commit_reader<R: Read>(mut reader: R, salt: &Salt) -> io::Result<Hash> {
+        let mut stream = HashStream::new().update(salt.as_ref());
+        let mut buffer = [0; BUFFER_SIZE];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => stream = stream.update(&buffer[..len]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(stream.hash())
+    }
+
+    /// Checks that the data read from `reader` together with `salt` opens the `commitment`.
+    /// Hashes are compared in constant time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from `reader` fails.
+    pub fn verify_reader<R: Read>(reader: R, salt: &Salt, commitment: &Hash) -> io::Result<bool> {
+        let actual = Self::commit_reader(reader, salt)?;
+        Ok(verify_hash_eq(&actual, commitment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileCommitment, Salt, BUFFER_SIZE, SALT_SIZE};
+    use crate::crypto::hash;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn commitments_to_cursor() {
+        // Data spans several buffers.
+        let data: Vec<u8> = (0..3 * BUFFER_SIZE + 5).map(|i| i as u8).collect();
+        let salt = Salt::generate();
+        let commitment = FileCommitment::commit_reader(Cursor::new(&data), &salt).unwrap();
+
+        let mut salted_data = salt.as_bytes().to_vec();
+        salted_data.extend_from_slice(&data);
+        assert_eq!(commitment, hash(&salted_data));
+        assert_ne!(commitment, hash(&data));
+
+        assert!(FileCommitment::verify_reader(Cursor::new(&data), &salt, &commitment).unwrap());
+        let wrong_salt = Salt::new([0; SALT_SIZE]);
+        assert_ne!(salt, wrong_salt);
+        assert!(
+            !FileCommitment::verify_reader(Cursor::new(&data), &wrong_salt, &commitment).unwrap()
+        );
+        let other_data = &data[1..];
+        assert!(
+            !FileCommitment::verify_reader(Cursor::new(other_data), &salt, &commitment).unwrap()
+        );
+
+        // Commitments to the same data with different salts differ.
+        let other_commitment =
+            FileCommitment::commit_reader(Cursor::new(&data), &Salt::generate()).unwrap();
+        assert_ne!(commitment, other_commitment);
+    }
+}
@@ -14,27 +14,69 @@
 
 //! Common macros for crypto module.
 
+/// Implements a wrapper for a public cryptographic primitive.
+///
+/// Primitives declared with the `constant_time_eq` flag (e.g., signatures, which are
+/// compared against expected values) are compared in constant time; other primitives
+/// use the cheaper derived comparison.
 macro_rules! implement_public_crypto_wrapper {
     ($(#[$attr:meta])* struct $name:ident, $size:expr) => {
+        implement_public_crypto_wrapper! {
+            @define [PartialEq, Eq, PartialOrd, Ord, Hash]
+            $(#[$attr])* struct $name, $size
+        }
+    };
+    ($(#[$attr:meta])* constant_time_eq struct $name:ident, $size:expr) => {
+        implement_public_crypto_wrapper! {
+            @define [] $(#[$attr])* struct $name, $size
+        }
+        implement_constant_time_eq! { $name }
+
+        // Ordering and hashing are consistent with the constant-time equality.
+        // Unlike equality, they are not used to check secrets, so they may be timing-variable.
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.as_ref().cmp(other.as_ref())
+            }
+        }
+
+        impl std::hash::Hash for $name {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                std::hash::Hash::hash(self.as_ref(), state);
+            }
+        }
+    };
+    (@define [$($derive:ident),*] $(#[$attr:meta])* struct $name:ident, $size:expr) => {
         /// Cryptographic primitive implementation newtype.
-        #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+        #[derive(Clone, Copy, $($derive),*)]
         $(#[$attr])*
         pub struct $name($crate::crypto::crypto_impl::$name);
 
         impl $name {
             /// Creates a new instance filled with zeros.
-            pub fn zero() -> Self {
+            pub const fn zero() -> Self {
                 $name::new([0; $size])
             }
         }
 
         impl $name {
             /// Creates a new instance from bytes array.
-            pub fn new(bytes_array: [u8; $size]) -> Self {
+            ///
+            /// The constructor is `const`, so it can be used to define constants,
+            /// such as trusted hashes pinned at compile time.
+            pub const fn new(bytes_array: [u8; $size]) -> Self {
                 $name($crate::crypto::crypto_impl::$name(bytes_array))
             }
 
-            /// Creates a new instance from bytes slice.
+            /// Creates a new instance from bytes slice. Returns `None` if the length
+            /// of the slice differs from the size of the primitive, so the constructor
+            /// is safe to use with untrusted input.
             pub fn from_slice(bytes_slice: &[u8]) -> Option<Self> {
                 $crate::crypto::crypto_impl::$name::from_slice(bytes_slice).map($name)
             }
@@ -57,6 +99,21 @@ macro_rules! implement_public_crypto_wrapper {
             }
         }
 
+        impl From<[u8; $size]> for $name {
+            fn from(bytes_array: [u8; $size]) -> Self {
+                Self::new(bytes_array)
+            }
+        }
+
+        /// Fails if the length of the slice differs from the size of the primitive.
+        impl std::convert::TryFrom<&[u8]> for $name {
+            type Error = std::array::TryFromSliceError;
+
+            fn try_from(bytes_slice: &[u8]) -> Result<Self, Self::Error> {
+                <[u8; $size]>::try_from(bytes_slice).map(Self::new)
+            }
+        }
+
         impl Default for $name {
             fn default() -> Self {
                 Self::zero()
@@ -90,8 +147,89 @@ macro_rules! implement_public_crypto_wrapper {
     };
 }
 
-macro_rules! implement_serde {
+/// Implements `PartialEq` and `Eq` for a crypto wrapper comparing its bytes in constant time.
+///
+/// The comparison time depends only on the length of the compared values, which is the same
+/// for all values of a wrapper, and not on their contents. Thus, comparing a provided value
+/// with an expected one (e.g., a secret key or a signature) does not reveal how many leading
+/// bytes of the values match.
+macro_rules! implement_constant_time_eq {
     ($name:ident) => {
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                $crate::crypto::crypto_impl::verify_eq(self.as_ref(), other.as_ref())
+            }
+        }
+
+        impl Eq for $name {}
+    };
+}
+
+macro_rules! implement_private_crypto_wrapper {
+    ($(#[$attr:meta])* struct $name:ident, $size:expr) => {
+        /// Cryptographic primitive implementation newtype for secret data.
+        ///
+        /// Unlike public primitives, this type does not implement `Copy` or `Display`,
+        /// its `Debug` output does not reveal the bytes of the primitive, values
+        /// are compared in constant time, and the bytes are zeroed on drop.
+        #[derive(Clone)]
+        $(#[$attr])*
+        pub struct $name($crate::crypto::crypto_impl::$name);
+
+        implement_constant_time_eq! { $name }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                $crate::crypto::crypto_impl::memzero(&mut (self.0).0);
+            }
+        }
+
+        impl $name {
+            /// Creates a new instance filled with zeros.
+            pub fn zero() -> Self {
+                $name::new([0; $size])
+            }
+
+            /// Creates a new instance from bytes array.
+            pub fn new(bytes_array: [u8; $size]) -> Self {
+                $name($crate::crypto::crypto_impl::$name(bytes_array))
+            }
+
+            /// Creates a new instance from bytes slice. Returns `None` if the length
+            /// of the slice differs from the size of the primitive, so the constructor
+            /// is safe to use with untrusted input.
+            pub fn from_slice(bytes_slice: &[u8]) -> Option<Self> {
+                $crate::crypto::crypto_impl::$name::from_slice(bytes_slice).map($name)
+            }
+
+            /// Returns a hex representation of binary data.
+            /// Lower case letters are used (e.g. `f9b4ca`).
+            pub fn to_hex(&self) -> String {
+                $crate::crypto::encode_hex(&self[..])
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                self.0.as_ref()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}(****)", stringify!($name))
+            }
+        }
+    };
+}
+
+/// Implements `serde` traits for a crypto wrapper.
+///
+/// Human-readable formats (e.g., JSON) represent the wrapper as a hex string. Binary formats
+/// (e.g., `bincode`) represent it as a fixed-size tuple of bytes, which is encoded without
+/// a length prefix, so the encoding has exactly `$size` bytes.
+macro_rules! implement_serde {
+    ($name:ident, $size:expr) => {
         impl FromHex for $name {
             type Error = FromHexError;
 
@@ -110,8 +248,18 @@ macro_rules! implement_serde {
             where
                 S: Serializer,
             {
-                let hex_string = encode_hex(&self[..]);
-                ser.serialize_str(&hex_string)
+                use serde::ser::SerializeTuple;
+
+                if ser.is_human_readable() {
+                    let hex_string = encode_hex(&self[..]);
+                    ser.serialize_str(&hex_string)
+                } else {
+                    let mut tuple = ser.serialize_tuple($size)?;
+                    for byte in &self[..] {
+                        tuple.serialize_element(byte)?;
+                    }
+                    tuple.end()
+                }
             }
         }
 
@@ -134,7 +282,117 @@ macro_rules! implement_serde {
                         $name::from_hex(s).map_err(|_| de::Error::custom("Invalid hex"))
                     }
                 }
-                deserializer.deserialize_str(HexVisitor)
+
+                struct BytesVisitor;
+
+                impl<'v> Visitor<'v> for BytesVisitor {
+                    type Value = $name;
+                    fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+                        write!(fmt, "{} bytes", $size)
+                    }
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: de::SeqAccess<'v>,
+                    {
+                        let mut bytes = [0_u8; $size];
+                        for (i, byte) in bytes.iter_mut().enumerate() {
+                            *byte = seq
+                                .next_element()?
+                                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                        }
+                        Ok($name::new(bytes))
+                    }
+                    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Self::Value, E>
+                    where
+                        E: de::Error,
+                    {
+                        $name::from_slice(bytes)
+                            .ok_or_else(|| de::Error::invalid_length(bytes.len(), &self))
+                    }
+                }
+
+                if deserializer.is_human_readable() {
+                    deserializer.deserialize_str(HexVisitor)
+                } else {
+                    deserializer.deserialize_tuple($size, BytesVisitor)
+                }
+            }
+        }
+    };
+}
+
+/// Implements `rkyv` traits for a crypto wrapper. The archived form of the wrapper is
+/// a `#[repr(transparent)]` byte array, so the archived values can be accessed in place
+/// without any parsing. Any byte array of the proper length is a valid archived value.
+#[cfg(feature = "rkyv")]
+macro_rules! implement_rkyv {
+    ($(#[$attr:meta])* $name:ident => $archived:ident, $size:expr) => {
+        $(#[$attr])*
+        #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+        #[repr(transparent)]
+        pub struct $archived([u8; $size]);
+
+        impl $archived {
+            /// Returns the bytes of the archived value.
+            pub fn as_bytes(&self) -> &[u8; $size] {
+                &self.0
+            }
+
+            /// Converts the archived value into its unarchived counterpart.
+            pub fn to_native(&self) -> $name {
+                $name::new(self.0)
+            }
+        }
+
+        impl fmt::Debug for $archived {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.to_native(), f)
+            }
+        }
+
+        impl PartialEq<$name> for $archived {
+            fn eq(&self, other: &$name) -> bool {
+                self.0 == other.as_bytes()
+            }
+        }
+
+        impl PartialEq<$archived> for $name {
+            fn eq(&self, other: &$archived) -> bool {
+                self.as_bytes() == other.0
+            }
+        }
+
+        impl rkyv::Archive for $name {
+            type Archived = $archived;
+            type Resolver = ();
+
+            #[allow(unsafe_code)]
+            unsafe fn resolve(&self, _pos: usize, _resolver: (), out: *mut Self::Archived) {
+                out.write($archived(self.as_bytes()));
+            }
+        }
+
+        impl<S: rkyv::Fallible + ?Sized> rkyv::Serialize<S> for $name {
+            fn serialize(&self, _serializer: &mut S) -> Result<(), S::Error> {
+                Ok(())
+            }
+        }
+
+        impl<D: rkyv::Fallible + ?Sized> rkyv::Deserialize<$name, D> for $archived {
+            fn deserialize(&self, _deserializer: &mut D) -> Result<$name, D::Error> {
+                Ok(self.to_native())
+            }
+        }
+
+        impl<C: ?Sized> rkyv::bytecheck::CheckBytes<C> for $archived {
+            type Error = std::convert::Infallible;
+
+            #[allow(unsafe_code)]
+            unsafe fn check_bytes<'a>(
+                value: *const Self,
+                _context: &mut C,
+            ) -> Result<&'a Self, Self::Error> {
+                Ok(&*value)
             }
         }
     };
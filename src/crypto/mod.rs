@@ -20,11 +20,26 @@
 //! cryptography applied in the system and add abstractions best
 //! suited for Exonum.
 
+#[cfg(all(feature = "sodiumoxide-crypto", feature = "rust-crypto"))]
+compile_error!(
+    "Features `sodiumoxide-crypto` and `rust-crypto` select different cryptographic backends \
+     and are mutually exclusive. To use `rust-crypto`, disable default features of `merkledb`."
+);
+#[cfg(not(any(feature = "sodiumoxide-crypto", feature = "rust-crypto")))]
+compile_error!(
+    "A cryptographic backend must be selected with either the `sodiumoxide-crypto` \
+     or the `rust-crypto` feature."
+);
+
+#[cfg(feature = "rust-crypto")]
+mod rust_crypto;
 #[cfg(feature = "sodiumoxide-crypto")]
 mod sodiumoxide;
 
 #[doc(inline)]
-pub use crate::crypto::crypto_impl::HASH_SIZE;
+pub use crate::crypto::crypto_impl::{
+    HASH512_SIZE, HASH_SIZE, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SEED_LENGTH, SIGNATURE_LENGTH,
+};
 
 use hex::{encode as encode_hex, FromHex, FromHexError};
 use serde::{
@@ -35,20 +50,37 @@ use serde::{
 use std::{
     default::Default,
     fmt::{self, Debug},
+    io,
     ops::{Index, Range, RangeFrom, RangeFull, RangeTo},
 };
 
 // A way to set an active cryptographic backend is to export it as `crypto_impl`.
+#[cfg(feature = "rust-crypto")]
+use crate::crypto::rust_crypto as crypto_impl;
 #[cfg(feature = "sodiumoxide-crypto")]
 use crate::crypto::sodiumoxide as crypto_impl;
 
+#[cfg(feature = "tokio")]
+pub use self::async_hash::{hash_async, AsyncHashError};
+pub use self::{
+    commitment::{FileCommitment, Salt, SALT_SIZE},
+    ring::HashRing,
+};
+
 #[macro_use]
 mod macros;
+#[cfg(feature = "tokio")]
+mod async_hash;
+mod commitment;
+mod ring;
 
 /// The size to crop the string in debug messages.
 const BYTES_IN_DEBUG: usize = 4;
 /// The size of ellipsis in debug messages.
 const BYTES_IN_ELLIPSIS: usize = 3;
+/// The minimum size of hashed data for which a `tracing` span is emitted.
+#[cfg(feature = "tracing")]
+const TRACED_HASH_INPUT_SIZE: usize = 1 << 20;
 
 fn write_short_hex(f: &mut impl fmt::Write, slice: &[u8]) -> fmt::Result {
     for byte in slice.iter().take(BYTES_IN_DEBUG) {
@@ -74,10 +106,202 @@ fn write_short_hex(f: &mut impl fmt::Write, slice: &[u8]) -> fmt::Result {
 /// let hash = merkledb::crypto::hash(&data);
 /// ```
 pub fn hash(data: &[u8]) -> Hash {
+    #[cfg(feature = "tracing")]
+    {
+        if data.len() >= TRACED_HASH_INPUT_SIZE {
+            return traced!("hash", size = data.len(); Hash(crypto_impl::hash(data)));
+        }
+    }
+
     let dig = crypto_impl::hash(data);
     Hash(dig)
 }
 
+/// Calculates a SHA-512 hash of a bytes slice.
+///
+/// Unlike [`hash`], the hash function does not depend on the crypto backend; this function
+/// is intended for protocols which explicitly require SHA-512. Use [`hash`] for
+/// all other purposes.
+///
+/// # Examples
+///
+/// ```
+/// # use merkledb::crypto::{hash512, HASH512_SIZE};
+/// let hash = hash512(b"abc");
+/// assert_eq!(hash.as_ref().len(), HASH512_SIZE);
+/// assert!(hash.to_hex().starts_with("ddaf35a1"));
+/// ```
+///
+/// [`hash`]: fn.hash.html
+pub fn hash512(data: &[u8]) -> Hash512 {
+    Hash512(crypto_impl::hash512(data))
+}
+
+/// Calculates a BLAKE2b hash of a bytes slice with the 256-bit digest.
+///
+/// Like [`hash512`], this function does not depend on the crypto backend choice of
+/// the hash function, and is intended for protocols explicitly requiring BLAKE2b.
+///
+/// # Examples
+///
+/// ```
+/// # use merkledb::crypto::{blake2b, hash};
+/// let hash_value = blake2b(b"abc");
+/// assert_eq!(
+///     hash_value.to_hex(),
+///     "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319"
+/// );
+/// assert_ne!(hash_value, hash(b"abc"));
+/// ```
+///
+/// [`hash512`]: fn.hash512.html
+pub fn blake2b(data: &[u8]) -> Hash {
+    Hash(crypto_impl::blake2b(data))
+}
+
+/// Calculates a hash of the concatenation of byte slices yielded by an iterator.
+///
+/// The slices are fed to a [`HashStream`] one by one, so they don't need to be collected
+/// into a single buffer.
+///
+/// # Examples
+///
+/// ```
+/// # use merkledb::crypto::{hash, hash_iter};
+/// let lines = vec!["first line", "second line"];
+/// assert_eq!(hash_iter(&lines), hash(b"first linesecond line"));
+/// ```
+///
+/// [`HashStream`]: struct.HashStream.html
+pub fn hash_iter<I>(parts: I) -> Hash
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    parts
+        .into_iter()
+        .fold(HashStream::new(), |stream, part| {
+            stream.update(part.as_ref())
+        })
+        .hash()
+}
+
+/// Calculates hashes of many independent byte slices, e.g., leaves of a Merkle tree.
+///
+/// Each output hash is equal to [`hash`] of the corresponding input, and the outputs
+/// are returned in the input order. With the `parallel` feature, inputs are hashed
+/// on multiple cores using `rayon`; otherwise, they are hashed sequentially.
+///
+/// # Examples
+///
+/// ```
+/// # use merkledb::crypto::{hash, hash_batch};
+/// let leaves: Vec<&[u8]> = vec![b"first", b"second", b"third"];
+/// let hashes = hash_batch(&leaves);
+/// assert_eq!(hashes[1], hash(b"second"));
+/// ```
+///
+/// [`hash`]: fn.hash.html
+pub fn hash_batch(chunks: &[&[u8]]) -> Vec<Hash> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        chunks.par_iter().map(|chunk| hash(chunk)).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        chunks.iter().map(|chunk| hash(chunk)).collect()
+    }
+}
+
+/// Compares two hashes in constant time.
+///
+/// Unlike `==` (i.e., `PartialEq`), which may return as soon as the first differing byte
+/// is found, the running time of this function does not depend on the contents
+/// of the hashes. Use it when one of the hashes is secret and the comparison result
+/// is observable by an attacker, e.g., when checking MAC-like authentication tags.
+/// For public data, such as Merkle roots of indexes or hashes in proofs, timing
+/// does not leak anything useful, so the faster `==` is fine.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::crypto::{hash, verify_hash_eq};
+///
+/// let expected = hash(b"data");
+/// assert!(verify_hash_eq(&expected, &hash(b"data")));
+/// assert!(!verify_hash_eq(&expected, &hash(b"other data")));
+/// ```
+pub fn verify_hash_eq(a: &Hash, b: &Hash) -> bool {
+    crypto_impl::verify_eq(a.as_ref(), b.as_ref())
+}
+
+/// Generates a random Ed25519 key pair.
+///
+/// # Examples
+///
+/// ```
+/// # merkledb::crypto::init();
+/// let (public_key, secret_key) = merkledb::crypto::gen_keypair();
+/// ```
+pub fn gen_keypair() -> (PublicKey, SecretKey) {
+    let (public_key, secret_key) = crypto_impl::gen_keypair();
+    (PublicKey(public_key), SecretKey(secret_key))
+}
+
+/// Generates an Ed25519 key pair deterministically from the specified seed. The same seed
+/// always yields the same key pair, which is useful for reproducible test fixtures and
+/// for keys derived from a master secret.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::crypto::{gen_keypair_from_seed, Seed, SEED_LENGTH};
+///
+/// # merkledb::crypto::init();
+/// let seed = Seed::new([1; SEED_LENGTH]);
+/// let (public_key, secret_key) = gen_keypair_from_seed(&seed);
+/// assert_eq!(gen_keypair_from_seed(&seed), (public_key, secret_key));
+/// ```
+pub fn gen_keypair_from_seed(seed: &Seed) -> (PublicKey, SecretKey) {
+    let (public_key, secret_key) = crypto_impl::gen_keypair_from_seed(&seed.0);
+    (PublicKey(public_key), SecretKey(secret_key))
+}
+
+/// Computes the Ed25519 signature of a slice of bytes with the specified secret key.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::crypto::{gen_keypair, sign, verify};
+///
+/// # merkledb::crypto::init();
+/// let (public_key, secret_key) = gen_keypair();
+/// let signature = sign(b"message", &secret_key);
+/// assert!(verify(&signature, b"message", &public_key));
+/// ```
+pub fn sign(data: &[u8], secret_key: &SecretKey) -> Signature {
+    Signature(crypto_impl::sign(data, &secret_key.0))
+}
+
+/// Verifies that the Ed25519 signature of a slice of bytes was created with the secret key
+/// corresponding to the specified public key.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::crypto::{gen_keypair, sign, verify};
+///
+/// # merkledb::crypto::init();
+/// let (public_key, secret_key) = gen_keypair();
+/// let signature = sign(b"message", &secret_key);
+/// assert!(!verify(&signature, b"other message", &public_key));
+/// ```
+pub fn verify(signature: &Signature, data: &[u8], public_key: &PublicKey) -> bool {
+    crypto_impl::verify(&signature.0, data, &public_key.0)
+}
+
 /// Initializes the cryptographic backend.
 ///
 /// # Panics
@@ -134,10 +358,15 @@ impl HashStream {
 
     /// Processes a chunk of stream and returns a `HashStream` with the updated internal state.
     pub fn update(mut self, chunk: &[u8]) -> Self {
-        self.0.update(chunk);
+        self.update_mut(chunk);
         self
     }
 
+    /// Processes a chunk of stream in place.
+    pub fn update_mut(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
     /// Returns the resulting hash of the system calculated upon the commit
     /// of currently supplied data.
     pub fn hash(self) -> Hash {
@@ -146,19 +375,235 @@ impl HashStream {
     }
 }
 
-implement_public_crypto_wrapper! { struct Hash, HASH_SIZE }
+/// Feeds written data into the stream, which allows to hash the output of serializers
+/// or to copy data into the stream with [`io::copy`]. Writes always consume
+/// the entire buffer and never fail.
+///
+/// # Examples
+///
+/// ```
+/// # use merkledb::crypto::{hash, HashStream};
+/// # use std::io;
+/// let data = vec![1_u8; 10_000];
+/// let mut hash_stream = HashStream::new();
+/// io::copy(&mut &data[..], &mut hash_stream)?;
+/// assert_eq!(hash_stream.hash(), hash(&data));
+/// # Ok::<_, io::Error>(())
+/// ```
+///
+/// [`io::copy`]: https://doc.rust-lang.org/std/io/fn.copy.html
+impl io::Write for HashStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update_mut(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+implement_public_crypto_wrapper! {
+    ///
+    /// Hashes are ordered lexicographically by their bytes, which is consistent with
+    /// the ordering of their hex representations. Thus, hashes can be used as keys
+    /// of a `BTreeMap` for ordered iteration and range scans.
+    struct Hash, HASH_SIZE
+}
 
-implement_serde! { Hash }
+implement_serde! { Hash, HASH_SIZE }
 implement_index_traits! { Hash }
 
+implement_public_crypto_wrapper! {
+    /// SHA-512 hash, which is twice as long as [`Hash`](struct.Hash.html).
+    struct Hash512, HASH512_SIZE
+}
+
+implement_serde! { Hash512, HASH512_SIZE }
+implement_index_traits! { Hash512 }
+
+impl Hash {
+    /// Returns the position of this hash on a ring of `u64` values used for consistent hashing,
+    /// which is equal to the first 8 bytes of the hash interpreted as a big-endian integer
+    /// (i.e., the top 64 bits of the hash).
+    ///
+    /// See [`HashRing`] for a higher-level helper.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::crypto::{Hash, HASH_SIZE};
+    /// let mut bytes = [0; HASH_SIZE];
+    /// bytes[..8].copy_from_slice(&[1, 0, 0, 0, 0, 0, 0, 2]);
+    /// assert_eq!(Hash::new(bytes).ring_position(), (1 << 56) + 2);
+    /// ```
+    ///
+    /// [`HashRing`]: struct.HashRing.html
+    pub fn ring_position(&self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.as_ref()[..8]);
+        u64::from_be_bytes(bytes)
+    }
+
+    /// Derives a child hash from this (master) hash and a label.
+    ///
+    /// The derivation follows the expand step of HKDF ([RFC 5869]): the child is the HMAC
+    /// of the label followed by the `0x01` block counter, keyed with the master hash.
+    /// Thus, children are deterministic, children with different labels are unrelated,
+    /// and the master hash cannot be recovered from its children. Children can be used
+    /// as masters in turn, which allows to build trees of subkeys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::crypto::hash;
+    /// let master = hash(b"master");
+    /// let accounts = master.derive_child(b"accounts");
+    /// assert_eq!(accounts, master.derive_child(b"accounts"));
+    /// assert_ne!(accounts, master.derive_child(b"blocks"));
+    /// let alice = accounts.derive_child(b"alice");
+    /// # assert_ne!(alice, master.derive_child(b"alice"));
+    /// ```
+    ///
+    /// [RFC 5869]: https://tools.ietf.org/html/rfc5869
+    pub fn derive_child(&self, label: &[u8]) -> Self {
+        /// Counter of the first (and only) output block in HKDF-Expand.
+        const FIRST_BLOCK: [u8; 1] = [1];
+        Self(crypto_impl::hmac(self.as_ref(), &[label, &FIRST_BLOCK]))
+    }
+
+    /// Returns a copy of this hash with all bits except for the leading `prefix_bits` set to zero.
+    /// Bits are counted from the most significant bit of the first byte, i.e., in the same order
+    /// as in [`ring_position`]. If `prefix_bits` exceeds the bit length of the hash,
+    /// the hash is returned unchanged.
+    ///
+    /// Masked hashes can be used to group identifiers into buckets of the configurable size
+    /// (e.g., to achieve k-anonymity when revealing an identifier partially).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::crypto::{Hash, HASH_SIZE};
+    /// let hash = Hash::new([0xff; HASH_SIZE]);
+    /// let masked = hash.masked(12);
+    /// assert_eq!(masked.as_ref()[..3], [0xff, 0xf0, 0]);
+    /// assert_eq!(masked.masked(12), masked);
+    /// ```
+    ///
+    /// [`ring_position`]: #method.ring_position
+    pub fn masked(&self, prefix_bits: u32) -> Self {
+        let mut bytes = self.as_bytes();
+        let full_bytes = (prefix_bits / 8) as usize;
+        if full_bytes < HASH_SIZE {
+            let partial_bits = prefix_bits % 8;
+            bytes[full_bytes] &= !(0xff_u8 >> partial_bits);
+            for byte in &mut bytes[full_bytes + 1..] {
+                *byte = 0;
+            }
+        }
+        Self::new(bytes)
+    }
+
+    /// Returns a short human-readable fingerprint of this hash for comparing hashes by eye
+    /// or reading them aloud (e.g., during out-of-band verification).
+    ///
+    /// The fingerprint encodes the first 8 bytes of the hash as 4 pronounceable
+    /// five-letter words separated by dashes, using the [proquint] encoding: each word
+    /// encodes 16 bits in the big-endian order. Hence, the fingerprint is deterministic,
+    /// and hashes with different fingerprints are different. Note that the fingerprint
+    /// covers only 64 bits of the hash, so it should not be relied upon if an adversary
+    /// can grind hashes to match a fingerprint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::crypto::{Hash, HASH_SIZE};
+    /// let mut bytes = [0; HASH_SIZE];
+    /// bytes[..4].copy_from_slice(&[127, 0, 0, 1]);
+    /// assert_eq!(Hash::new(bytes).fingerprint(), "lusab-babad-babab-babab");
+    /// ```
+    ///
+    /// [proquint]: https://arxiv.org/abs/0901.4016
+    pub fn fingerprint(&self) -> String {
+        const CONSONANTS: &[u8; 16] = b"bdfghjklmnprstvz";
+        const VOWELS: &[u8; 4] = b"aiou";
+        const WORDS: usize = 4;
+
+        let mut fingerprint = String::with_capacity(WORDS * 6 - 1);
+        for (i, chunk) in self.as_ref()[..WORDS * 2].chunks(2).enumerate() {
+            if i > 0 {
+                fingerprint.push('-');
+            }
+            let word = usize::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+            fingerprint.extend(
+                [
+                    CONSONANTS[word >> 12],
+                    VOWELS[(word >> 10) & 0x3],
+                    CONSONANTS[(word >> 6) & 0xf],
+                    VOWELS[(word >> 4) & 0x3],
+                    CONSONANTS[word & 0xf],
+                ]
+                .iter()
+                .map(|&letter| char::from(letter)),
+            );
+        }
+        fingerprint
+    }
+}
+implement_public_crypto_wrapper! {
+    /// Ed25519 public key used to [`verify`](fn.verify.html) signatures.
+    struct PublicKey, PUBLIC_KEY_LENGTH
+}
+
+implement_serde! { PublicKey, PUBLIC_KEY_LENGTH }
+implement_index_traits! { PublicKey }
+
+implement_private_crypto_wrapper! {
+    /// Ed25519 secret key used to [`sign`](fn.sign.html) data. The bytes of the key
+    /// are zeroed when the key is dropped.
+    struct SecretKey, SECRET_KEY_LENGTH
+}
+
+implement_serde! { SecretKey, SECRET_KEY_LENGTH }
+implement_index_traits! { SecretKey }
+
+implement_private_crypto_wrapper! {
+    /// Seed used to generate an Ed25519 key pair with
+    /// [`gen_keypair_from_seed`](fn.gen_keypair_from_seed.html). The bytes of the seed
+    /// are zeroed when the seed is dropped.
+    struct Seed, SEED_LENGTH
+}
+
+implement_serde! { Seed, SEED_LENGTH }
+implement_index_traits! { Seed }
+
+implement_public_crypto_wrapper! {
+    /// Ed25519 signature produced by [`sign`](fn.sign.html). Signatures are compared
+    /// in constant time.
+    constant_time_eq struct Signature, SIGNATURE_LENGTH
+}
+
+implement_serde! { Signature, SIGNATURE_LENGTH }
+implement_index_traits! { Signature }
+
+#[cfg(feature = "rkyv")]
+implement_rkyv! {
+    /// Archived form of a [`Hash`](struct.Hash.html) for the `rkyv` zero-copy framework.
+    Hash => ArchivedHash, HASH_SIZE
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{fmt, hash, Hash, HashStream, Serialize, HASH_SIZE};
+    use super::{
+        blake2b, fmt, gen_keypair, gen_keypair_from_seed, hash, hash512, sign, verify, Debug, Hash,
+        Hash512, HashStream, PublicKey, SecretKey, Seed, Serialize, Signature, HASH512_SIZE,
+        HASH_SIZE, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SEED_LENGTH, SIGNATURE_LENGTH,
+    };
 
     use hex::FromHex;
     use serde::de::DeserializeOwned;
 
-    use std::str::FromStr;
+    use std::{collections::HashSet, str::FromStr};
 
     #[test]
     fn to_from_hex_hash() {
@@ -174,6 +619,23 @@ mod tests {
         assert_eq!(original, from_hex);
     }
 
+    #[test]
+    fn to_from_hex_hash512() {
+        let original = hash512(b"abc");
+        assert_eq!(
+            original.to_hex(),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        let from_hex = Hash512::from_hex(original.to_hex()).unwrap();
+        assert_eq!(original, from_hex);
+        let from_str = Hash512::from_str(&original.to_string()).unwrap();
+        assert_eq!(original, from_str);
+
+        assert_ne!(original.as_ref(), hash(b"abc").as_ref());
+        assert!(Hash512::from_hex(hash(b"abc").to_hex()).is_err());
+    }
+
     #[test]
     fn zero_hash() {
         let hash = Hash::zero();
@@ -183,6 +645,54 @@ mod tests {
     #[test]
     fn serialize_deserialize_hash() {
         assert_serialize_deserialize(&Hash::new([207; HASH_SIZE]));
+        assert_serialize_deserialize(&Hash512::new([207; HASH512_SIZE]));
+    }
+
+    #[test]
+    fn serde_representation_depends_on_format() {
+        let hash = hash(b"merkledb");
+
+        // Human-readable formats use hex strings.
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}\"", hash.to_hex()));
+        let hex_json = "\"b7ee5c49786227b9b2648ad4a960cc1ba48bedcc3648e6abd400fe2b5d15dee7\"";
+        assert_eq!(serde_json::from_str::<Hash>(hex_json).unwrap(), hash);
+
+        // Binary formats use raw bytes without a length prefix.
+        let bytes = bincode::serialize(&hash).unwrap();
+        assert_eq!(bytes.len(), HASH_SIZE);
+        assert_eq!(bytes, hash.as_ref());
+        assert_eq!(bincode::deserialize::<Hash>(&bytes).unwrap(), hash);
+        assert!(bincode::deserialize::<Hash>(&bytes[1..]).is_err());
+
+        let signature = Signature::new([3; SIGNATURE_LENGTH]);
+        assert_eq!(
+            bincode::serialize(&signature).unwrap(),
+            [3; SIGNATURE_LENGTH].to_vec()
+        );
+        let hashes = vec![hash, Hash::zero()];
+        let bytes = bincode::serialize(&hashes).unwrap();
+        // Length prefix of the vector followed by the hashes.
+        assert_eq!(bytes.len(), 8 + 2 * HASH_SIZE);
+        assert_eq!(bincode::deserialize::<Vec<Hash>>(&bytes).unwrap(), hashes);
+    }
+
+    #[test]
+    fn bincode_representation_before_and_after_raw_bytes() {
+        let hash = Hash::new([0xab; HASH_SIZE]);
+
+        // Previously, primitives were encoded as hex strings with a `u64` length prefix.
+        let mut old_bytes = vec![64, 0, 0, 0, 0, 0, 0, 0];
+        old_bytes.extend_from_slice(&b"ab".repeat(HASH_SIZE));
+        assert_eq!(bincode::serialize(&hash.to_hex()).unwrap(), old_bytes);
+        // Now, they are encoded as raw bytes.
+        assert_eq!(bincode::serialize(&hash).unwrap(), vec![0xab; HASH_SIZE]);
+
+        // Data in the old format is not recognized; it needs to be decoded as a string.
+        let misread: Hash = bincode::deserialize(&old_bytes).unwrap();
+        assert_ne!(misread, hash);
+        let hex_string: String = bincode::deserialize(&old_bytes).unwrap();
+        assert_eq!(Hash::from_hex(hex_string).unwrap(), hash);
     }
 
     #[test]
@@ -196,6 +706,15 @@ mod tests {
         assert_eq!(format!("{:?}", &hash), "Hash(\"80808080...\")");
     }
 
+    #[test]
+    fn debug_format_hash512() {
+        let hash = Hash512::new([1; HASH512_SIZE]);
+        assert_eq!(format!("{:?}", &hash), "Hash512(\"01010101...\")");
+        let hash = Hash512::new([128; HASH512_SIZE]);
+        assert_eq!(format!("{:?}", &hash), "Hash512(\"80808080...\")");
+        assert_eq!(format!("{}", &hash), "80".repeat(HASH512_SIZE));
+    }
+
     // Note that only public values have Display impl.
     #[test]
     fn display_format() {
@@ -214,6 +733,143 @@ mod tests {
         );
     }
 
+    #[test]
+    fn verify_hash_eq_compares_all_bytes() {
+        use super::verify_hash_eq;
+
+        let original = hash(&[1, 2, 3]);
+        assert!(verify_hash_eq(&original, &original));
+        assert!(verify_hash_eq(&original, &hash(&[1, 2, 3])));
+        assert!(verify_hash_eq(&Hash::zero(), &Hash::zero()));
+
+        for i in 0..HASH_SIZE {
+            let mut bytes = original.as_bytes();
+            bytes[i] ^= 1;
+            let modified = Hash::new(bytes);
+            assert!(!verify_hash_eq(&original, &modified));
+            assert!(!verify_hash_eq(&modified, &original));
+        }
+    }
+
+    #[test]
+    fn hash_from_bytes() {
+        let bytes = [42; HASH_SIZE];
+        let hash: Hash = bytes.into();
+        assert_eq!(hash, Hash::new(bytes));
+        assert_eq!(Hash::from([0; HASH_SIZE]), Hash::zero());
+
+        assert_eq!(Hash::try_from(&bytes[..]).unwrap(), hash);
+        assert!(Hash::try_from(&bytes[1..]).is_err());
+        assert!(Hash::try_from(&[0; HASH_SIZE + 1][..]).is_err());
+    }
+
+    #[test]
+    fn public_primitives_from_slice() {
+        fn check<T>(size: usize, from_slice: fn(&[u8]) -> Option<T>)
+        where
+            T: AsRef<[u8]> + Debug + PartialEq + for<'a> TryFrom<&'a [u8]>,
+        {
+            let bytes: Vec<u8> = (0..=size as u8).collect();
+            assert!(from_slice(&[]).is_none());
+            assert!(from_slice(&bytes[..size - 1]).is_none());
+            assert!(from_slice(&bytes).is_none());
+            assert!(T::try_from(&bytes[..size - 1]).is_err());
+            assert!(T::try_from(&bytes).is_err());
+
+            let value = from_slice(&bytes[..size]).unwrap();
+            assert_eq!(value.as_ref(), &bytes[..size]);
+            assert_eq!(T::try_from(&bytes[..size]).ok(), Some(value));
+        }
+
+        check(HASH_SIZE, Hash::from_slice);
+        check(HASH512_SIZE, Hash512::from_slice);
+        check(PUBLIC_KEY_LENGTH, PublicKey::from_slice);
+        check(SIGNATURE_LENGTH, Signature::from_slice);
+
+        // Indexing works on values constructed from slices.
+        let bytes: Vec<u8> = (0..HASH_SIZE as u8).collect();
+        let hash = Hash::from_slice(&bytes).unwrap();
+        assert_eq!(hash[..], bytes[..]);
+        assert_eq!(hash[..4], bytes[..4]);
+        assert_eq!(hash[4..], bytes[4..]);
+        assert_eq!(hash[4..8], bytes[4..8]);
+    }
+
+    #[test]
+    fn masked_hashes() {
+        let original = hash(b"identifier");
+        assert_eq!(original.masked(0), Hash::zero());
+        assert_eq!(original.masked(256), original);
+        assert_eq!(original.masked(1_000), original);
+
+        let ones = Hash::new([0xff; HASH_SIZE]);
+        for prefix_bits in 0..=256_u32 {
+            let masked = ones.masked(prefix_bits);
+            let set_bits: u32 = masked.as_ref().iter().map(|byte| byte.count_ones()).sum();
+            assert_eq!(set_bits, prefix_bits);
+            assert_eq!(masked.masked(prefix_bits), masked);
+            let masked_original = original.masked(prefix_bits);
+            for i in 0..HASH_SIZE {
+                let expected_byte = original.as_ref()[i] & masked.as_ref()[i];
+                assert_eq!(masked_original.as_ref()[i], expected_byte);
+            }
+        }
+        assert_eq!(ones.masked(3).as_ref()[..2], [0b1110_0000, 0]);
+        assert_eq!(ones.masked(9).as_ref()[..3], [0xff, 0b1000_0000, 0]);
+
+        // Hashes with the same prefix fall into the same bucket.
+        let mut bytes = original.as_bytes();
+        bytes[2] ^= 1;
+        let other = Hash::new(bytes);
+        assert_eq!(original.masked(16), other.masked(16));
+        assert_ne!(original.masked(24), other.masked(24));
+    }
+
+    #[test]
+    fn derived_children() {
+        let master = hash(b"master");
+        let child = master.derive_child(b"accounts");
+        assert_eq!(child, master.derive_child(b"accounts"));
+        assert_ne!(child, master);
+        assert_ne!(child, master.derive_child(b"blocks"));
+        assert_ne!(child, master.derive_child(b""));
+        assert_ne!(child, hash(b"master").derive_child(b"accounts\x01"));
+        assert_ne!(child, hash(b"other master").derive_child(b"accounts"));
+
+        // HMAC-SHA-256 of `b"accounts\x01"` keyed with `hash(b"master")`.
+        let expected =
+            Hash::from_hex("995830851ea0d7262daf3a19cd23ef29db784a54cadee80e8448a5e99aa253d5")
+                .unwrap();
+        assert_eq!(child, expected);
+
+        let grandchild = child.derive_child(b"alice");
+        assert_ne!(grandchild, master.derive_child(b"alice"));
+        assert_eq!(grandchild, child.derive_child(b"alice"));
+    }
+
+    #[test]
+    fn hashes_are_ordered_as_hex_strings() {
+        use std::collections::BTreeMap;
+
+        let hashes: Vec<_> = (0_u8..32)
+            .map(|i| hash(&[i]))
+            .chain(vec![Hash::zero(), Hash::new([0xff; HASH_SIZE])])
+            .collect();
+        let map: BTreeMap<_, _> = hashes.iter().map(|hash| (*hash, hash.to_hex())).collect();
+
+        let mut hex_strings: Vec<_> = hashes.iter().map(Hash::to_hex).collect();
+        hex_strings.sort();
+        let ordered: Vec<_> = map.values().cloned().collect();
+        assert_eq!(ordered, hex_strings);
+        assert_eq!(map.keys().next(), Some(&Hash::zero()));
+        assert_eq!(map.keys().next_back(), Some(&Hash::new([0xff; HASH_SIZE])));
+
+        // Range scans use the same ordering.
+        let mid = Hash::from_hex(&hex_strings[hex_strings.len() / 2]).unwrap();
+        let tail: Vec<_> = map.range(mid..).map(|(_, hex)| hex.clone()).collect();
+        assert_eq!(tail, hex_strings[hex_strings.len() / 2..]);
+    }
+
     #[test]
     fn hash_streaming_zero() {
         let h1 = hash(&[]);
@@ -222,6 +878,41 @@ mod tests {
         assert_eq!(h1, h2);
     }
 
+    #[test]
+    fn hash_batch_matches_single_hashes() {
+        use super::hash_batch;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let buffers: Vec<Vec<u8>> = (0..300)
+            .map(|_| {
+                let len = rng.gen_range(0..2_048);
+                (0..len).map(|_| rng.gen()).collect()
+            })
+            .collect();
+        let chunks: Vec<&[u8]> = buffers.iter().map(Vec::as_slice).collect();
+
+        let hashes = hash_batch(&chunks);
+        assert_eq!(hashes.len(), chunks.len());
+        for (chunk, chunk_hash) in chunks.iter().zip(&hashes) {
+            assert_eq!(*chunk_hash, hash(chunk));
+        }
+        assert!(hash_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn hash_of_iterator() {
+        use super::hash_iter;
+
+        assert_eq!(hash_iter(Vec::<Vec<u8>>::new()), hash(&[]));
+        let parts: Vec<Vec<u8>> = vec![vec![1, 2], vec![], vec![3, 4, 5], vec![6]];
+        assert_eq!(hash_iter(&parts), hash(&parts.concat()));
+        assert_eq!(
+            hash_iter(["log", "line"].iter().map(|s| s.as_bytes())),
+            hash(b"logline")
+        );
+    }
+
     #[test]
     fn hash_streaming_chunks() {
         let data: [u8; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 0];
@@ -231,6 +922,275 @@ mod tests {
         assert_eq!(h1, h2);
     }
 
+    #[test]
+    fn hash_streaming_file_via_io_copy() {
+        use std::{
+            fs::File,
+            io::{self, Seek, SeekFrom, Write},
+        };
+
+        let data: Vec<u8> = (0..10_240).map(|i| (i * 7) as u8).collect();
+        let mut file: File = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        let mut stream = HashStream::new();
+        let copied = io::copy(&mut file, &mut stream).unwrap();
+        stream.flush().unwrap();
+        assert_eq!(copied, data.len() as u64);
+        assert_eq!(stream.hash(), hash(&data));
+    }
+
+    declare_hash_type!(BlockHash);
+    declare_hash_type!(TxHash);
+
+    #[test]
+    fn declared_hash_types() {
+        use crate::{BinaryKey, BinaryValue, ObjectHash};
+
+        let block_hash = BlockHash::new(hash(&[1, 2, 3]));
+        let tx_hash = TxHash::new(hash(&[1, 2, 3]));
+        assert_eq!(block_hash.as_hash(), tx_hash.as_hash());
+        assert_eq!(block_hash.to_string(), tx_hash.as_hash().to_hex());
+        assert_eq!(block_hash.object_hash(), *block_hash.as_hash());
+
+        assert_eq!(
+            BlockHash::from_str(&block_hash.to_hex()).unwrap(),
+            block_hash
+        );
+        assert_eq!(TxHash::from_str(&tx_hash.to_hex()).unwrap(), tx_hash);
+        assert!(TxHash::from_str("abc").is_err());
+        assert_serialize_deserialize(&block_hash);
+        assert_eq!(
+            serde_json::to_string(&tx_hash).unwrap(),
+            serde_json::to_string(tx_hash.as_hash()).unwrap()
+        );
+
+        let bytes = block_hash.to_bytes();
+        assert_eq!(BlockHash::from_bytes(bytes.into()).unwrap(), block_hash);
+        let mut key = vec![0; block_hash.size()];
+        block_hash.write(&mut key);
+        assert_eq!(BlockHash::read(&key), block_hash);
+        assert_eq!(BlockHash::zero().as_hash(), &Hash::zero());
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn hash_rkyv_zero_copy() {
+        use rkyv::Deserialize;
+
+        let hashes: Vec<_> = (0_u8..10).map(|i| hash(&[i])).collect();
+        let bytes = rkyv::to_bytes::<_, 512>(&hashes).unwrap();
+        // Hashes are stored inline, without any framing.
+        assert!(bytes.len() >= hashes.len() * HASH_SIZE);
+
+        let archived = rkyv::check_archived_root::<Vec<Hash>>(&bytes).unwrap();
+        assert_eq!(archived.len(), hashes.len());
+        for (archived_hash, hash) in archived.iter().zip(&hashes) {
+            assert_eq!(archived_hash, hash);
+            assert_eq!(archived_hash.as_bytes(), &hash.as_bytes());
+        }
+
+        let restored: Vec<Hash> = archived.deserialize(&mut rkyv::Infallible).unwrap();
+        assert_eq!(restored, hashes);
+    }
+
+    #[test]
+    fn hash_fingerprint() {
+        let hashes: Vec<_> = (0_u32..10_000).map(|i| hash(&i.to_le_bytes())).collect();
+        for hash in &hashes[..10] {
+            let fingerprint = hash.fingerprint();
+            assert_eq!(fingerprint, hash.fingerprint());
+            assert_eq!(fingerprint.len(), 23);
+            assert_eq!(fingerprint.split('-').count(), 4);
+        }
+
+        // Collisions are rare even in the first two words of fingerprints.
+        let prefixes: HashSet<_> = hashes
+            .iter()
+            .map(|hash| hash.fingerprint()[..11].to_owned())
+            .collect();
+        assert!(prefixes.len() >= hashes.len() - 1);
+        let first_words: HashSet<_> = hashes
+            .iter()
+            .map(|hash| hash.fingerprint()[..5].to_owned())
+            .collect();
+        assert!(first_words.len() > hashes.len() * 9 / 10);
+
+        assert_eq!(Hash::zero().fingerprint(), "babab-babab-babab-babab");
+        assert_eq!(
+            Hash::new([0xff; HASH_SIZE]).fingerprint(),
+            "zuzuz-zuzuz-zuzuz-zuzuz"
+        );
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        crate::crypto::init();
+        let (public_key, secret_key) = gen_keypair();
+        let message = b"message to sign".to_vec();
+        let signature = sign(&message, &secret_key);
+        assert!(verify(&signature, &message, &public_key));
+        // Signatures are deterministic.
+        assert_eq!(signature, sign(&message, &secret_key));
+
+        for i in 0..message.len() {
+            let mut mutated_message = message.clone();
+            mutated_message[i] ^= 1;
+            assert!(!verify(&signature, &mutated_message, &public_key));
+        }
+        for i in 0..SIGNATURE_LENGTH {
+            let mut bytes = signature.as_bytes();
+            bytes[i] ^= 1;
+            assert!(!verify(&Signature::new(bytes), &message, &public_key));
+        }
+        let (other_public_key, _) = gen_keypair();
+        assert!(!verify(&signature, &message, &other_public_key));
+    }
+
+    #[test]
+    fn signature_types_round_trip() {
+        crate::crypto::init();
+        let (public_key, secret_key) = gen_keypair();
+        let signature = sign(b"data", &secret_key);
+
+        assert_serialize_deserialize(&public_key);
+        assert_serialize_deserialize(&secret_key);
+        assert_serialize_deserialize(&signature);
+        assert_eq!(
+            PublicKey::from_hex(public_key.to_hex()).unwrap(),
+            public_key
+        );
+        assert_eq!(
+            Signature::from_str(&signature.to_string()).unwrap(),
+            signature
+        );
+        let restored_key = SecretKey::from_slice(&secret_key[..]).unwrap();
+        assert_eq!(restored_key, secret_key);
+        assert!(SecretKey::from_slice(&secret_key[1..]).is_none());
+        assert_eq!(secret_key.as_ref().len(), SECRET_KEY_LENGTH);
+    }
+
+    #[test]
+    fn secret_key_debug_does_not_leak_bytes() {
+        crate::crypto::init();
+        let (public_key, secret_key) = gen_keypair();
+        assert_eq!(format!("{:?}", secret_key), "SecretKey(****)");
+        assert_eq!(format!("{:?}", SecretKey::zero()), "SecretKey(****)");
+        let public_key_debug = format!("{:?}", public_key);
+        assert!(public_key_debug.starts_with("PublicKey(\""));
+        assert!(public_key_debug.contains(&public_key.to_hex()[..8]));
+    }
+
+    #[test]
+    fn keypair_from_seed() {
+        crate::crypto::init();
+        let seed = Seed::new([42; SEED_LENGTH]);
+        let (public_key, secret_key) = gen_keypair_from_seed(&seed);
+        for _ in 0..3 {
+            let (same_public_key, same_secret_key) =
+                gen_keypair_from_seed(&Seed::new([42; SEED_LENGTH]));
+            assert_eq!(same_public_key, public_key);
+            assert_eq!(same_secret_key, secret_key);
+        }
+        // The secret key starts with the seed.
+        assert_eq!(secret_key[..SEED_LENGTH], seed[..]);
+
+        let (other_public_key, _) = gen_keypair_from_seed(&Seed::new([43; SEED_LENGTH]));
+        assert_ne!(other_public_key, public_key);
+
+        let signature = sign(b"message", &secret_key);
+        assert!(verify(&signature, b"message", &public_key));
+        assert!(!verify(&signature, b"message", &other_public_key));
+        assert!(!verify(&signature, b"other message", &public_key));
+
+        let restored_seed = Seed::from_hex(seed.to_hex()).unwrap();
+        assert_eq!(restored_seed, seed);
+        assert_eq!(format!("{:?}", seed), "Seed(****)");
+    }
+
+    #[test]
+    fn constant_time_comparison_of_secrets() {
+        crate::crypto::init();
+        let (_, secret_key) = gen_keypair();
+        let (_, other_secret_key) = gen_keypair();
+        assert_eq!(secret_key, secret_key.clone());
+        assert_eq!(SecretKey::from_slice(&secret_key[..]).unwrap(), secret_key);
+        assert_ne!(secret_key, other_secret_key);
+        assert_ne!(secret_key, SecretKey::zero());
+
+        // Values differing only in the last byte.
+        let mut bytes = [7; SECRET_KEY_LENGTH];
+        let key = SecretKey::new(bytes);
+        bytes[SECRET_KEY_LENGTH - 1] ^= 1;
+        assert_ne!(key, SecretKey::new(bytes));
+
+        let signature = sign(b"message", &secret_key);
+        assert_eq!(signature, sign(b"message", &secret_key));
+        assert_eq!(Signature::from_slice(&signature[..]).unwrap(), signature);
+        assert_ne!(signature, sign(b"message", &other_secret_key));
+        assert_ne!(signature, sign(b"other message", &secret_key));
+        let mut bytes = signature.as_bytes();
+        bytes[0] ^= 1;
+        assert_ne!(signature, Signature::new(bytes));
+
+        // Ordering and hashing of signatures are consistent with equality.
+        let signatures: HashSet<_> = vec![signature, signature, Signature::new(bytes)]
+            .into_iter()
+            .collect();
+        assert_eq!(signatures.len(), 2);
+        assert_eq!(signature.cmp(&signature), std::cmp::Ordering::Equal);
+        assert_eq!(
+            signature.cmp(&Signature::new(bytes)),
+            signature.as_ref().cmp(&bytes[..])
+        );
+    }
+
+    /// Checks outputs of the backend against fixed vectors, so that hashes and signatures
+    /// are the same for all backends.
+    #[test]
+    fn backend_outputs_are_fixed() {
+        crate::crypto::init();
+        assert_eq!(
+            hash(&[]).to_hex(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let mut stream = HashStream::new();
+        for chunk in [&b"a"[..], b"b", b"c"] {
+            stream = stream.update(chunk);
+        }
+        assert_eq!(
+            stream.hash().to_hex(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash512(&[]).to_hex(),
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+             47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"
+        );
+        assert_eq!(
+            blake2b(b"abc").to_hex(),
+            "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319"
+        );
+
+        // Test vector 1 from RFC 8032. The secret key is the seed followed by the public key.
+        let public_key =
+            PublicKey::from_hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")
+                .unwrap();
+        let secret_key = SecretKey::from_hex(
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60\
+             d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+        )
+        .unwrap();
+        let signature = sign(&[], &secret_key);
+        assert_eq!(
+            signature.to_hex(),
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555\
+             fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        );
+        assert!(verify(&signature, &[], &public_key));
+    }
+
     fn assert_serialize_deserialize<T>(original_value: &T)
     where
         T: Serialize + DeserializeOwned + PartialEq + fmt::Debug,
@@ -238,5 +1198,9 @@ mod tests {
         let json = serde_json::to_string(original_value).unwrap();
         let deserialized_value: T = serde_json::from_str(&json).unwrap();
         assert_eq!(*original_value, deserialized_value);
+
+        let bytes = bincode::serialize(original_value).unwrap();
+        let deserialized_value: T = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(*original_value, deserialized_value);
     }
 }
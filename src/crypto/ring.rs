@@ -0,0 +1,178 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Consistent hashing on a ring of hash positions.
+
+use std::collections::BTreeMap;
+
+use super::{Hash, HashStream};
+use crate::BinaryValue;
+
+/// Ring for consistent hashing, which maps hashes to nodes.
+///
+/// Each node is placed on the ring at several pseudo-random positions (*virtual nodes*)
+/// derived from the serialized node identifier. A hash is mapped to the node owning the first
+/// position on the ring at or after the [`ring_position`] of the hash, wrapping around
+/// the ring if necessary. As a result, adding or removing a node only remaps keys adjacent
+/// to the positions of this node, i.e., approximately `1 / (nodes_count + 1)` of all keys.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::crypto::{hash, HashRing};
+///
+/// let mut ring = HashRing::new(16);
+/// ring.add_node("alice".to_owned());
+/// ring.add_node("bob".to_owned());
+///
+/// let key = hash(b"key");
+/// let node = ring.node_for(&key).unwrap().clone();
+/// // The placement is deterministic.
+/// assert_eq!(ring.node_for(&key), Some(&node));
+/// ```
+///
+/// [`ring_position`]: struct.Hash.html#method.ring_position
+#[derive(Debug, Clone)]
+pub struct HashRing<N> {
+    positions: BTreeMap<u64, N>,
+    virtual_nodes: u32,
+}
+
+impl<N: BinaryValue + Clone + PartialEq> HashRing<N> {
+    /// Creates an empty ring placing each node at `virtual_nodes` positions. More virtual nodes
+    /// result in a more uniform distribution of keys among nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `virtual_nodes` is zero.
+    pub fn new(virtual_nodes: u32) -> Self {
+        assert!(
+            virtual_nodes > 0,
+            "Number of virtual nodes must be positive"
+        );
+        Self {
+            positions: BTreeMap::new(),
+            virtual_nodes,
+        }
+    }
+
+    fn node_positions(&self, node: &N) -> impl Iterator<Item = u64> {
+        let node_bytes = node.to_bytes();
+        (0..self.virtual_nodes).map(move |i| {
+            HashStream::new()
+                .update(&node_bytes)
+                .update(&i.to_le_bytes())
+                .hash()
+                .ring_position()
+        })
+    }
+
+    /// Adds a node to the ring. Adding a node already present in the ring has no effect.
+    pub fn add_node(&mut self, node: N) {
+        for position in self.node_positions(&node).collect::<Vec<_>>() {
+            self.positions.insert(position, node.clone());
+        }
+    }
+
+    /// Removes a node from the ring.
+    pub fn remove_node(&mut self, node: &N) {
+        self.positions.retain(|_, ring_node| ring_node != node);
+    }
+
+    /// Returns the node responsible for the specified hash, or `None` if the ring is empty.
+    pub fn node_for(&self, hash: &Hash) -> Option<&N> {
+        let position = hash.ring_position();
+        self.positions
+            .range(position..)
+            .chain(&self.positions)
+            .next()
+            .map(|(_, node)| node)
+    }
+
+    /// Returns the number of positions (i.e., virtual nodes) on the ring.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns `true` if there are no nodes on the ring.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashRing;
+    use crate::crypto::{hash, Hash};
+
+    fn keys() -> Vec<Hash> {
+        (0_u32..10_000).map(|i| hash(&i.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn empty_ring() {
+        let mut ring = HashRing::<u64>::new(4);
+        assert!(ring.is_empty());
+        assert!(ring.node_for(&Hash::zero()).is_none());
+
+        ring.add_node(1);
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring.node_for(&Hash::new([0xff; 32])), Some(&1));
+        ring.remove_node(&1);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn adding_node_remaps_fraction_of_keys() {
+        let mut ring = HashRing::new(64);
+        for node in 0_u64..10 {
+            ring.add_node(node);
+        }
+        // Re-adding a node does not change anything.
+        ring.add_node(0);
+        assert_eq!(ring.len(), 640);
+
+        let keys = keys();
+        let placement: Vec<_> = keys
+            .iter()
+            .map(|key| *ring.node_for(key).unwrap())
+            .collect();
+        // All nodes receive keys.
+        for node in 0_u64..10 {
+            assert!(placement.contains(&node));
+        }
+
+        ring.add_node(10);
+        let mut remapped = 0;
+        for (key, &old_node) in keys.iter().zip(&placement) {
+            let new_node = *ring.node_for(key).unwrap();
+            if new_node != old_node {
+                // Keys can be remapped only to the new node.
+                assert_eq!(new_node, 10);
+                remapped += 1;
+            }
+        }
+        // The expected fraction of remapped keys is 1/11.
+        assert!(remapped > 0);
+        assert!(remapped < keys.len() / 5, "remapped = {}", remapped);
+
+        // Removing the node restores the original placement.
+        ring.remove_node(&10);
+        let restored: Vec<_> = keys
+            .iter()
+            .map(|key| *ring.node_for(key).unwrap())
+            .collect();
+        assert_eq!(restored, placement);
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module implements cryptographic backend based on pure-Rust crates
+//! from [RustCrypto](https://github.com/RustCrypto) and on
+//! [`ed25519-dalek`](https://github.com/dalek-cryptography/ed25519-dalek).
+//! Unlike the sodiumoxide backend, it does not require a C toolchain, which simplifies
+//! cross-compilation (e.g., to musl or WebAssembly targets).
+//!
+//! The backend uses the same primitives as the sodiumoxide backend (SHA-256, SHA-512,
+//! BLAKE2b, HMAC-SHA-256 and Ed25519), and its outputs are byte-identical to the outputs
+//! of the sodiumoxide backend. In particular, secret keys use the libsodium layout,
+//! i.e., the 32-byte seed followed by the 32-byte public key. Thus, databases and keys
+//! are portable between builds with different backends.
+
+use blake2::{digest::consts::U32, Blake2b};
+use ed25519_dalek::ExpandedSecretKey;
+use hmac::{Hmac, Mac};
+use sha2::{Digest as _, Sha256, Sha512};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+use std::convert::TryFrom;
+
+/// Number of bytes in a `Hash`.
+pub const HASH_SIZE: usize = 32;
+
+/// Number of bytes in a `Hash512`.
+pub const HASH512_SIZE: usize = 64;
+
+/// Number of bytes in a `PublicKey`.
+pub const PUBLIC_KEY_LENGTH: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+
+/// Number of bytes in a `SecretKey`.
+pub const SECRET_KEY_LENGTH: usize = ed25519_dalek::KEYPAIR_LENGTH;
+
+/// Number of bytes in a `Signature`.
+pub const SIGNATURE_LENGTH: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+/// Number of bytes in a `Seed`.
+pub const SEED_LENGTH: usize = ed25519_dalek::SECRET_KEY_LENGTH;
+
+/// Defines a newtype for a public byte array with the interface of the primitives
+/// of the sodiumoxide backend.
+macro_rules! byte_array_type {
+    ($(#[$attr:meta])* $name:ident, $size:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub [u8; $size]);
+
+        impl $name {
+            /// Creates an instance from the bytes slice. Returns `None` if the slice has
+            /// an invalid length.
+            pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+                <[u8; $size]>::try_from(bytes).ok().map(Self)
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+    };
+}
+
+byte_array_type! {
+    /// Digest type for the pure-Rust implementation.
+    Hash, HASH_SIZE
+}
+
+byte_array_type! {
+    /// Digest type for SHA-512 hashes for the pure-Rust implementation.
+    Hash512, HASH512_SIZE
+}
+
+byte_array_type! {
+    /// Ed25519 public key for the pure-Rust implementation.
+    PublicKey, PUBLIC_KEY_LENGTH
+}
+
+byte_array_type! {
+    /// Ed25519 signature for the pure-Rust implementation.
+    Signature, SIGNATURE_LENGTH
+}
+
+/// Defines a newtype for a secret byte array, which is compared in constant time
+/// and is zeroed on drop.
+macro_rules! secret_byte_array_type {
+    ($(#[$attr:meta])* $name:ident, $size:expr) => {
+        $(#[$attr])*
+        #[derive(Clone)]
+        pub struct $name(pub [u8; $size]);
+
+        impl $name {
+            /// Creates an instance from the bytes slice. Returns `None` if the slice has
+            /// an invalid length.
+            pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+                <[u8; $size]>::try_from(bytes).ok().map(Self)
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.ct_eq(&other.0).into()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.0.zeroize();
+            }
+        }
+    };
+}
+
+secret_byte_array_type! {
+    /// Ed25519 secret key for the pure-Rust implementation, consisting of the seed followed
+    /// by the public key.
+    SecretKey, SECRET_KEY_LENGTH
+}
+
+secret_byte_array_type! {
+    /// Ed25519 seed for the pure-Rust implementation.
+    Seed, SEED_LENGTH
+}
+
+/// Contains the state for multi-part (streaming) hash computations
+/// for the pure-Rust implementation.
+#[derive(Clone, Default)]
+pub struct HashState(Sha256);
+
+impl HashState {
+    /// Creates a new state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a chunk of data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// Returns the hash of the processed data.
+    pub fn finalize(self) -> Hash {
+        Hash::from_slice(&self.0.finalize()).unwrap()
+    }
+}
+
+/// Initializes the backend. The pure-Rust backend does not require initialization,
+/// so this function always succeeds.
+pub fn init() -> bool {
+    true
+}
+
+/// Calculates hash of a bytes slice.
+pub fn hash(data: &[u8]) -> Hash {
+    Hash::from_slice(&Sha256::digest(data)).unwrap()
+}
+
+/// Calculates SHA-512 hash of a bytes slice.
+pub fn hash512(data: &[u8]) -> Hash512 {
+    Hash512::from_slice(&Sha512::digest(data)).unwrap()
+}
+
+/// Calculates BLAKE2b hash of a bytes slice with the native `HASH_SIZE`-byte digest
+/// (i.e., BLAKE2b-256 rather than a truncated BLAKE2b-512 digest).
+pub fn blake2b(data: &[u8]) -> Hash {
+    Hash::from_slice(&Blake2b::<U32>::digest(data)).unwrap()
+}
+
+/// Calculates HMAC-SHA-256 of the concatenation of byte slices with the specified key.
+pub fn hmac(key: &[u8], parts: &[&[u8]]) -> Hash {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    Hash::from_slice(&mac.finalize().into_bytes()).unwrap()
+}
+
+/// Fills the buffer with cryptographically secure random bytes.
+pub fn fill_random(buffer: &mut [u8]) {
+    getrandom::getrandom(buffer).expect("Cannot obtain random bytes from the OS");
+}
+
+/// Compares two byte slices in constant time.
+pub fn verify_eq(x: &[u8], y: &[u8]) -> bool {
+    x.ct_eq(y).into()
+}
+
+/// Zeroes the buffer in a way that is not optimized away by the compiler.
+pub fn memzero(buffer: &mut [u8]) {
+    buffer.zeroize();
+}
+
+/// Generates a random Ed25519 key pair.
+pub fn gen_keypair() -> (PublicKey, SecretKey) {
+    let mut seed = Seed([0; SEED_LENGTH]);
+    fill_random(&mut seed.0);
+    gen_keypair_from_seed(&seed)
+}
+
+/// Generates an Ed25519 key pair deterministically from the seed.
+pub fn gen_keypair_from_seed(seed: &Seed) -> (PublicKey, SecretKey) {
+    let secret = ed25519_dalek::SecretKey::from_bytes(&seed.0).expect("Seed has valid length");
+    let public = ed25519_dalek::PublicKey::from(&secret);
+
+    let mut secret_key = SecretKey([0; SECRET_KEY_LENGTH]);
+    secret_key.0[..SEED_LENGTH].copy_from_slice(secret.as_bytes());
+    secret_key.0[SEED_LENGTH..].copy_from_slice(public.as_bytes());
+    (PublicKey(public.to_bytes()), secret_key)
+}
+
+/// Signs a slice of bytes with the Ed25519 secret key.
+pub fn sign(data: &[u8], secret_key: &SecretKey) -> Signature {
+    let (seed, public_key) = secret_key.0.split_at(SEED_LENGTH);
+    let secret = ed25519_dalek::SecretKey::from_bytes(seed).expect("Seed has valid length");
+    // Like libsodium, use the public key stored in the secret key.
+    let public = ed25519_dalek::PublicKey::from_bytes(public_key)
+        .unwrap_or_else(|_| ed25519_dalek::PublicKey::from(&secret));
+    let signature = ExpandedSecretKey::from(&secret).sign(data, &public);
+    Signature(signature.to_bytes())
+}
+
+/// Verifies the Ed25519 signature of a slice of bytes with the public key.
+///
+/// Like libsodium, this function rejects public keys and signature components
+/// of small order.
+pub fn verify(signature: &Signature, data: &[u8], public_key: &PublicKey) -> bool {
+    let public_key = match ed25519_dalek::PublicKey::from_bytes(&public_key.0) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    let signature = ed25519_dalek::Signature::from(signature.0);
+    public_key.verify_strict(data, &signature).is_ok()
+}
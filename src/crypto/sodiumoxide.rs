@@ -27,7 +27,11 @@
 //! fast signing and key generation, as well as security and collision
 //! resilience.
 
-pub use sodiumoxide::crypto::hash::sha256;
+pub use sodiumoxide::crypto::hash::{sha256, sha512};
+
+use sodiumoxide::crypto::{auth::hmacsha256, generichash, sign::ed25519};
+
+use std::convert::TryFrom;
 
 /// Digest type for sodiumoxide-based implementation.
 pub use self::sha256::Digest as Hash;
@@ -36,16 +40,122 @@ pub use self::sha256::Digest as Hash;
 /// for sodiumoxide-based implementation.
 pub use self::sha256::State as HashState;
 
+/// Digest type for SHA-512 hashes for sodiumoxide-based implementation.
+pub use self::sha512::Digest as Hash512;
+
+/// Ed25519 public key for sodiumoxide-based implementation.
+pub use self::ed25519::PublicKey;
+
+/// Ed25519 secret key for sodiumoxide-based implementation.
+pub use self::ed25519::SecretKey;
+
+/// Ed25519 seed for sodiumoxide-based implementation.
+pub use self::ed25519::Seed;
+
 /// Number of bytes in a `Hash`.
 pub const HASH_SIZE: usize = self::sha256::DIGESTBYTES;
 
+/// Number of bytes in a `Hash512`.
+pub const HASH512_SIZE: usize = self::sha512::DIGESTBYTES;
+
+/// Number of bytes in a `PublicKey`.
+pub const PUBLIC_KEY_LENGTH: usize = ed25519::PUBLICKEYBYTES;
+
+/// Number of bytes in a `SecretKey`.
+pub const SECRET_KEY_LENGTH: usize = ed25519::SECRETKEYBYTES;
+
+/// Number of bytes in a `Signature`.
+pub const SIGNATURE_LENGTH: usize = ed25519::SIGNATUREBYTES;
+
+/// Number of bytes in a `Seed`.
+pub const SEED_LENGTH: usize = ed25519::SEEDBYTES;
+
+/// Ed25519 signature for sodiumoxide-based implementation.
+///
+/// Unlike the signature type from `sodiumoxide`, the bytes of the signature are accessible
+/// as a tuple field, like for other primitives of the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Signature(pub [u8; SIGNATURE_LENGTH]);
+
+impl Signature {
+    /// Creates a signature from the bytes slice. Returns `None` if the slice has
+    /// an invalid length.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        <[u8; SIGNATURE_LENGTH]>::try_from(bytes).ok().map(Self)
+    }
+}
+
+impl AsRef<[u8]> for Signature {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 /// Initializes the sodium library and automatically selects faster versions
 /// of the primitives, if possible.
 pub fn init() -> bool {
     sodiumoxide::init().is_ok()
 }
 
+/// Generates a random Ed25519 key pair.
+pub fn gen_keypair() -> (PublicKey, SecretKey) {
+    ed25519::gen_keypair()
+}
+
+/// Generates an Ed25519 key pair deterministically from the seed.
+pub fn gen_keypair_from_seed(seed: &Seed) -> (PublicKey, SecretKey) {
+    ed25519::keypair_from_seed(seed)
+}
+
+/// Signs a slice of bytes with the Ed25519 secret key.
+pub fn sign(data: &[u8], secret_key: &SecretKey) -> Signature {
+    Signature(ed25519::sign_detached(data, secret_key).to_bytes())
+}
+
+/// Verifies the Ed25519 signature of a slice of bytes with the public key.
+pub fn verify(signature: &Signature, data: &[u8], public_key: &PublicKey) -> bool {
+    let signature = ed25519::Signature::from(signature.0);
+    ed25519::verify_detached(&signature, data, public_key)
+}
+
 /// Calculates hash of a bytes slice.
 pub fn hash(data: &[u8]) -> Hash {
     sha256::hash(data)
-}
\ No newline at end of file
+}
+
+/// Calculates SHA-512 hash of a bytes slice.
+pub fn hash512(data: &[u8]) -> Hash512 {
+    sha512::hash(data)
+}
+
+/// Calculates BLAKE2b hash of a bytes slice with the native `HASH_SIZE`-byte digest
+/// (i.e., BLAKE2b-256 rather than a truncated BLAKE2b-512 digest).
+pub fn blake2b(data: &[u8]) -> Hash {
+    let digest = generichash::hash(data, Some(HASH_SIZE), None)
+        .expect("`HASH_SIZE` is a valid BLAKE2b digest size");
+    sha256::Digest::from_slice(digest.as_ref()).unwrap()
+}
+
+/// Calculates HMAC-SHA-256 of the concatenation of byte slices with the specified key.
+pub fn hmac(key: &[u8], parts: &[&[u8]]) -> Hash {
+    let mut state = hmacsha256::State::init(key);
+    for part in parts {
+        state.update(part);
+    }
+    sha256::Digest(state.finalize().0)
+}
+
+/// Fills the buffer with cryptographically secure random bytes.
+pub fn fill_random(buffer: &mut [u8]) {
+    sodiumoxide::randombytes::randombytes_into(buffer);
+}
+
+/// Compares two byte slices in constant time.
+pub fn verify_eq(x: &[u8], y: &[u8]) -> bool {
+    sodiumoxide::utils::memcmp(x, y)
+}
+
+/// Zeroes the buffer in a way that is not optimized away by the compiler.
+pub fn memzero(buffer: &mut [u8]) {
+    sodiumoxide::utils::memzero(buffer);
+}
@@ -16,18 +16,24 @@ use std::{
     cell::RefCell,
     collections::{BTreeMap, HashMap, HashSet},
     fmt,
+    fs::File,
+    io,
     iter::{Iterator as StdIterator, Peekable},
     marker::PhantomData,
     mem,
     ops::{Bound, Deref, DerefMut},
+    path::Path,
     rc::Rc,
     result::Result as StdResult,
+    sync::Arc,
 };
 
 use crate::{
+    patch_file,
     validation::assert_valid_name_component,
     views::{
-        get_object_hash, AsReadonly, ChangesIter, IndexesPool, RawAccess, ResolvedAddress, View,
+        get_object_hash, indexes_eq, AsReadonly, ChangesIter, IndexAddress, IndexesPool, RawAccess,
+        ResolvedAddress, View, ViewWithMetadata,
     },
     Error, Result, SystemSchema,
 };
@@ -72,6 +78,16 @@ impl ViewChanges {
         self.data
     }
 
+    /// Extends these changes with newer ones. If the view was cleared as a part
+    /// of the newer changes, the old changes are forgotten.
+    pub(crate) fn extend(&mut self, newer: Self) {
+        if newer.is_cleared() {
+            *self = newer;
+        } else {
+            self.data.extend(newer.data);
+        }
+    }
+
     /// Returns a value for the specified key, or an `Err(_)` if the value should be determined
     /// by the underlying snapshot.
     pub fn get(&self, key: &[u8]) -> StdResult<Option<Vec<u8>>, ()> {
@@ -281,15 +297,11 @@ impl WorkingPatch {
             // we extend these changes with the new changes (relying on the fact that
             // newer changes override older ones), unless the view was cleared (in which case,
             // the old changes do not matter and should be forgotten).
-            let patch_changes = patch
+            patch
                 .changes
                 .entry(address)
-                .or_insert_with(ViewChanges::new);
-            if changes.is_cleared() {
-                *patch_changes = changes;
-            } else {
-                patch_changes.data.extend(changes.data);
-            }
+                .or_insert_with(ViewChanges::new)
+                .extend(changes);
         }
     }
 }
@@ -583,6 +595,104 @@ pub trait Database: Send + Sync + 'static {
     /// will be returned. In case of an error, the method guarantees no changes are applied to
     /// the database.
     fn merge_sync(&self, patch: Patch) -> Result<()>;
+
+    /// Atomically applies changes streamed by the `source` to the database.
+    ///
+    /// The `source` is called once with a sink receiving the changes. The changes are applied
+    /// only if the `source` returns `Ok(())`; otherwise, the error is returned and
+    /// the database is left intact. As with patches, changes of a view made after clearing
+    /// the view are retained.
+    ///
+    /// Unlike `merge()`, this method allows backends to write changes directly to the storage
+    /// without collecting them into a `Patch` first. The default implementation collects
+    /// the changes into a patch and merges it.
+    ///
+    /// # Logical Safety
+    ///
+    /// The same considerations as for `merge()` apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error returned by the `source`, or an error in the same situations
+    /// as `merge()`.
+    fn merge_streamed(
+        &self,
+        source: &mut dyn FnMut(&mut dyn ChangesSink) -> Result<()>,
+    ) -> Result<()> {
+        let mut changes: HashMap<ResolvedAddress, ViewChanges> = HashMap::new();
+        source(&mut changes)?;
+        self.merge(Patch::from_changes(self.snapshot(), changes))
+    }
+
+    /// Returns an estimate of the total size of data stored in the database, in bytes.
+    ///
+    /// The estimate is meant to be cheap to compute; e.g., for `RocksDB` it is based
+    /// on the statistics maintained by the storage engine and does not scan the data.
+    /// Changes merged into the database may not be reflected in the estimate immediately.
+    ///
+    /// The default implementation returns 0, i.e., reports that the size is unknown.
+    /// Backends should override it if they can estimate the size cheaply.
+    fn approximate_size(&self) -> u64 {
+        0
+    }
+
+    /// Returns an estimate of the size of data stored at the specified resolved address,
+    /// in bytes.
+    ///
+    /// Depending on the implementation, the estimate may include data of other indexes
+    /// sharing the storage with the specified one (e.g., other indexes in the same group).
+    /// Use [`approximate_index_size`] to get an estimate for an index by its address.
+    ///
+    /// The default implementation returns 0, i.e., reports that the size is unknown.
+    /// Backends should override it if they can estimate the size cheaply.
+    ///
+    /// [`approximate_index_size`]: trait.DatabaseExt.html#method.approximate_index_size
+    fn approximate_size_at(&self, _addr: &ResolvedAddress) -> u64 {
+        0
+    }
+}
+
+/// Receiver of changes streamed to the database with [`Database::merge_streamed`].
+///
+/// [`Database::merge_streamed`]: trait.Database.html#method.merge_streamed
+pub trait ChangesSink {
+    /// Removes all entries from the view at the specified address.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the change cannot be recorded.
+    fn clear(&mut self, address: &ResolvedAddress) -> Result<()>;
+
+    /// Changes a single entry of the view at the specified address.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the change cannot be recorded.
+    fn change(&mut self, address: &ResolvedAddress, key: &[u8], change: &Change) -> Result<()>;
+}
+
+impl ChangesSink for HashMap<ResolvedAddress, ViewChanges> {
+    fn clear(&mut self, address: &ResolvedAddress) -> Result<()> {
+        if let Some(changes) = self.get_mut(address) {
+            changes.clear();
+        } else {
+            let mut changes = ViewChanges::new();
+            changes.clear();
+            self.insert(address.clone(), changes);
+        }
+        Ok(())
+    }
+
+    fn change(&mut self, address: &ResolvedAddress, key: &[u8], change: &Change) -> Result<()> {
+        if let Some(changes) = self.get_mut(address) {
+            changes.data.insert(key.to_vec(), change.clone());
+        } else {
+            let mut changes = ViewChanges::new();
+            changes.data.insert(key.to_vec(), change.clone());
+            self.insert(address.clone(), changes);
+        }
+        Ok(())
+    }
 }
 
 /// Extension trait for `Database`.
@@ -676,6 +786,107 @@ pub trait DatabaseExt: Database {
             removed_aggregated_addrs: HashSet::new(),
         })
     }
+
+    /// Returns an estimate of the size of an index with the specified address, in bytes,
+    /// or `None` if the index does not exist.
+    ///
+    /// See [`Database::approximate_size_at`] for details on how the estimate is computed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::{access::CopyAccessExt, Database, DatabaseExt, TemporaryDB};
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// fork.get_list("list").extend(vec![1_u32, 2, 3]);
+    /// db.merge(fork.into_patch()).unwrap();
+    ///
+    /// assert!(db.approximate_index_size("list").unwrap() > 0);
+    /// assert!(db.approximate_index_size("other_list").is_none());
+    /// ```
+    ///
+    /// [`Database::approximate_size_at`]: trait.Database.html#method.approximate_size_at
+    fn approximate_index_size(&self, addr: impl Into<IndexAddress>) -> Option<u64> {
+        let addr = addr.into();
+        let snapshot = self.snapshot();
+        let metadata = ViewWithMetadata::get_metadata_unchecked(&*snapshot, &addr)?;
+        let resolved = ResolvedAddress::new(addr.name(), Some(metadata.identifier()));
+        Some(self.approximate_size_at(&resolved))
+    }
+
+    /// Reads a patch exported with [`Patch::write_to`] and atomically merges it
+    /// into the database.
+    ///
+    /// The patch is read from the `reader` incrementally and streamed to the database
+    /// with [`Database::merge_streamed`], so backends supporting streaming (e.g., `RocksDB`)
+    /// do not collect the changes into a `Patch`. The integrity of the patch is verified
+    /// before any changes are applied. Thus, if the data is truncated or corrupted, an error
+    /// is returned and the database is left intact.
+    ///
+    /// Indexes are referenced in the patch by their addresses rather than by identifiers,
+    /// which are specific for a database instance. The addresses are resolved against
+    /// the current state of the database; indexes created in the patch get new identifiers
+    /// in the database.
+    ///
+    /// As with `Database::merge()`, it is up to the caller to ensure that the patch
+    /// is applied to the same database state it was created for; e.g., changed indexes
+    /// must have the same contents as in the database from which the patch was exported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data cannot be read or is malformed, if the patch changes
+    /// an index missing in the database, or in the same situations as `Database::merge()`.
+    ///
+    /// [`Patch::write_to`]: struct.Patch.html#method.write_to
+    /// [`Database::merge_streamed`]: trait.Database.html#method.merge_streamed
+    fn apply_patch_from<R: io::Read>(&self, reader: R) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let snapshot = self.snapshot();
+        let mut reader = Some(reader);
+        self.merge_streamed(&mut |sink| {
+            let reader = reader.take().expect("Patch source is called several times");
+            patch_file::read_changes(reader, &*snapshot, sink)
+        })
+    }
+
+    /// Reads a patch exported with [`Patch::write_to`] from the file at the specified
+    /// path and atomically merges it into the database. See [`apply_patch_from`]
+    /// for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::{access::CopyAccessExt, Database, DatabaseExt, TemporaryDB};
+    /// # use std::fs::File;
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// let path = dir.path().join("patch");
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// fork.get_list("list").extend(vec![1_u32, 2, 3]);
+    /// fork.into_patch().write_to(File::create(&path).unwrap()).unwrap();
+    ///
+    /// db.apply_patch_file(&path).unwrap();
+    /// assert_eq!(db.snapshot().get_list::<_, u32>("list").len(), 3);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened, or in the same situations
+    /// as [`apply_patch_from`].
+    ///
+    /// [`Patch::write_to`]: struct.Patch.html#method.write_to
+    /// [`apply_patch_from`]: #method.apply_patch_from
+    fn apply_patch_file(&self, path: impl AsRef<Path>) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let file =
+            File::open(path).map_err(|err| Error::new(format!("Cannot open patch file: {err}")))?;
+        self.apply_patch_from(io::BufReader::new(file))
+    }
 }
 
 impl<T: Database> DatabaseExt for T {}
@@ -685,6 +896,23 @@ impl<T: Database> DatabaseExt for T {}
 /// A `Snapshot` instance is an immutable representation of a certain storage state.
 /// It provides read isolation, so consistency is guaranteed even if the data in
 /// the database changes between reads.
+///
+/// # Isolation guarantees
+///
+/// All reads from a snapshot, including iteration and index hashes, reflect the database
+/// state at the moment the snapshot was created, for as long as the snapshot is alive.
+/// Subsequent merges (including clearing and removal of indexes), as well as maintenance
+/// of the underlying storage such as [`RocksDB::compact`], do not affect the snapshot.
+/// Backends implement this as follows:
+///
+/// - [`RocksDB`] snapshots pin the sequence number of the database, so that RocksDB
+///   retains all data visible to the snapshot during compaction until the snapshot
+///   is dropped. Thus, long-lived snapshots may increase the disk usage.
+/// - [`TemporaryDB`] snapshots copy the database contents.
+///
+/// [`RocksDB::compact`]: struct.RocksDB.html#method.compact
+/// [`RocksDB`]: struct.RocksDB.html
+/// [`TemporaryDB`]: struct.TemporaryDB.html
 pub trait Snapshot: Send + Sync + 'static {
     /// Returns a value corresponding to the specified address and key as a raw vector of bytes,
     /// or `None` if it does not exist.
@@ -700,6 +928,44 @@ pub trait Snapshot: Send + Sync + 'static {
     /// Returns an iterator over the entries of the snapshot in ascending order starting from
     /// the specified key. The iterator element type is `(&[u8], &[u8])`.
     fn iter(&self, name: &ResolvedAddress, from: &[u8]) -> Iter<'_>;
+
+    /// Checks whether this snapshot represents the same database state as `other`, i.e.,
+    /// contains the same indexes with the same contents. The snapshots may originate from
+    /// different databases, including databases with different backends.
+    ///
+    /// The comparison first checks [`state_hash`]es of the snapshots, which allows to quickly
+    /// detect differences in Merkelized indexes. If the state hashes are equal, contents of all
+    /// indexes are compared one by one, since non-Merkelized indexes do not influence
+    /// the state hash. Thus, the comparison may be expensive for large databases.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, Database, Snapshot, TemporaryDB};
+    ///
+    /// let (db, other_db) = (TemporaryDB::new(), TemporaryDB::new());
+    /// for db in &[&db, &other_db] {
+    ///     let fork = db.fork();
+    ///     fork.get_map("map").put(&1_u8, "!".to_owned());
+    ///     db.merge(fork.into_patch()).unwrap();
+    /// }
+    /// assert!(db.snapshot().state_eq(&*other_db.snapshot()));
+    ///
+    /// let fork = db.fork();
+    /// fork.get_map("map").put(&2_u8, "?".to_owned());
+    /// db.merge(fork.into_patch()).unwrap();
+    /// assert!(!db.snapshot().state_eq(&*other_db.snapshot()));
+    /// ```
+    ///
+    /// [`state_hash`]: struct.SystemSchema.html#method.state_hash
+    fn state_eq(&self, other: &dyn Snapshot) -> bool
+    where
+        Self: Sized,
+    {
+        let this: &dyn Snapshot = self;
+        SystemSchema::new(this).state_hash() == SystemSchema::new(other).state_hash()
+            && indexes_eq(this, other)
+    }
 }
 
 /// A trait that defines a streaming iterator over storage view entries. Unlike
@@ -714,44 +980,230 @@ pub trait Iterator {
 }
 
 impl Patch {
+    /// Creates a patch with the specified changes on top of the `snapshot`.
+    pub(crate) fn from_changes(
+        snapshot: Box<dyn Snapshot>,
+        changes: HashMap<ResolvedAddress, ViewChanges>,
+    ) -> Self {
+        Self {
+            snapshot,
+            changes,
+            changed_aggregated_addrs: HashMap::new(),
+            removed_aggregated_addrs: HashSet::new(),
+        }
+    }
+
     /// Iterates over changes in this patch.
     pub(crate) fn into_changes(self) -> HashMap<ResolvedAddress, ViewChanges> {
         self.changes
     }
+
+    /// Iterates over changes of the indexes in this patch, e.g., to log or check proposed writes
+    /// before merging the patch into the database. Each change is accompanied by the address
+    /// of the changed index and the changed key; changes are ordered by the index address
+    /// (in the same way as indexes are ordered in the storage) and then by the key.
+    ///
+    /// Changes of system views (e.g., index metadata) are not listed. The [state aggregator],
+    /// on the other hand, is a regular `ProofMapIndex` named `__STATE_AGGREGATOR__`, so if
+    /// the patch changes aggregated indexes, the resulting changes of the aggregator are listed
+    /// together with changes of other indexes. If an index was cleared within the patch,
+    /// only the keys written after clearing are listed; use [`cleared_indexes`] to find out
+    /// which indexes were cleared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, Change, Database, IndexAddress, TemporaryDB};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// fork.get_map("map").put(&1_u8, "one".to_owned());
+    /// let patch = fork.into_patch();
+    ///
+    /// let changes: Vec<_> = patch.changes().collect();
+    /// assert_eq!(
+    ///     changes,
+    ///     vec![(IndexAddress::from("map"), &[1_u8][..], Change::Put(b"one".to_vec()))]
+    /// );
+    /// ```
+    ///
+    /// [state aggregator]: struct.SystemSchema.html#method.state_aggregator
+    /// [`cleared_indexes`]: #method.cleared_indexes
+    pub fn changes(&self) -> impl StdIterator<Item = (IndexAddress, &[u8], Change)> + '_ {
+        self.changed_indexes()
+            .into_iter()
+            .flat_map(|(addr, changes)| {
+                changes
+                    .data
+                    .iter()
+                    .map(move |(key, change)| (addr.clone(), key.as_slice(), change.clone()))
+            })
+    }
+
+    /// Iterates over addresses of the indexes cleared in this patch, in the same order
+    /// as [`changes`]. Changes made to a cleared index after clearing are listed
+    /// by [`changes`] as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, Database, IndexAddress, TemporaryDB};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// fork.get_list("list").extend(vec![1_u32, 2, 3]);
+    /// db.merge(fork.into_patch()).unwrap();
+    ///
+    /// let fork = db.fork();
+    /// fork.get_list::<_, u32>("list").clear();
+    /// let patch = fork.into_patch();
+    /// let cleared: Vec<_> = patch.cleared_indexes().collect();
+    /// assert_eq!(cleared, vec![IndexAddress::from("list")]);
+    /// ```
+    ///
+    /// [`changes`]: #method.changes
+    pub fn cleared_indexes(&self) -> impl StdIterator<Item = IndexAddress> + '_ {
+        self.changed_indexes()
+            .into_iter()
+            .filter(|(_, changes)| changes.is_cleared())
+            .map(|(addr, _)| addr)
+    }
+
+    /// Returns addresses and changes of the indexes changed in this patch, ordered
+    /// by the fully qualified names of the indexes.
+    fn changed_indexes(&self) -> Vec<(IndexAddress, &ViewChanges)> {
+        let mut addresses = IndexesPool::new(self).index_addresses(self.changes.keys());
+        let mut indexes: Vec<_> = self
+            .changes
+            .iter()
+            .filter_map(|(resolved, changes)| Some((addresses.remove(resolved)?, changes)))
+            .collect();
+        indexes.sort_by_cached_key(|(addr, _)| addr.fully_qualified_name());
+        indexes
+    }
+
+    /// Returns the total number of changed keys in this patch.
+    pub(crate) fn changes_count(&self) -> usize {
+        self.changes
+            .values()
+            .map(|changes| changes.data.len())
+            .sum()
+    }
+
+    /// Exports the changes in this patch to the `writer`, so that they can be applied
+    /// to another database instance with [`DatabaseExt::apply_patch_file`] or
+    /// [`DatabaseExt::apply_patch_from`].
+    ///
+    /// Only the changes are exported; the snapshot the patch is based on is not.
+    /// Indexes are referenced by their addresses, so the patch can be applied to a database
+    /// in which the indexes have different identifiers. Data is written incrementally,
+    /// so the `writer` should be buffered for efficiency.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if writing to the `writer` fails, or an error with
+    /// the `InvalidInput` kind if the patch cannot be exported. The latter happens if indexes
+    /// change their identifiers within the patch, e.g., if the patch flushes a migration
+    /// or removes and then recreates an index.
+    ///
+    /// [`DatabaseExt::apply_patch_file`]: trait.DatabaseExt.html#method.apply_patch_file
+    /// [`DatabaseExt::apply_patch_from`]: trait.DatabaseExt.html#method.apply_patch_from
+    pub fn write_to<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        patch_file::write_changes(writer, &*self.snapshot, &self.changes)
+    }
 }
 
 impl Snapshot for Patch {
     fn get(&self, name: &ResolvedAddress, key: &[u8]) -> Option<Vec<u8>> {
-        self.changes
-            .get(name)
-            .map_or(Err(()), |changes| changes.get(key))
-            // At this point, `Err(_)` signifies that we need to retrieve data from the snapshot.
-            .unwrap_or_else(|()| self.snapshot.get(name, key))
+        get_with_changes(&*self.snapshot, &self.changes, name, key)
     }
 
     fn contains(&self, name: &ResolvedAddress, key: &[u8]) -> bool {
-        self.changes
-            .get(name)
-            .map_or(Err(()), |changes| changes.contains(key))
-            // At this point, `Err(_)` signifies that we need to retrieve data from the snapshot.
-            .unwrap_or_else(|()| self.snapshot.contains(name, key))
+        contains_with_changes(&*self.snapshot, &self.changes, name, key)
     }
 
     fn iter(&self, name: &ResolvedAddress, from: &[u8]) -> Iter<'_> {
-        let maybe_changes = self.changes.get(name);
-        let changes_iter = maybe_changes.map(|changes| {
-            changes
-                .data
-                .range::<[u8], _>((Bound::Included(from), Bound::Unbounded))
-        });
+        iter_with_changes(&*self.snapshot, &self.changes, name, from)
+    }
+}
 
-        let is_cleared = maybe_changes.map_or(false, ViewChanges::is_cleared);
-        if is_cleared {
-            // Ignore all changes from the snapshot.
-            Box::new(ChangesIter::new(changes_iter.unwrap()))
-        } else {
-            Box::new(ForkIter::new(self.snapshot.iter(name, from), changes_iter))
-        }
+/// Snapshot with changes applied on top of it. Unlike a [`Patch`], the changes are shared,
+/// so several snapshots can be created from the same changes without copying them.
+///
+/// [`Patch`]: struct.Patch.html
+pub(crate) struct SharedChangesSnapshot {
+    snapshot: Box<dyn Snapshot>,
+    changes: Arc<HashMap<ResolvedAddress, ViewChanges>>,
+}
+
+impl SharedChangesSnapshot {
+    pub(crate) fn new(
+        snapshot: Box<dyn Snapshot>,
+        changes: Arc<HashMap<ResolvedAddress, ViewChanges>>,
+    ) -> Self {
+        Self { snapshot, changes }
+    }
+}
+
+impl Snapshot for SharedChangesSnapshot {
+    fn get(&self, name: &ResolvedAddress, key: &[u8]) -> Option<Vec<u8>> {
+        get_with_changes(&*self.snapshot, &self.changes, name, key)
+    }
+
+    fn contains(&self, name: &ResolvedAddress, key: &[u8]) -> bool {
+        contains_with_changes(&*self.snapshot, &self.changes, name, key)
+    }
+
+    fn iter(&self, name: &ResolvedAddress, from: &[u8]) -> Iter<'_> {
+        iter_with_changes(&*self.snapshot, &self.changes, name, from)
+    }
+}
+
+fn get_with_changes(
+    snapshot: &dyn Snapshot,
+    changes: &HashMap<ResolvedAddress, ViewChanges>,
+    name: &ResolvedAddress,
+    key: &[u8],
+) -> Option<Vec<u8>> {
+    changes
+        .get(name)
+        .map_or(Err(()), |changes| changes.get(key))
+        // At this point, `Err(_)` signifies that we need to retrieve data from the snapshot.
+        .unwrap_or_else(|()| snapshot.get(name, key))
+}
+
+fn contains_with_changes(
+    snapshot: &dyn Snapshot,
+    changes: &HashMap<ResolvedAddress, ViewChanges>,
+    name: &ResolvedAddress,
+    key: &[u8],
+) -> bool {
+    changes
+        .get(name)
+        .map_or(Err(()), |changes| changes.contains(key))
+        // At this point, `Err(_)` signifies that we need to retrieve data from the snapshot.
+        .unwrap_or_else(|()| snapshot.contains(name, key))
+}
+
+fn iter_with_changes<'a>(
+    snapshot: &'a dyn Snapshot,
+    changes: &'a HashMap<ResolvedAddress, ViewChanges>,
+    name: &ResolvedAddress,
+    from: &[u8],
+) -> Iter<'a> {
+    let maybe_changes = changes.get(name);
+    let changes_iter = maybe_changes.map(|changes| {
+        changes
+            .data
+            .range::<[u8], _>((Bound::Included(from), Bound::Unbounded))
+    });
+
+    let is_cleared = maybe_changes.map_or(false, ViewChanges::is_cleared);
+    if is_cleared {
+        // Ignore all changes from the snapshot.
+        Box::new(ChangesIter::new(changes_iter.unwrap()))
+    } else {
+        Box::new(ForkIter::new(snapshot.iter(name, from), changes_iter))
     }
 }
 
@@ -1232,7 +1684,7 @@ mod tests {
         AsReadonly, Change, Database, DatabaseExt, Fork, OwnedReadonlyFork, Patch, Rc,
         ResolvedAddress, Snapshot, StdIterator, SystemSchema, View,
     };
-    use crate::{access::CopyAccessExt, ObjectHash, TemporaryDB};
+    use crate::{access::CopyAccessExt, IndexAddress, ObjectHash, Result, TemporaryDB};
 
     use std::{collections::HashSet, iter::FromIterator};
 
@@ -1531,4 +1983,373 @@ mod tests {
         // Since the index is already created, this should lead to a panic.
         let _readonly_entry = fork.readonly().get_entry::<_, u32>("entry");
     }
+
+    #[test]
+    fn approximate_size_is_plausible() {
+        use crate::{DbOptions, RocksDB};
+
+        fn fill(db: &dyn Database) {
+            let fork = db.fork();
+            let mut map = fork.get_map("map");
+            for i in 0_u32..1_000 {
+                map.put(&i, vec![i as u8; 100]);
+            }
+            fork.get_list(("group", &1_u8)).push(1_u64);
+            db.merge(fork.into_patch()).unwrap();
+        }
+
+        let db = TemporaryDB::new();
+        let initial_size = db.approximate_size();
+        fill(&db);
+        let map_size = db.approximate_index_size("map").unwrap();
+        // Each entry takes at least 104 bytes (a 4-byte key and a 100-byte value).
+        assert!(map_size >= 104_000, "{}", map_size);
+        assert!(db.approximate_size() >= initial_size + map_size);
+        assert!(db.approximate_index_size(("group", &1_u8)).unwrap() > 0);
+        assert!(db.approximate_index_size("missing").is_none());
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = RocksDB::open(&dir, &DbOptions::default()).unwrap();
+        fill(&db);
+        let map_size = db.approximate_index_size("map").unwrap();
+        assert!(map_size > 0);
+        assert!(map_size < 100_000_000, "{}", map_size);
+        assert!(db.approximate_size() >= map_size);
+        assert!(db.approximate_index_size("missing").is_none());
+    }
+
+    #[test]
+    fn default_approximate_size_estimates() {
+        /// Database relying on the default size estimates.
+        struct PlainDB(TemporaryDB);
+
+        impl Database for PlainDB {
+            fn snapshot(&self) -> Box<dyn Snapshot> {
+                self.0.snapshot()
+            }
+
+            fn merge(&self, patch: Patch) -> Result<()> {
+                self.0.merge(patch)
+            }
+
+            fn merge_sync(&self, patch: Patch) -> Result<()> {
+                self.0.merge_sync(patch)
+            }
+        }
+
+        let db = PlainDB(TemporaryDB::new());
+        let fork = db.fork();
+        let mut map = fork.get_map("map");
+        for i in 0_u32..1_000 {
+            map.put(&i, vec![i as u8; 100]);
+        }
+        db.merge(fork.into_patch()).unwrap();
+
+        // The sizes are unknown, but the index existence is still checked.
+        assert_eq!(db.approximate_size(), 0);
+        assert_eq!(db.approximate_index_size("map"), Some(0));
+        assert!(db.approximate_index_size("missing").is_none());
+        assert_eq!(db.0.approximate_index_size("map"), Some(104_000));
+    }
+
+    #[test]
+    fn rocksdb_with_column_family_options() {
+        use std::collections::HashMap;
+
+        use crate::{ColumnFamilyOptions, CompactionStyle, CompressionType, DbOptions, RocksDB};
+
+        let mut cf_options = HashMap::new();
+        cf_options.insert(
+            "list".to_owned(),
+            ColumnFamilyOptions::new(Some(CompressionType::Lz4), Some(16 * 1_024), None),
+        );
+        cf_options.insert(
+            "group".to_owned(),
+            ColumnFamilyOptions::new(None, Some(64 * 1_024), Some(CompactionStyle::Universal)),
+        );
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = RocksDB::open_with(&dir, &DbOptions::default(), cf_options.clone()).unwrap();
+        let fork = db.fork();
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        fork.get_map(("group", &1_u8)).put(&1_u8, "!".to_owned());
+        fork.get_entry("entry").set(42_u64);
+        db.merge(fork.into_patch()).unwrap();
+        drop(db);
+
+        let db = RocksDB::open_with(&dir, &DbOptions::default(), cf_options).unwrap();
+        let snapshot = db.snapshot();
+        assert_eq!(snapshot.get_proof_list::<_, u32>("list").len(), 3);
+        assert_eq!(
+            snapshot.get_map::<_, u8, String>(("group", &1_u8)).get(&1),
+            Some("!".to_owned())
+        );
+        assert_eq!(snapshot.get_entry::<_, u64>("entry").get(), Some(42));
+    }
+
+    #[test]
+    fn state_eq_for_snapshots_from_different_databases() {
+        use crate::{DbOptions, RocksDB};
+
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        fork.get_map(("group", &1_u8)).put(&1_u8, "!".to_owned());
+        fork.get_entry("entry").set(42_u64);
+        db.merge(fork.into_patch()).unwrap();
+
+        // Create the same indexes in another order, so that their identifiers differ.
+        let dir = tempfile::TempDir::new().unwrap();
+        let copy = RocksDB::open(&dir, &DbOptions::default()).unwrap();
+        let fork = copy.fork();
+        fork.get_entry("entry").set(42_u64);
+        fork.get_map(("group", &1_u8)).put(&1_u8, "!".to_owned());
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        copy.merge(fork.into_patch()).unwrap();
+
+        let snapshot = db.snapshot();
+        assert!(snapshot.state_eq(&*copy.snapshot()));
+        assert!(copy.snapshot().state_eq(&*snapshot));
+
+        // Changing a non-Merkelized index does not influence the state hash,
+        // but breaks equality nonetheless.
+        let fork = copy.fork();
+        fork.get_map(("group", &1_u8)).put(&2_u8, "?".to_owned());
+        copy.merge(fork.into_patch()).unwrap();
+        let copy_snapshot = copy.snapshot();
+        assert_eq!(
+            SystemSchema::new(&snapshot).state_hash(),
+            SystemSchema::new(&copy_snapshot).state_hash()
+        );
+        assert!(!snapshot.state_eq(&*copy_snapshot));
+
+        // Empty indexes are a part of the state as well.
+        let fork = db.fork();
+        fork.get_map(("group", &1_u8)).put(&2_u8, "?".to_owned());
+        fork.get_list::<_, u8>("empty").clear();
+        db.merge(fork.into_patch()).unwrap();
+        assert!(!db.snapshot().state_eq(&*copy_snapshot));
+
+        let fork = copy.fork();
+        fork.get_list::<_, u8>("empty").clear();
+        copy.merge(fork.into_patch()).unwrap();
+        assert!(db.snapshot().state_eq(&*copy.snapshot()));
+
+        // Changes in a Merkelized index are detected via the state hash.
+        let fork = db.fork();
+        fork.get_proof_list("list").push(4_u32);
+        let patch = fork.into_patch();
+        assert!(!patch.state_eq(&*copy.snapshot()));
+    }
+
+    #[test]
+    fn patch_file_round_trip() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        fork.get_map(("map", &1_u8)).put(&1_u8, "!".to_owned());
+        db.merge(fork.into_patch()).unwrap();
+
+        let fork = db.fork();
+        fork.get_proof_list("list").push(4_u32);
+        fork.get_map::<_, u8, String>(("map", &1_u8)).clear();
+        fork.get_entry("entry").set(42_u64);
+        fork.get_proof_entry("proof_entry").set("?".to_owned());
+        let patch = fork.into_patch();
+        let mut bytes = vec![];
+        patch.write_to(&mut bytes).unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("patch");
+        std::fs::write(&path, &bytes).unwrap();
+        let other_db = TemporaryDB::new();
+        let fork = other_db.fork();
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        fork.get_map(("map", &1_u8)).put(&1_u8, "!".to_owned());
+        other_db.merge(fork.into_patch()).unwrap();
+        other_db.apply_patch_file(&path).unwrap();
+
+        db.merge(patch).unwrap();
+        let snapshot = other_db.snapshot();
+        assert!(db.snapshot().state_eq(&*snapshot));
+        let list = snapshot.get_proof_list::<_, u32>("list");
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+        assert_eq!(aggregator.get("list").unwrap(), list.object_hash());
+        assert_eq!(snapshot.get_entry("entry").get(), Some(42_u64));
+        assert!(snapshot
+            .get_map::<_, u8, String>(("map", &1_u8))
+            .get(&1)
+            .is_none());
+    }
+
+    #[test]
+    fn patch_file_is_applied_by_index_addresses() {
+        use crate::{DbOptions, RocksDB};
+
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        fork.get_map(("map", &1_u8)).put(&1_u8, "!".to_owned());
+        fork.get_entry("other").set(1_u8);
+        db.merge(fork.into_patch()).unwrap();
+
+        // Create the same indexes in another database in a different order, so that
+        // their identifiers differ.
+        let dir = tempfile::TempDir::new().unwrap();
+        let other_db = RocksDB::open(&dir, &DbOptions::default()).unwrap();
+        let fork = other_db.fork();
+        fork.get_entry("other").set(1_u8);
+        fork.get_map(("map", &1_u8)).put(&1_u8, "!".to_owned());
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        other_db.merge(fork.into_patch()).unwrap();
+        assert!(db.snapshot().state_eq(&*other_db.snapshot()));
+
+        let fork = db.fork();
+        fork.get_proof_list("list").push(4_u32);
+        fork.get_map(("map", &1_u8)).put(&2_u8, "?".to_owned());
+        fork.get_map(("map", &2_u8)).put(&1_u8, "new".to_owned());
+        fork.get_proof_entry("new_entry").set(5_u64);
+        let patch = fork.into_patch();
+        let mut bytes = vec![];
+        patch.write_to(&mut bytes).unwrap();
+        db.merge(patch).unwrap();
+
+        other_db.apply_patch_from(bytes.as_slice()).unwrap();
+        let snapshot = other_db.snapshot();
+        assert!(db.snapshot().state_eq(&*snapshot));
+        let list = snapshot.get_proof_list::<_, u32>("list");
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+        assert_eq!(aggregator.get("list").unwrap(), list.object_hash());
+        let map = snapshot.get_map::<_, u8, String>(("map", &1_u8));
+        assert_eq!(map.values().collect::<Vec<_>>(), vec!["!", "?"]);
+        let map = snapshot.get_map::<_, u8, String>(("map", &2_u8));
+        assert_eq!(map.get(&1).unwrap(), "new");
+        assert_eq!(snapshot.get_proof_entry("new_entry").get(), Some(5_u64));
+
+        // New indexes get fresh identifiers in the target database.
+        let fork = other_db.fork();
+        fork.get_entry("newer_entry").set(6_u8);
+        other_db.merge(fork.into_patch()).unwrap();
+        let snapshot = other_db.snapshot();
+        assert_eq!(snapshot.get_proof_entry("new_entry").get(), Some(5_u64));
+        assert_eq!(snapshot.get_entry("newer_entry").get(), Some(6_u8));
+    }
+
+    #[test]
+    fn patch_file_changing_missing_index_is_not_applied() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        fork.get_list("list").push(1_u32);
+        db.merge(fork.into_patch()).unwrap();
+
+        let fork = db.fork();
+        fork.get_list("list").push(2_u32);
+        let mut bytes = vec![];
+        fork.into_patch().write_to(&mut bytes).unwrap();
+
+        let other_db = TemporaryDB::new();
+        let err = other_db.apply_patch_from(bytes.as_slice()).unwrap_err();
+        assert!(
+            err.to_string().contains("missing in the database"),
+            "{}",
+            err
+        );
+        assert!(other_db.snapshot().index_type("list").is_none());
+    }
+
+    #[test]
+    fn truncated_or_corrupted_patch_file_is_not_applied() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        fork.get_proof_list("list").extend(vec![1_u32, 2, 3]);
+        fork.get_entry("entry").set(42_u64);
+        let mut bytes = vec![];
+        fork.into_patch().write_to(&mut bytes).unwrap();
+
+        for len in 0..bytes.len() {
+            let err = db.apply_patch_from(&bytes[..len]).unwrap_err();
+            assert!(err.to_string().contains("truncated"), "{}", err);
+        }
+        let idx = bytes.len() / 2;
+        bytes[idx] ^= 1;
+        assert!(db.apply_patch_from(bytes.as_slice()).is_err());
+        assert!(db
+            .apply_patch_from(b"not a patch file".as_ref())
+            .unwrap_err()
+            .to_string()
+            .contains("Not a patch file"));
+
+        let snapshot = db.snapshot();
+        assert!(snapshot.index_type("list").is_none());
+        assert!(snapshot.get_entry::<_, u64>("entry").get().is_none());
+        assert_eq!(
+            SystemSchema::new(&snapshot)
+                .state_aggregator()
+                .keys()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn patch_changes_introspection() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        fork.get_map(("map", &1_u8)).put(&3_u8, "three".to_owned());
+        fork.get_proof_entry("entry").set(1_u64);
+        db.merge(fork.into_patch()).unwrap();
+
+        let fork = db.fork();
+        {
+            let mut map = fork.get_map(("map", &1_u8));
+            map.put(&1_u8, "one".to_owned());
+            map.put(&2_u8, "two".to_owned());
+            map.remove(&3_u8);
+        }
+        let patch = fork.into_patch();
+        let map_addr = IndexAddress::from_root("map").append_key(&1_u8);
+        let changes: Vec<_> = patch.changes().collect();
+        assert_eq!(
+            changes,
+            vec![
+                (map_addr.clone(), &[1_u8][..], Change::Put(b"one".to_vec())),
+                (map_addr.clone(), &[2_u8][..], Change::Put(b"two".to_vec())),
+                (map_addr, &[3_u8][..], Change::Delete),
+            ]
+        );
+
+        // Changes of aggregated indexes include changes of the state aggregator.
+        let fork = db.fork();
+        fork.get_proof_entry("entry").set(2_u64);
+        let patch = fork.into_patch();
+        let mut changed_names: Vec<_> = patch
+            .changes()
+            .map(|(addr, ..)| addr.name().to_owned())
+            .collect();
+        changed_names.dedup();
+        assert_eq!(changed_names, vec!["__STATE_AGGREGATOR__", "entry"]);
+
+        // Empty patches have no changes.
+        assert_eq!(db.fork().into_patch().changes().count(), 0);
+        assert_eq!(db.fork().into_patch().cleared_indexes().count(), 0);
+
+        // Clearing is reported separately from changes made after it.
+        let fork = db.fork();
+        {
+            let mut map = fork.get_map(("map", &1_u8));
+            map.clear();
+            map.put(&4_u8, "four".to_owned());
+        }
+        let patch = fork.into_patch();
+        let map_addr = IndexAddress::from_root("map").append_key(&1_u8);
+        let cleared: Vec<_> = patch.cleared_indexes().collect();
+        assert_eq!(cleared, vec![map_addr.clone()]);
+        let changes: Vec<_> = patch.changes().collect();
+        assert_eq!(
+            changes,
+            vec![(map_addr, &[4_u8][..], Change::Put(b"four".to_vec()))]
+        );
+    }
 }
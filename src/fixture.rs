@@ -0,0 +1,271 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Builder of pre-filled databases for tests.
+
+use std::fmt;
+
+use crate::{
+    access::CopyAccessExt, BinaryKey, BinaryValue, Database, Fork, IndexAddress, ObjectHash,
+    Snapshot, TemporaryDB,
+};
+
+type Seeder = Box<dyn FnOnce(&Fork)>;
+
+/// Builder of a [`TemporaryDB`] seeded with the specified indexes and entries.
+///
+/// Indexes are filled in the order of builder calls within a single fork, which is merged
+/// into the database. Several calls for the same address add data to the same index.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::CopyAccessExt, SystemSchema, TestDbBuilder};
+///
+/// let snapshot = TestDbBuilder::new()
+///     .with_entry("entry", 42_u64)
+///     .with_proof_list("list", vec![1_u32, 2, 3])
+///     .with_map("map", vec![(1_u8, "one".to_owned())])
+///     .build_snapshot();
+///
+/// assert_eq!(snapshot.get_entry::<_, u64>("entry").get(), Some(42));
+/// assert_eq!(snapshot.get_proof_list::<_, u32>("list").len(), 3);
+/// let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+/// assert!(aggregator.contains("list"));
+/// ```
+///
+/// [`TemporaryDB`]: struct.TemporaryDB.html
+#[derive(Default)]
+pub struct TestDbBuilder {
+    seeders: Vec<Seeder>,
+}
+
+impl fmt::Debug for TestDbBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestDbBuilder")
+            .field("seeders_count", &self.seeders.len())
+            .finish()
+    }
+}
+
+impl TestDbBuilder {
+    /// Creates a builder of an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an arbitrary seeding function, which is called with the fork used to fill
+    /// the database.
+    pub fn with<F>(mut self, seeder: F) -> Self
+    where
+        F: FnOnce(&Fork) + 'static,
+    {
+        self.seeders.push(Box::new(seeder));
+        self
+    }
+
+    /// Sets the value of an `Entry`.
+    pub fn with_entry<V>(self, addr: impl Into<IndexAddress>, value: V) -> Self
+    where
+        V: BinaryValue + 'static,
+    {
+        let addr = addr.into();
+        self.with(move |fork| fork.get_entry(addr).set(value))
+    }
+
+    /// Sets the value of a `ProofEntry`.
+    pub fn with_proof_entry<V>(self, addr: impl Into<IndexAddress>, value: V) -> Self
+    where
+        V: BinaryValue + ObjectHash + 'static,
+    {
+        let addr = addr.into();
+        self.with(move |fork| fork.get_proof_entry(addr).set(value))
+    }
+
+    /// Appends values to a `ListIndex`.
+    pub fn with_list<V>(
+        self,
+        addr: impl Into<IndexAddress>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self
+    where
+        V: BinaryValue + 'static,
+    {
+        let addr = addr.into();
+        let values: Vec<_> = values.into_iter().collect();
+        self.with(move |fork| fork.get_list(addr).extend(values))
+    }
+
+    /// Appends values to a `ProofListIndex`.
+    pub fn with_proof_list<V>(
+        self,
+        addr: impl Into<IndexAddress>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self
+    where
+        V: BinaryValue + 'static,
+    {
+        let addr = addr.into();
+        let values: Vec<_> = values.into_iter().collect();
+        self.with(move |fork| fork.get_proof_list(addr).extend(values))
+    }
+
+    /// Puts entries into a `MapIndex`.
+    pub fn with_map<K, V>(
+        self,
+        addr: impl Into<IndexAddress>,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Self
+    where
+        K: BinaryKey + 'static,
+        V: BinaryValue + 'static,
+    {
+        let addr = addr.into();
+        let entries: Vec<_> = entries.into_iter().collect();
+        self.with(move |fork| {
+            let mut map = fork.get_map(addr);
+            for (key, value) in entries {
+                map.put(&key, value);
+            }
+        })
+    }
+
+    /// Puts entries into a `ProofMapIndex` with the default (hashed) key mode.
+    pub fn with_proof_map<K, V>(
+        self,
+        addr: impl Into<IndexAddress>,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Self
+    where
+        K: BinaryKey + ObjectHash + 'static,
+        V: BinaryValue + 'static,
+    {
+        let addr = addr.into();
+        let entries: Vec<_> = entries.into_iter().collect();
+        self.with(move |fork| {
+            let mut map = fork.get_proof_map(addr);
+            for (key, value) in entries {
+                map.put(&key, value);
+            }
+        })
+    }
+
+    /// Builds the database.
+    ///
+    /// # Panics
+    ///
+    /// Panics if seeding functions panic or the seeded data cannot be merged
+    /// into the database.
+    pub fn build(self) -> TemporaryDB {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        for seeder in self.seeders {
+            seeder(&fork);
+        }
+        db.merge(fork.into_patch())
+            .expect("Cannot merge seeded data into the database");
+        db
+    }
+
+    /// Builds the database and returns its snapshot.
+    pub fn build_snapshot(self) -> Box<dyn Snapshot> {
+        self.build().snapshot()
+    }
+
+    /// Builds the database and returns a fork based on the seeded data.
+    pub fn build_fork(self) -> Fork {
+        self.build().fork()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestDbBuilder;
+    use crate::{access::CopyAccessExt, Database, ObjectHash, SystemSchema, TemporaryDB};
+
+    #[test]
+    fn built_fixture_contains_expected_data() {
+        let db = TestDbBuilder::new()
+            .with_entry("entry", "foo".to_owned())
+            .with_proof_entry("proof_entry", 1_u64)
+            .with_list("list", vec![1_u8, 2])
+            .with_list("list", vec![3_u8])
+            .with_proof_list(("group", &1_u32), 0_u64..5)
+            .with_map("map", vec![(1_u32, 2_u64), (3, 4)])
+            .with_proof_map("proof_map", vec![(1_u32, 2_u64), (3, 4)])
+            .with(|fork| fork.get_key_set("set").insert(&5_u8))
+            .build();
+
+        let snapshot = db.snapshot();
+        assert_eq!(
+            snapshot.get_entry::<_, String>("entry").get(),
+            Some("foo".to_owned())
+        );
+        assert_eq!(
+            snapshot.get_proof_entry::<_, u64>("proof_entry").get(),
+            Some(1)
+        );
+        assert_eq!(
+            snapshot
+                .get_list::<_, u8>("list")
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        let proof_list = snapshot.get_proof_list::<_, u64>(("group", &1_u32));
+        assert_eq!(proof_list.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(snapshot.get_map::<_, u32, u64>("map").get(&3), Some(4));
+        assert!(snapshot.get_key_set::<_, u8>("set").contains(&5));
+
+        // Compare with the database filled manually.
+        let expected_db = TemporaryDB::new();
+        let fork = expected_db.fork();
+        fork.get_entry("entry").set("foo".to_owned());
+        fork.get_proof_entry("proof_entry").set(1_u64);
+        fork.get_list("list").extend(vec![1_u8, 2, 3]);
+        fork.get_proof_list(("group", &1_u32)).extend(0_u64..5);
+        let mut map = fork.get_map("map");
+        map.put(&1_u32, 2_u64);
+        map.put(&3_u32, 4_u64);
+        let mut proof_map = fork.get_proof_map("proof_map");
+        proof_map.put(&1_u32, 2_u64);
+        proof_map.put(&3_u32, 4_u64);
+        fork.get_key_set("set").insert(&5_u8);
+        expected_db.merge(fork.into_patch()).unwrap();
+
+        let expected_snapshot = expected_db.snapshot();
+        assert_eq!(
+            SystemSchema::new(&snapshot).state_hash(),
+            SystemSchema::new(&expected_snapshot).state_hash()
+        );
+        assert_eq!(
+            snapshot
+                .get_proof_map::<_, u32, u64>("proof_map")
+                .object_hash(),
+            expected_snapshot
+                .get_proof_map::<_, u32, u64>("proof_map")
+                .object_hash()
+        );
+        assert!(snapshot.state_eq(&*expected_snapshot));
+
+        // The fork is based on the seeded data.
+        let fork = TestDbBuilder::new()
+            .with_proof_list("list", vec![1_u32])
+            .build_fork();
+        let mut list = fork.get_proof_list::<_, u32>("list");
+        assert_eq!(list.len(), 1);
+        list.push(2);
+        assert_eq!(list.len(), 2);
+    }
+}
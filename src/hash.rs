@@ -15,11 +15,15 @@
 use byteorder::{ByteOrder, LittleEndian};
 use thiserror::Error;
 
-use std::error::Error as StdError;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error as StdError,
+    hash::Hash as StdHash,
+};
 
 use crate::{
-    crypto::{hash, Hash, HashStream, HASH_SIZE},
-    proof_map::ProofPath,
+    crypto::{blake2b, hash, Hash, HashStream, HASH_SIZE},
+    proof_map::{merkle_root_of_leaves, Hashed, ProofPath, ToProofPath},
     BinaryValue,
 };
 
@@ -67,6 +71,14 @@ pub enum HashTag {
     /// Hash prefix of a branch node in a Merkle Patricia tree built for
     /// a [Merkelized map](indexes/proof_map/struct.ProofMapIndex.html).
     MapBranchNode = 4,
+    /// Hash prefix of a [Merkelized map](indexes/proof_map/struct.ProofMapIndex.html)
+    /// together with the number of its entries.
+    SizedMapNode = 5,
+    /// Hash prefix of a branch node in a Merkle sum tree built for
+    /// a [`SumTreeIndex`](indexes/struct.SumTreeIndex.html).
+    SumTreeBranchNode = 6,
+    /// Hash prefix of a [`SumTreeIndex`](indexes/struct.SumTreeIndex.html).
+    SumTreeNode = 7,
 }
 
 impl HashTag {
@@ -124,10 +136,48 @@ impl HashTag {
     }
 
     /// Computes the hash for a Merkelized list containing the given values.
+    ///
+    /// The hash is equal to the `object_hash()` of a `ProofListIndex` with the same values.
     pub fn hash_list<V: BinaryValue + ?Sized>(values: &[V]) -> Hash {
         Self::hash_list_node(values.len() as u64, root_hash(values))
     }
 
+    /// Computes the hash for a Merkelized map containing the given entries. `KeyMode`
+    /// determines how keys are converted to paths in the Merkle Patricia tree.
+    ///
+    /// The hash is equal to the `object_hash()` of a `ProofMapIndex` with the same entries
+    /// and key mode. Keys are assumed to be unique.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{
+    ///     access::CopyAccessExt, proof_map::Hashed, Database, HashTag, ObjectHash, TemporaryDB,
+    /// };
+    /// use std::collections::BTreeMap;
+    ///
+    /// let entries: BTreeMap<_, _> = vec![(1_u8, "one".to_owned())].into_iter().collect();
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut map = fork.get_proof_map("map");
+    /// map.put(&1_u8, "one".to_owned());
+    /// assert_eq!(HashTag::hash_map::<Hashed, _, _>(&entries), map.object_hash());
+    /// ```
+    pub fn hash_map<'a, KeyMode, K, V>(entries: impl IntoIterator<Item = (&'a K, &'a V)>) -> Hash
+    where
+        KeyMode: ToProofPath<K>,
+        K: ?Sized + 'a,
+        V: BinaryValue + 'a,
+    {
+        let leaves = entries.into_iter().map(|(key, value)| {
+            (
+                KeyMode::transform_key(key),
+                Self::hash_leaf(&value.to_bytes()),
+            )
+        });
+        Self::hash_map_node(merkle_root_of_leaves(leaves))
+    }
+
     /// Obtains hash of a Merkelized map. `root` is the recursively defined
     /// hash of the root node of the binary Patricia Merkle tree corresponding to the map.
     ///
@@ -141,6 +191,24 @@ impl HashTag {
             .hash()
     }
 
+    /// Obtains hash of a Merkelized map together with the number of its entries.
+    /// `size` is the number of entries in the map, and `root` is the hash of the root node
+    /// of the binary Patricia Merkle tree corresponding to the map.
+    ///
+    /// ```text
+    /// h = sha256( HashTag::SizedMapNode || size as u64 || merkle_root )
+    /// ```
+    pub fn hash_sized_map_node(size: u64, root: Hash) -> Hash {
+        let mut size_bytes = [0; 8];
+        LittleEndian::write_u64(&mut size_bytes, size);
+
+        HashStream::new()
+            .update(&[Self::SizedMapNode as u8])
+            .update(&size_bytes)
+            .update(root.as_ref())
+            .hash()
+    }
+
     /// Obtains hash of a branch node in a Merkle Patricia tree.
     /// `branch_node` is the binary serialization of the node.
     ///
@@ -192,6 +260,128 @@ impl HashTag {
     }
 }
 
+/// Algorithm used to hash branch nodes of Merkelized indexes.
+///
+/// The node hasher determines how the Merkle trees of [`ProofListIndex`] and [`ProofMapIndex`]
+/// are built on top of the leaf hashes, and thus the root hashes of these indexes. Leaves
+/// (i.e., index values) are always hashed with [`HashTag::hash_leaf`], and the root hash
+/// is always wrapped with [`HashTag::hash_list_node`] / [`HashTag::hash_map_node`].
+/// Thus, the hasher can be changed without affecting value hashing.
+///
+/// The hasher is selected with a type parameter of the index. The [identifier] of the hasher
+/// is persisted in the index metadata, so that opening the index with another hasher
+/// results in an error. Proofs built for an index are checked with the same hasher.
+///
+/// [`ProofListIndex`]: indexes/proof_list/struct.ProofListIndex.html
+/// [`ProofMapIndex`]: indexes/proof_map/struct.ProofMapIndex.html
+/// [`HashTag::hash_leaf`]: enum.HashTag.html#method.hash_leaf
+/// [`HashTag::hash_list_node`]: enum.HashTag.html#method.hash_list_node
+/// [`HashTag::hash_map_node`]: enum.HashTag.html#method.hash_map_node
+/// [identifier]: #associatedconstant.ID
+pub trait NodeHasher: 'static {
+    /// Identifier of the hasher persisted in the metadata of Merkelized indexes.
+    /// Must be unique among hashers used within a database; `0` is reserved
+    /// for [`DefaultNodeHasher`].
+    ///
+    /// [`DefaultNodeHasher`]: struct.DefaultNodeHasher.html
+    const ID: u32;
+
+    /// Obtains a hashed value of a branch in a Merkle tree of a list.
+    fn hash_list_branch(left_hash: &Hash, right_hash: &Hash) -> Hash;
+
+    /// Obtains a hashed value of a branch with one child in a Merkle tree of a list.
+    fn hash_list_single_branch(hash: &Hash) -> Hash;
+
+    /// Obtains hash of a branch node in a Merkle Patricia tree of a map.
+    /// `branch_node` is the binary serialization of the node.
+    ///
+    /// This method is also used to hash the root of a map with a single entry;
+    /// in this case, `branch_node` is the compressed path to the entry followed by
+    /// the entry hash.
+    fn hash_map_branch(branch_node: &[u8]) -> Hash;
+}
+
+/// Node hasher using SHA-256 with the domain separation provided by [`HashTag`].
+///
+/// This hasher is used by Merkelized indexes by default.
+///
+/// [`HashTag`]: enum.HashTag.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultNodeHasher;
+
+impl NodeHasher for DefaultNodeHasher {
+    const ID: u32 = 0;
+
+    fn hash_list_branch(left_hash: &Hash, right_hash: &Hash) -> Hash {
+        HashTag::hash_node(left_hash, right_hash)
+    }
+
+    fn hash_list_single_branch(hash: &Hash) -> Hash {
+        HashTag::hash_single_node(hash)
+    }
+
+    fn hash_map_branch(branch_node: &[u8]) -> Hash {
+        HashTag::hash_map_branch(branch_node)
+    }
+}
+
+/// Node hasher using BLAKE2b with the 256-bit digest.
+///
+/// The hashed data is the same as for [`DefaultNodeHasher`], including the [`HashTag`]
+/// prefixes for domain separation; only the hash function differs.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{
+///     access::{CopyAccessExt, FromAccess}, Blake2bNodeHasher, Database, ObjectHash,
+///     ProofListIndex, TemporaryDB,
+/// };
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut list =
+///     ProofListIndex::<_, u32, Blake2bNodeHasher>::from_access(&fork, "list".into()).unwrap();
+/// list.extend(vec![1, 2, 3]);
+/// let mut default_list = fork.get_proof_list("default_list");
+/// default_list.extend(vec![1_u32, 2, 3]);
+/// assert_ne!(list.object_hash(), default_list.object_hash());
+/// ```
+///
+/// [`DefaultNodeHasher`]: struct.DefaultNodeHasher.html
+/// [`HashTag`]: enum.HashTag.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Blake2bNodeHasher;
+
+impl Blake2bNodeHasher {
+    fn hash_tagged(tag: HashTag, parts: &[&[u8]]) -> Hash {
+        let mut buffer = vec![tag as u8];
+        for part in parts {
+            buffer.extend_from_slice(part);
+        }
+        blake2b(&buffer)
+    }
+}
+
+impl NodeHasher for Blake2bNodeHasher {
+    const ID: u32 = 1;
+
+    fn hash_list_branch(left_hash: &Hash, right_hash: &Hash) -> Hash {
+        Self::hash_tagged(
+            HashTag::ListBranchNode,
+            &[left_hash.as_ref(), right_hash.as_ref()],
+        )
+    }
+
+    fn hash_list_single_branch(hash: &Hash) -> Hash {
+        Self::hash_tagged(HashTag::ListBranchNode, &[hash.as_ref()])
+    }
+
+    fn hash_map_branch(branch_node: &[u8]) -> Hash {
+        Self::hash_tagged(HashTag::MapBranchNode, &[branch_node])
+    }
+}
+
 /// Computes a Merkle root hash for a the given list of hashes.
 ///
 /// If `hashes` are empty then `Hash::zero()` value is returned.
@@ -235,6 +425,33 @@ pub fn root_hash<V: BinaryValue + ?Sized>(hashes: &[V]) -> Hash {
 /// The hash value returned by the `object_hash()` method isn't always irreversible.
 /// This hash is used, for example, in the storage as a key, as uniqueness is important
 /// in this case.
+///
+/// Hashes of in-memory maps are consistent with Merkelized indexes: `object_hash()`
+/// of a `BTreeMap` is equal to the hash of a `ProofMapIndex` with the same entries.
+/// This does not hold for lists, since `Vec<u8>` is hashed as a blob; use
+/// [`HashTag::hash_list`] to compute the hash of a `ProofListIndex` with the given values.
+///
+/// # Encoding of primitive types
+///
+/// Hashes of primitive types are part of the hashing scheme of Merkelized indexes
+/// and are stable across releases. For all types except `Hash`, the hash is [`hash`]
+/// (i.e., SHA-256) of the following encoding of the value, which coincides with
+/// [`BinaryValue::to_bytes()`]:
+///
+/// - `[u8]` and `Vec<u8>`: the bytes as is, without a length prefix
+/// - `str` and `String`: UTF-8 bytes of the string, without a length prefix
+/// - integers (`u8` to `u128`, `i8` to `i128`): little-endian encoding with the fixed
+///   width of the type; signed integers use two's complement
+/// - `bool`: a single byte, `1` for `true` and `0` for `false`
+/// - `()`: empty byte sequence
+///
+/// `Hash` is not rehashed: its `object_hash()` is the hash itself. This allows to use
+/// the hash of an object or a collection in place of the object, e.g., as a value
+/// in a Merkelized index.
+///
+/// [`hash`]: crypto/fn.hash.html
+/// [`BinaryValue::to_bytes()`]: trait.BinaryValue.html#tymethod.to_bytes
+/// [`HashTag::hash_list`]: enum.HashTag.html#method.hash_list
 pub trait ObjectHash {
     /// Returns a hash of the value.
     ///
@@ -261,6 +478,48 @@ impl ObjectHash for [u8] {
     }
 }
 
+/// Computes the hash of a `ProofMapIndex` with the same entries and the default
+/// (hashed) key mode.
+impl<K: ObjectHash, V: BinaryValue> ObjectHash for BTreeMap<K, V> {
+    fn object_hash(&self) -> Hash {
+        HashTag::hash_map::<Hashed, _, _>(self)
+    }
+}
+
+/// Computes the hash of a `ProofMapIndex` with the same entries and the default
+/// (hashed) key mode, i.e., the same hash as for a `BTreeMap` with the same entries.
+///
+/// Iteration order of a `HashMap` is not deterministic, so the entries are sorted
+/// by their keys before hashing. Hence the `Ord` requirement on keys; the ordering
+/// must be consistent with `Eq`, so that equal maps produce the same sequence of entries.
+impl<K, V, S> ObjectHash for HashMap<K, V, S>
+where
+    K: Ord + StdHash + ObjectHash,
+    V: BinaryValue,
+{
+    fn object_hash(&self) -> Hash {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by(|(x, _), (y, _)| x.cmp(y));
+        HashTag::hash_map::<Hashed, _, _>(entries)
+    }
+}
+
+/// Computes the hash of a `ProofMapIndex` with the elements of the set as keys and
+/// unit values `()`, using the default (hashed) key mode.
+///
+/// As with `HashMap`, elements are sorted before hashing, so the hash does not depend
+/// on the iteration order of the set. The ordering of elements must be consistent with `Eq`.
+impl<K, S> ObjectHash for HashSet<K, S>
+where
+    K: Ord + StdHash + ObjectHash,
+{
+    fn object_hash(&self) -> Hash {
+        let mut keys: Vec<_> = self.iter().collect();
+        keys.sort_unstable();
+        HashTag::hash_map::<Hashed, _, _>(keys.into_iter().map(|key| (key, &())))
+    }
+}
+
 /// Errors that can occur while validating a `ListProof` or `MapProof` against
 /// a trusted collection hash.
 #[derive(Debug, Error)]
@@ -277,8 +536,68 @@ pub enum ValidationError<E: StdError + 'static> {
 
 #[cfg(test)]
 mod tests {
-    use super::{hash, HashTag, ProofPath, HASH_SIZE};
-    use crate::crypto::{Hash, HashStream};
+    use super::{hash, HashTag, ObjectHash, ProofPath, HASH_SIZE};
+    use crate::{
+        access::CopyAccessExt,
+        crypto::{Hash, HashStream},
+        Database, TemporaryDB,
+    };
+
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    /// Pins `object_hash()` of primitive types, so that the hashing scheme does not
+    /// change inadvertently.
+    #[test]
+    fn object_hashes_of_primitive_types() {
+        fn hex(value: &(impl ObjectHash + ?Sized)) -> String {
+            value.object_hash().to_hex()
+        }
+
+        const EMPTY: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(hex(&[][..] as &[u8]), EMPTY);
+        assert_eq!(hex(&Vec::<u8>::new()), EMPTY);
+        assert_eq!(hex(""), EMPTY);
+        assert_eq!(hex(&String::new()), EMPTY);
+        assert_eq!(hex(&()), EMPTY);
+
+        const BYTES: &str = "039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81";
+        assert_eq!(hex(&[1_u8, 2, 3][..]), BYTES);
+        assert_eq!(hex(&vec![1_u8, 2, 3]), BYTES);
+
+        const STRING: &str = "b7ee5c49786227b9b2648ad4a960cc1ba48bedcc3648e6abd400fe2b5d15dee7";
+        assert_eq!(hex("merkledb"), STRING);
+        assert_eq!(hex(&"merkledb".to_owned()), STRING);
+        assert_eq!(hex(&b"merkledb"[..]), STRING);
+
+        const ONE_BYTE: &str = "4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a";
+        assert_eq!(hex(&1_u8), ONE_BYTE);
+        assert_eq!(hex(&true), ONE_BYTE);
+        assert_eq!(
+            hex(&1_u16),
+            "47dc540c94ceb704a23875c11273e16bb0b8a87aed84de911f2133568115f254"
+        );
+        assert_eq!(
+            hex(&0x0102_0304_u32),
+            "ee10da4aefe61a37df1dee937ca3221afa3b2351f9ea34edbbb769573c6785f7"
+        );
+        assert_eq!(
+            hex(&1_u128),
+            "4cbbd8ca5215b8d161aec181a74b694f4e24b001d5b081dc0030ed797a8973e0"
+        );
+        assert_eq!(
+            hex(&-1_i8),
+            "a8100ae6aa1940d0b663bb31cd466142ebbdbd5187131b92d93818987832eb89"
+        );
+        assert_eq!(
+            hex(&-1_i64),
+            "12a3ae445661ce5dee78d0650d33362dec29c4f82af05e7e57fb595bbbacf0ca"
+        );
+        assert_eq!(hex(&u64::MAX), hex(&-1_i64));
+
+        // Hashes are not rehashed.
+        let value_hash = hash(b"merkledb");
+        assert_eq!(value_hash.object_hash(), value_hash);
+    }
 
     #[test]
     fn empty_list_hash() {
@@ -322,4 +641,36 @@ mod tests {
             HashTag::hash_single_entry_map(&path, &value_hash)
         );
     }
+
+    #[test]
+    fn hash_map_and_set_hashes_do_not_depend_on_order() {
+        let mut map = HashMap::new();
+        for i in 0_u32..100 {
+            map.insert(i, i.to_string());
+        }
+        // Build the same map in the reverse order and with a different capacity.
+        let mut other_map = HashMap::with_capacity(1_000);
+        for i in (0_u32..100).rev() {
+            other_map.insert(i, i.to_string());
+        }
+        assert_eq!(map.object_hash(), other_map.object_hash());
+
+        let btree_map: BTreeMap<_, _> = map.clone().into_iter().collect();
+        assert_eq!(map.object_hash(), btree_map.object_hash());
+        other_map.insert(100, "100".to_owned());
+        assert_ne!(map.object_hash(), other_map.object_hash());
+
+        let set: HashSet<_> = (0_u32..100).collect();
+        let other_set: HashSet<_> = (0_u32..100).rev().collect();
+        assert_eq!(set.object_hash(), other_set.object_hash());
+        assert_ne!(set.object_hash(), map.object_hash());
+
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut index = fork.get_proof_map::<_, u32, ()>("set");
+        for i in 0_u32..100 {
+            index.put(&i, ());
+        }
+        assert_eq!(set.object_hash(), index.object_hash());
+    }
 }
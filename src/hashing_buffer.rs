@@ -0,0 +1,172 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reusable buffer for serializing values before hashing.
+
+use std::cell::RefCell;
+
+use crate::{
+    crypto::{hash, Hash},
+    BinaryValue,
+};
+
+/// Capacity of the thread-local buffer retained between uses. If a larger value is hashed,
+/// the buffer is deallocated afterwards, so that a single large value does not pin
+/// the memory for the lifetime of the thread.
+const MAX_RETAINED_CAPACITY: usize = 64 * 1_024;
+
+thread_local! {
+    static THREAD_BUFFER: RefCell<HashingBuffer> = RefCell::new(HashingBuffer::new());
+}
+
+/// Reusable scratch buffer for serializing values before hashing.
+///
+/// Hashing a [`BinaryValue`] requires its serialization, which usually allocates
+/// a new vector for each hashed value. `HashingBuffer` serializes values with
+/// [`BinaryValue::write_bytes()`] into the same vector, so that hashing many values
+/// in a loop does not allocate once the buffer has grown to fit the values. The hashes
+/// are the same as for hashing the output of [`BinaryValue::to_bytes()`].
+///
+/// The buffer can be passed explicitly, or the thread-local buffer can be used
+/// via [`with_thread_local()`] and [`hash_thread_local()`]. The latter is used
+/// by [`ObjectHash`] implementations for built-in types and by the `ObjectHash` derive macro.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{BinaryValue, HashingBuffer, ObjectHash};
+///
+/// let mut buffer = HashingBuffer::new();
+/// let values = vec!["foo".to_owned(), "bar".to_owned()];
+/// for value in &values {
+///     assert_eq!(buffer.hash_value(value), value.object_hash());
+/// }
+/// assert_eq!(
+///     HashingBuffer::hash_thread_local(&1_u64),
+///     merkledb::crypto::hash(&1_u64.to_bytes())
+/// );
+/// ```
+///
+/// [`BinaryValue`]: trait.BinaryValue.html
+/// [`BinaryValue::write_bytes()`]: trait.BinaryValue.html#method.write_bytes
+/// [`BinaryValue::to_bytes()`]: trait.BinaryValue.html#tymethod.to_bytes
+/// [`with_thread_local()`]: #method.with_thread_local
+/// [`hash_thread_local()`]: #method.hash_thread_local
+/// [`ObjectHash`]: trait.ObjectHash.html
+#[derive(Debug, Default)]
+pub struct HashingBuffer {
+    bytes: Vec<u8>,
+}
+
+impl HashingBuffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty buffer able to hold at least `capacity` bytes without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of bytes the buffer can hold without reallocation.
+    pub fn capacity(&self) -> usize {
+        self.bytes.capacity()
+    }
+
+    /// Hashes bytes written by `write` into the buffer. The buffer is empty when passed
+    /// to `write`.
+    pub fn hash_with<F>(&mut self, write: F) -> Hash
+    where
+        F: FnOnce(&mut Vec<u8>),
+    {
+        self.bytes.clear();
+        write(&mut self.bytes);
+        hash(&self.bytes)
+    }
+
+    /// Hashes the serialized `value`. The output is equal to the hash of
+    /// `value.to_bytes()`.
+    pub fn hash_value<V: BinaryValue>(&mut self, value: &V) -> Hash {
+        self.hash_with(|bytes| value.write_bytes(bytes))
+    }
+
+    /// Calls `action` with the thread-local buffer.
+    ///
+    /// If the thread-local buffer is already in use (e.g., if this method is called
+    /// recursively from `action`), `action` is called with a new buffer instead.
+    pub fn with_thread_local<F, R>(action: F) -> R
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        THREAD_BUFFER.with(|buffer| {
+            if let Ok(mut buffer) = buffer.try_borrow_mut() {
+                let output = action(&mut buffer);
+                if buffer.capacity() > MAX_RETAINED_CAPACITY {
+                    *buffer = Self::new();
+                }
+                output
+            } else {
+                action(&mut Self::new())
+            }
+        })
+    }
+
+    /// Hashes the serialized `value` using the thread-local buffer. The output is equal
+    /// to the hash of `value.to_bytes()`.
+    pub fn hash_thread_local<V: BinaryValue>(value: &V) -> Hash {
+        Self::with_thread_local(|buffer| buffer.hash_value(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashingBuffer, MAX_RETAINED_CAPACITY};
+    use crate::{crypto::hash, BinaryValue};
+
+    #[test]
+    fn buffer_hashes_are_unchanged() {
+        let mut buffer = HashingBuffer::with_capacity(16);
+        let long_value = vec![1_u8; 100];
+        assert_eq!(buffer.hash_value(&long_value), hash(&long_value));
+        assert!(buffer.capacity() >= 100);
+        // Shorter values do not retain bytes of previous ones.
+        assert_eq!(buffer.hash_value(&5_u32), hash(&5_u32.to_bytes()));
+        assert_eq!(buffer.hash_value(&()), hash(&[]));
+        assert_eq!(buffer.hash_value(&"foo".to_owned()), hash("foo".as_bytes()));
+    }
+
+    #[test]
+    fn thread_local_buffer_is_reentrant() {
+        let hashes = HashingBuffer::with_thread_local(|outer| {
+            let inner_hash = HashingBuffer::hash_thread_local(&1_u64);
+            (outer.hash_value(&2_u64), inner_hash)
+        });
+        assert_eq!(hashes, (hash(&2_u64.to_bytes()), hash(&1_u64.to_bytes())));
+    }
+
+    #[test]
+    fn large_thread_local_buffer_is_released() {
+        let large_value = vec![0_u8; MAX_RETAINED_CAPACITY + 1];
+        HashingBuffer::hash_thread_local(&large_value);
+        let capacity = HashingBuffer::with_thread_local(|buffer| buffer.capacity());
+        assert!(capacity <= MAX_RETAINED_CAPACITY);
+
+        HashingBuffer::hash_thread_local(&vec![0_u8; 100]);
+        let capacity = HashingBuffer::with_thread_local(|buffer| buffer.capacity());
+        assert!(capacity >= 100);
+    }
+}
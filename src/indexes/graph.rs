@@ -0,0 +1,269 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An implementation of a directed graph with nodes identified by hashes.
+//!
+//! [`GraphIndex`] is intended for content-addressed directed acyclic graphs (DAGs),
+//! in which each node is identified by its hash.
+//!
+//! [`GraphIndex`]: struct.GraphIndex.html
+
+use thiserror::Error;
+
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    access::{Access, AccessError, FromAccess},
+    crypto::Hash,
+    views::{IndexAddress, RawAccessMut},
+    Group, KeySetIndex,
+};
+
+const CHILDREN_NAME: &str = "children";
+const PARENTS_NAME: &str = "parents";
+
+type Adjacency<T> = Group<T, Hash, KeySetIndex<<T as Access>::Base, Hash>>;
+
+/// Error returned by [`GraphIndex::ancestors()`] if the traversed part of the graph
+/// contains a cycle.
+///
+/// [`GraphIndex::ancestors()`]: struct.GraphIndex.html#method.ancestors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("graph contains a cycle passing through node {node:?}")]
+pub struct CycleError {
+    /// A node on the detected cycle.
+    pub node: Hash,
+}
+
+/// A directed graph with nodes identified by hashes.
+///
+/// The graph consists of two groups of [`KeySetIndex`]es: the group at the address
+/// `{addr}.children` maps each node to the set of its children, and the group at the address
+/// `{addr}.parents` maps each node to the set of its parents. Both groups are keyed by
+/// the node hash. Nodes do not need to be added separately; a node exists in the graph
+/// if it is incident to at least one edge.
+///
+/// The graph is intended to be acyclic, but this is not enforced when edges are added,
+/// since checking it would require traversing the graph on each insertion. Instead,
+/// cycles are detected by [`ancestors()`].
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::FromAccess, crypto::hash, Database, GraphIndex, TemporaryDB};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut graph = GraphIndex::from_access(&fork, "graph".into()).unwrap();
+/// let (root, child, grandchild) = (hash(b"root"), hash(b"child"), hash(b"grandchild"));
+/// graph.add_edge(&root, &child);
+/// graph.add_edge(&child, &grandchild);
+///
+/// assert_eq!(graph.children(&root), vec![child]);
+/// assert_eq!(graph.parents(&grandchild), vec![child]);
+/// assert_eq!(graph.ancestors(&grandchild).unwrap(), vec![child, root]);
+/// ```
+///
+/// [`KeySetIndex`]: struct.KeySetIndex.html
+/// [`ancestors()`]: #method.ancestors
+pub struct GraphIndex<T: Access> {
+    children: Adjacency<T>,
+    parents: Adjacency<T>,
+}
+
+impl<T: Access> fmt::Debug for GraphIndex<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("GraphIndex").finish_non_exhaustive()
+    }
+}
+
+impl<T: Access> FromAccess<T> for GraphIndex<T> {
+    fn from_access(access: T, addr: IndexAddress) -> Result<Self, AccessError> {
+        Ok(Self {
+            children: Group::from_access(access.clone(), addr.clone().append_name(CHILDREN_NAME))?,
+            parents: Group::from_access(access, addr.append_name(PARENTS_NAME))?,
+        })
+    }
+}
+
+impl<T: Access> GraphIndex<T> {
+    /// Returns `true` if the graph contains an edge from `parent` to `child`.
+    pub fn contains_edge(&self, parent: &Hash, child: &Hash) -> bool {
+        self.children.get(parent).contains(child)
+    }
+
+    /// Returns children of the specified node in the ascending order of their hashes.
+    pub fn children(&self, node: &Hash) -> Vec<Hash> {
+        self.children.get(node).iter().collect()
+    }
+
+    /// Returns parents of the specified node in the ascending order of their hashes.
+    pub fn parents(&self, node: &Hash) -> Vec<Hash> {
+        self.parents.get(node).iter().collect()
+    }
+
+    /// Returns all ancestors of the specified node, i.e., nodes from which the specified node
+    /// is reachable. Each ancestor is returned once, in the order of the depth-first traversal
+    /// of parents, which are visited in the ascending order of their hashes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a cycle is reachable from the specified node when moving
+    /// from children to parents; in particular, if the node is its own ancestor.
+    /// Cycles in other parts of the graph do not affect the result.
+    pub fn ancestors(&self, node: &Hash) -> Result<Vec<Hash>, CycleError> {
+        let mut ancestors = vec![];
+        let mut visited = HashSet::new();
+        // Nodes on the path from `node` to the currently visited node. An edge leading
+        // to a node on the path closes a cycle.
+        let mut path = HashSet::new();
+        path.insert(*node);
+        let mut stack = vec![(*node, self.parents(node).into_iter())];
+
+        while let Some((current, parents)) = stack.last_mut() {
+            if let Some(parent) = parents.next() {
+                if path.contains(&parent) {
+                    return Err(CycleError { node: parent });
+                }
+                if visited.insert(parent) {
+                    ancestors.push(parent);
+                    path.insert(parent);
+                    stack.push((parent, self.parents(&parent).into_iter()));
+                }
+            } else {
+                let current = *current;
+                path.remove(&current);
+                stack.pop();
+            }
+        }
+        Ok(ancestors)
+    }
+}
+
+impl<T> GraphIndex<T>
+where
+    T: Access,
+    T::Base: RawAccessMut,
+{
+    /// Adds an edge from `parent` to `child`. Adding an existing edge has no effect.
+    pub fn add_edge(&mut self, parent: &Hash, child: &Hash) {
+        self.children.get(parent).insert(child);
+        self.parents.get(child).insert(parent);
+    }
+
+    /// Removes an edge from `parent` to `child`. Removing a non-existing edge has no effect.
+    pub fn remove_edge(&mut self, parent: &Hash, child: &Hash) {
+        self.children.get(parent).remove(child);
+        self.parents.get(child).remove(parent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CycleError, GraphIndex};
+    use crate::{
+        access::FromAccess,
+        crypto::{hash, Hash},
+        Database, TemporaryDB,
+    };
+
+    fn node(name: &str) -> Hash {
+        hash(name.as_bytes())
+    }
+
+    fn sorted(mut hashes: Vec<Hash>) -> Vec<Hash> {
+        hashes.sort();
+        hashes
+    }
+
+    #[test]
+    fn dag_ancestors() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut graph = GraphIndex::from_access(&fork, "graph".into()).unwrap();
+        // a   b
+        //  \ / \
+        //   c   d
+        //    \ /
+        //     e   f
+        let edges = [("a", "c"), ("b", "c"), ("b", "d"), ("c", "e"), ("d", "e")];
+        for &(parent, child) in &edges {
+            graph.add_edge(&node(parent), &node(child));
+        }
+        // Duplicate edges are ignored.
+        graph.add_edge(&node("a"), &node("c"));
+
+        assert!(graph.contains_edge(&node("b"), &node("d")));
+        assert!(!graph.contains_edge(&node("d"), &node("b")));
+        assert_eq!(
+            graph.children(&node("b")),
+            sorted(vec![node("c"), node("d")])
+        );
+        assert_eq!(
+            graph.parents(&node("c")),
+            sorted(vec![node("a"), node("b")])
+        );
+        assert!(graph.children(&node("f")).is_empty());
+
+        let ancestors = graph.ancestors(&node("e")).unwrap();
+        assert_eq!(ancestors.len(), 4);
+        assert_eq!(
+            sorted(ancestors),
+            sorted(vec![node("a"), node("b"), node("c"), node("d")])
+        );
+        assert_eq!(graph.ancestors(&node("d")).unwrap(), vec![node("b")]);
+        assert!(graph.ancestors(&node("a")).unwrap().is_empty());
+        assert!(graph.ancestors(&node("f")).unwrap().is_empty());
+
+        graph.remove_edge(&node("b"), &node("d"));
+        assert!(graph.ancestors(&node("d")).unwrap().is_empty());
+        assert_eq!(graph.ancestors(&node("e")).unwrap().len(), 4);
+
+        // The graph is persisted.
+        db.merge(fork.into_patch()).unwrap();
+        let snapshot = db.snapshot();
+        let graph = GraphIndex::from_access(snapshot.as_ref(), "graph".into()).unwrap();
+        assert_eq!(
+            graph.parents(&node("e")),
+            sorted(vec![node("c"), node("d")])
+        );
+    }
+
+    #[test]
+    fn cycle_detection() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut graph = GraphIndex::from_access(&fork, "graph".into()).unwrap();
+        // a -> b -> c -> d, with an accidental edge d -> b.
+        let edges = [("a", "b"), ("b", "c"), ("c", "d"), ("x", "a")];
+        for &(parent, child) in &edges {
+            graph.add_edge(&node(parent), &node(child));
+        }
+        assert_eq!(graph.ancestors(&node("d")).unwrap().len(), 4);
+
+        graph.add_edge(&node("d"), &node("b"));
+        let err = graph.ancestors(&node("d")).unwrap_err();
+        assert!([node("b"), node("c"), node("d")].contains(&err.node));
+        assert!(graph.ancestors(&node("b")).is_err());
+        // Nodes not downstream of the cycle are unaffected.
+        assert_eq!(graph.ancestors(&node("a")).unwrap(), vec![node("x")]);
+
+        // Self-loops are cycles as well.
+        graph.add_edge(&node("x"), &node("x"));
+        assert_eq!(
+            graph.ancestors(&node("x")).unwrap_err(),
+            CycleError { node: node("x") }
+        );
+    }
+}
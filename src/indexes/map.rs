@@ -22,7 +22,10 @@ use std::{borrow::Borrow, marker::PhantomData};
 
 use crate::{
     access::{Access, AccessError, FromAccess},
-    indexes::iter::{Entries, IndexIterator, Keys, Values},
+    indexes::{
+        iter::{Entries, IndexIterator, Keys, Values},
+        IndexOptions, SizeError,
+    },
     views::{IndexAddress, IndexType, RawAccess, RawAccessMut, View, ViewWithMetadata},
     BinaryKey, BinaryValue,
 };
@@ -37,6 +40,7 @@ use crate::{
 #[derive(Debug)]
 pub struct MapIndex<T: RawAccess, K: ?Sized, V> {
     base: View<T>,
+    options: IndexOptions,
     _k: PhantomData<K>,
     _v: PhantomData<V>,
 }
@@ -63,11 +67,38 @@ where
         let base = view.into();
         Self {
             base,
+            options: IndexOptions::default(),
             _v: PhantomData,
             _k: PhantomData,
         }
     }
 
+    /// Sets limits on the size of entries inserted into this index instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, IndexOptions, TemporaryDB, Database, MapIndex};
+    ///
+    /// let db = TemporaryDB::default();
+    /// let fork = db.fork();
+    /// let mut index: MapIndex<_, u8, Vec<u8>> = fork
+    ///     .get_map("name")
+    ///     .with_options(IndexOptions::new(1, 16));
+    /// assert!(index.try_put(&1, vec![0; 16]).is_ok());
+    /// assert!(index.try_put(&2, vec![0; 17]).is_err());
+    /// ```
+    #[must_use]
+    pub fn with_options(mut self, options: IndexOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the limits on the size of entries inserted into this index instance.
+    pub fn options(&self) -> &IndexOptions {
+        &self.options
+    }
+
     /// Returns a value corresponding to the key.
     ///
     /// # Examples
@@ -231,6 +262,11 @@ where
 {
     /// Inserts a key-value pair into a map.
     ///
+    /// # Panics
+    ///
+    /// Panics if the key or the value exceeds the size limits set by the [index options].
+    /// Use [`try_put`] to handle such errors.
+    ///
     /// # Examples
     ///
     /// ```
@@ -243,8 +279,28 @@ where
     /// index.put(&1, 2);
     /// assert!(index.contains(&1));
     /// ```
+    ///
+    /// [index options]: #method.with_options
+    /// [`try_put`]: #method.try_put
     pub fn put(&mut self, key: &K, value: V) {
+        self.try_put(key, value)
+            .unwrap_or_else(|err| panic!("MerkleDB error: {err}"));
+    }
+
+    /// Inserts a key-value pair into a map, checking that the key and the value do not exceed
+    /// the size limits set by the [index options].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key or the value is too large. In this case, the map
+    /// is not changed.
+    ///
+    /// [index options]: #method.with_options
+    pub fn try_put(&mut self, key: &K, value: V) -> Result<(), SizeError> {
+        let value = value.into_bytes();
+        self.options.check(key.size(), value.len())?;
         self.base.put(key, value);
+        Ok(())
     }
 
     /// Removes a key from a map.
@@ -494,4 +550,63 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn oversized_entries_are_rejected() {
+        use crate::{IndexOptions, MapIndex, SizeError};
+
+        let db = TemporaryDB::default();
+        let fork = db.fork();
+        let mut map_index: MapIndex<_, str, Vec<u8>> = fork
+            .get_map(IDX_NAME)
+            .with_options(IndexOptions::new(4, 100));
+        map_index.try_put("key", vec![0; 100]).unwrap();
+
+        let err = map_index.try_put("key", vec![1; 101]).unwrap_err();
+        assert_eq!(
+            err,
+            SizeError::ValueTooLarge {
+                size: 101,
+                max: 100
+            }
+        );
+        let err = map_index.try_put("other", vec![]).unwrap_err();
+        assert_eq!(err, SizeError::KeyTooLarge { size: 5, max: 4 });
+        // The map is not changed on error.
+        assert_eq!(map_index.get("key"), Some(vec![0; 100]));
+        assert!(!map_index.contains("other"));
+    }
+
+    #[test]
+    fn default_options_reject_oversized_values() {
+        use crate::{MapIndex, SizeError};
+
+        let db = TemporaryDB::default();
+        let fork = db.fork();
+        let mut map_index: MapIndex<_, u8, Vec<u8>> = fork.get_map(IDX_NAME);
+        map_index.try_put(&1, vec![0; 1 << 20]).unwrap();
+
+        let err = map_index.try_put(&2, vec![0; (1 << 26) + 1]).unwrap_err();
+        assert_eq!(
+            err,
+            SizeError::ValueTooLarge {
+                size: (1 << 26) + 1,
+                max: 1 << 26,
+            }
+        );
+        assert!(!map_index.contains(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "value size (101 bytes) exceeds the limit of 100 bytes")]
+    fn oversized_value_panics_on_put() {
+        use crate::{IndexOptions, MapIndex};
+
+        let db = TemporaryDB::default();
+        let fork = db.fork();
+        let mut map_index: MapIndex<_, u8, Vec<u8>> = fork
+            .get_map(IDX_NAME)
+            .with_options(IndexOptions::new(4, 100));
+        map_index.put(&1, vec![0; 101]);
+    }
 }
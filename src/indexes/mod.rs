@@ -16,24 +16,36 @@
 
 pub use self::{
     entry::Entry,
+    graph::{CycleError, GraphIndex},
     group::Group,
     iter::{Entries, IndexIterator, Keys, Values},
     key_set::KeySetIndex,
     list::ListIndex,
     map::MapIndex,
+    options::{IndexOptions, SizeError},
     proof_entry::ProofEntry,
+    proof_limits::{ProofError, ProofLimit, ProofLimits},
+    queue::{ProofQueue, Queue},
+    sharded_map::{ShardedEntries, ShardedMap},
     sparse_list::SparseListIndex,
+    sum_tree::{CheckedSumProof, SumNode, SumOverflowError, SumProof, SumProofError, SumTreeIndex},
     value_set::ValueSetIndex,
 };
 
 mod entry;
+mod graph;
 mod group;
 mod iter;
 mod key_set;
 mod list;
 mod map;
+mod options;
 mod proof_entry;
+mod proof_limits;
 pub mod proof_list;
 pub mod proof_map;
+mod queue;
+mod sharded_map;
 mod sparse_list;
+mod sum_tree;
 mod value_set;
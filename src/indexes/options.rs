@@ -0,0 +1,102 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Options restricting the size of entries stored in indexes.
+
+use thiserror::Error;
+
+/// Default maximum size of a key, in bytes (64 KiB).
+const DEFAULT_MAX_KEY_BYTES: usize = 1 << 16;
+/// Default maximum size of a value, in bytes (64 MiB).
+const DEFAULT_MAX_VALUE_BYTES: usize = 1 << 26;
+
+/// Limits on the size of keys and values inserted into map indexes, such as [`MapIndex`]
+/// and [`ProofMapIndex`].
+///
+/// The limits are checked when an entry is inserted into the index. They are intended
+/// to catch bugs (e.g., accidental storing of a whole collection under a single key)
+/// early, rather than to fail during hash recomputation or when reading the entry.
+/// The defaults are generous: 64 KiB for keys and 64 MiB for values.
+///
+/// The options are not persisted; they apply to a particular index instance created with
+/// the `with_options` method of the index.
+///
+/// [`MapIndex`]: ../struct.MapIndex.html
+/// [`ProofMapIndex`]: ../proof_map/struct.ProofMapIndex.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct IndexOptions {
+    /// Maximum size of a serialized key, in bytes.
+    pub max_key_bytes: usize,
+    /// Maximum size of a serialized value, in bytes.
+    pub max_value_bytes: usize,
+}
+
+impl IndexOptions {
+    /// Creates options with the specified limits.
+    pub fn new(max_key_bytes: usize, max_value_bytes: usize) -> Self {
+        Self {
+            max_key_bytes,
+            max_value_bytes,
+        }
+    }
+
+    /// Checks the sizes of a serialized key and value against the limits.
+    pub(crate) fn check(&self, key_bytes: usize, value_bytes: usize) -> Result<(), SizeError> {
+        if key_bytes > self.max_key_bytes {
+            Err(SizeError::KeyTooLarge {
+                size: key_bytes,
+                max: self.max_key_bytes,
+            })
+        } else if value_bytes > self.max_value_bytes {
+            Err(SizeError::ValueTooLarge {
+                size: value_bytes,
+                max: self.max_value_bytes,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_KEY_BYTES, DEFAULT_MAX_VALUE_BYTES)
+    }
+}
+
+/// Error returned when an inserted entry exceeds the limits set by [`IndexOptions`].
+///
+/// [`IndexOptions`]: struct.IndexOptions.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum SizeError {
+    /// The key is too large.
+    #[error("key size ({size} bytes) exceeds the limit of {max} bytes")]
+    KeyTooLarge {
+        /// Size of the serialized key.
+        size: usize,
+        /// Maximum allowed size.
+        max: usize,
+    },
+
+    /// The value is too large.
+    #[error("value size ({size} bytes) exceeds the limit of {max} bytes")]
+    ValueTooLarge {
+        /// Size of the serialized value.
+        size: usize,
+        /// Maximum allowed size.
+        max: usize,
+    },
+}
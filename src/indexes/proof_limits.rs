@@ -0,0 +1,111 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Limits on the size of proofs built by Merkelized indexes.
+
+use thiserror::Error;
+
+use std::fmt;
+
+/// Limits on the size of proofs created by bounded proof methods, such as
+/// [`ProofListIndex::get_range_proof_bounded`] and [`ProofMapIndex::get_multiproof_bounded`].
+///
+/// Limits are checked while the proof entries are being read from the storage,
+/// so a request exceeding the limits fails before the proof is built.
+///
+/// [`ProofListIndex::get_range_proof_bounded`]: ../proof_list/struct.ProofListIndex.html#method.get_range_proof_bounded
+/// [`ProofMapIndex::get_multiproof_bounded`]: ../proof_map/struct.ProofMapIndex.html#method.get_multiproof_bounded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProofLimits {
+    /// Maximum number of entries (list elements or map keys) in the proof.
+    pub max_entries: usize,
+    /// Maximum total size of the serialized keys and values in the proof, in bytes.
+    /// List indexes are not taken into account.
+    pub max_bytes: usize,
+}
+
+impl ProofLimits {
+    /// Creates limits with the specified values.
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            max_entries,
+            max_bytes,
+        }
+    }
+
+    /// Checks the number of entries in the proof.
+    pub(crate) fn check_entries(&self, entries: usize) -> Result<(), ProofError> {
+        if entries > self.max_entries {
+            Err(ProofError::TooLarge {
+                limit: ProofLimit::Entries,
+                max: self.max_entries,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks the total size of keys and values in the proof.
+    pub(crate) fn check_bytes(&self, bytes: usize) -> Result<(), ProofError> {
+        if bytes > self.max_bytes {
+            Err(ProofError::TooLarge {
+                limit: ProofLimit::Bytes,
+                max: self.max_bytes,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// No limits on the proof size.
+impl Default for ProofLimits {
+    fn default() -> Self {
+        Self::new(usize::MAX, usize::MAX)
+    }
+}
+
+/// Kind of a limit exceeded by a proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProofLimit {
+    /// Number of entries in the proof.
+    Entries,
+    /// Total size of keys and values in the proof.
+    Bytes,
+}
+
+impl fmt::Display for ProofLimit {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::Entries => "entries",
+            Self::Bytes => "bytes",
+        })
+    }
+}
+
+/// Errors that can occur while building a bounded proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ProofError {
+    /// The requested proof exceeds the configured limits.
+    #[error("requested proof exceeds the limit of {max} {limit}")]
+    TooLarge {
+        /// Exceeded limit.
+        limit: ProofLimit,
+        /// Value of the exceeded limit.
+        max: usize,
+    },
+}
@@ -0,0 +1,191 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Frontier of a Merkle tree allowing to compute list hashes on appends.
+
+use anyhow::ensure;
+use byteorder::{ByteOrder, LittleEndian};
+use serde_derive::{Deserialize, Serialize};
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use super::{key::MAX_INDEX, tree_height_by_length};
+use crate::{
+    crypto::{Hash, HASH_SIZE},
+    hash::{DefaultNodeHasher, NodeHasher},
+    BinaryValue, HashTag, ObjectHash,
+};
+
+/// Frontier of the Merkle tree of a [`ProofListIndex`].
+///
+/// The frontier consists of the hashes of the rightmost perfect subtrees of the tree.
+/// For a list with `n` elements, there are at most `log2(n) + 1` such hashes; a new element
+/// can be appended to the frontier knowing only the element itself.
+///
+/// The frontier allows to compute the hash of a list that is only appended to without
+/// storing the entire list, e.g., on a light producer which periodically syncs with
+/// the full index.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::CopyAccessExt, Database, ObjectHash, TemporaryDB};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut list = fork.get_proof_list("list");
+/// list.extend(vec![1_u32, 2, 3]);
+///
+/// let frontier = list.frontier();
+/// let (hash, frontier) = frontier.append(&4_u32);
+/// list.push(4);
+/// assert_eq!(hash, list.object_hash());
+/// assert_eq!(frontier, list.frontier());
+/// ```
+///
+/// # Node hasher
+///
+/// The `H` type parameter specifies the [`NodeHasher`] used to hash branches of the tree.
+/// It must match the hasher of the `ProofListIndex` the frontier was obtained from;
+/// the hasher is not a part of the serialized frontier. Frontiers of lists with the default
+/// hasher are represented by the [`Frontier`] alias.
+///
+/// [`ProofListIndex`]: struct.ProofListIndex.html
+/// [`NodeHasher`]: ../../trait.NodeHasher.html
+/// [`Frontier`]: type.Frontier.html
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenericFrontier<H> {
+    len: u64,
+    /// Hashes of perfect subtrees, one for each bit set in `len`, ordered from the smallest
+    /// subtree to the largest one (i.e., from the rightmost subtree to the leftmost one).
+    peaks: Vec<Hash>,
+    #[serde(skip)]
+    _hasher: PhantomData<H>,
+}
+
+/// Frontier of the Merkle tree of a `ProofListIndex` using the [`DefaultNodeHasher`].
+///
+/// See [`GenericFrontier`] for the description and examples of usage.
+///
+/// [`DefaultNodeHasher`]: ../../struct.DefaultNodeHasher.html
+/// [`GenericFrontier`]: struct.GenericFrontier.html
+pub type Frontier = GenericFrontier<DefaultNodeHasher>;
+
+impl<H: NodeHasher> GenericFrontier<H> {
+    /// Creates the frontier of an empty list.
+    pub fn new() -> Self {
+        Self::from_parts(0, vec![])
+    }
+
+    /// Creates a frontier from its parts.
+    ///
+    /// `peaks` must contain the hashes of perfect subtrees of the tree, one for each bit set
+    /// in `len`, ordered from the smallest subtree to the largest one.
+    pub(super) fn from_parts(len: u64, peaks: Vec<Hash>) -> Self {
+        debug_assert_eq!(len.count_ones() as usize, peaks.len());
+        Self {
+            len,
+            peaks,
+            _hasher: PhantomData,
+        }
+    }
+
+    /// Returns the length of the list corresponding to the frontier.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the frontier corresponds to an empty list.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends a value to the frontier. Returns the hash of the list with the value appended
+    /// (which is equal to its `object_hash()`) and the updated frontier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list length exceeds the maximum supported length of `ProofListIndex`.
+    pub fn append<V: BinaryValue>(mut self, value: &V) -> (Hash, Self) {
+        assert!(self.len <= MAX_INDEX, "list index overflow");
+
+        let mut hash = HashTag::hash_leaf(&value.to_bytes());
+        // Perfect subtrees of equal size are merged, similar to carrying on increment.
+        let carries = self.len.trailing_ones() as usize;
+        for peak in self.peaks.drain(..carries) {
+            hash = H::hash_list_branch(&peak, &hash);
+        }
+        self.peaks.insert(0, hash);
+        self.len += 1;
+        (self.object_hash(), self)
+    }
+
+    /// Computes the root of the Merkle tree corresponding to the frontier.
+    pub fn merkle_root(&self) -> Hash {
+        let mut peaks = self.peaks.iter();
+        let mut root = match peaks.next() {
+            Some(hash) => *hash,
+            None => return Hash::zero(),
+        };
+
+        let lowest_level = self.len.trailing_zeros();
+        let root_level = u32::from(tree_height_by_length(self.len)) - 1;
+        for level in lowest_level..root_level {
+            // The rightmost node on `level` has a left sibling iff the corresponding bit
+            // is set in the list length, with the exception of the lowest peak itself.
+            // The sibling is the next peak.
+            let has_sibling = level > lowest_level && self.len & (1 << level) != 0;
+            let sibling = if has_sibling { peaks.next() } else { None };
+            root = sibling.map_or_else(
+                || H::hash_list_single_branch(&root),
+                |sibling| H::hash_list_branch(sibling, &root),
+            );
+        }
+        root
+    }
+}
+
+impl<H: NodeHasher> ObjectHash for GenericFrontier<H> {
+    fn object_hash(&self) -> Hash {
+        HashTag::hash_list_node(self.len, self.merkle_root())
+    }
+}
+
+/// The frontier is serialized as the little-endian list length followed by the peak hashes.
+impl<H: NodeHasher> BinaryValue for GenericFrontier<H> {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 8 + self.peaks.len() * HASH_SIZE];
+        LittleEndian::write_u64(&mut bytes[..8], self.len);
+        for (chunk, hash) in bytes[8..].chunks_mut(HASH_SIZE).zip(&self.peaks) {
+            chunk.copy_from_slice(hash.as_ref());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
+        ensure!(bytes.len() >= 8, "Frontier is too short");
+        let len = LittleEndian::read_u64(&bytes[..8]);
+        ensure!(len <= MAX_INDEX + 1, "Frontier length is too large");
+        ensure!(
+            bytes.len() - 8 == len.count_ones() as usize * HASH_SIZE,
+            "Frontier does not match its length"
+        );
+
+        let peaks = bytes[8..]
+            .chunks(HASH_SIZE)
+            .map(|chunk| Hash::from_slice(chunk).unwrap())
+            .collect();
+        Ok(Self::from_parts(len, peaks))
+    }
+}
@@ -14,9 +14,14 @@
 
 //! An implementation of a Merkelized version of an array list (Merkle tree).
 
-pub use self::proof::{CheckedListProof, ListProof, ListProofError, ValidationError};
+pub use self::{
+    frontier::{Frontier, GenericFrontier},
+    proof::{CheckedListProof, GenericListProof, ListProof, ListProofError, ValidationError},
+};
+
+use thiserror::Error;
 
-use std::{cmp, iter, marker::PhantomData, ops::RangeBounds};
+use std::{borrow::Cow, cmp, iter, marker::PhantomData, ops::RangeBounds};
 
 use self::{
     key::{ProofListKey, MAX_INDEX},
@@ -25,12 +30,16 @@ use self::{
 use crate::{
     access::{Access, AccessError, FromAccess},
     crypto::Hash,
-    hash::HashTag,
-    indexes::iter::{Entries, IndexIterator, Values},
+    hash::{DefaultNodeHasher, HashTag, NodeHasher},
+    indexes::{
+        iter::{Entries, IndexIterator, Values},
+        ProofError, ProofLimits,
+    },
     views::{IndexState, IndexType, RawAccess, RawAccessMut, View, ViewWithMetadata},
     BinaryValue, IndexAddress, ObjectHash,
 };
 
+mod frontier;
 mod key;
 mod proof;
 mod proof_builder;
@@ -45,6 +54,51 @@ fn tree_height_by_length(len: u64) -> u8 {
     }
 }
 
+/// Errors detected by [`ProofListIndex::verify_integrity()`].
+///
+/// Each error points to the first divergent position found when recomputing the Merkle tree
+/// bottom-up, from the list elements to the root.
+///
+/// [`ProofListIndex::verify_integrity()`]: struct.ProofListIndex.html#method.verify_integrity
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IntegrityError {
+    /// A list element is missing from the storage.
+    #[error("element at index {index} is missing")]
+    MissingValue {
+        /// Index of the element.
+        index: u64,
+    },
+
+    /// A list element cannot be deserialized.
+    #[error("element at index {index} cannot be decoded: {error}")]
+    UndecodableValue {
+        /// Index of the element.
+        index: u64,
+        /// Deserialization error.
+        error: anyhow::Error,
+    },
+
+    /// A node of the Merkle tree is missing or is not a valid hash.
+    #[error("tree node at height {height}, index {index} is missing or malformed")]
+    MalformedNode {
+        /// Height of the node.
+        height: u8,
+        /// Index of the node on its height.
+        index: u64,
+    },
+
+    /// The hash stored in a node of the Merkle tree differs from the hash recomputed
+    /// from its children (or from the list element for nodes at height 1).
+    #[error("tree node at height {height}, index {index} diverges from the recomputed hash")]
+    HashMismatch {
+        /// Height of the node.
+        height: u8,
+        /// Index of the node on its height.
+        index: u64,
+    },
+}
+
 /// A Merkelized version of an array list that provides proofs of existence for the list items.
 ///
 /// `ProofListIndex` implements a Merkle tree, storing elements as leaves and using `u64` as
@@ -66,6 +120,14 @@ fn tree_height_by_length(len: u64) -> u8 {
 /// index values; it is unnecessary to check on the calling side whether the index exceeds
 /// `2 ** 56 - 1` .
 ///
+/// # Node hashing
+///
+/// The `H` type parameter specifies the [`NodeHasher`] used to hash branches of the Merkle tree;
+/// by default, it is [`DefaultNodeHasher`]. The hasher is recorded in the index metadata
+/// when the list is created, and opening the list with another hasher fails with
+/// [`AccessErrorKind::WrongNodeHasher`]. Proofs built for the list are parameterized
+/// with the same hasher.
+///
 /// [`BinaryValue`]: ../../trait.BinaryValue.html
 /// [`ListIndex`]: ../struct.ListIndex.html
 /// [`extend`]: #method.extend
@@ -73,17 +135,22 @@ fn tree_height_by_length(len: u64) -> u8 {
 /// [`get`]: #method.get
 /// [`iter_from`]: #method.iter_from
 /// [`get_proof`]: #method.get_proof
+/// [`NodeHasher`]: ../../trait.NodeHasher.html
+/// [`DefaultNodeHasher`]: ../../struct.DefaultNodeHasher.html
+/// [`AccessErrorKind::WrongNodeHasher`]: ../../access/enum.AccessErrorKind.html#variant.WrongNodeHasher
 #[derive(Debug)]
-pub struct ProofListIndex<T: RawAccess, V> {
+pub struct ProofListIndex<T: RawAccess, V, H: NodeHasher = DefaultNodeHasher> {
     base: View<T>,
     state: IndexState<T, u64>,
     _v: PhantomData<V>,
+    _hasher: PhantomData<H>,
 }
 
-impl<T, V> MerkleTree<V> for ProofListIndex<T, V>
+impl<T, V, H> MerkleTree<V, H> for ProofListIndex<T, V, H>
 where
     T: RawAccess,
     V: BinaryValue,
+    H: NodeHasher,
 {
     fn len(&self) -> u64 {
         self.len()
@@ -94,29 +161,43 @@ where
     }
 
     fn merkle_root(&self) -> Hash {
-        self.get_branch(self.root_key()).unwrap_or_default()
+        self.merkle_root()
     }
 
     fn values<'s>(&'s self, start_index: u64) -> Box<dyn Iterator<Item = V> + 's> {
         Box::new(self.iter_from(start_index))
     }
+
+    fn sized_values<'s>(&'s self, start_index: u64) -> Box<dyn Iterator<Item = (V, usize)> + 's> {
+        // Values are read in the serialized form to learn their size without re-encoding them.
+        let entries = Entries::<u64, Vec<u8>>::with_prefix(&self.base, &0_u8, Some(&start_index));
+        Box::new(entries.map(|(_, bytes)| {
+            let size = bytes.len();
+            let value = V::from_bytes(Cow::Owned(bytes)).expect("Error while deserializing value");
+            (value, size)
+        }))
+    }
 }
 
-impl<T, V> FromAccess<T> for ProofListIndex<T::Base, V>
+impl<T, V, H> FromAccess<T> for ProofListIndex<T::Base, V, H>
 where
     T: Access,
     V: BinaryValue,
+    H: NodeHasher,
 {
     fn from_access(access: T, addr: IndexAddress) -> Result<Self, AccessError> {
-        let view = access.get_or_create_view(addr, IndexType::ProofList)?;
+        let mut view = access.get_or_create_view(addr.clone(), IndexType::ProofList)?;
+        view.bind_node_hasher(H::ID)
+            .map_err(|kind| AccessError { addr, kind })?;
         Ok(Self::new(view))
     }
 }
 
-impl<T, V> ProofListIndex<T, V>
+impl<T, V, H> ProofListIndex<T, V, H>
 where
     T: RawAccess,
     V: BinaryValue,
+    H: NodeHasher,
 {
     pub(crate) fn new(view: ViewWithMetadata<T>) -> Self {
         let (base, state) = view.into_parts();
@@ -124,6 +205,7 @@ where
             base,
             state,
             _v: PhantomData,
+            _hasher: PhantomData,
         }
     }
 
@@ -148,6 +230,10 @@ where
         ProofListKey::new(self.height(), 0)
     }
 
+    fn merkle_root(&self) -> Hash {
+        self.get_branch(self.root_key()).unwrap_or_default()
+    }
+
     /// Returns the element at the indicated position or `None` if the indicated position
     /// is out of bounds.
     ///
@@ -254,6 +340,89 @@ where
         tree_height_by_length(self.len())
     }
 
+    /// Checks integrity of the list by recomputing its Merkle tree from scratch and comparing
+    /// it with the stored one.
+    ///
+    /// The check verifies that all list elements are present and can be decoded, and that
+    /// the hash of each stored tree node (including the root) matches the hash recomputed from
+    /// its children. The tree is traversed bottom-up and left to right, and the first divergent
+    /// position is reported. The check reads the whole list, so it is intended for maintenance
+    /// tools rather than for regular operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofListIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_list("name");
+    /// index.extend(vec![1_u32, 2, 3]);
+    /// assert!(index.verify_integrity().is_ok());
+    /// ```
+    pub fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        let len = self.len();
+        if len == 0 {
+            return Ok(());
+        }
+
+        for index in 0..len {
+            let bytes = self
+                .base
+                .get::<_, Vec<u8>>(&ProofListKey::leaf(index))
+                .ok_or(IntegrityError::MissingValue { index })?;
+            if let Err(error) = V::from_bytes(Cow::Borrowed(&bytes)) {
+                return Err(IntegrityError::UndecodableValue { index, error });
+            }
+            self.check_node(ProofListKey::new(1, index), HashTag::hash_leaf(&bytes))?;
+        }
+
+        // Nodes on each height are checked before they are used to compute their parents,
+        // so `get_branch_unchecked` cannot panic.
+        let mut last_index_on_height = len - 1;
+        for height in 1..self.height() {
+            for index in (0..=last_index_on_height).step_by(2) {
+                let key = ProofListKey::new(height, index);
+                let expected_hash = if index < last_index_on_height {
+                    H::hash_list_branch(
+                        &self.get_branch_unchecked(key),
+                        &self.get_branch_unchecked(key.as_right()),
+                    )
+                } else {
+                    H::hash_list_single_branch(&self.get_branch_unchecked(key))
+                };
+                self.check_node(key.parent(), expected_hash)?;
+            }
+            last_index_on_height /= 2;
+        }
+        Ok(())
+    }
+
+    fn check_node(&self, key: ProofListKey, expected_hash: Hash) -> Result<(), IntegrityError> {
+        let stored_hash = self
+            .base
+            .get::<_, Vec<u8>>(&key)
+            .and_then(|bytes| Hash::from_slice(&bytes));
+        match stored_hash {
+            Some(hash) if hash == expected_hash => Ok(()),
+            Some(_) => Err(IntegrityError::HashMismatch {
+                height: key.height(),
+                index: key.index(),
+            }),
+            None => Err(IntegrityError::MalformedNode {
+                height: key.height(),
+                index: key.index(),
+            }),
+        }
+    }
+}
+
+impl<T, V, H> ProofListIndex<T, V, H>
+where
+    T: RawAccess,
+    V: BinaryValue,
+    H: NodeHasher,
+{
     /// Returns a proof of existence for the list element at the specified position.
     ///
     /// Returns a proof of absence if the list doesn't contain an element with the specified `index`.
@@ -271,8 +440,46 @@ where
     /// let proof = index.get_proof(0);
     /// let proof_of_absence = index.get_proof(1);
     /// ```
-    pub fn get_proof(&self, index: u64) -> ListProof<V> {
-        self.create_proof(index)
+    pub fn get_proof(&self, index: u64) -> GenericListProof<V, H> {
+        traced!(
+            "list_proof",
+            index = index,
+            list_len = self.len();
+            self.create_proof(index)
+        )
+    }
+
+    /// Returns the length of the list together with the proof of existence for its last
+    /// element, which is useful for clients following the tip of the list.
+    ///
+    /// The proof has the same size as a proof for any other single element, i.e.,
+    /// it is logarithmic in the list length. If the list is empty, the returned length is 0
+    /// and the proof contains no elements; such a proof can still be checked against
+    /// the list hash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ObjectHash, ProofListIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_list("name");
+    /// let (len, proof) = index.get_head_proof();
+    /// assert_eq!(len, 0);
+    /// let checked_proof = proof.check_against_hash(index.object_hash()).unwrap();
+    /// assert!(checked_proof.entries().is_empty());
+    ///
+    /// index.extend(vec![1, 2, 3]);
+    /// let (len, proof) = index.get_head_proof();
+    /// assert_eq!(len, 3);
+    /// let checked_proof = proof.check_against_hash(index.object_hash()).unwrap();
+    /// assert_eq!(checked_proof.entries(), [(2, 3)]);
+    /// assert_eq!(checked_proof.list_len(), 3);
+    /// ```
+    pub fn get_head_proof(&self) -> (u64, GenericListProof<V, H>) {
+        let len = self.len();
+        (len, self.get_proof(len.saturating_sub(1)))
     }
 
     /// Returns the proof of existence for the list elements in the specified range.
@@ -303,8 +510,13 @@ where
     /// let empty_proof = index.get_range_proof(100..10000);
     /// assert!(empty_proof.entries_unchecked().is_empty());
     /// ```
-    pub fn get_range_proof<R: RangeBounds<u64>>(&self, range: R) -> ListProof<V> {
-        self.create_range_proof(range)
+    pub fn get_range_proof<R: RangeBounds<u64>>(&self, range: R) -> GenericListProof<V, H> {
+        traced!(
+            "list_range_proof",
+            list_len = self.len();
+            self.create_range_proof(range);
+            |proof| entries = proof.entries_unchecked().len()
+        )
     }
 
     /// Returns an iterator over the list values.
@@ -344,12 +556,98 @@ where
     pub fn iter_from(&self, from: u64) -> Values<'_, V> {
         self.index_iter(Some(&from)).skip_keys()
     }
+
+    /// Returns the proof that the elements at `index` and `index + 1` are adjacent in the list.
+    ///
+    /// This is a range proof for `index..index + 2`. The adjacent elements can be retrieved
+    /// from the checked proof with [`CheckedListProof::adjacent_pair`]. If `index + 1` is out
+    /// of bounds, the proof contains fewer than two elements, and thus does not prove adjacency.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ObjectHash, ProofListIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_list("name");
+    /// index.extend(vec![1, 2, 3, 4, 5]);
+    ///
+    /// let proof = index.get_adjacency_proof(2);
+    /// let checked_proof = proof.check_against_hash(index.object_hash()).unwrap();
+    /// assert_eq!(checked_proof.adjacent_pair(2), Some((&3, &4)));
+    /// assert_eq!(checked_proof.adjacent_pair(3), None);
+    /// ```
+    ///
+    /// [`CheckedListProof::adjacent_pair`]: struct.CheckedListProof.html#method.adjacent_pair
+    pub fn get_adjacency_proof(&self, index: u64) -> GenericListProof<V, H> {
+        self.create_range_proof(index..index.saturating_add(2))
+    }
+
+    /// Returns the proof of existence for the list elements in the specified range,
+    /// or an error if the proof would exceed the specified `limits`.
+    ///
+    /// The number of elements in the range is checked before reading any elements,
+    /// and the total size of the elements is checked as they are read, so that
+    /// oversized requests fail without building the proof.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range bounds are illegal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{
+    ///     access::CopyAccessExt, Database, ProofError, ProofLimit, ProofLimits, TemporaryDB,
+    /// };
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_list("name");
+    /// index.extend(0_u32..1_000);
+    ///
+    /// let limits = ProofLimits::new(100, 1_024);
+    /// let proof = index.get_range_proof_bounded(10..20, &limits).unwrap();
+    /// assert!(proof.indexes_unchecked().eq(10..20));
+    ///
+    /// let err = index.get_range_proof_bounded(.., &limits).unwrap_err();
+    /// assert_eq!(err, ProofError::TooLarge { limit: ProofLimit::Entries, max: 100 });
+    /// ```
+    pub fn get_range_proof_bounded<R: RangeBounds<u64>>(
+        &self,
+        range: R,
+        limits: &ProofLimits,
+    ) -> Result<GenericListProof<V, H>, ProofError> {
+        self.create_range_proof_bounded(range, limits)
+    }
+
+    /// Returns the frontier of the Merkle tree of this list, which allows to compute
+    /// hashes of the list after appending new values without accessing the list.
+    ///
+    /// See [`Frontier`] for an example of usage.
+    ///
+    /// [`Frontier`]: struct.GenericFrontier.html
+    pub fn frontier(&self) -> GenericFrontier<H> {
+        let len = self.len();
+        let peaks = (0..64_u8)
+            .filter(|&level| len & (1 << level) != 0)
+            .map(|level| {
+                // The perfect subtree for `level` covers `2 ** level` leaves, starting
+                // from the leaf index with all bits up to `level` cleared.
+                let start = (len >> level >> 1) << 1;
+                self.get_branch_unchecked(ProofListKey::new(level + 1, start))
+            })
+            .collect();
+        GenericFrontier::from_parts(len, peaks)
+    }
 }
 
-impl<T, V> ProofListIndex<T, V>
+impl<T, V, H> ProofListIndex<T, V, H>
 where
     T: RawAccessMut,
     V: BinaryValue,
+    H: NodeHasher,
 {
     fn set_len(&mut self, len: u64) {
         self.state.set(len);
@@ -395,7 +693,7 @@ where
 
             while index < stop_index {
                 let key = ProofListKey::new(height, index);
-                let branch_hash = HashTag::hash_node(
+                let branch_hash = H::hash_list_branch(
                     &self.get_branch_unchecked(key),
                     &self.get_branch_unchecked(key.as_right()),
                 );
@@ -405,7 +703,7 @@ where
 
             if stop_index % 2 == 0 {
                 let key = ProofListKey::new(height, stop_index);
-                let branch_hash = HashTag::hash_single_node(&self.get_branch_unchecked(key));
+                let branch_hash = H::hash_list_single_branch(&self.get_branch_unchecked(key));
                 self.base.put(&key.parent(), branch_hash);
             }
 
@@ -454,10 +752,10 @@ where
                     let key = ProofListKey::new(height, last_index);
                     let hash = self.get_branch_unchecked(key);
                     let parent_hash = if key.is_left() {
-                        HashTag::hash_single_node(&hash)
+                        H::hash_list_single_branch(&hash)
                     } else {
                         let left_sibling = self.get_branch_unchecked(key.as_left());
-                        HashTag::hash_node(&left_sibling, &hash)
+                        H::hash_list_branch(&left_sibling, &hash)
                     };
                     self.base.put(&key.parent(), parent_hash);
                 }
@@ -670,6 +968,49 @@ where
     }
 }
 
+impl<T, V, H> ProofListIndex<T, V, H>
+where
+    T: RawAccessMut,
+    V: BinaryValue,
+    H: NodeHasher,
+{
+    /// Appends elements from an iterator to the back of the list and returns proofs
+    /// of existence for each appended element against the updated list hash.
+    ///
+    /// Proofs are returned together with the indexes of the elements in the order
+    /// of appending. Since proofs for adjacent elements share most of their hashes,
+    /// each node of the Merkle tree is read once for all proofs; thus, this method is
+    /// more efficient than calling [`get_proof`] for each appended element.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ObjectHash, ProofListIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_list("name");
+    /// index.push(1_u32);
+    ///
+    /// let proofs = index.append_batch_with_proofs(vec![2, 3]);
+    /// assert!(proofs.iter().map(|(i, _)| *i).eq(vec![1, 2]));
+    /// for (i, proof) in proofs {
+    ///     let checked_proof = proof.check_against_hash(index.object_hash()).unwrap();
+    ///     assert_eq!(checked_proof.entries(), [(i, index.get(i).unwrap())]);
+    /// }
+    /// ```
+    ///
+    /// [`get_proof`]: #method.get_proof
+    pub fn append_batch_with_proofs<I>(&mut self, values: I) -> Vec<(u64, GenericListProof<V, H>)>
+    where
+        I: IntoIterator<Item = V>,
+    {
+        let old_len = self.len();
+        self.extend(values);
+        self.create_element_proofs(old_len..self.len())
+    }
+}
+
 /// `object_hash` for a list depends on all list items. It explicitly commits to the list length
 /// in order to be able to more easily prove absence of elements and to prevent second pre-image
 /// attacks.
@@ -711,7 +1052,8 @@ where
 /// ```
 ///
 /// where `left_hash` is the hash of the left child and `right_hash` is the optional hash
-/// of the right child, which may be absent if the tree is not balanced.
+/// of the right child, which may be absent if the tree is not balanced. This holds for
+/// the [`DefaultNodeHasher`]; other node hashers define their own hashing of branch nodes.
 ///
 /// For leaves, the hash is
 ///
@@ -736,20 +1078,24 @@ where
 /// let hash = index.object_hash();
 /// assert_ne!(hash, default_hash);
 /// ```
-impl<T, V> ObjectHash for ProofListIndex<T, V>
+///
+/// [`DefaultNodeHasher`]: ../../struct.DefaultNodeHasher.html
+impl<T, V, H> ObjectHash for ProofListIndex<T, V, H>
 where
     T: RawAccess,
     V: BinaryValue,
+    H: NodeHasher,
 {
     fn object_hash(&self) -> Hash {
         HashTag::hash_list_node(self.len(), self.merkle_root())
     }
 }
 
-impl<'a, T, V> IntoIterator for &'a ProofListIndex<T, V>
+impl<'a, T, V, H> IntoIterator for &'a ProofListIndex<T, V, H>
 where
     T: RawAccess,
     V: BinaryValue,
+    H: NodeHasher,
 {
     type Item = V;
     type IntoIter = Values<'a, V>;
@@ -759,10 +1105,11 @@ where
     }
 }
 
-impl<T, V> IndexIterator for ProofListIndex<T, V>
+impl<T, V, H> IndexIterator for ProofListIndex<T, V, H>
 where
     T: RawAccess,
     V: BinaryValue,
+    H: NodeHasher,
 {
     type Key = u64;
     type Value = V;
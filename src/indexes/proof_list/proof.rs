@@ -17,15 +17,24 @@ pub use crate::ValidationError;
 use serde_derive::{Deserialize, Serialize};
 use thiserror::Error;
 
-use std::cmp::Ordering;
+use std::{cmp::Ordering, fmt, marker::PhantomData};
 
 use super::{
     key::{ProofListKey, MAX_INDEX},
     tree_height_by_length,
 };
-use crate::{crypto::Hash, BinaryValue, HashTag};
+use crate::{
+    crypto::Hash,
+    hash::{DefaultNodeHasher, NodeHasher},
+    BinaryValue, HashTag,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
 pub struct HashedEntry {
     #[serde(flatten)]
     key: ProofListKey,
@@ -117,16 +126,40 @@ impl HashedEntry {
 /// (e.g., ordering of `proof` / `entries`; see [`check()`]) only if these invariants are checked
 /// during proof verification.
 ///
+/// ## Node hasher
+///
+/// The `H` type parameter specifies the [`NodeHasher`] used to restore the hash
+/// of the list. It must match the hasher of the `ProofListIndex` the proof was built for;
+/// the hasher is not a part of the serialized proof. Proofs for lists with the default hasher
+/// are represented by the [`ListProof`] alias.
+///
 /// [`get_proof()`]: struct.ProofListIndex.html#method.get_proof
 /// [`get_range_proof()`]: struct.ProofListIndex.html#method.get_range_proof
 /// [`check()`]: #method.check
+/// [`NodeHasher`]: ../../trait.NodeHasher.html
+/// [`ListProof`]: type.ListProof.html
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
-pub struct ListProof<V> {
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub struct GenericListProof<V, H> {
     proof: Vec<HashedEntry>,
     entries: Vec<(u64, V)>,
     length: u64,
+    #[serde(skip)]
+    _hasher: PhantomData<H>,
 }
 
+/// Proof for elements of a `ProofListIndex` using the [`DefaultNodeHasher`].
+///
+/// See [`GenericListProof`] for the description and examples of usage.
+///
+/// [`DefaultNodeHasher`]: ../../struct.DefaultNodeHasher.html
+/// [`GenericListProof`]: struct.GenericListProof.html
+pub type ListProof<V> = GenericListProof<V, DefaultNodeHasher>;
+
 /// Merges two iterators with `HashedEntry`s so that the elements in the resulting iterator
 /// are ordered by increasing `HashedEntry.key`.
 ///
@@ -219,7 +252,10 @@ fn merge(
 /// # Examples
 ///
 /// See unit tests at the end of this file.
-fn hash_layer(layer: &mut Vec<HashedEntry>, last_index: u64) -> Result<(), ListProofError> {
+fn hash_layer<H: NodeHasher>(
+    layer: &mut Vec<HashedEntry>,
+    last_index: u64,
+) -> Result<(), ListProofError> {
     let new_len = (layer.len() + 1) / 2;
     for i in 0..new_len {
         let x = &layer[2 * i];
@@ -229,14 +265,14 @@ fn hash_layer(layer: &mut Vec<HashedEntry>, last_index: u64) -> Result<(), ListP
             if !x.key.is_left() || y.key.index() != x.key.index() + 1 {
                 return Err(ListProofError::MissingHash);
             }
-            HashedEntry::new(x.key.parent(), HashTag::hash_node(&x.hash, &y.hash))
+            HashedEntry::new(x.key.parent(), H::hash_list_branch(&x.hash, &y.hash))
         } else {
             // If there is an odd number of hashes on the layer, the solitary hash must have
             // the greatest possible index.
             if last_index % 2 == 1 || x.key.index() != last_index {
                 return Err(ListProofError::MissingHash);
             }
-            HashedEntry::new(x.key.parent(), HashTag::hash_single_node(&x.hash))
+            HashedEntry::new(x.key.parent(), H::hash_list_single_branch(&x.hash))
         };
     }
 
@@ -244,7 +280,7 @@ fn hash_layer(layer: &mut Vec<HashedEntry>, last_index: u64) -> Result<(), ListP
     Ok(())
 }
 
-impl<V: BinaryValue> ListProof<V> {
+impl<V: BinaryValue, H: NodeHasher> GenericListProof<V, H> {
     pub(super) fn new<I>(values: I, length: u64) -> Self
     where
         I: IntoIterator<Item = (u64, V)>,
@@ -253,6 +289,7 @@ impl<V: BinaryValue> ListProof<V> {
             entries: values.into_iter().collect(),
             proof: vec![],
             length,
+            _hasher: PhantomData,
         }
     }
 
@@ -272,6 +309,7 @@ impl<V: BinaryValue> ListProof<V> {
             entries: vec![],
             proof,
             length,
+            _hasher: PhantomData,
         }
     }
 
@@ -418,7 +456,7 @@ impl<V: BinaryValue> ListProof<V> {
                 .map_err(|_| ListProofError::RedundantHash)?;
 
             // Zip the current layer.
-            hash_layer(&mut layer, last_index)?;
+            hash_layer::<H>(&mut layer, last_index)?;
             last_index /= 2;
             hashes = remaining_hashes;
         }
@@ -548,6 +586,38 @@ impl<V: BinaryValue> ListProof<V> {
             })
     }
 
+    /// Verifies the correctness of the proof against a trusted list hash passed by reference.
+    ///
+    /// This is equivalent to [`check_against_hash()`], but is more convenient if the trusted
+    /// hash is a constant, e.g., a hash pinned at compile time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::{access::CopyAccessExt, crypto::Hash, Database, TemporaryDB};
+    /// // Hash of a `ProofListIndex` containing `1_u64, 2, 3`.
+    /// const TRUSTED_HASH: Hash = Hash::new([
+    ///     0x5f, 0x77, 0x2b, 0x30, 0xf8, 0x78, 0x37, 0xcf, 0x1d, 0x3c, 0x7c, 0x2f, 0xfa, 0x4d,
+    ///     0xd5, 0xbd, 0xcd, 0x3e, 0xdb, 0x36, 0xae, 0x82, 0x0c, 0x4a, 0x37, 0x73, 0x98, 0x7b,
+    ///     0x0c, 0xeb, 0xfa, 0xbc,
+    /// ]);
+    ///
+    /// let fork = TemporaryDB::new().fork();
+    /// let mut list = fork.get_proof_list("list");
+    /// list.extend(vec![1_u64, 2, 3]);
+    /// let proof = list.get_proof(1);
+    /// let checked_proof = proof.check_against(&TRUSTED_HASH).unwrap();
+    /// assert_eq!(checked_proof.entries(), &[(1, 2)]);
+    /// ```
+    ///
+    /// [`check_against_hash()`]: #method.check_against_hash
+    pub fn check_against(
+        &self,
+        trusted_list_hash: &Hash,
+    ) -> Result<CheckedListProof<'_, V>, ValidationError<ListProofError>> {
+        self.check_against_hash(*trusted_list_hash)
+    }
+
     /// Creates `ListProof` from `proof` and `entries` vectors. Used to construct proof
     /// after deserialization.
     pub fn from_raw_parts(proof: Vec<HashedEntry>, entries: Vec<(u64, V)>, length: u64) -> Self {
@@ -555,6 +625,7 @@ impl<V: BinaryValue> ListProof<V> {
             proof,
             entries,
             length,
+            _hasher: PhantomData,
         }
     }
 }
@@ -563,7 +634,7 @@ impl<V: BinaryValue> ListProof<V> {
 ///
 /// See [`ListProof`] for an example of usage.
 ///
-/// [`ListProof`]: struct.ListProof.html#workflow
+/// [`ListProof`]: struct.GenericListProof.html#workflow
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct CheckedListProof<'a, V> {
     entries: &'a [(u64, V)],
@@ -571,6 +642,44 @@ pub struct CheckedListProof<'a, V> {
     hash: Hash,
 }
 
+/// Summarizes the proof outcome for logging: the hash and the length of the list,
+/// and the indexes of proven elements.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofListIndex};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut list = fork.get_proof_list("list");
+/// list.extend(vec![1_u32, 2, 3, 4]);
+/// let proof = list.get_range_proof(1..3);
+/// let checked_proof = proof.check().unwrap();
+/// assert_eq!(
+///     checked_proof.to_string(),
+///     format!(
+///         "list proof (hash: {}, length: 4, entries: 2 in 1..=2)",
+///         checked_proof.index_hash(),
+///     ),
+/// );
+/// ```
+impl<V> fmt::Display for CheckedListProof<'_, V> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "list proof (hash: {}, length: {}, entries: {}",
+            self.hash,
+            self.length,
+            self.entries.len()
+        )?;
+        if let (Some((first, _)), Some((last, _))) = (self.entries.first(), self.entries.last()) {
+            write!(formatter, " in {}..={}", first, last)?;
+        }
+        formatter.write_str(")")
+    }
+}
+
 impl<'a, V> CheckedListProof<'a, V> {
     /// Returns indexes and references to elements in the proof.
     pub fn entries(&self) -> &'a [(u64, V)] {
@@ -592,6 +701,19 @@ impl<'a, V> CheckedListProof<'a, V> {
     pub fn index_hash(&self) -> Hash {
         self.hash
     }
+
+    /// Returns references to the elements at `index` and `index + 1` if both of them
+    /// are proven, or `None` otherwise.
+    pub fn adjacent_pair(&self, index: u64) -> Option<(&'a V, &'a V)> {
+        let next_index = index.checked_add(1)?;
+        let position = self.entries.iter().position(|(i, _)| *i == index)?;
+        match self.entries.get(position + 1) {
+            Some((i, next_value)) if *i == next_index => {
+                Some((&self.entries[position].1, next_value))
+            }
+            _ => None,
+        }
+    }
 }
 
 /// An error that is returned when the list proof is invalid.
@@ -639,7 +761,8 @@ pub enum ListProofError {
 #[cfg(test)]
 mod tests {
     use super::{
-        hash_layer, merge, BinaryValue, Hash, HashTag, HashedEntry, ListProof, ProofListKey,
+        hash_layer, merge, BinaryValue, DefaultNodeHasher, Hash, HashTag, HashedEntry, ListProof,
+        ProofListKey,
     };
     use crate::{access::CopyAccessExt, Database, TemporaryDB};
 
@@ -687,7 +810,7 @@ mod tests {
             entry(1, 7),
             entry(1, 8),
         ];
-        hash_layer(&mut layer, 8).unwrap();
+        hash_layer::<DefaultNodeHasher>(&mut layer, 8).unwrap();
         assert!(layer.iter().map(|entry| entry.key).eq(vec![
             ProofListKey::new(2, 0),
             ProofListKey::new(2, 3),
@@ -708,27 +831,27 @@ mod tests {
 
         // layer[0] has odd index
         let mut layer = vec![entry(1, 1), entry(1, 2)];
-        assert!(hash_layer(&mut layer, 2).is_err());
+        assert!(hash_layer::<DefaultNodeHasher>(&mut layer, 2).is_err());
 
         // layer[1] is not adjacent to layer[0]
         let mut layer = vec![entry(1, 0), entry(1, 2)];
-        assert!(hash_layer(&mut layer, 3).is_err());
+        assert!(hash_layer::<DefaultNodeHasher>(&mut layer, 3).is_err());
         let mut layer = vec![entry(1, 0), entry(1, 3)];
-        assert!(hash_layer(&mut layer, 3).is_err());
+        assert!(hash_layer::<DefaultNodeHasher>(&mut layer, 3).is_err());
 
         // layer[-1] has odd index, while there is even number of elements in the layer
         let mut layer = vec![entry(1, 0), entry(1, 1), entry(1, 7)];
-        assert!(hash_layer(&mut layer, 7).is_err());
+        assert!(hash_layer::<DefaultNodeHasher>(&mut layer, 7).is_err());
 
         // layer[-1] has index lesser that the layer length
         let mut layer = vec![entry(1, 0), entry(1, 1), entry(1, 4)];
-        assert!(hash_layer(&mut layer, 6).is_err());
+        assert!(hash_layer::<DefaultNodeHasher>(&mut layer, 6).is_err());
     }
 
     #[test]
     fn hash_ops_examples() {
         // Empty proof.
-        let proof = ListProof::<u32>::empty(Hash::zero(), 15);
+        let proof = ListProof::empty(Hash::zero(), 15);
         assert_eq!(proof.hash_ops().unwrap(), 0);
 
         // Proof for a single-element tree.
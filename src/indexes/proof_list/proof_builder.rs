@@ -14,14 +14,22 @@
 
 //! Building `ListProof`s.
 
-use std::ops::{Bound, RangeBounds};
-
-use super::{key::ProofListKey, tree_height_by_length, ListProof};
-use crate::{crypto::Hash, BinaryValue};
+use std::{
+    cmp,
+    ops::{Bound, Range, RangeBounds},
+};
+
+use super::{key::ProofListKey, tree_height_by_length, GenericListProof};
+use crate::{
+    crypto::Hash,
+    hash::NodeHasher,
+    indexes::{ProofError, ProofLimits},
+    BinaryValue,
+};
 
 /// Encapsulation of a binary Merkle tree allowing to access its terminal and intermediate
-/// nodes.
-pub trait MerkleTree<V> {
+/// nodes. Branch nodes of the tree are hashed with the node hasher `H`.
+pub trait MerkleTree<V, H: NodeHasher> {
     /// Gets the length of the tree.
     fn len(&self) -> u64;
 
@@ -34,6 +42,10 @@ pub trait MerkleTree<V> {
     /// Iterates over values starting from the specified index.
     fn values<'s>(&'s self, start_index: u64) -> Box<dyn Iterator<Item = V> + 's>;
 
+    /// Iterates over values starting from the specified index, together with the sizes
+    /// of their serialized forms in bytes.
+    fn sized_values<'s>(&'s self, start_index: u64) -> Box<dyn Iterator<Item = (V, usize)> + 's>;
+
     /// Gets the Merkle root of the tree.
     fn merkle_root(&self) -> Hash {
         let tree_height = tree_height_by_length(self.len());
@@ -41,21 +53,38 @@ pub trait MerkleTree<V> {
     }
 }
 
-pub trait BuildProof<V> {
-    fn create_proof(&self, index: u64) -> ListProof<V>;
-    fn create_range_proof(&self, indexes: impl RangeBounds<u64>) -> ListProof<V>;
+pub trait BuildProof<V, H> {
+    fn create_proof(&self, index: u64) -> GenericListProof<V, H>;
+    fn create_range_proof(&self, indexes: impl RangeBounds<u64>) -> GenericListProof<V, H>;
+    fn create_range_proof_bounded(
+        &self,
+        indexes: impl RangeBounds<u64>,
+        limits: &ProofLimits,
+    ) -> Result<GenericListProof<V, H>, ProofError>;
+    fn create_element_proofs(&self, indexes: Range<u64>) -> Vec<(u64, GenericListProof<V, H>)>;
 }
 
-impl<V, T> BuildProof<V> for T
+impl<V, H, T> BuildProof<V, H> for T
 where
     V: BinaryValue,
-    T: MerkleTree<V>,
+    H: NodeHasher,
+    T: MerkleTree<V, H>,
 {
-    fn create_proof(&self, index: u64) -> ListProof<V> {
-        create_proof(self, index, index)
+    fn create_proof(&self, index: u64) -> GenericListProof<V, H> {
+        create_proof(self, index, index, &ProofLimits::default())
+            .expect("proof size is not limited")
+    }
+
+    fn create_range_proof(&self, indexes: impl RangeBounds<u64>) -> GenericListProof<V, H> {
+        self.create_range_proof_bounded(indexes, &ProofLimits::default())
+            .expect("proof size is not limited")
     }
 
-    fn create_range_proof(&self, indexes: impl RangeBounds<u64>) -> ListProof<V> {
+    fn create_range_proof_bounded(
+        &self,
+        indexes: impl RangeBounds<u64>,
+        limits: &ProofLimits,
+    ) -> Result<GenericListProof<V, H>, ProofError> {
         // Inclusive lower boundary of the proof range.
         let from = match indexes.start_bound() {
             Bound::Unbounded => 0_u64,
@@ -75,7 +104,7 @@ where
         if (from >= self.len() && indexes.end_bound() == Bound::Unbounded) || from == to {
             // We assume the first condition is a "legal" case of the caller not knowing
             // the list length, so we don't want to panic in the `to > from` assertion below.
-            return ListProof::empty(self.merkle_root(), self.len());
+            return Ok(GenericListProof::empty(self.merkle_root(), self.len()));
         }
         assert!(
             to > from,
@@ -83,26 +112,47 @@ where
             from,
             to
         );
-        create_proof(self, from, to - 1)
+        create_proof(self, from, to - 1, limits)
+    }
+
+    fn create_element_proofs(&self, indexes: Range<u64>) -> Vec<(u64, GenericListProof<V, H>)> {
+        let to = indexes.end.min(self.len());
+        if indexes.start >= to {
+            return vec![];
+        }
+        create_element_proofs(self, indexes.start, to - 1)
     }
 }
 
-/// Creates a `ListProof` for a contiguous closed range of indexes `[from, inclusive_to]`.
+/// Creates a `ListProof` for a contiguous closed range of indexes `[from, inclusive_to]`,
+/// or returns an error if the elements in the range exceed the `limits`. The number
+/// of elements is checked before reading them, and their total size is checked
+/// as they are read.
 ///
 /// The caller must ensure that `inclusive_to >= from`.
-fn create_proof<V: BinaryValue>(
-    tree: &impl MerkleTree<V>,
+fn create_proof<V: BinaryValue, H: NodeHasher>(
+    tree: &impl MerkleTree<V, H>,
     from: u64,
     inclusive_to: u64,
-) -> ListProof<V> {
+    limits: &ProofLimits,
+) -> Result<GenericListProof<V, H>, ProofError> {
     let tree_len = tree.len();
     let tree_height = tree_height_by_length(tree_len);
     if from >= tree_len {
-        return ListProof::empty(tree.merkle_root(), tree_len);
+        return Ok(GenericListProof::empty(tree.merkle_root(), tree_len));
     }
 
-    let items = (from..=inclusive_to).zip(tree.values(from));
-    let mut proof = ListProof::new(items, tree_len);
+    let entries = cmp::min(inclusive_to, tree_len - 1) - from + 1;
+    limits.check_entries(usize::try_from(entries).unwrap_or(usize::MAX))?;
+    let mut bytes = 0_usize;
+    let items = (from..=inclusive_to)
+        .zip(tree.sized_values(from))
+        .map(|(index, (value, size))| {
+            bytes = bytes.saturating_add(size);
+            limits.check_bytes(bytes).map(|()| (index, value))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut proof = GenericListProof::new(items, tree_len);
 
     // `left` and `right` track the indexes of elements for which we build the proof,
     // on the particular `height` of the tree. Both these values are inclusive; i.e., the range
@@ -134,5 +184,50 @@ fn create_proof<V: BinaryValue>(
         right /= 2;
         last_index_on_level /= 2;
     }
-    proof
+    Ok(proof)
+}
+
+/// Creates a separate `ListProof` for each element in the closed range `[from, inclusive_to]`.
+///
+/// Proofs for adjacent elements share most of their hashes, so the proofs are built
+/// simultaneously level by level, and each tree node is read at most once. Thus, the number
+/// of read nodes is `O(inclusive_to - from + height)` rather than
+/// `O((inclusive_to - from) * height)` for building the proofs one by one.
+///
+/// The caller must ensure that `from <= inclusive_to < tree.len()`.
+fn create_element_proofs<V: BinaryValue, H: NodeHasher>(
+    tree: &impl MerkleTree<V, H>,
+    from: u64,
+    inclusive_to: u64,
+) -> Vec<(u64, GenericListProof<V, H>)> {
+    let tree_len = tree.len();
+    let tree_height = tree_height_by_length(tree_len);
+    let mut proofs: Vec<_> = (from..=inclusive_to)
+        .zip(tree.values(from))
+        .map(|(index, value)| (index, GenericListProof::new(vec![(index, value)], tree_len)))
+        .collect();
+
+    let mut last_index_on_level = tree_len - 1;
+    for height in 1..tree_height {
+        // Siblings of adjacent elements are either the same or adjacent as well,
+        // so it is enough to remember the last read node.
+        let mut last_sibling: Option<(u64, Hash)> = None;
+        for (index, proof) in &mut proofs {
+            // Index of the node on the path from the element to the root on this `height`.
+            let node_index = *index >> (height - 1);
+            let sibling_index = node_index ^ 1;
+            if sibling_index > last_index_on_level {
+                continue;
+            }
+
+            let hash = match last_sibling {
+                Some((cached_index, hash)) if cached_index == sibling_index => hash,
+                _ => tree.node(ProofListKey::new(height, sibling_index)),
+            };
+            last_sibling = Some((sibling_index, hash));
+            proof.push_hash(height, sibling_index, hash);
+        }
+        last_index_on_level /= 2;
+    }
+    proofs
 }
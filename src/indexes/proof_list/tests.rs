@@ -16,12 +16,16 @@
 
 #![allow(clippy::too_many_lines)]
 
+use assert_matches::assert_matches;
 use rand::{thread_rng, Rng};
 use serde_json::{self, json};
 
 use std::cmp;
 
-use super::{key::ProofListKey, tree_height_by_length, ListProof, ListProofError, ProofListIndex};
+use super::{
+    key::ProofListKey, tree_height_by_length, IntegrityError, ListProof, ListProofError,
+    ProofListIndex, ValidationError,
+};
 use crate::{
     access::CopyAccessExt, crypto::Hash, BinaryValue, Database, HashTag, ObjectHash, TemporaryDB,
 };
@@ -1230,3 +1234,482 @@ mod root_hash {
         assert_object_hash_correct(&to_list_of_hashes(&[]));
     }
 }
+
+#[test]
+fn bounded_range_proofs() {
+    use crate::{ProofError, ProofLimit, ProofLimits};
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+    list.extend((0_u32..1_000).map(|i| vec![0_u8; i as usize % 10]));
+
+    let limits = ProofLimits::new(100, 200);
+    let proof = list.get_range_proof_bounded(0..40, &limits).unwrap();
+    let checked = proof.check_against_hash(list.object_hash()).unwrap();
+    assert_eq!(checked.entries().len(), 40);
+
+    let err = list.get_range_proof_bounded(0..101, &limits).unwrap_err();
+    let expected = ProofError::TooLarge {
+        limit: ProofLimit::Entries,
+        max: 100,
+    };
+    assert_eq!(err, expected);
+    // Huge ranges are clamped to the list length and rejected without reading elements.
+    let err = list
+        .get_range_proof_bounded(0..u64::max_value(), &limits)
+        .unwrap_err();
+    assert_eq!(err, expected);
+
+    // 50 elements of average size 4.5 bytes exceed 200 bytes.
+    let err = list.get_range_proof_bounded(0..50, &limits).unwrap_err();
+    assert_eq!(
+        err,
+        ProofError::TooLarge {
+            limit: ProofLimit::Bytes,
+            max: 200,
+        }
+    );
+
+    // Ranges outside the list produce empty proofs.
+    let proof = list.get_range_proof_bounded(2_000.., &limits).unwrap();
+    assert!(proof.entries_unchecked().is_empty());
+    let proof = list
+        .get_range_proof_bounded(.., &ProofLimits::default())
+        .unwrap();
+    assert_eq!(proof.entries_unchecked().len(), 1_000);
+}
+
+#[test]
+fn hash_of_in_memory_list_matches_index() {
+    let db = TemporaryDB::new();
+    let mut rng = thread_rng();
+
+    for &len in &[0_usize, 1, 2, 3, 5, 8, 13, 64, 100, 257] {
+        let fork = db.fork();
+        let mut list = fork.get_proof_list(IDX_NAME);
+        let values = random_values(&mut rng, len);
+        list.extend(values.iter().cloned());
+        assert_eq!(
+            HashTag::hash_list(&values),
+            list.object_hash(),
+            "len = {}",
+            len
+        );
+    }
+}
+
+#[test]
+fn frontier_appends_match_index() {
+    use super::Frontier;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+    let mut frontier = Frontier::new();
+    assert_eq!(frontier.object_hash(), list.object_hash());
+
+    for i in 0_u64..300 {
+        let (hash, new_frontier) = frontier.append(&i);
+        list.push(i);
+        assert_eq!(hash, list.object_hash(), "len = {}", list.len());
+        assert_eq!(new_frontier, list.frontier(), "len = {}", list.len());
+        frontier = new_frontier;
+    }
+
+    // The frontier can be persisted and restored.
+    let restored = Frontier::from_bytes(frontier.to_bytes().into()).unwrap();
+    assert_eq!(restored, frontier);
+    let (hash, _) = restored.append(&300_u64);
+    list.push(300);
+    assert_eq!(hash, list.object_hash());
+    assert!(Frontier::from_bytes(vec![3, 0, 0, 0, 0, 0, 0, 0].into()).is_err());
+}
+
+#[test]
+fn frontier_appends_match_index_with_custom_hasher() {
+    use super::GenericFrontier;
+    use crate::{access::FromAccess, Blake2bNodeHasher};
+
+    type Blake2bList<T> = ProofListIndex<T, u64, Blake2bNodeHasher>;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = Blake2bList::from_access(&fork, IDX_NAME.into()).unwrap();
+    let mut frontier = GenericFrontier::<Blake2bNodeHasher>::new();
+    assert_eq!(frontier.object_hash(), list.object_hash());
+
+    for i in 0_u64..300 {
+        let (hash, new_frontier) = frontier.append(&i);
+        list.push(i);
+        assert_eq!(hash, list.object_hash(), "len = {}", list.len());
+        assert_eq!(new_frontier, list.frontier(), "len = {}", list.len());
+        frontier = new_frontier;
+    }
+
+    // The frontier can be persisted and restored.
+    let restored =
+        GenericFrontier::<Blake2bNodeHasher>::from_bytes(frontier.to_bytes().into()).unwrap();
+    assert_eq!(restored, frontier);
+    let (hash, _) = restored.append(&300_u64);
+    list.push(300);
+    assert_eq!(hash, list.object_hash());
+
+    // A frontier with the default hasher leads to a different hash.
+    let default_frontier = Frontier::from_bytes(frontier.to_bytes().into()).unwrap();
+    assert_ne!(default_frontier.object_hash(), frontier.object_hash());
+}
+
+#[test]
+fn proofs_from_snapshot() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+    list.extend(0_u64..20);
+    let fork_proof = list.get_proof(5);
+    let fork_range_proof = list.get_range_proof(3..15);
+    drop(list);
+    db.merge(fork.into_patch()).unwrap();
+
+    // Proofs are built purely from the snapshot, without any fork involved.
+    let snapshot = db.snapshot();
+    let list = snapshot.get_proof_list::<_, u64>(IDX_NAME);
+    let list_hash = list.object_hash();
+
+    let proof = list.get_proof(5);
+    assert_eq!(proof, fork_proof);
+    let checked_proof = proof.check_against_hash(list_hash).unwrap();
+    assert_eq!(checked_proof.entries(), [(5, 5)]);
+
+    let proof = list.get_range_proof(3..15);
+    assert_eq!(proof, fork_range_proof);
+    let checked_proof = proof.check_against_hash(list_hash).unwrap();
+    assert_eq!(checked_proof.entries().len(), 12);
+
+    let proof = list.get_adjacency_proof(19);
+    let checked_proof = proof.check_against_hash(list_hash).unwrap();
+    assert_eq!(checked_proof.entries(), [(19, 19)]);
+    let proof = list.get_proof(100);
+    let checked_proof = proof.check_against_hash(list_hash).unwrap();
+    assert!(checked_proof.entries().is_empty());
+}
+
+#[test]
+fn integrity_check_detects_corrupted_nodes() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list::<_, u32>(IDX_NAME);
+    list.verify_integrity().unwrap();
+    list.extend(0..11);
+    list.verify_integrity().unwrap();
+
+    // Corrupt an intermediate node.
+    let key = ProofListKey::new(2, 3);
+    let original_hash: Hash = list.base.get(&key).unwrap();
+    list.base.put(&key, Hash::zero());
+    assert_matches!(
+        list.verify_integrity().unwrap_err(),
+        IntegrityError::HashMismatch {
+            height: 2,
+            index: 3
+        }
+    );
+    list.base.put(&key, original_hash);
+    list.verify_integrity().unwrap();
+
+    // Corrupt the root.
+    let root_key = ProofListKey::new(list.height(), 0);
+    list.base.put(&root_key, vec![1_u8, 2, 3]);
+    assert_matches!(
+        list.verify_integrity().unwrap_err(),
+        IntegrityError::MalformedNode {
+            height: 5,
+            index: 0
+        }
+    );
+    list.base.put(&root_key, original_hash);
+    assert_matches!(
+        list.verify_integrity().unwrap_err(),
+        IntegrityError::HashMismatch {
+            height: 5,
+            index: 0
+        }
+    );
+    list.set(0, 0);
+    list.verify_integrity().unwrap();
+
+    // Replace elements bypassing the tree updates.
+    list.base.put(&ProofListKey::leaf(7), 100_u32);
+    list.base.put(&ProofListKey::leaf(9), 100_u32);
+    assert_matches!(
+        list.verify_integrity().unwrap_err(),
+        IntegrityError::HashMismatch {
+            height: 1,
+            index: 7
+        }
+    );
+    list.base.put(&ProofListKey::leaf(7), vec![0_u8; 3]);
+    assert_matches!(
+        list.verify_integrity().unwrap_err(),
+        IntegrityError::UndecodableValue { index: 7, .. }
+    );
+    list.base.remove(&ProofListKey::leaf(7));
+    assert_matches!(
+        list.verify_integrity().unwrap_err(),
+        IntegrityError::MissingValue { index: 7 }
+    );
+}
+
+#[test]
+fn adjacency_proofs() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+    list.extend(0_u32..10);
+    let list_hash = list.object_hash();
+
+    for i in 0..9 {
+        let proof = list.get_adjacency_proof(i);
+        let checked_proof = proof.check_against_hash(list_hash).unwrap();
+        let expected = (i as u32, i as u32 + 1);
+        assert_eq!(
+            checked_proof.adjacent_pair(i),
+            Some((&expected.0, &expected.1))
+        );
+        // The proof does not prove adjacency for other indexes.
+        assert_eq!(checked_proof.adjacent_pair(i + 1), None);
+        assert_eq!(checked_proof.adjacent_pair(i.wrapping_sub(1)), None);
+    }
+
+    // The last element has no successor.
+    let proof = list.get_adjacency_proof(9);
+    let checked_proof = proof.check_against_hash(list_hash).unwrap();
+    assert_eq!(checked_proof.entries(), [(9, 9)]);
+    assert_eq!(checked_proof.adjacent_pair(9), None);
+
+    // A proof claiming non-adjacent elements to be adjacent fails verification.
+    let proof = list.get_adjacency_proof(2);
+    let forged_proof: ListProof<u64> =
+        ListProof::from_raw_parts(proof.proof_unchecked().to_vec(), vec![(2, 2), (4, 4)], 10);
+    assert!(forged_proof.check_against_hash(list_hash).is_err());
+    let forged_proof: ListProof<u64> =
+        ListProof::from_raw_parts(proof.proof_unchecked().to_vec(), vec![(2, 2), (3, 4)], 10);
+    assert!(forged_proof.check_against_hash(list_hash).is_err());
+}
+
+#[test]
+fn display_for_proof_outcomes() {
+    use crate::{ProofError, ProofLimit, ProofLimits};
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+    list.extend(0_u32..10);
+    let list_hash = list.object_hash();
+
+    let proof = list.get_range_proof(3..7);
+    let message = proof.check().unwrap().to_string();
+    assert!(message.contains(&list_hash.to_string()), "{}", message);
+    assert!(message.contains("length: 10"), "{}", message);
+    assert!(message.contains("entries: 4 in 3..=6"), "{}", message);
+
+    let proof = list.get_range_proof(20..);
+    let message = proof.check().unwrap().to_string();
+    assert!(message.ends_with("entries: 0)"), "{}", message);
+
+    let err = list
+        .get_range_proof_bounded(.., &ProofLimits::new(5, 1_000))
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ProofError::TooLarge {
+            limit: ProofLimit::Entries,
+            max: 5,
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "requested proof exceeds the limit of 5 entries"
+    );
+}
+
+#[test]
+fn custom_node_hasher() {
+    use crate::{
+        access::{AccessErrorKind, FromAccess},
+        Blake2bNodeHasher, SystemSchema,
+    };
+
+    type Blake2bList<T> = ProofListIndex<T, u32, Blake2bNodeHasher>;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut default_list = fork.get_proof_list(IDX_NAME);
+    default_list.extend(0_u32..10);
+    let mut list = Blake2bList::from_access(&fork, "blake2b".into()).unwrap();
+    list.extend(0_u32..10);
+
+    assert_eq!(list.iter().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+    assert_ne!(list.object_hash(), default_list.object_hash());
+    assert_ne!(
+        list.object_hash(),
+        HashTag::hash_list(&(0_u32..10).collect::<Vec<_>>())
+    );
+    assert!(list.verify_integrity().is_ok());
+
+    // The root hash does not depend on the way the list is built.
+    let mut other_list = Blake2bList::from_access(&fork, "other".into()).unwrap();
+    other_list.extend(vec![0_u32, 0, 0]);
+    other_list.extend(3_u32..12);
+    other_list.set(1, 1);
+    other_list.set(2, 2);
+    other_list.truncate(10);
+    assert_eq!(other_list.object_hash(), list.object_hash());
+    let hash = list.object_hash();
+    list.push(10);
+    assert_ne!(list.object_hash(), hash);
+    list.pop();
+    assert_eq!(list.object_hash(), hash);
+
+    // Proofs are built and checked with the custom hasher.
+    let proof = list.get_range_proof(2..5);
+    let checked_proof = proof.check_against_hash(hash).unwrap();
+    assert_eq!(*checked_proof.entries(), [(2, 2), (3, 3), (4, 4)]);
+    // The proof cannot be checked with another hasher.
+    let proof: ListProof<u32> = ListProof::from_raw_parts(
+        proof.proof_unchecked().to_vec(),
+        proof.entries_unchecked().to_vec(),
+        proof.list_len(),
+    );
+    assert!(proof.check_against_hash(hash).is_err());
+
+    // Lists with custom hashers are aggregated with their own hashes.
+    drop((default_list, list, other_list));
+    db.merge(fork.into_patch()).unwrap();
+    let snapshot = db.snapshot();
+    let list = Blake2bList::from_access(snapshot.as_ref(), "blake2b".into()).unwrap();
+    let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+    assert_eq!(aggregator.get("blake2b"), Some(list.object_hash()));
+
+    // The list cannot be opened with another hasher.
+    let err =
+        ProofListIndex::<_, u32>::from_access(snapshot.as_ref(), "blake2b".into()).unwrap_err();
+    assert_matches!(
+        err.kind,
+        AccessErrorKind::WrongNodeHasher {
+            expected: 0,
+            actual: 1
+        }
+    );
+}
+
+#[test]
+fn append_batch_with_proofs() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+    assert!(list.append_batch_with_proofs(vec![]).is_empty());
+
+    let mut len = 0_u32;
+    for batch_len in [1_u32, 1, 2, 5, 8, 3, 17, 64, 0, 10] {
+        let values = (len..len + batch_len).map(|i| i * 3);
+        let proofs = list.append_batch_with_proofs(values);
+        let list_hash = list.object_hash();
+        assert_eq!(proofs.len(), batch_len as usize);
+
+        for (i, (index, proof)) in (u64::from(len)..).zip(proofs) {
+            assert_eq!(index, i);
+            assert_eq!(proof, list.get_proof(index));
+            let checked_proof = proof.check_against_hash(list_hash).unwrap();
+            assert_eq!(checked_proof.entries(), [(index, list.get(index).unwrap())]);
+        }
+        len += batch_len;
+    }
+    assert_eq!(list.len(), u64::from(len));
+}
+
+#[test]
+fn check_against_const_hash() {
+    // Hash of a list containing `1_u64, 2, 3`, pinned at compile time.
+    const TRUSTED_HASH: Hash = Hash::new([
+        0x5f, 0x77, 0x2b, 0x30, 0xf8, 0x78, 0x37, 0xcf, 0x1d, 0x3c, 0x7c, 0x2f, 0xfa, 0x4d, 0xd5,
+        0xbd, 0xcd, 0x3e, 0xdb, 0x36, 0xae, 0x82, 0x0c, 0x4a, 0x37, 0x73, 0x98, 0x7b, 0x0c, 0xeb,
+        0xfa, 0xbc,
+    ]);
+    const ZERO_HASH: Hash = Hash::zero();
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+    list.extend(vec![1_u64, 2, 3]);
+    assert_eq!(list.object_hash(), TRUSTED_HASH);
+
+    for index in 0..3 {
+        let proof = list.get_proof(index);
+        let checked_proof = proof.check_against(&TRUSTED_HASH).unwrap();
+        assert_eq!(checked_proof.entries(), [(index, index + 1)]);
+        assert_matches!(
+            proof.check_against(&ZERO_HASH).unwrap_err(),
+            ValidationError::UnmatchedRootHash
+        );
+    }
+    let proof = list.get_range_proof(..);
+    assert_eq!(
+        proof.check_against(&TRUSTED_HASH).unwrap().entries().len(),
+        3
+    );
+}
+
+#[test]
+fn head_proof() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list(IDX_NAME);
+
+    let (len, proof) = list.get_head_proof();
+    assert_eq!(len, 0);
+    let checked_proof = proof.check_against_hash(list.object_hash()).unwrap();
+    assert!(checked_proof.entries().is_empty());
+    assert_eq!(checked_proof.list_len(), 0);
+
+    let mut rng = thread_rng();
+    for _ in 0..50 {
+        let batch_len = rng.gen_range(1..10);
+        let values = (0..batch_len).map(|_| rng.gen::<u64>());
+        list.extend(values);
+
+        let (len, proof) = list.get_head_proof();
+        assert_eq!(len, list.len());
+        let checked_proof = proof.check_against_hash(list.object_hash()).unwrap();
+        assert_eq!(checked_proof.list_len(), len);
+        assert_eq!(checked_proof.entries(), [(len - 1, list.last().unwrap())]);
+        assert_eq!(proof, list.get_proof(len - 1));
+    }
+}
+
+#[cfg(feature = "rkyv")]
+#[test]
+fn archived_proof_is_validated() {
+    use rkyv::Deserialize;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut list = fork.get_proof_list::<_, u64>(IDX_NAME);
+    list.extend(0_u64..20);
+    let proof = list.get_range_proof(3..8);
+    let bytes = rkyv::to_bytes::<_, 1_024>(&proof).unwrap();
+
+    let archived = rkyv::check_archived_root::<ListProof<u64>>(&bytes).unwrap();
+    let restored: ListProof<u64> = archived.deserialize(&mut rkyv::Infallible).unwrap();
+    assert_eq!(restored, proof);
+    let checked_proof = restored.check_against_hash(list.object_hash()).unwrap();
+    assert_eq!(checked_proof.list_len(), 20);
+    assert_eq!(
+        checked_proof.entries(),
+        (3_u64..8).map(|i| (i, i)).collect::<Vec<_>>().as_slice()
+    );
+
+    // Truncated data is rejected instead of being accessed out of bounds.
+    let truncated = &bytes[..bytes.len() - 4];
+    assert!(rkyv::check_archived_root::<ListProof<u64>>(truncated).is_err());
+}
@@ -165,6 +165,11 @@ impl ops::Not for ChildKind {
 ///
 /// [`PROOF_MAP_KEY_SIZE`]: constant.PROOF_MAP_KEY_SIZE.html
 #[derive(Copy, Clone)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
 pub struct ProofPath {
     bytes: [u8; PROOF_PATH_SIZE],
     start: u16,
@@ -194,6 +199,34 @@ impl ProofPath {
         Self::from_inner(inner)
     }
 
+    /// Constructs the `ProofPath` covering all keys starting with the specified bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix is longer than the `KEY_SIZE`.
+    pub(crate) fn from_key_prefix(prefix: &[u8]) -> Self {
+        assert!(
+            prefix.len() <= KEY_SIZE,
+            "Key prefix is longer than the key ({} > {} bytes)",
+            prefix.len(),
+            KEY_SIZE
+        );
+        let mut bytes = [0; KEY_SIZE];
+        bytes[..prefix.len()].copy_from_slice(prefix);
+        let path = Self::from_bytes(bytes);
+        if prefix.len() == KEY_SIZE {
+            path
+        } else {
+            path.prefix(prefix.len() as u16 * 8)
+        }
+    }
+
+    /// Checks whether this path and the `other` one have common descendants, i.e.,
+    /// one of the paths is a prefix of the other.
+    pub(crate) fn overlaps(&self, other: &Self) -> bool {
+        self.starts_with(other) || other.starts_with(self)
+    }
+
     /// Constructs the `ProofPath` from the inner buffer.
     fn from_inner(bytes: [u8; PROOF_PATH_SIZE]) -> Self {
         debug_assert!(
@@ -925,6 +958,21 @@ mod tests {
         assert!(!b.is_leaf());
     }
 
+    #[test]
+    fn test_proof_path_from_key_prefix() {
+        let key = [0xab; KEY_SIZE];
+        let path = ProofPath::from_bytes(key);
+
+        let prefix = ProofPath::from_key_prefix(&[0xab, 0xab]);
+        assert_eq!(prefix.len(), 16);
+        assert!(path.starts_with(&prefix));
+        assert!(path.overlaps(&prefix) && prefix.overlaps(&path));
+        assert!(!ProofPath::from_key_prefix(&[0xab, 0xaa]).overlaps(&path));
+
+        assert!(ProofPath::from_key_prefix(&[]).is_empty());
+        assert_eq!(ProofPath::from_key_prefix(&key), path);
+    }
+
     #[test]
     fn test_proof_path_debug_leaf() {
         use std::fmt::Write;
@@ -14,23 +14,39 @@
 
 //! An implementation of a Merkelized version of a map (Merkle Patricia tree).
 
-pub(crate) use self::key::{BitsRange, ProofPath};
+pub(crate) use self::{
+    key::{BitsRange, ProofPath},
+    proof::merkle_root_of_leaves,
+};
 pub use self::{
     key::{Hashed, Raw, RawKey, ToProofPath, KEY_SIZE as PROOF_MAP_KEY_SIZE, PROOF_PATH_SIZE},
-    proof::{CheckedMapProof, MapProof, MapProofError, ValidationError},
+    proof::{CheckedMapProof, GenericMapProof, MapProof, MapProofError, ValidationError, Verified},
+    size_proof::SizeProof,
+    union_proof::{CheckedUnionProof, OverlappingKeysError, UnionProof},
 };
 
-use std::{fmt, io, marker::PhantomData};
+use std::{
+    borrow::{Borrow, Cow},
+    cell::RefCell,
+    cmp::Ordering,
+    fmt, io,
+    marker::PhantomData,
+};
 
 use self::{
-    key::{ChildKind, VALUE_KEY_PREFIX},
+    key::{ChildKind, BRANCH_KEY_PREFIX, LEAF_KEY_PREFIX, VALUE_KEY_PREFIX},
     node::{BranchNode, Node},
+    node_cache::NodeCache,
     proof_builder::{BuildProof, MerklePatriciaTree},
 };
 use crate::{
     access::{Access, AccessError, FromAccess},
-    crypto::Hash,
-    indexes::iter::{Entries, IndexIterator, Keys, Values},
+    crypto::{Hash, HASH_SIZE},
+    hash::{DefaultNodeHasher, NodeHasher},
+    indexes::{
+        iter::{Entries, IndexIterator, Keys, Values},
+        IndexOptions, ProofError, ProofLimits, SizeError,
+    },
     views::{
         BinaryAttribute, IndexAddress, IndexState, IndexType, RawAccess, RawAccessMut, View,
         ViewWithMetadata,
@@ -40,18 +56,22 @@ use crate::{
 
 mod key;
 mod node;
+mod node_cache;
 mod proof;
 mod proof_builder;
+mod size_proof;
 #[cfg(test)]
 mod tests;
+mod union_proof;
 
 // Necessary to allow building proofs.
-impl<T, K, V, KeyMode> MerklePatriciaTree<K, V> for ProofMapIndex<T, K, V, KeyMode>
+impl<T, K, V, KeyMode, H> MerklePatriciaTree<K, V, H> for ProofMapIndex<T, K, V, KeyMode, H>
 where
     T: RawAccess,
     K: BinaryKey + ToOwned + ?Sized,
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     fn root_node(&self) -> Option<(ProofPath, Node)> {
         self.get_root_node()
@@ -64,6 +84,17 @@ where
     fn value(&self, key: &K) -> V {
         self.get_value_unchecked(key)
     }
+
+    fn sized_value(&self, key: &K) -> (V, usize) {
+        // The value is read in the serialized form to learn its size without re-encoding it.
+        let bytes: Vec<u8> = self
+            .base
+            .get(&key.to_value_path())
+            .expect("Value for the given key is absent");
+        let size = bytes.len();
+        let value = V::from_bytes(Cow::Owned(bytes)).expect("Error while deserializing value");
+        (value, size)
+    }
 }
 
 /// A Merkelized version of a map that provides proofs of existence or non-existence for the map
@@ -73,14 +104,34 @@ where
 /// `ProofMapIndex` requires that keys implement the [`BinaryKey`] trait and
 /// values implement the [`BinaryValue`] trait.
 ///
+/// # Node hashing
+///
+/// The `H` type parameter specifies the [`NodeHasher`] used to hash branches of the Merkle
+/// Patricia tree; by default, it is [`DefaultNodeHasher`]. The hasher is recorded in the index
+/// metadata when the map is created, and opening the map with another hasher fails with
+/// [`AccessErrorKind::WrongNodeHasher`]. Proofs built for the map are parameterized
+/// with the same hasher.
+///
 /// [`BinaryKey`]: ../../trait.BinaryKey.html
 /// [`BinaryValue`]: ../../trait.BinaryValue.html
-pub struct ProofMapIndex<T: RawAccess, K: ?Sized, V, KeyMode: ToProofPath<K> = Hashed> {
+/// [`NodeHasher`]: ../../trait.NodeHasher.html
+/// [`DefaultNodeHasher`]: ../../struct.DefaultNodeHasher.html
+/// [`AccessErrorKind::WrongNodeHasher`]: ../../access/enum.AccessErrorKind.html#variant.WrongNodeHasher
+pub struct ProofMapIndex<
+    T: RawAccess,
+    K: ?Sized,
+    V,
+    KeyMode: ToProofPath<K> = Hashed,
+    H: NodeHasher = DefaultNodeHasher,
+> {
     base: View<T>,
-    state: IndexState<T, ProofPath>,
+    state: IndexState<T, MapState>,
+    options: IndexOptions,
+    node_cache: RefCell<NodeCache>,
     _k: PhantomData<K>,
     _v: PhantomData<V>,
     _key_mode: PhantomData<KeyMode>,
+    _hasher: PhantomData<H>,
 }
 
 /// TODO Clarify documentation. [ECR-2820]
@@ -109,41 +160,113 @@ impl<T: BinaryKey + ?Sized> ValuePath for T {
     }
 }
 
-impl BinaryAttribute for ProofPath {
+/// Persistent state of a non-empty `ProofMapIndex`: the path to the root node
+/// of the Merkle Patricia tree and, unless the state is legacy, the number of entries
+/// in the map and the root hash of the tree.
+///
+/// The root hash is stored so that the hash of the map can be obtained without knowing
+/// its node hasher, e.g., by the state aggregator.
+///
+/// # Format
+///
+/// The state is serialized as the format version byte (`1`), the root path,
+/// the number of entries as `u64` in little-endian and the root hash. Maps created
+/// with older versions of the library have a legacy state consisting of the root path only
+/// (which is distinguished by its length). Such a state is read as is; the number of entries
+/// and the root hash are then restored from the tree, and the state is rewritten
+/// in the current format on the next modification of the map. Since node hashers could not
+/// be specified in older versions, legacy maps always use the default hasher.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MapState {
+    root_path: ProofPath,
+    /// Number of entries in the map and the root hash of the tree, or `None` for a legacy state.
+    summary: Option<(u64, Hash)>,
+}
+
+impl MapState {
+    /// Current version of the state format.
+    const VERSION: u8 = 1;
+    /// Size of the serialized state in the current format.
+    const SIZE: usize = 1 + PROOF_PATH_SIZE + 8 + HASH_SIZE;
+}
+
+impl BinaryAttribute for MapState {
     fn size(&self) -> usize {
-        PROOF_PATH_SIZE
+        if self.summary.is_some() {
+            Self::SIZE
+        } else {
+            PROOF_PATH_SIZE
+        }
     }
 
     fn write(&self, buffer: &mut Vec<u8>) {
         let mut tmp = [0_u8; PROOF_PATH_SIZE];
-        BinaryKey::write(self, &mut tmp);
-        buffer.extend_from_slice(&tmp[..]);
+        BinaryKey::write(&self.root_path, &mut tmp);
+        if let Some((size, root_hash)) = self.summary {
+            buffer.push(Self::VERSION);
+            buffer.extend_from_slice(&tmp[..]);
+            buffer.extend_from_slice(&size.to_le_bytes());
+            buffer.extend_from_slice(root_hash.as_ref());
+        } else {
+            buffer.extend_from_slice(&tmp[..]);
+        }
     }
 
     fn read(buffer: &[u8]) -> Result<Self, io::Error> {
-        if buffer.len() != PROOF_PATH_SIZE {
+        if buffer.len() == PROOF_PATH_SIZE {
+            return Ok(Self {
+                root_path: <ProofPath as BinaryKey>::read(buffer),
+                summary: None,
+            });
+        }
+
+        if buffer.len() != Self::SIZE || buffer[0] != Self::VERSION {
             return Err(io::Error::new(
                 io::ErrorKind::Other,
-                "Invalid `ProofPath` size",
+                "Invalid `ProofMapIndex` state",
             ));
         }
-        Ok(<Self as BinaryKey>::read(buffer))
+        let (path_bytes, rest) = buffer[1..].split_at(PROOF_PATH_SIZE);
+        let (size_bytes, hash_bytes) = rest.split_at(8);
+        let mut size = [0_u8; 8];
+        size.copy_from_slice(size_bytes);
+        Ok(Self {
+            root_path: <ProofPath as BinaryKey>::read(path_bytes),
+            summary: Some((
+                u64::from_le_bytes(size),
+                Hash::from_slice(hash_bytes).unwrap(),
+            )),
+        })
     }
 }
 
-impl<T, K, V, KeyMode> FromAccess<T> for ProofMapIndex<T::Base, K, V, KeyMode>
+impl<T, K, V, KeyMode, H> FromAccess<T> for ProofMapIndex<T::Base, K, V, KeyMode, H>
 where
     T: Access,
     K: BinaryKey + ?Sized,
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     fn from_access(access: T, addr: IndexAddress) -> Result<Self, AccessError> {
-        let view = access.get_or_create_view(addr, IndexType::ProofMap)?;
+        let mut view = access.get_or_create_view(addr.clone(), IndexType::ProofMap)?;
+        view.bind_node_hasher(H::ID)
+            .map_err(|kind| AccessError { addr, kind })?;
         Ok(Self::new(view))
     }
 }
 
+/// Computes the root hash of a map with a single entry in the same way as
+/// `HashTag::hash_single_entry_map`, but with the specified node hasher.
+fn hash_single_entry_map<H: NodeHasher>(path: &ProofPath, child_hash: &Hash) -> Hash {
+    // `HASH_SIZE` bytes are necessary for `path` bytes, and 2 additional bytes
+    // for the `LEB128` encoding of bit length (`HASH_SIZE * 8`).
+    let mut bytes = [0; 2 * HASH_SIZE + 2];
+    path.write_compressed(&mut bytes[..HASH_SIZE + 2]);
+    bytes[HASH_SIZE + 2..].copy_from_slice(child_hash.as_ref());
+    H::hash_map_branch(&bytes)
+}
+
 /// Raw variant of the `ProofMapIndex`, useful for keys that mapped directly to
 /// `ProofPath` without hashing. For example `Hash` and `PublicKey`.
 ///
@@ -151,26 +274,30 @@ where
 /// as a key for this map.
 pub type RawProofMapIndex<T, K, V> = ProofMapIndex<T, K, V, Raw>;
 
-impl<T, K, V, KeyMode> ProofMapIndex<T, K, V, KeyMode>
+impl<T, K, V, KeyMode, H> ProofMapIndex<T, K, V, KeyMode, H>
 where
     T: RawAccess,
     K: BinaryKey + ?Sized,
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     pub(crate) fn new(view: ViewWithMetadata<T>) -> Self {
         let (base, state) = view.into_parts();
         Self {
             base,
             state,
+            options: IndexOptions::default(),
+            node_cache: RefCell::default(),
             _k: PhantomData,
             _v: PhantomData,
             _key_mode: PhantomData,
+            _hasher: PhantomData,
         }
     }
 
     fn get_root_path(&self) -> Option<ProofPath> {
-        self.state.get()
+        self.state.get().map(|state| state.root_path)
     }
 
     fn get_root_node(&self) -> Option<(ProofPath, Node)> {
@@ -183,10 +310,20 @@ where
     fn get_node_unchecked(&self, key: &ProofPath) -> Node {
         // TODO: Unwraps? (ECR-84)
         if key.is_leaf() {
-            Node::Leaf(self.base.get(key).unwrap())
-        } else {
-            Node::Branch(self.base.get(key).unwrap())
+            return Node::Leaf(self.base.get(key).unwrap());
         }
+
+        if self.node_cache.borrow().capacity() == 0 {
+            // The cache is disabled, so the node is read without cloning it into the cache.
+            return Node::Branch(self.base.get(key).unwrap());
+        }
+        let mut cache = self.node_cache.borrow_mut();
+        if let Some(branch) = cache.get(key) {
+            return Node::Branch(branch);
+        }
+        let branch: BranchNode = self.base.get(key).unwrap();
+        cache.insert(key, branch.clone());
+        Node::Branch(branch)
     }
 
     fn get_value_unchecked(&self, key: &K) -> V {
@@ -194,13 +331,86 @@ where
     }
 
     pub(crate) fn merkle_root(&self) -> Hash {
-        match self.get_root_node() {
-            Some((path, Node::Leaf(hash))) => HashTag::hash_single_entry_map(&path, &hash),
-            Some((_, Node::Branch(branch))) => branch.object_hash(),
+        match self.state.get() {
             None => Hash::zero(),
+            Some(MapState {
+                summary: Some((_, root_hash)),
+                ..
+            }) => root_hash,
+            Some(MapState { root_path, .. }) => self.hash_root_node(&root_path),
+        }
+    }
+
+    fn hash_root_node(&self, root_path: &ProofPath) -> Hash {
+        match self.get_node_unchecked(root_path) {
+            Node::Leaf(hash) => hash_single_entry_map::<H>(root_path, &hash),
+            Node::Branch(branch) => branch.hash_with::<H>(),
         }
     }
 
+    /// Sets limits on the size of entries inserted into this index instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, IndexOptions, TemporaryDB, Database, ProofMapIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index: ProofMapIndex<_, str, String> = fork
+    ///     .get_proof_map("name")
+    ///     .with_options(IndexOptions::new(8, 1_024));
+    /// assert!(index.try_put("key", "value".to_owned()).is_ok());
+    /// assert!(index.try_put("long key", "value".to_owned()).is_ok());
+    /// assert!(index.try_put("longer key", "value".to_owned()).is_err());
+    /// ```
+    #[must_use]
+    pub fn with_options(mut self, options: IndexOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the limits on the size of entries inserted into this index instance.
+    pub fn options(&self) -> &IndexOptions {
+        &self.options
+    }
+
+    /// Sets the maximum number of branch nodes cached in memory by this index instance.
+    ///
+    /// Caching branch nodes speeds up building proofs and updating the map, since nodes
+    /// close to the root are read on each operation. When the cache is full, the least recently
+    /// used nodes are evicted. Eviction only means that the evicted nodes will be read
+    /// from the storage again; it never affects hashes or proofs produced by the index.
+    ///
+    /// The cache is empty and disabled (has zero capacity) by default. The cache belongs
+    /// to the index instance and is dropped together with it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ObjectHash, ProofMapIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_map("name");
+    /// index.set_node_cache_capacity(16);
+    /// for i in 0_u32..100 {
+    ///     index.put(&i, i.to_string());
+    /// }
+    ///
+    /// let proof = index.get_proof(5);
+    /// let checked_proof = proof.check_against_hash(index.object_hash()).unwrap();
+    /// assert_eq!(checked_proof.entries().next(), Some((&5, &"5".to_owned())));
+    /// ```
+    pub fn set_node_cache_capacity(&mut self, capacity: usize) {
+        self.node_cache.get_mut().set_capacity(capacity);
+    }
+
+    /// Returns the maximum number of branch nodes cached in memory by this index instance.
+    pub fn node_cache_capacity(&self) -> usize {
+        self.node_cache.borrow().capacity()
+    }
+
     /// Returns a value corresponding to the key.
     ///
     /// # Examples
@@ -224,6 +434,9 @@ where
 
     /// Returns `true` if the map contains a value for the specified key.
     ///
+    /// The value itself is not read; only the presence of the corresponding leaf
+    /// of the Merkle Patricia tree is checked.
+    ///
     /// # Examples
     ///
     /// ```
@@ -240,44 +453,67 @@ where
     /// assert!(index.contains(&hash));
     /// ```
     pub fn contains(&self, key: &K) -> bool {
-        self.base.contains(&key.to_value_path())
+        // The leaf of the tree holds only the value hash, so checking it is cheaper
+        // than checking the value itself, which may be large.
+        self.base.contains(&KeyMode::transform_key(key))
     }
 
-    /// Returns the proof of existence or non-existence for the specified key.
+    /// Returns the number of entries in the map.
+    ///
+    /// The number of entries is maintained by the map, so this operation is cheap.
+    /// For maps created with an older version of the library, the entries are counted
+    /// on each call until the map is modified.
     ///
     /// # Examples
     ///
     /// ```
-    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofMapIndex, crypto::Hash};
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofMapIndex};
     ///
     /// let db = TemporaryDB::new();
     /// let fork = db.fork();
-    /// let index = fork.get_proof_map::<_, Hash, u8>("name");
+    /// let mut index = fork.get_proof_map("name");
+    /// assert_eq!(index.size(), 0);
     ///
-    /// let proof = index.get_proof(Hash::default());
+    /// index.put(&1_u8, 2_u8);
+    /// index.put(&1_u8, 3_u8);
+    /// index.put(&2_u8, 3_u8);
+    /// assert_eq!(index.size(), 2);
     /// ```
-    pub fn get_proof(&self, key: K::Owned) -> MapProof<K::Owned, V, KeyMode> {
-        self.create_proof(key)
+    pub fn size(&self) -> u64 {
+        match self.state.get() {
+            None => 0,
+            Some(MapState {
+                summary: Some((size, _)),
+                ..
+            }) => size,
+            Some(_) => self.keys().count() as u64,
+        }
     }
 
-    /// Returns the combined proof of existence or non-existence for the multiple specified keys.
+    /// Returns the proof of the number of entries in the map.
+    ///
+    /// The proof is checked against the *sized hash* of the map, which commits both to
+    /// the number of entries and to the map contents. See [`SizeProof`] for details.
     ///
     /// # Examples
     ///
     /// ```
-    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofMapIndex};
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ObjectHash};
     ///
     /// let db = TemporaryDB::new();
     /// let fork = db.fork();
-    /// let index = fork.get_proof_map::<_, String, u8>("name");
+    /// let mut index = fork.get_proof_map("name");
+    /// index.put(&1_u8, 2_u8);
     ///
-    /// let proof = index.get_multiproof(vec!["foo".to_owned(), "bar".to_owned()]);
+    /// let proof = index.get_size_proof();
+    /// let trusted_hash = proof.sized_hash();
+    /// assert_eq!(proof.check_against_hash(trusted_hash).unwrap(), 1);
+    /// assert_eq!(proof.index_hash(), index.object_hash());
     /// ```
-    pub fn get_multiproof<KI>(&self, keys: KI) -> MapProof<K::Owned, V, KeyMode>
-    where
-        KI: IntoIterator<Item = K::Owned>,
-    {
-        self.create_multiproof(keys)
+    ///
+    /// [`SizeProof`]: struct.SizeProof.html
+    pub fn get_size_proof(&self) -> SizeProof {
+        SizeProof::new(self.size(), self.merkle_root())
     }
 
     /// Returns an iterator over the entries of the map in ascending order.
@@ -401,16 +637,275 @@ where
     }
 }
 
-impl<T, K, V, KeyMode> ProofMapIndex<T, K, V, KeyMode>
+impl<T, K, V, KeyMode, H> ProofMapIndex<T, K, V, KeyMode, H>
+where
+    T: RawAccess,
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+    KeyMode: ToProofPath<K>,
+    H: NodeHasher,
+{
+    /// Returns the proof of existence or non-existence for the specified key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofMapIndex, crypto::Hash};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let index = fork.get_proof_map::<_, Hash, u8>("name");
+    ///
+    /// let proof = index.get_proof(Hash::default());
+    /// ```
+    pub fn get_proof(&self, key: K::Owned) -> GenericMapProof<K::Owned, V, KeyMode, H> {
+        traced!(
+            "map_proof";
+            self.create_proof(key);
+            |proof| proof_nodes = proof.proof_unchecked().len()
+        )
+    }
+
+    /// Returns the combined proof of existence or non-existence for the multiple specified keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofMapIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let index = fork.get_proof_map::<_, String, u8>("name");
+    ///
+    /// let proof = index.get_multiproof(vec!["foo".to_owned(), "bar".to_owned()]);
+    /// ```
+    pub fn get_multiproof<KI>(&self, keys: KI) -> GenericMapProof<K::Owned, V, KeyMode, H>
+    where
+        KI: IntoIterator<Item = K::Owned>,
+    {
+        traced!(
+            "map_multiproof";
+            self.create_multiproof(keys);
+            |proof|
+                entries = proof.all_entries_unchecked().count(),
+                proof_nodes = proof.proof_unchecked().len()
+        )
+    }
+
+    /// Returns the combined proof of existence or non-existence for the multiple specified keys,
+    /// or an error if the proof would exceed the specified `limits`.
+    ///
+    /// The number and the size of keys are checked as the keys are consumed from `keys`,
+    /// so that requests with an unbounded number of keys fail early. The size of values
+    /// is checked as they are read when building the proof. Duplicate keys are counted
+    /// separately in the number and the size of keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{
+    ///     access::CopyAccessExt, Database, ProofError, ProofLimit, ProofLimits, TemporaryDB,
+    /// };
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_map::<_, u64, u64>("name");
+    /// index.put(&1, 2);
+    ///
+    /// let limits = ProofLimits::new(100, 1_024);
+    /// let proof = index.get_multiproof_bounded(vec![1, 2], &limits).unwrap();
+    /// assert_eq!(proof.all_entries_unchecked().count(), 2);
+    ///
+    /// let err = index.get_multiproof_bounded(0.., &limits).unwrap_err();
+    /// assert_eq!(err, ProofError::TooLarge { limit: ProofLimit::Entries, max: 100 });
+    /// ```
+    pub fn get_multiproof_bounded<KI>(
+        &self,
+        keys: KI,
+        limits: &ProofLimits,
+    ) -> Result<GenericMapProof<K::Owned, V, KeyMode, H>, ProofError>
+    where
+        KI: IntoIterator<Item = K::Owned>,
+    {
+        self.create_multiproof_bounded(keys, limits)
+    }
+
+    /// Returns all keys changed in this map compared to `checkpoint`, which is usually the same
+    /// map in an older snapshot of the database, together with their combined proof against
+    /// `current_root`, the hash of this map. Keys added or modified since the checkpoint
+    /// are present in the proof with their current values, and removed keys are present
+    /// in the proof as missing. Changed keys are returned in the order of their
+    /// serialization.
+    ///
+    /// A follower having the checkpoint state of the map can check the proof against
+    /// `current_root` and then apply the changes from the proof. The resulting map
+    /// has the same hash as this map.
+    ///
+    /// # Complexity
+    ///
+    /// Unless the map is unchanged, which is detected by comparing the map hashes,
+    /// both maps are scanned entirely. Thus, the complexity of this method is linear
+    /// in the number of entries in the maps rather than in the number of changes,
+    /// and the method is best suited for maps of moderate size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `current_root` is not the hash of this map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, Database, ObjectHash, ProofMapIndex, TemporaryDB};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// fork.get_proof_map("map").put(&1_u8, "one".to_owned());
+    /// fork.get_proof_map("map").put(&2_u8, "two".to_owned());
+    /// db.merge(fork.into_patch()).unwrap();
+    /// let checkpoint = db.snapshot();
+    ///
+    /// let fork = db.fork();
+    /// let mut map = fork.get_proof_map::<_, u8, String>("map");
+    /// map.remove(&1);
+    /// map.put(&3, "three".to_owned());
+    /// let current_root = map.object_hash();
+    ///
+    /// let old_map = checkpoint.get_proof_map::<_, u8, String>("map");
+    /// let (changed_keys, proof) = map.changes_with_proofs_since(&old_map, current_root);
+    /// assert_eq!(changed_keys, vec![1, 3]);
+    /// let delta = proof.check_against_hash(current_root).unwrap();
+    /// assert_eq!(delta.missing_keys().collect::<Vec<_>>(), vec![&1]);
+    /// assert_eq!(
+    ///     delta.entries().collect::<Vec<_>>(),
+    ///     vec![(&3, &"three".to_owned())]
+    /// );
+    /// ```
+    pub fn changes_with_proofs_since<U>(
+        &self,
+        checkpoint: &ProofMapIndex<U, K, V, KeyMode, H>,
+        current_root: Hash,
+    ) -> (Vec<K::Owned>, GenericMapProof<K::Owned, V, KeyMode, H>)
+    where
+        U: RawAccess,
+    {
+        assert_eq!(
+            self.object_hash(),
+            current_root,
+            "`current_root` does not match the hash of the map"
+        );
+        if checkpoint.object_hash() == current_root {
+            return (vec![], self.get_multiproof(vec![]));
+        }
+
+        let mut old_entries = checkpoint.iter().peekable();
+        let mut new_entries = self.iter().peekable();
+        let mut changed_keys = vec![];
+
+        // Entries in both maps are ordered by their value paths.
+        loop {
+            let ordering = match (old_entries.peek(), new_entries.peek()) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((old_key, _)), Some((new_key, _))) => {
+                    let old_path = Borrow::<K>::borrow(old_key).to_value_path();
+                    old_path.cmp(&Borrow::<K>::borrow(new_key).to_value_path())
+                }
+            };
+
+            match ordering {
+                // The key was removed.
+                Ordering::Less => changed_keys.extend(old_entries.next().map(|(key, _)| key)),
+                // The key was added.
+                Ordering::Greater => changed_keys.extend(new_entries.next().map(|(key, _)| key)),
+                Ordering::Equal => {
+                    if let (Some((key, old_value)), Some((_, new_value))) =
+                        (old_entries.next(), new_entries.next())
+                    {
+                        if old_value.to_bytes() != new_value.to_bytes() {
+                            changed_keys.push(key);
+                        }
+                    }
+                }
+            }
+        }
+
+        let keys = changed_keys
+            .iter()
+            .map(|key| Borrow::<K>::borrow(key).to_owned())
+            .collect();
+        (keys, self.get_multiproof(changed_keys))
+    }
+}
+
+impl<T, K, V, H> ProofMapIndex<T, K, V, Raw, H>
+where
+    T: RawAccess,
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+    Raw: ToProofPath<K>,
+    H: NodeHasher,
+{
+    /// Returns the proof of all entries with keys starting with the specified `prefix`.
+    /// The proof should be verified with [`MapProof::check_prefix()`], which ensures that
+    /// no entries with the prefix are omitted from the proof.
+    ///
+    /// Keys are restored from the Merkle Patricia tree, so this method assumes that
+    /// the key is serialized as its raw bytes, which is the case for `Hash`
+    /// and `[u8; 32]` keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` is longer than the key size (32 bytes).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, crypto::Hash, Database, ObjectHash, TemporaryDB};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut map = fork.get_raw_proof_map::<_, Hash, u32>("directory");
+    /// for (i, &dir) in [1_u8, 1, 2].iter().enumerate() {
+    ///     let mut key = [0; 32];
+    ///     key[0] = dir;
+    ///     key[1] = i as u8;
+    ///     map.put(&Hash::new(key), i as u32);
+    /// }
+    ///
+    /// let proof = map.get_prefix_proof(&[1]);
+    /// let checked_proof = proof
+    ///     .check_prefix_against_hash(&[1], map.object_hash())
+    ///     .unwrap();
+    /// let values: Vec<_> = checked_proof.entries().map(|(_, value)| *value).collect();
+    /// assert_eq!(values, vec![0, 1]);
+    /// ```
+    ///
+    /// [`MapProof::check_prefix()`]: struct.GenericMapProof.html#method.check_prefix
+    pub fn get_prefix_proof(&self, prefix: &[u8]) -> GenericMapProof<K::Owned, V, Raw, H> {
+        let prefix = ProofPath::from_key_prefix(prefix);
+        traced!(
+            "map_prefix_proof", prefix_len = prefix.len();
+            proof_builder::create_prefix_proof(self, &prefix);
+            |proof|
+                entries = proof.all_entries_unchecked().count(),
+                proof_nodes = proof.proof_unchecked().len()
+        )
+    }
+}
+
+impl<T, K, V, KeyMode, H> ProofMapIndex<T, K, V, KeyMode, H>
 where
     T: RawAccessMut,
     K: BinaryKey + ?Sized,
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
-    fn insert_leaf(&mut self, proof_path: &ProofPath, key: &K, value: V) -> Hash {
+    // `value` is the serialized value; it is hashed and stored as is.
+    fn insert_leaf(&mut self, proof_path: &ProofPath, key: &K, value: Vec<u8>) -> Hash {
         debug_assert!(proof_path.is_leaf());
-        let hash = HashTag::hash_leaf(&value.to_bytes());
+        let hash = HashTag::hash_leaf(&value);
         self.base.put(proof_path, hash);
         self.base.put(&key.to_value_path(), value);
         hash
@@ -421,19 +916,46 @@ where
         self.base.remove(&key.to_value_path());
     }
 
-    fn update_root_path(&mut self, path: ProofPath) {
-        self.state.set(path);
+    fn update_state(&mut self, root_path: ProofPath, size: u64) {
+        let root_hash = self.hash_root_node(&root_path);
+        self.state.set(MapState {
+            root_path,
+            summary: Some((size, root_hash)),
+        });
+    }
+
+    /// Returns the number of entries tracked in the map state, or `None` if the map is empty
+    /// or its state is legacy.
+    fn tracked_size(&self) -> Option<u64> {
+        self.state
+            .get()
+            .and_then(|state| state.summary)
+            .map(|(size, _)| size)
+    }
+
+    fn put_branch(&mut self, path: &ProofPath, branch: BranchNode) {
+        let cache = self.node_cache.get_mut();
+        if cache.capacity() > 0 {
+            cache.insert(path, branch.clone());
+        }
+        self.base.put(path, branch);
+    }
+
+    fn remove_branch(&mut self, path: &ProofPath) {
+        self.node_cache.get_mut().remove(path);
+        self.base.remove(path);
     }
 
     // Inserts a new node of the current branch and returns the updated hash
-    // or, if a new node has a shorter key, returns a new key length.
+    // or, if a new node has a shorter key, returns a new key length. The returned flag
+    // is set if a new entry was added rather than an existing one updated.
     fn insert_branch(
         &mut self,
         parent: &BranchNode,
         proof_path: &ProofPath,
         key: &K,
-        value: V,
-    ) -> (Option<u16>, Hash) {
+        value: Vec<u8>,
+    ) -> (Option<u16>, Hash, bool) {
         let child_path = parent
             .child_path(proof_path.bit(0))
             .start_from(proof_path.start());
@@ -444,7 +966,7 @@ where
             if child_path.is_leaf() {
                 // there is a leaf in branch and we needs to update its value
                 let hash = self.insert_leaf(proof_path, key, value);
-                (None, hash)
+                (None, hash, false)
             } else {
                 match self.get_node_unchecked(&child_path) {
                     Node::Leaf(_) => {
@@ -452,7 +974,8 @@ where
                     }
                     // There is a child in branch and we needs to lookup it recursively
                     Node::Branch(mut branch) => {
-                        let (j, h) = self.insert_branch(&branch, &proof_path.suffix(i), key, value);
+                        let (j, h, added) =
+                            self.insert_branch(&branch, &proof_path.suffix(i), key, value);
                         match j {
                             Some(j) => {
                                 branch.set_child(
@@ -463,9 +986,9 @@ where
                             }
                             None => branch.set_child_hash(proof_path.bit(i), &h),
                         };
-                        let hash = branch.object_hash();
-                        self.base.put(&child_path, branch);
-                        (None, hash)
+                        let hash = branch.hash_with::<H>();
+                        self.put_branch(&child_path, branch);
+                        (None, hash, added)
                     }
                 }
             }
@@ -483,9 +1006,9 @@ where
                 &parent.child_hash(proof_path.bit(0)),
             );
 
-            let hash = new_branch.object_hash();
-            self.base.put(&proof_path.prefix(i), new_branch);
-            (Some(i), hash)
+            let hash = new_branch.hash_with::<H>();
+            self.put_branch(&proof_path.prefix(i), new_branch);
+            (Some(i), hash, true)
         }
     }
 
@@ -514,22 +1037,22 @@ where
                             let key = branch.child_path(child);
                             let hash = branch.child_hash(child);
 
-                            self.base.remove(&child_path);
+                            self.remove_branch(&child_path);
                             return RemoveAction::Branch((key, hash));
                         }
                         RemoveAction::Branch((key, hash)) => {
                             let new_child_path = key.start_from(suffix_path.start());
                             branch.set_child(suffix_path.bit(0), &new_child_path, &hash);
-                            let h = branch.object_hash();
+                            let h = branch.hash_with::<H>();
 
-                            self.base.put(&child_path, branch);
+                            self.put_branch(&child_path, branch);
                             return RemoveAction::UpdateHash(h);
                         }
                         RemoveAction::UpdateHash(hash) => {
                             branch.set_child_hash(suffix_path.bit(0), &hash);
-                            let h = branch.object_hash();
+                            let h = branch.hash_with::<H>();
 
-                            self.base.put(&child_path, branch);
+                            self.put_branch(&child_path, branch);
                             return RemoveAction::UpdateHash(h);
                         }
                         RemoveAction::KeyNotFound => return RemoveAction::KeyNotFound,
@@ -542,6 +1065,11 @@ where
 
     /// Inserts the key-value pair into the proof map.
     ///
+    /// # Panics
+    ///
+    /// Panics if the key or the value exceeds the size limits set by the [index options].
+    /// Use [`try_put`] to handle such errors.
+    ///
     /// # Examples
     ///
     /// ```
@@ -555,9 +1083,34 @@ where
     /// index.put(&hash, 2);
     /// assert!(index.contains(&hash));
     /// ```
+    ///
+    /// [index options]: #method.with_options
+    /// [`try_put`]: #method.try_put
     pub fn put(&mut self, key: &K, value: V) {
+        self.try_put(key, value)
+            .unwrap_or_else(|err| panic!("MerkleDB error: {err}"));
+    }
+
+    /// Inserts the key-value pair into the proof map, checking that the key and the value
+    /// do not exceed the size limits set by the [index options].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key or the value is too large. In this case, the map
+    /// is not changed.
+    ///
+    /// [index options]: #method.with_options
+    pub fn try_put(&mut self, key: &K, value: V) -> Result<(), SizeError> {
+        let value = value.into_bytes();
+        self.options.check(key.size(), value.len())?;
+        self.put_unchecked(key, value);
+        Ok(())
+    }
+
+    fn put_unchecked(&mut self, key: &K, value: Vec<u8>) {
+        let tracked_size = self.tracked_size();
         let proof_path = KeyMode::transform_key(key);
-        let root_path = match self.get_root_node() {
+        let (root_path, added) = match self.get_root_node() {
             Some((prefix, Node::Leaf(prefix_data))) => {
                 let prefix_path = prefix;
                 let i = prefix_path.common_prefix_len(&proof_path);
@@ -568,10 +1121,10 @@ where
                     branch.set_child(proof_path.bit(i), &proof_path.suffix(i), &leaf_hash);
                     branch.set_child(prefix_path.bit(i), &prefix_path.suffix(i), &prefix_data);
                     let new_prefix = proof_path.prefix(i);
-                    self.base.put(&new_prefix, branch);
-                    new_prefix
+                    self.put_branch(&new_prefix, branch);
+                    (new_prefix, true)
                 } else {
-                    proof_path
+                    (proof_path, false)
                 }
             }
             Some((prefix, Node::Branch(mut branch))) => {
@@ -581,13 +1134,13 @@ where
                 if i == prefix_path.len() {
                     let suffix_path = proof_path.suffix(i);
                     // Just cut the prefix and recursively descent on.
-                    let (j, h) = self.insert_branch(&branch, &suffix_path, key, value);
+                    let (j, h, added) = self.insert_branch(&branch, &suffix_path, key, value);
                     match j {
                         Some(j) => branch.set_child(suffix_path.bit(0), &suffix_path.prefix(j), &h),
                         None => branch.set_child_hash(suffix_path.bit(0), &h),
                     };
-                    self.base.put(&prefix_path, branch);
-                    prefix_path
+                    self.put_branch(&prefix_path, branch);
+                    (prefix_path, added)
                 } else {
                     // Inserts a new branch and adds current branch as its child
                     let hash = self.insert_leaf(&proof_path, key, value);
@@ -595,21 +1148,26 @@ where
                     new_branch.set_child(
                         prefix_path.bit(i),
                         &prefix_path.suffix(i),
-                        &branch.object_hash(),
+                        &branch.hash_with::<H>(),
                     );
                     new_branch.set_child(proof_path.bit(i), &proof_path.suffix(i), &hash);
                     // Saves a new branch
                     let new_prefix = prefix_path.prefix(i);
-                    self.base.put(&new_prefix, new_branch);
-                    new_prefix
+                    self.put_branch(&new_prefix, new_branch);
+                    (new_prefix, true)
                 }
             }
             None => {
                 self.insert_leaf(&proof_path, key, value);
-                proof_path
+                (proof_path, true)
             }
         };
-        self.update_root_path(root_path);
+        // If the size is not tracked, the entries (including the inserted one) are counted.
+        let size = tracked_size.map_or_else(
+            || self.keys().count() as u64,
+            |size| size + u64::from(added),
+        );
+        self.update_state(root_path, size);
     }
 
     /// Removes a key from the proof map.
@@ -631,6 +1189,7 @@ where
     /// assert!(!index.contains(&hash));
     /// ```
     pub fn remove(&mut self, key: &K) {
+        let tracked_size = self.tracked_size();
         let proof_path = KeyMode::transform_key(key);
         match self.get_root_node() {
             // If we have only on leaf, then we just need to remove it (if any)
@@ -646,30 +1205,124 @@ where
                 let i = prefix.common_prefix_len(&proof_path);
                 if i == prefix.len() {
                     let suffix_path = proof_path.suffix(i);
-                    match self.remove_node(&branch, &suffix_path, key) {
+                    let root_path = match self.remove_node(&branch, &suffix_path, key) {
                         RemoveAction::Leaf => {
                             // After removing one of leaves second child becomes a new root.
-                            self.base.remove(&prefix);
-                            let root_path = branch.child_path(!suffix_path.bit(0));
-                            self.update_root_path(root_path);
+                            self.remove_branch(&prefix);
+                            branch.child_path(!suffix_path.bit(0))
                         }
                         RemoveAction::Branch((key, hash)) => {
                             let new_child_path = key.start_from(suffix_path.start());
                             branch.set_child(suffix_path.bit(0), &new_child_path, &hash);
-                            self.base.put(&prefix, branch);
+                            self.put_branch(&prefix, branch);
+                            prefix
                         }
                         RemoveAction::UpdateHash(hash) => {
                             branch.set_child_hash(suffix_path.bit(0), &hash);
-                            self.base.put(&prefix, branch);
+                            self.put_branch(&prefix, branch);
+                            prefix
                         }
-                        RemoveAction::KeyNotFound => {}
-                    }
+                        RemoveAction::KeyNotFound => return,
+                    };
+                    let size =
+                        tracked_size.map_or_else(|| self.keys().count() as u64, |size| size - 1);
+                    self.update_state(root_path, size);
                 }
             }
             None => {}
         }
     }
 
+    /// Rebuilds branch nodes of the Merkle Patricia tree from its leaves and returns
+    /// the number of reclaimed nodes.
+    ///
+    /// The tree is rebuilt in its canonical form: each branch node has exactly two children,
+    /// and no branch nodes are stored except for those reachable from the root. Thus,
+    /// degenerate paths and dangling nodes left in the storage (e.g., after an interrupted
+    /// migration or by older versions of the index) are removed. Since the root hash
+    /// of the map depends only on its entries, compaction does not change it.
+    ///
+    /// # Notes
+    ///
+    /// Similar to [`clear`], the amount of allocated memory is linearly dependent
+    /// on the number of elements in the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ObjectHash, ProofMapIndex};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut index = fork.get_proof_map("name");
+    /// for i in 0_u32..100 {
+    ///     index.put(&i, u64::from(i));
+    /// }
+    /// for i in 0_u32..90 {
+    ///     index.remove(&i);
+    /// }
+    ///
+    /// let hash = index.object_hash();
+    /// index.compact_tree();
+    /// assert_eq!(index.object_hash(), hash);
+    /// ```
+    ///
+    /// [`clear`]: #method.clear
+    pub fn compact_tree(&mut self) -> usize {
+        let stale_branches: Vec<ProofPath> = self
+            .base
+            .iter::<_, ProofPath, ()>(&BRANCH_KEY_PREFIX)
+            .map(|(path, ())| path)
+            .collect();
+        let mut leaves: Vec<(ProofPath, Hash)> = self.base.iter(&LEAF_KEY_PREFIX).collect();
+        // `unwrap` is safe here because all paths start from the same position `0`.
+        leaves.sort_unstable_by(|(x, _), (y, _)| x.partial_cmp(y).unwrap());
+
+        let stale_count = stale_branches.len();
+        for path in &stale_branches {
+            self.remove_branch(path);
+        }
+        self.node_cache.get_mut().clear();
+
+        if leaves.is_empty() {
+            self.state.unset();
+            stale_count
+        } else {
+            let mut branch_count = 0;
+            let (root_path, _) = self.build_subtree(&leaves, &mut branch_count);
+            self.update_state(root_path, leaves.len() as u64);
+            stale_count.saturating_sub(branch_count)
+        }
+    }
+
+    /// Stores branch nodes of the subtree containing the specified leaves, which must be
+    /// sorted by their paths. Returns the path and the hash of the subtree root.
+    fn build_subtree(
+        &mut self,
+        leaves: &[(ProofPath, Hash)],
+        branch_count: &mut usize,
+    ) -> (ProofPath, Hash) {
+        let (first_path, _) = leaves[0];
+        if leaves.len() == 1 {
+            return leaves[0];
+        }
+
+        let (last_path, _) = leaves[leaves.len() - 1];
+        let prefix_len = first_path.common_prefix_len(&last_path);
+        let split = leaves.partition_point(|(path, _)| path.bit(prefix_len) == ChildKind::Left);
+        let (left_path, left_hash) = self.build_subtree(&leaves[..split], branch_count);
+        let (right_path, right_hash) = self.build_subtree(&leaves[split..], branch_count);
+
+        let mut branch = BranchNode::empty();
+        branch.set_child(ChildKind::Left, &left_path, &left_hash);
+        branch.set_child(ChildKind::Right, &right_path, &right_hash);
+        let hash = branch.hash_with::<H>();
+        let path = first_path.prefix(prefix_len);
+        self.put_branch(&path, branch);
+        *branch_count += 1;
+        (path, hash)
+    }
+
     /// Clears the proof map, removing all entries.
     ///
     /// # Notes
@@ -697,6 +1350,71 @@ where
     pub fn clear(&mut self) {
         self.base.clear();
         self.state.unset();
+        self.node_cache.get_mut().clear();
+    }
+}
+
+impl<T, K, V, KeyMode, H> ProofMapIndex<T, K, V, KeyMode, H>
+where
+    T: RawAccessMut,
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+    KeyMode: ToProofPath<K>,
+    H: NodeHasher,
+{
+    /// Adds all entries from the `other` map to this map, and returns the hash of the resulting
+    /// map together with the proof that the resulting map is the union of this map
+    /// (as the left map) and the `other` map (as the right map). See [`UnionProof`]
+    /// for details on the proof.
+    ///
+    /// The proof is built before modifying this map, so the maps should have disjoint keys
+    /// (e.g., if the maps are shards of a larger map). The complexity of this method is linear
+    /// in the number of entries in the `other` map.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the maps have a common key. In this case, this map is not modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, Database, ObjectHash, TemporaryDB};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut map = fork.get_proof_map::<_, u64, String>("map");
+    /// map.put(&1, "one".to_owned());
+    /// let mut shard = fork.get_proof_map::<_, u64, String>("shard");
+    /// shard.put(&2, "two".to_owned());
+    /// let (left_hash, right_hash) = (map.object_hash(), shard.object_hash());
+    ///
+    /// let (union_hash, proof) = map.union_with_proof(&shard).unwrap();
+    /// assert_eq!(union_hash, map.object_hash());
+    /// assert_eq!(map.get(&2).unwrap(), "two");
+    /// proof
+    ///     .check_against_hashes(left_hash, right_hash, union_hash)
+    ///     .unwrap();
+    ///
+    /// // Maps with common keys cannot be united.
+    /// assert!(map.union_with_proof(&shard).is_err());
+    /// ```
+    ///
+    /// [`UnionProof`]: struct.UnionProof.html
+    pub fn union_with_proof<U>(
+        &mut self,
+        other: &ProofMapIndex<U, K, V, KeyMode, H>,
+    ) -> Result<(Hash, UnionProof<H>), OverlappingKeysError>
+    where
+        U: RawAccess,
+    {
+        let proof = traced!(
+            "map_union_proof", entries = other.size();
+            union_proof::create_union_proof(self, other)
+        )?;
+        for (key, value) in other.iter() {
+            self.put(key.borrow(), value);
+        }
+        Ok((self.object_hash(), proof))
     }
 }
 
@@ -779,24 +1497,26 @@ where
 /// let hash = index.object_hash();
 /// assert_ne!(hash, default_hash);
 /// ```
-impl<T, K, V, KeyMode> ObjectHash for ProofMapIndex<T, K, V, KeyMode>
+impl<T, K, V, KeyMode, H> ObjectHash for ProofMapIndex<T, K, V, KeyMode, H>
 where
     T: RawAccess,
     K: BinaryKey + ?Sized,
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     fn object_hash(&self) -> Hash {
         HashTag::hash_map_node(self.merkle_root())
     }
 }
 
-impl<'a, T, K, V, KeyMode> IntoIterator for &'a ProofMapIndex<T, K, V, KeyMode>
+impl<'a, T, K, V, KeyMode, H> IntoIterator for &'a ProofMapIndex<T, K, V, KeyMode, H>
 where
     T: RawAccess,
     K: BinaryKey + ?Sized,
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     type Item = (K::Owned, V);
     type IntoIter = Entries<'a, K, V>;
@@ -806,12 +1526,13 @@ where
     }
 }
 
-impl<T, K, V, KeyMode> IndexIterator for ProofMapIndex<T, K, V, KeyMode>
+impl<T, K, V, KeyMode, H> IndexIterator for ProofMapIndex<T, K, V, KeyMode, H>
 where
     T: RawAccess,
     K: BinaryKey + ?Sized,
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     type Key = K;
     type Value = V;
@@ -821,31 +1542,40 @@ where
     }
 }
 
-impl<T, K, V, KeyMode> fmt::Debug for ProofMapIndex<T, K, V, KeyMode>
+impl<T, K, V, KeyMode, H> fmt::Debug for ProofMapIndex<T, K, V, KeyMode, H>
 where
     T: RawAccess,
     K: BinaryKey + ?Sized,
     V: BinaryValue + fmt::Debug,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     #[allow(clippy::option_if_let_else)]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        struct Entry<'a, T: RawAccess, K: ?Sized, V: BinaryValue, KeyMode: ToProofPath<K>> {
-            index: &'a ProofMapIndex<T, K, V, KeyMode>,
+        struct Entry<'a, T, K, V, KeyMode, H>
+        where
+            T: RawAccess,
+            K: ?Sized,
+            V: BinaryValue,
+            KeyMode: ToProofPath<K>,
+            H: NodeHasher,
+        {
+            index: &'a ProofMapIndex<T, K, V, KeyMode, H>,
             path: ProofPath,
             hash: Hash,
             node: Node,
         }
 
-        impl<'a, T, K, V, KeyMode> Entry<'a, T, K, V, KeyMode>
+        impl<'a, T, K, V, KeyMode, H> Entry<'a, T, K, V, KeyMode, H>
         where
             T: RawAccess,
             K: BinaryKey + ?Sized,
             V: BinaryValue,
             KeyMode: ToProofPath<K>,
+            H: NodeHasher,
         {
             fn new(
-                index: &'a ProofMapIndex<T, K, V, KeyMode>,
+                index: &'a ProofMapIndex<T, K, V, KeyMode, H>,
                 hash: Hash,
                 path: ProofPath,
             ) -> Self {
@@ -866,12 +1596,13 @@ where
             }
         }
 
-        impl<T, K, V, KeyMode> fmt::Debug for Entry<'_, T, K, V, KeyMode>
+        impl<T, K, V, KeyMode, H> fmt::Debug for Entry<'_, T, K, V, KeyMode, H>
         where
             T: RawAccess,
             K: BinaryKey + ?Sized,
             V: BinaryValue + fmt::Debug,
             KeyMode: ToProofPath<K>,
+            H: NodeHasher,
         {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                 match self.node {
@@ -20,7 +20,8 @@ use std::borrow::Cow;
 use super::key::{ChildKind, ProofPath, PROOF_PATH_SIZE};
 use crate::{
     crypto::{Hash, HASH_SIZE},
-    BinaryKey, BinaryValue, HashTag, ObjectHash,
+    hash::{DefaultNodeHasher, NodeHasher},
+    BinaryKey, BinaryValue, ObjectHash,
 };
 
 const BRANCH_NODE_SIZE: usize = 2 * (HASH_SIZE + PROOF_PATH_SIZE);
@@ -102,8 +103,9 @@ impl BinaryValue for BranchNode {
     }
 }
 
-impl ObjectHash for BranchNode {
-    fn object_hash(&self) -> Hash {
+impl BranchNode {
+    /// Computes the hash of the node with the specified node hasher.
+    pub(crate) fn hash_with<H: NodeHasher>(&self) -> Hash {
         let mut bytes: SmallVec<[u8; 256]> = smallvec![0_u8; 132];
         let mut pos = HASH_SIZE * 2;
         // Writes hashes to the buffer.
@@ -114,7 +116,13 @@ impl ObjectHash for BranchNode {
         pos += self
             .child_path(ChildKind::Right)
             .write_compressed(&mut bytes[pos..]);
-        HashTag::hash_map_branch(&bytes[..pos])
+        H::hash_map_branch(&bytes[..pos])
+    }
+}
+
+impl ObjectHash for BranchNode {
+    fn object_hash(&self) -> Hash {
+        self.hash_with::<DefaultNodeHasher>()
     }
 }
 
@@ -0,0 +1,108 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bounded in-memory cache of branch nodes of a `ProofMapIndex`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::{key::PROOF_PATH_SIZE, node::BranchNode, ProofPath};
+use crate::BinaryKey;
+
+type CacheKey = [u8; PROOF_PATH_SIZE];
+
+fn cache_key(path: &ProofPath) -> CacheKey {
+    let mut key = [0; PROOF_PATH_SIZE];
+    path.write(&mut key);
+    key
+}
+
+/// Cache of branch nodes with the least recently used (LRU) eviction policy.
+///
+/// Nodes are keyed by the serialized form of their paths, i.e., in the same way as
+/// in the storage. A zero capacity disables the cache.
+#[derive(Debug, Default)]
+pub(super) struct NodeCache {
+    capacity: usize,
+    /// Counter incremented on each access, used to order nodes by the access time.
+    tick: u64,
+    nodes: HashMap<CacheKey, (BranchNode, u64)>,
+    /// Keys of cached nodes ordered by the last access time.
+    access_order: BTreeMap<u64, CacheKey>,
+}
+
+impl NodeCache {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Sets the cache capacity, evicting the least recently used nodes if necessary.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn evict(&mut self) {
+        while self.nodes.len() > self.capacity {
+            let (_, key) = self
+                .access_order
+                .pop_first()
+                .expect("access order is consistent with cached nodes");
+            self.nodes.remove(&key);
+        }
+    }
+
+    /// Returns a cached node and marks it as recently used.
+    pub fn get(&mut self, path: &ProofPath) -> Option<BranchNode> {
+        let tick = self.next_tick();
+        let (node, last_access) = self.nodes.get_mut(&cache_key(path))?;
+        let key = self.access_order.remove(last_access).unwrap();
+        *last_access = tick;
+        self.access_order.insert(tick, key);
+        Some(node.clone())
+    }
+
+    /// Inserts or replaces a node in the cache.
+    pub fn insert(&mut self, path: &ProofPath, node: BranchNode) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = cache_key(path);
+        let tick = self.next_tick();
+        if let Some((_, last_access)) = self.nodes.insert(key, (node, tick)) {
+            self.access_order.remove(&last_access);
+        }
+        self.access_order.insert(tick, key);
+        self.evict();
+    }
+
+    pub fn remove(&mut self, path: &ProofPath) {
+        if let Some((_, last_access)) = self.nodes.remove(&cache_key(path)) {
+            self.access_order.remove(&last_access);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.access_order.clear();
+    }
+}
@@ -18,15 +18,24 @@ use serde::{Deserializer, Serializer};
 use serde_derive::{Deserialize, Serialize};
 use thiserror::Error;
 
-use std::{borrow::Cow, marker::PhantomData};
+use std::{
+    borrow::{Borrow, Cow},
+    fmt,
+    marker::PhantomData,
+};
 
 use super::{
+    hash_single_entry_map,
     key::{BitsRange, ChildKind, ProofPath, KEY_SIZE},
     node::BranchNode,
 };
-use crate::{crypto::Hash, BinaryValue, HashTag, ObjectHash};
+use crate::{
+    crypto::Hash,
+    hash::{DefaultNodeHasher, NodeHasher},
+    BinaryValue, HashTag,
+};
 
-use crate::indexes::proof_map::key::{Hashed, ToProofPath};
+use crate::indexes::proof_map::key::{Hashed, Raw, ToProofPath};
 
 impl serde::Serialize for ProofPath {
     fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
@@ -124,17 +133,46 @@ pub enum MapProofError {
     /// Entries in the proof are not ordered by increasing path.
     #[error("invalid path ordering")]
     InvalidOrdering(ProofPath, ProofPath),
+
+    /// A key in the proof of entries with a certain key prefix does not start with the prefix.
+    #[error("key in the prefix proof does not start with the prefix")]
+    OutsidePrefix {
+        /// Path corresponding to the key prefix.
+        prefix: ProofPath,
+        /// Path corresponding to the key.
+        path: ProofPath,
+    },
+
+    /// A hashed subtree in the proof of entries with a certain key prefix may contain
+    /// entries with the prefix.
+    #[error("hashed subtree in the prefix proof may contain keys with the prefix")]
+    HiddenPrefixEntries {
+        /// Path corresponding to the key prefix.
+        prefix: ProofPath,
+        /// Path to the hashed subtree.
+        path: ProofPath,
+    },
 }
 
 // Used instead of `(ProofPath, Hash)` only for the purpose of clearer (de)serialization.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-struct MapProofEntry {
-    path: ProofPath,
-    hash: Hash,
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub(super) struct MapProofEntry {
+    pub(super) path: ProofPath,
+    pub(super) hash: Hash,
 }
 
 // Used instead of `(K, Option<V>)` only for the purpose of clearer (de)serialization.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
 #[serde(untagged)]
 enum OptionalEntry<K, V> {
     Missing { missing: K },
@@ -260,29 +298,84 @@ impl<K, V> OptionalEntry<K, V> {
 /// (e.g., ordering of `proof`; see [`check()`]) only if these invariants are checked
 /// during proof verification.
 ///
+/// ## Node hasher
+///
+/// The `H` type parameter specifies the [`NodeHasher`] used to restore the hash
+/// of the map. It must match the hasher of the `ProofMapIndex` the proof was built for;
+/// the hasher is not a part of the serialized proof. Proofs for maps with the default hasher
+/// are represented by the [`MapProof`] alias.
+///
+/// [`NodeHasher`]: ../../trait.NodeHasher.html
+/// [`MapProof`]: type.MapProof.html
 /// [`get_proof()`]: struct.ProofMapIndex.html#method.get_proof
 /// [`get_multiproof()`]: struct.ProofMapIndex.html#method.get_multiproof
 /// [`check()`]: #method.check
 /// [`ProofPath`]: struct.ProofPath.html
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub struct MapProof<K, V, KeyMode = Hashed> {
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub struct GenericMapProof<K, V, KeyMode, H> {
     entries: Vec<OptionalEntry<K, V>>,
     proof: Vec<MapProofEntry>,
     #[serde(skip)]
     _key_mode: PhantomData<KeyMode>,
+    #[serde(skip)]
+    _hasher: PhantomData<H>,
 }
 
+/// Proof for entries of a `ProofMapIndex` using the [`DefaultNodeHasher`].
+///
+/// See [`GenericMapProof`] for the description and examples of usage.
+///
+/// [`DefaultNodeHasher`]: ../../struct.DefaultNodeHasher.html
+/// [`GenericMapProof`]: struct.GenericMapProof.html
+pub type MapProof<K, V, KeyMode = Hashed> = GenericMapProof<K, V, KeyMode, DefaultNodeHasher>;
+
 /// Version of `MapProof` obtained after verification.
 ///
 /// See [`MapProof`] for an example of usage.
 ///
-/// [`MapProof`]: struct.MapProof.html#workflow
+/// [`MapProof`]: struct.GenericMapProof.html#workflow
 #[derive(Debug, Serialize)]
 pub struct CheckedMapProof<'a, K, V> {
     entries: &'a [OptionalEntry<K, V>],
     hash: Hash,
 }
 
+/// Outcome of verifying a key against a [`CheckedMapProof`].
+///
+/// [`CheckedMapProof`]: struct.CheckedMapProof.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verified<V> {
+    /// The key is present in the map with the specified value.
+    Present(V),
+    /// The key is absent from the map.
+    Absent,
+}
+
+impl<V> Verified<V> {
+    /// Returns `true` if the key is present in the map.
+    pub fn is_present(&self) -> bool {
+        matches!(self, Self::Present(_))
+    }
+
+    /// Returns `true` if the key is absent from the map.
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Self::Absent)
+    }
+
+    /// Converts the outcome into an `Option` containing the value of the key, if any.
+    pub fn into_option(self) -> Option<V> {
+        match self {
+            Self::Present(value) => Some(value),
+            Self::Absent => None,
+        }
+    }
+}
+
 /// Computes the root hash of the Merkle Patricia tree backing the specified entries
 /// in the map view.
 ///
@@ -294,29 +387,35 @@ pub struct CheckedMapProof<'a, K, V> {
 /// zero or more nodes are evicted from the contour, and a single new node is
 /// added to it.
 ///
-/// `entries` are assumed to be sorted by the path in increasing order.
-fn collect(entries: &[Cow<'_, MapProofEntry>]) -> Result<Hash, MapProofError> {
+/// `entries` are assumed to be sorted by the path in increasing order. Branches of the tree
+/// are hashed with the node hasher `H`.
+pub(super) fn collect<H: NodeHasher>(
+    entries: &[Cow<'_, MapProofEntry>],
+) -> Result<Hash, MapProofError> {
     fn common_prefix(x: &ProofPath, y: &ProofPath) -> ProofPath {
         x.prefix(x.common_prefix_len(y))
     }
 
-    fn hash_branch(left_child: &MapProofEntry, right_child: &MapProofEntry) -> Hash {
+    fn hash_branch<H: NodeHasher>(left_child: &MapProofEntry, right_child: &MapProofEntry) -> Hash {
         let mut branch = BranchNode::empty();
         branch.set_child(ChildKind::Left, &left_child.path, &left_child.hash);
         branch.set_child(ChildKind::Right, &right_child.path, &right_child.hash);
-        branch.object_hash()
+        branch.hash_with::<H>()
     }
 
     /// Folds two last entries in a contour and replaces them with the folded entry.
     ///
     /// Returns an updated common prefix between two last entries in the contour.
-    fn fold(contour: &mut Vec<MapProofEntry>, last_prefix: ProofPath) -> Option<ProofPath> {
+    fn fold<H: NodeHasher>(
+        contour: &mut Vec<MapProofEntry>,
+        last_prefix: ProofPath,
+    ) -> Option<ProofPath> {
         let last_entry = contour.pop().unwrap();
         let penultimate_entry = contour.pop().unwrap();
 
         contour.push(MapProofEntry {
             path: last_prefix,
-            hash: hash_branch(&penultimate_entry, &last_entry),
+            hash: hash_branch::<H>(&penultimate_entry, &last_entry),
         });
 
         if contour.len() > 1 {
@@ -332,7 +431,7 @@ fn collect(entries: &[Cow<'_, MapProofEntry>]) -> Result<Hash, MapProofError> {
 
         1 => {
             if entries[0].path.is_leaf() {
-                Ok(HashTag::hash_single_entry_map(
+                Ok(hash_single_entry_map::<H>(
                     &entries[0].path,
                     &entries[0].hash,
                 ))
@@ -352,7 +451,7 @@ fn collect(entries: &[Cow<'_, MapProofEntry>]) -> Result<Hash, MapProofError> {
                 let new_prefix_len = new_prefix.len();
 
                 while contour.len() > 1 && new_prefix_len < last_prefix.len() {
-                    if let Some(prefix) = fold(&mut contour, last_prefix) {
+                    if let Some(prefix) = fold::<H>(&mut contour, last_prefix) {
                         last_prefix = prefix;
                     }
                 }
@@ -362,7 +461,7 @@ fn collect(entries: &[Cow<'_, MapProofEntry>]) -> Result<Hash, MapProofError> {
             }
 
             while contour.len() > 1 {
-                if let Some(prefix) = fold(&mut contour, last_prefix) {
+                if let Some(prefix) = fold::<H>(&mut contour, last_prefix) {
                     last_prefix = prefix;
                 }
             }
@@ -372,7 +471,52 @@ fn collect(entries: &[Cow<'_, MapProofEntry>]) -> Result<Hash, MapProofError> {
     }
 }
 
-impl<K, V, KeyMode> MapProof<K, V, KeyMode> {
+/// Checks that `entries` are ordered by increasing path and that no path is a prefix
+/// of another path.
+pub(super) fn check_ordering(entries: &[MapProofEntry]) -> Result<(), MapProofError> {
+    use self::MapProofError::{DuplicatePath, EmbeddedPaths, InvalidOrdering};
+    use std::cmp::Ordering;
+
+    for w in entries.windows(2) {
+        let (prev_path, path) = (&w[0].path, &w[1].path);
+        match prev_path.partial_cmp(path) {
+            Some(Ordering::Less) => {
+                if path.starts_with(prev_path) {
+                    return Err(EmbeddedPaths {
+                        prefix: *prev_path,
+                        path: *path,
+                    });
+                }
+            }
+            Some(Ordering::Equal) => {
+                return Err(DuplicatePath(*path));
+            }
+            Some(Ordering::Greater) => {
+                return Err(InvalidOrdering(*prev_path, *path));
+            }
+            None => unreachable!("Incomparable keys in proof"),
+        }
+    }
+    Ok(())
+}
+
+/// Computes the Merkle root of a Merkle Patricia tree with the specified leaves, i.e.,
+/// pairs of leaf paths and value hashes. Leaves may be provided in any order; if several
+/// leaves have the same path, only one of them is taken into account. Branches are hashed
+/// with the default node hasher.
+pub fn merkle_root_of_leaves(leaves: impl IntoIterator<Item = (ProofPath, Hash)>) -> Hash {
+    let mut entries: Vec<_> = leaves
+        .into_iter()
+        .map(|(path, hash)| MapProofEntry { path, hash })
+        .collect();
+    // `unwrap` is safe here because all paths start from the same position `0`.
+    entries.sort_unstable_by(|x, y| x.path.partial_cmp(&y.path).unwrap());
+    entries.dedup_by(|x, y| x.path == y.path);
+    let entries: Vec<_> = entries.iter().map(Cow::Borrowed).collect();
+    collect::<DefaultNodeHasher>(&entries).expect("leaf paths cannot form a malformed tree")
+}
+
+impl<K, V, KeyMode, H> GenericMapProof<K, V, KeyMode, H> {
     /// Provides access to the proof part of the view. Useful mainly for debug purposes.
     pub fn proof_unchecked(&self) -> Vec<(ProofPath, Hash)> {
         self.proof
@@ -405,6 +549,7 @@ impl<K, V, KeyMode> MapProof<K, V, KeyMode> {
             entries: vec![],
             proof: vec![],
             _key_mode: PhantomData,
+            _hasher: PhantomData,
         }
     }
 
@@ -445,36 +590,17 @@ impl<K, V, KeyMode> MapProof<K, V, KeyMode> {
 }
 
 #[allow(clippy::use_self)] // false positives in `map_values`
-impl<K, V, KeyMode> MapProof<K, V, KeyMode>
+impl<K, V, KeyMode, H> GenericMapProof<K, V, KeyMode, H>
 where
     V: BinaryValue,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
     fn precheck(&self) -> Result<(), MapProofError> {
-        use self::MapProofError::{DuplicatePath, EmbeddedPaths, InvalidOrdering};
-        use std::cmp::Ordering;
+        use self::MapProofError::{DuplicatePath, EmbeddedPaths};
 
         // Check that entries in `proof` are in increasing order.
-        for w in self.proof.windows(2) {
-            let (prev_path, path) = (&w[0].path, &w[1].path);
-            match prev_path.partial_cmp(path) {
-                Some(Ordering::Less) => {
-                    if path.starts_with(prev_path) {
-                        return Err(EmbeddedPaths {
-                            prefix: *prev_path,
-                            path: *path,
-                        });
-                    }
-                }
-                Some(Ordering::Equal) => {
-                    return Err(DuplicatePath(*path));
-                }
-                Some(Ordering::Greater) => {
-                    return Err(InvalidOrdering(*prev_path, *path));
-                }
-                None => unreachable!("Incomparable keys in proof"),
-            }
-        }
+        check_ordering(&self.proof)?;
 
         // Check that no entry has a prefix among the paths in the proof entries.
         // In order to do this, it suffices to locate the closest smaller path in the proof entries
@@ -571,7 +697,7 @@ where
             }
         }
 
-        collect(&proof).map(|merkle_root| CheckedMapProof {
+        collect::<H>(&proof).map(|merkle_root| CheckedMapProof {
             entries: &self.entries,
             hash: HashTag::hash_map_node(merkle_root),
         })
@@ -594,13 +720,24 @@ where
             })
     }
 
+    /// Checks this proof against a trusted map hash passed by reference. This is equivalent
+    /// to [`check_against_hash()`], but is more convenient if the trusted hash is a constant.
+    ///
+    /// [`check_against_hash()`]: #method.check_against_hash
+    pub fn check_against(
+        &self,
+        trusted_map_hash: &Hash,
+    ) -> Result<CheckedMapProof<'_, K, V>, ValidationError<MapProofError>> {
+        self.check_against_hash(*trusted_map_hash)
+    }
+
     /// Maps values in this proof. Note that this transform may render the proof invalid.
-    pub fn map_values<U, F>(self, mut map_fn: F) -> MapProof<K, U, KeyMode>
+    pub fn map_values<U, F>(self, mut map_fn: F) -> GenericMapProof<K, U, KeyMode, H>
     where
         U: BinaryValue,
         F: FnMut(V) -> U,
     {
-        MapProof {
+        GenericMapProof {
             entries: self
                 .entries
                 .into_iter()
@@ -614,7 +751,387 @@ where
                 .collect(),
             proof: self.proof,
             _key_mode: PhantomData,
+            _hasher: PhantomData,
+        }
+    }
+
+    /// Updates this proof (a *witness* for its keys) after the map has changed, so that
+    /// it can be checked against the new map hash.
+    ///
+    /// `changes` must be a proof against the new map hash for *all* keys changed
+    /// since this proof was created, such as the one returned by
+    /// [`ProofMapIndex::changes_with_proofs_since`]. Since subtrees of the map that contain
+    /// no changed keys are the same in the old and new map, the updated proof is assembled
+    /// from such subtrees of this proof and the subtrees from `changes`. Entries of this proof
+    /// for changed keys are replaced with their new state.
+    ///
+    /// The updated proof is not verified by this method; as usual, it should be checked
+    /// against the trusted map hash. If `changes` do not include all changed keys, the check
+    /// will fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this proof or `changes` are malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, Database, ObjectHash, ProofMapIndex, TemporaryDB};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut map = fork.get_proof_map::<_, u8, u8>("map");
+    /// for i in 0..10 {
+    ///     map.put(&i, i);
+    /// }
+    /// let witness = map.get_proof(5);
+    /// db.merge(fork.into_patch()).unwrap();
+    /// let checkpoint = db.snapshot();
+    ///
+    /// let fork = db.fork();
+    /// let mut map = fork.get_proof_map::<_, u8, u8>("map");
+    /// map.put(&4, 40);
+    /// map.remove(&6);
+    /// let old_map = checkpoint.get_proof_map::<_, u8, u8>("map");
+    /// let (_, changes) = map.changes_with_proofs_since(&old_map, map.object_hash());
+    ///
+    /// let witness = witness.update_witness(&changes).unwrap();
+    /// let checked_witness = witness.check_against_hash(map.object_hash()).unwrap();
+    /// assert_eq!(checked_witness.entries().collect::<Vec<_>>(), vec![(&5, &5)]);
+    /// ```
+    ///
+    /// [`ProofMapIndex::changes_with_proofs_since`]: struct.ProofMapIndex.html#method.changes_with_proofs_since
+    pub fn update_witness(&self, changes: &Self) -> Result<Self, MapProofError>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        fn leaf<K, V: BinaryValue, KeyMode: ToProofPath<K>>(key: &K, value: &V) -> MapProofEntry {
+            MapProofEntry {
+                path: KeyMode::transform_key(key),
+                hash: HashTag::hash_leaf(&value.to_bytes()),
+            }
+        }
+
+        self.precheck()?;
+        changes.precheck()?;
+
+        let changed_paths: Vec<_> = changes
+            .entries
+            .iter()
+            .map(|entry| KeyMode::transform_key(entry.key()))
+            .collect();
+        let is_unchanged = |path: &ProofPath| {
+            changed_paths
+                .iter()
+                .all(|changed_path| !changed_path.starts_with(path))
+        };
+
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let path = KeyMode::transform_key(entry.key());
+            let changed_entry = changes
+                .entries
+                .iter()
+                .find(|change| KeyMode::transform_key(change.key()) == path);
+            entries.push(changed_entry.unwrap_or(entry).clone());
         }
+
+        // Known subtrees of the new map: unchanged subtrees from this proof and all subtrees
+        // from `changes`. Both `self` and `changes` cover the entire map, so the finest
+        // subtrees among the known ones cover the new map as well.
+        let mut nodes: Vec<MapProofEntry> = self
+            .proof
+            .iter()
+            .copied()
+            .filter(|node| is_unchanged(&node.path))
+            .chain(self.entries.iter().filter_map(|entry| {
+                let (key, value) = entry.as_kv()?;
+                let node = leaf::<K, V, KeyMode>(key, value);
+                Some(node).filter(|node| is_unchanged(&node.path))
+            }))
+            .chain(changes.proof.iter().copied())
+            .chain(changes.entries.iter().filter_map(|entry| {
+                let (key, value) = entry.as_kv()?;
+                Some(leaf::<K, V, KeyMode>(key, value))
+            }))
+            .collect();
+        nodes.sort_unstable_by(|x, y| {
+            x.path
+                .partial_cmp(&y.path)
+                .expect("Incomparable paths in proof")
+        });
+        nodes.dedup_by(|x, y| x.path == y.path);
+
+        // Since the nodes are sorted, a node containing other nodes is immediately followed
+        // by one of them.
+        let entry_paths: Vec<_> = entries
+            .iter()
+            .filter_map(|entry| entry.as_kv().map(|(key, _)| KeyMode::transform_key(key)))
+            .collect();
+        let proof = nodes
+            .iter()
+            .enumerate()
+            .filter(|(i, node)| {
+                let is_finest = nodes
+                    .get(i + 1)
+                    .map_or(true, |next| !next.path.starts_with(&node.path));
+                is_finest && !entry_paths.contains(&node.path)
+            })
+            .map(|(_, node)| *node)
+            .collect();
+
+        Ok(Self {
+            entries,
+            proof,
+            _key_mode: PhantomData,
+            _hasher: PhantomData,
+        })
+    }
+
+    /// Projects this proof onto a subset of its keys, returning a proof covering only
+    /// the entries and missing keys for the specified `keys`. The projected proof restores
+    /// the same map hash as this proof.
+    ///
+    /// Dropped entries are replaced with the hashes of the smallest subtrees of the map
+    /// containing no retained keys, so the projected proof is usually smaller than this one.
+    /// Keys not covered by this proof are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this proof is malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkledb::{access::CopyAccessExt, Database, ObjectHash, ProofMapIndex, TemporaryDB};
+    ///
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut map = fork.get_proof_map::<_, u8, u8>("map");
+    /// for i in 0..10 {
+    ///     map.put(&i, i);
+    /// }
+    /// let proof = map.get_multiproof(vec![1, 2, 3, 4]);
+    /// let projected_proof = proof.project(&[2, 4]).unwrap();
+    /// let checked_proof = projected_proof.check_against_hash(map.object_hash()).unwrap();
+    /// assert_eq!(checked_proof.entries().collect::<Vec<_>>(), vec![(&2, &2), (&4, &4)]);
+    /// ```
+    pub fn project<'k, I>(&self, keys: I) -> Result<Self, MapProofError>
+    where
+        I: IntoIterator<Item = &'k K>,
+        K: Clone + 'k,
+        V: Clone,
+    {
+        self.precheck()?;
+
+        let retained_paths: Vec<_> = keys.into_iter().map(KeyMode::transform_key).collect();
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| retained_paths.contains(&KeyMode::transform_key(entry.key())))
+            .cloned()
+            .collect();
+        let missing_paths: Vec<_> = entries
+            .iter()
+            .filter_map(|entry| entry.as_missing().map(KeyMode::transform_key))
+            .collect();
+
+        // Nodes of the cut defined by this proof, with a flag indicating retained leaves.
+        let mut nodes: Vec<_> = self
+            .proof
+            .iter()
+            .map(|node| (*node, false))
+            .chain(self.entries.iter().filter_map(|entry| {
+                let (key, value) = entry.as_kv()?;
+                let path = KeyMode::transform_key(key);
+                let node = MapProofEntry {
+                    path,
+                    hash: HashTag::hash_leaf(&value.to_bytes()),
+                };
+                Some((node, retained_paths.contains(&path)))
+            }))
+            .collect();
+        nodes.sort_unstable_by(|(x, _), (y, _)| {
+            x.path
+                .partial_cmp(&y.path)
+                .expect("Incomparable paths in proof")
+        });
+        for window in nodes.windows(2) {
+            if window[0].0.path == window[1].0.path {
+                return Err(MapProofError::DuplicatePath(window[0].0.path));
+            }
+        }
+
+        let mut proof = vec![];
+        if nodes.len() > 1 {
+            // The root of the tree is never collapsed, since a proof with a single
+            // non-leaf node is invalid.
+            collapse_subtrees::<H>(&nodes, &missing_paths, &mut proof)?;
+        } else {
+            proof.extend(
+                nodes
+                    .iter()
+                    .filter(|(_, retained)| !retained)
+                    .map(|(node, _)| *node),
+            );
+        }
+
+        Ok(Self {
+            entries,
+            proof,
+            _key_mode: PhantomData,
+            _hasher: PhantomData,
+        })
+    }
+}
+
+impl<K, V, H> GenericMapProof<K, V, Raw, H>
+where
+    V: BinaryValue,
+    Raw: ToProofPath<K>,
+    H: NodeHasher,
+{
+    /// Checks this proof as a proof of *all* entries in the map with keys starting
+    /// with `prefix`, such as the one returned by [`ProofMapIndex::get_prefix_proof`].
+    ///
+    /// In addition to the checks performed by [`check()`], the following is verified:
+    ///
+    /// - All keys in the proof start with `prefix`.
+    /// - No subtree of the map included into the proof as a hash may contain keys
+    ///   starting with `prefix`.
+    ///
+    /// Thus, if the proof is valid, the entries of the checked proof are exactly
+    /// the entries of the map with the specified key prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` is longer than the key size (32 bytes).
+    ///
+    /// [`ProofMapIndex::get_prefix_proof`]: struct.ProofMapIndex.html#method.get_prefix_proof
+    /// [`check()`]: #method.check
+    pub fn check_prefix(&self, prefix: &[u8]) -> Result<CheckedMapProof<'_, K, V>, MapProofError> {
+        let prefix = ProofPath::from_key_prefix(prefix);
+        for entry in &self.entries {
+            let path = Raw::transform_key(entry.key());
+            if !path.starts_with(&prefix) {
+                return Err(MapProofError::OutsidePrefix { prefix, path });
+            }
+        }
+        for entry in &self.proof {
+            if entry.path.overlaps(&prefix) {
+                return Err(MapProofError::HiddenPrefixEntries {
+                    prefix,
+                    path: entry.path,
+                });
+            }
+        }
+        self.check()
+    }
+
+    /// Checks this proof as a proof of all entries with keys starting with `prefix`
+    /// against a trusted map hash. See [`check_prefix()`] for details.
+    ///
+    /// [`check_prefix()`]: #method.check_prefix
+    pub fn check_prefix_against_hash(
+        &self,
+        prefix: &[u8],
+        expected_map_hash: Hash,
+    ) -> Result<CheckedMapProof<'_, K, V>, ValidationError<MapProofError>> {
+        self.check_prefix(prefix)
+            .map_err(ValidationError::Malformed)
+            .and_then(|checked| {
+                if checked.index_hash() == expected_map_hash {
+                    Ok(checked)
+                } else {
+                    Err(ValidationError::UnmatchedRootHash)
+                }
+            })
+    }
+}
+
+/// Splits `nodes` forming a subtree of a Merkle Patricia tree into the left and right subtrees
+/// and adds their nodes to `proof`, replacing subtrees that contain neither retained leaves nor
+/// `missing_paths` with their root nodes.
+///
+/// `nodes` must contain at least 2 nodes sorted by path.
+fn collapse_subtrees<H: NodeHasher>(
+    nodes: &[(MapProofEntry, bool)],
+    missing_paths: &[ProofPath],
+    proof: &mut Vec<MapProofEntry>,
+) -> Result<(), MapProofError> {
+    fn common_prefix_len(nodes: &[(MapProofEntry, bool)]) -> u16 {
+        let (first, last) = (&nodes[0].0, &nodes[nodes.len() - 1].0);
+        first.path.common_prefix_len(&last.path)
+    }
+
+    let prefix_len = common_prefix_len(nodes);
+    // Since the nodes are sorted, nodes in the left subtree precede nodes in the right one.
+    let split_index = nodes
+        .iter()
+        .position(|(node, _)| node.path.bit(prefix_len) == ChildKind::Right)
+        .expect("nodes with the common prefix of maximum length diverge");
+
+    for &subtree in &[&nodes[..split_index], &nodes[split_index..]] {
+        if let [(node, retained)] = subtree {
+            if !*retained {
+                proof.push(*node);
+            }
+            continue;
+        }
+
+        let path = subtree[0].0.path.prefix(common_prefix_len(subtree));
+        let is_needed = subtree.iter().any(|(_, retained)| *retained)
+            || missing_paths
+                .iter()
+                .any(|missing_path| missing_path.starts_with(&path));
+        if is_needed {
+            collapse_subtrees::<H>(subtree, missing_paths, proof)?;
+        } else {
+            let entries: Vec<_> = subtree
+                .iter()
+                .map(|(node, _)| Cow::Borrowed(node))
+                .collect();
+            let hash = collect::<H>(&entries)?;
+            proof.push(MapProofEntry { path, hash });
+        }
+    }
+    Ok(())
+}
+
+/// Summarizes the proof outcome for logging: the hash of the map, the number of proven
+/// entries and the number of keys proven to be missing.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ProofMapIndex};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut map = fork.get_proof_map("map");
+/// map.put(&1_u32, "one".to_owned());
+/// let proof = map.get_multiproof(vec![1, 2]);
+/// let checked_proof = proof.check().unwrap();
+/// assert_eq!(
+///     checked_proof.to_string(),
+///     format!("map proof (hash: {}, entries: 1, missing keys: 1)", checked_proof.index_hash()),
+/// );
+/// ```
+impl<K, V> fmt::Display for CheckedMapProof<'_, K, V> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing_keys = self
+            .entries
+            .iter()
+            .filter(|entry| entry.as_missing().is_some())
+            .count();
+        write!(
+            formatter,
+            "map proof (hash: {}, entries: {}, missing keys: {})",
+            self.hash,
+            self.entries.len() - missing_keys,
+            missing_keys
+        )
     }
 }
 
@@ -635,6 +1152,45 @@ impl<'a, K, V> CheckedMapProof<'a, K, V> {
         self.entries.iter().map(OptionalEntry::as_tuple)
     }
 
+    /// Returns the verified state of the specified key: whether the key is present in the map
+    /// (and if so, with which value), or is absent from it. Returns `None` if the proof
+    /// does not cover the key, i.e., if the key was not requested when building the proof.
+    ///
+    /// Unlike comparing the proof contents with an expected value, this allows to distinguish
+    /// an absent key from a key present with a different value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use merkledb::{access::CopyAccessExt, TemporaryDB, Database, ObjectHash};
+    /// # use merkledb::proof_map::Verified;
+    /// let db = TemporaryDB::new();
+    /// let fork = db.fork();
+    /// let mut map = fork.get_proof_map("map");
+    /// map.put(&1_u8, "one".to_owned());
+    ///
+    /// let proof = map.get_multiproof(vec![1, 2]);
+    /// let checked_proof = proof.check_against_hash(map.object_hash()).unwrap();
+    /// assert_eq!(checked_proof.verify(&1), Some(Verified::Present(&"one".to_owned())));
+    /// assert_eq!(checked_proof.verify(&2), Some(Verified::Absent));
+    /// assert_eq!(checked_proof.verify(&3), None);
+    /// ```
+    pub fn verify<Q>(&self, key: &Q) -> Option<Verified<&'a V>>
+    where
+        K: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        self.entries
+            .iter()
+            .find(|entry| entry.key().borrow() == key)
+            .map(|entry| {
+                entry
+                    .as_tuple()
+                    .1
+                    .map_or(Verified::Absent, Verified::Present)
+            })
+    }
+
     /// Returns the `object_hash()` of the underlying `ProofMapIndex`.
     pub fn index_hash(&self) -> Hash {
         self.hash
@@ -20,23 +20,31 @@ use std::borrow::Borrow;
 use super::{
     key::{BitsRange, ChildKind, ProofPath},
     node::{BranchNode, Node},
-    MapProof, ToProofPath,
+    GenericMapProof, Raw, ToProofPath,
+};
+use crate::{
+    crypto::Hash,
+    hash::NodeHasher,
+    indexes::{ProofError, ProofLimits},
+    BinaryKey,
 };
-use crate::{crypto::Hash, BinaryKey};
 
 // Expected size of the proof, in number of hashed entries.
 const DEFAULT_PROOF_CAPACITY: usize = 8;
 
-impl<K, V, KeyMode> MapProof<K, V, KeyMode> {
+impl<K, V, KeyMode, H> GenericMapProof<K, V, KeyMode, H> {
     /// Includes a proof of existence / absence of a single key when a proof of multiple
-    /// keys is requested.
+    /// keys is requested. The size of the value is added to `bytes`, which is checked
+    /// against the `limits`.
     fn process_key<Q: ?Sized>(
         mut self,
-        tree: &impl MerklePatriciaTree<Q, V>,
+        tree: &impl MerklePatriciaTree<Q, V, H>,
         contour: &mut Vec<ContourNode>,
         proof_path: &ProofPath,
         key: K,
-    ) -> Self
+        limits: &ProofLimits,
+        bytes: &mut usize,
+    ) -> Result<Self, ProofError>
     where
         K: Borrow<Q>,
     {
@@ -74,7 +82,7 @@ impl<K, V, KeyMode> MapProof<K, V, KeyMode> {
                 }
             } else {
                 // Both children of `branch` do not fit; stop here
-                break self.add_missing(key);
+                break Ok(self.add_missing(key));
             }
 
             let node = tree.node(&node_path);
@@ -84,8 +92,10 @@ impl<K, V, KeyMode> MapProof<K, V, KeyMode> {
                 }
                 Node::Leaf(_) => {
                     // We have reached the leaf node and haven't diverged!
-                    let value = tree.value(key.borrow());
-                    break self.add_entry(key, value);
+                    let (value, size) = tree.sized_value(key.borrow());
+                    *bytes = bytes.saturating_add(size);
+                    limits.check_bytes(*bytes)?;
+                    break Ok(self.add_entry(key, value));
                 }
             }
         }
@@ -93,8 +103,8 @@ impl<K, V, KeyMode> MapProof<K, V, KeyMode> {
 }
 
 /// Encapsulation of a Merkle Patricia tree allowing to access its terminal and intermediate
-/// nodes.
-pub trait MerklePatriciaTree<K: ?Sized, V> {
+/// nodes. Branch nodes of the tree are hashed with the node hasher `H`.
+pub trait MerklePatriciaTree<K: ?Sized, V, H: NodeHasher> {
     /// Gets the root node of the tree.
     fn root_node(&self) -> Option<(ProofPath, Node)>;
 
@@ -109,6 +119,13 @@ pub trait MerklePatriciaTree<K: ?Sized, V> {
     /// It is assumed that this method cannot fail since it is queried with `key`s
     /// that are guaranteed to be present in the tree.
     fn value(&self, key: &K) -> V;
+
+    /// Looks up the value by its full key, together with the size of its serialized form
+    /// in bytes.
+    ///
+    /// It is assumed that this method cannot fail since it is queried with `key`s
+    /// that are guaranteed to be present in the tree.
+    fn sized_value(&self, key: &K) -> (V, usize);
 }
 
 /// Combines two lists of hashes produces when building a `MapProof`.
@@ -145,10 +162,10 @@ impl ContourNode {
     }
 
     // Adds this contour node into a proof builder.
-    fn add_to_proof<K, V, KeyMode>(
+    fn add_to_proof<K, V, KeyMode, H>(
         self,
-        mut builder: MapProof<K, V, KeyMode>,
-    ) -> MapProof<K, V, KeyMode> {
+        mut builder: GenericMapProof<K, V, KeyMode, H>,
+    ) -> GenericMapProof<K, V, KeyMode, H> {
         if !self.visited_right {
             // This works due to the following observation: If neither of the child nodes
             // were visited when the node is being ejected from the contour,
@@ -177,24 +194,34 @@ impl ContourNode {
 /// implement `BuildProof` as well.
 ///
 /// [`MerklePatriciaTree`]: trait.MerklePatriciaTree.html
-pub trait BuildProof<K: ToOwned + ?Sized, V, KeyMode> {
+pub trait BuildProof<K: ToOwned + ?Sized, V, KeyMode, H> {
     /// Creates a proof of existence / absence for a single key.
-    fn create_proof(&self, key: K::Owned) -> MapProof<K::Owned, V, KeyMode>;
+    fn create_proof(&self, key: K::Owned) -> GenericMapProof<K::Owned, V, KeyMode, H>;
 
     /// Creates a proof of existence / absence for multiple keys.
     fn create_multiproof(
         &self,
         keys: impl IntoIterator<Item = K::Owned>,
-    ) -> MapProof<K::Owned, V, KeyMode>;
+    ) -> GenericMapProof<K::Owned, V, KeyMode, H>;
+
+    /// Creates a proof of existence / absence for multiple keys, or returns an error
+    /// if the proof exceeds the `limits`. The number and the size of keys are checked
+    /// as the keys are consumed, and the size of values is checked as they are read.
+    fn create_multiproof_bounded(
+        &self,
+        keys: impl IntoIterator<Item = K::Owned>,
+        limits: &ProofLimits,
+    ) -> Result<GenericMapProof<K::Owned, V, KeyMode, H>, ProofError>;
 }
 
-impl<K, V, T, KeyMode> BuildProof<K, V, KeyMode> for T
+impl<K, V, T, KeyMode, H> BuildProof<K, V, KeyMode, H> for T
 where
     K: BinaryKey + ?Sized,
-    T: MerklePatriciaTree<K, V>,
+    T: MerklePatriciaTree<K, V, H>,
     KeyMode: ToProofPath<K>,
+    H: NodeHasher,
 {
-    fn create_proof(&self, key: K::Owned) -> MapProof<K::Owned, V, KeyMode> {
+    fn create_proof(&self, key: K::Owned) -> GenericMapProof<K::Owned, V, KeyMode, H> {
         let searched_path = KeyMode::transform_key(key.borrow());
         match self.root_node() {
             Some((root_path, Node::Branch(root_branch))) => {
@@ -229,7 +256,7 @@ where
                                 // The key is there, we've just gotten the value, so we just
                                 // need to return it.
                                 let value = self.value(key.borrow());
-                                break MapProof::new()
+                                break GenericMapProof::new()
                                     .add_entry(key, value)
                                     .add_proof_entries(combine_hashes(left_hashes, right_hashes));
                             }
@@ -242,7 +269,7 @@ where
                             ChildKind::Right => right_hashes.push((node_path, next_hash)),
                         }
 
-                        break MapProof::new()
+                        break GenericMapProof::new()
                             .add_missing(key)
                             .add_proof_entries(combine_hashes(left_hashes, right_hashes));
                     }
@@ -252,29 +279,49 @@ where
             Some((root_path, Node::Leaf(hash))) => {
                 if root_path == searched_path {
                     let value = self.value(key.borrow());
-                    MapProof::new().add_entry(key, value)
+                    GenericMapProof::new().add_entry(key, value)
                 } else {
-                    MapProof::new()
+                    GenericMapProof::new()
                         .add_missing(key)
                         .add_proof_entry(root_path, hash)
                 }
             }
 
-            None => MapProof::new().add_missing(key),
+            None => GenericMapProof::new().add_missing(key),
         }
     }
 
-    #[allow(clippy::option_if_let_else)]
     fn create_multiproof(
         &self,
         keys: impl IntoIterator<Item = K::Owned>,
-    ) -> MapProof<K::Owned, V, KeyMode> {
+    ) -> GenericMapProof<K::Owned, V, KeyMode, H> {
+        self.create_multiproof_bounded(keys, &ProofLimits::default())
+            .expect("proof size is not limited")
+    }
+
+    #[allow(clippy::option_if_let_else)]
+    fn create_multiproof_bounded(
+        &self,
+        keys: impl IntoIterator<Item = K::Owned>,
+        limits: &ProofLimits,
+    ) -> Result<GenericMapProof<K::Owned, V, KeyMode, H>, ProofError> {
+        // Keys are checked before building the proof, so that requests with an unbounded
+        // number of keys are rejected early. Duplicate keys are counted separately.
+        let mut bytes = 0_usize;
+        let mut checked_keys = vec![];
+        for key in keys {
+            limits.check_entries(checked_keys.len() + 1)?;
+            bytes = bytes.saturating_add(key.borrow().size());
+            limits.check_bytes(bytes)?;
+            checked_keys.push(key);
+        }
+
         match self.root_node() {
             Some((root_path, Node::Branch(root_branch))) => {
-                let mut proof: MapProof<K::Owned, V, KeyMode> = MapProof::new();
+                let mut proof: GenericMapProof<K::Owned, V, KeyMode, H> = GenericMapProof::new();
 
                 let searched_paths = {
-                    let mut keys: Vec<_> = keys
+                    let mut keys: Vec<_> = checked_keys
                         .into_iter()
                         .map(|k| (KeyMode::transform_key(k.borrow()), k))
                         .collect();
@@ -295,7 +342,14 @@ where
                         // The key has already been looked up; skipping.
                         continue;
                     }
-                    proof = proof.process_key(self, &mut contour, &proof_path, key);
+                    proof = proof.process_key(
+                        self,
+                        &mut contour,
+                        &proof_path,
+                        key,
+                        limits,
+                        &mut bytes,
+                    )?;
                     last_searched_path = Some(proof_path);
                 }
 
@@ -303,14 +357,14 @@ where
                 while let Some(node) = contour.pop() {
                     proof = node.add_to_proof(proof);
                 }
-                proof
+                Ok(proof)
             }
             Some((root_path, Node::Leaf(merkle_root))) => {
-                let mut proof = MapProof::new();
+                let mut proof = GenericMapProof::new();
                 // (One of) keys corresponding to the existing table entry.
                 let mut found_key: Option<K::Owned> = None;
 
-                for key in keys {
+                for key in checked_keys {
                     let searched_path = KeyMode::transform_key(key.borrow());
                     if root_path == searched_path {
                         found_key = Some(key);
@@ -320,16 +374,82 @@ where
                 }
 
                 if let Some(key) = found_key {
-                    let value = self.value(key.borrow());
-                    proof.add_entry(key, value)
+                    let (value, size) = self.sized_value(key.borrow());
+                    limits.check_bytes(bytes.saturating_add(size))?;
+                    Ok(proof.add_entry(key, value))
                 } else {
-                    proof.add_proof_entry(root_path, merkle_root)
+                    Ok(proof.add_proof_entry(root_path, merkle_root))
                 }
             }
 
-            None => keys
+            None => Ok(checked_keys
                 .into_iter()
-                .fold(MapProof::new(), MapProof::add_missing),
+                .fold(GenericMapProof::new(), GenericMapProof::add_missing)),
+        }
+    }
+}
+
+/// Adds entries and proof entries for a subtree with the root at `path` to a proof
+/// of entries with the specified key prefix. Subtrees not overlapping with the prefix
+/// are included into the proof as hashes; other subtrees are traversed recursively.
+fn add_prefix_subtree<K, V, H>(
+    tree: &impl MerklePatriciaTree<K, V, H>,
+    mut proof: GenericMapProof<K::Owned, V, Raw, H>,
+    prefix: &ProofPath,
+    path: ProofPath,
+    hash: Hash,
+) -> GenericMapProof<K::Owned, V, Raw, H>
+where
+    K: BinaryKey + ?Sized,
+    H: NodeHasher,
+{
+    if !path.overlaps(prefix) {
+        return proof.add_proof_entry(path, hash);
+    }
+
+    match tree.node(&path) {
+        Node::Leaf(_) => {
+            // Raw keys are serialized as their bytes, so the key can be restored from the path.
+            let key = K::read(path.raw_key());
+            let value = tree.value(key.borrow());
+            proof.add_entry(key, value)
+        }
+        Node::Branch(branch) => {
+            for kind in [ChildKind::Left, ChildKind::Right] {
+                let (child_path, child_hash) = (branch.child_path(kind), branch.child_hash(kind));
+                proof = add_prefix_subtree(tree, proof, prefix, child_path, child_hash);
+            }
+            proof
+        }
+    }
+}
+
+/// Builds a proof of all entries with the specified key prefix in a Merkelized map
+/// with raw keys. Entries with the prefix are included into the proof together with
+/// their values, and all other parts of the tree are represented by hashes of subtrees
+/// which do not overlap with the prefix.
+pub(super) fn create_prefix_proof<K, V, H>(
+    tree: &impl MerklePatriciaTree<K, V, H>,
+    prefix: &ProofPath,
+) -> GenericMapProof<K::Owned, V, Raw, H>
+where
+    K: BinaryKey + ?Sized,
+    H: NodeHasher,
+{
+    match tree.root_node() {
+        Some((_, Node::Branch(root_branch))) => {
+            // Children of the root are always included, even if the root path does not overlap
+            // with the prefix, since a proof consisting of a single branch is invalid.
+            let mut proof = GenericMapProof::new();
+            for kind in [ChildKind::Left, ChildKind::Right] {
+                let (path, hash) = (root_branch.child_path(kind), root_branch.child_hash(kind));
+                proof = add_prefix_subtree(tree, proof, prefix, path, hash);
+            }
+            proof
+        }
+        Some((root_path, Node::Leaf(hash))) => {
+            add_prefix_subtree(tree, GenericMapProof::new(), prefix, root_path, hash)
         }
+        None => GenericMapProof::new(),
     }
 }
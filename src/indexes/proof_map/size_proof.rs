@@ -0,0 +1,97 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proofs of the number of entries in a `ProofMapIndex`.
+
+use serde_derive::{Deserialize, Serialize};
+
+use std::convert::Infallible;
+
+use crate::{crypto::Hash, HashTag, ValidationError};
+
+/// Proof of the number of entries in a [`ProofMapIndex`].
+///
+/// The `object_hash()` of a map does not depend on the number of its entries. Thus, the proof
+/// is checked against the *sized hash* of the map, which is defined similarly
+/// to the hash of a `ProofListIndex`:
+///
+/// ```text
+/// h = sha256( HashTag::SizedMapNode || size as u64 || merkle_root )
+/// ```
+///
+/// where `merkle_root` is the root hash of the Patricia Merkle tree of the map.
+/// The sized hash needs to be obtained from a trusted source, e.g., signed by the
+/// party maintaining the map. The proof also allows to restore the `object_hash()`
+/// of the map via [`index_hash`], so that the proven size can be linked to the state
+/// of the database.
+///
+/// See [`ProofMapIndex::get_size_proof`] for an example of usage.
+///
+/// [`ProofMapIndex`]: struct.ProofMapIndex.html
+/// [`index_hash`]: #method.index_hash
+/// [`ProofMapIndex::get_size_proof`]: struct.ProofMapIndex.html#method.get_size_proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeProof {
+    size: u64,
+    merkle_root: Hash,
+}
+
+impl SizeProof {
+    pub(super) fn new(size: u64, merkle_root: Hash) -> Self {
+        Self { size, merkle_root }
+    }
+
+    /// Returns the number of entries in the map asserted by the proof. The size is not
+    /// verified until the proof is checked.
+    pub fn size_unchecked(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the sized hash of the map, which commits to both the size and the contents
+    /// of the map.
+    pub fn sized_hash(&self) -> Hash {
+        HashTag::hash_sized_map_node(self.size, self.merkle_root)
+    }
+
+    /// Returns the `object_hash()` of the underlying `ProofMapIndex`.
+    pub fn index_hash(&self) -> Hash {
+        HashTag::hash_map_node(self.merkle_root)
+    }
+
+    /// Checks the proof against the trusted sized hash of the map and returns the number
+    /// of entries in the map.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sized hash of the proof does not match the trusted one.
+    pub fn check_against_hash(
+        &self,
+        expected_hash: Hash,
+    ) -> Result<u64, ValidationError<Infallible>> {
+        if self.sized_hash() == expected_hash {
+            Ok(self.size)
+        } else {
+            Err(ValidationError::UnmatchedRootHash)
+        }
+    }
+
+    /// Checks the proof against the trusted sized hash of the map passed by reference.
+    /// This is equivalent to [`check_against_hash()`], but is more convenient if the trusted
+    /// hash is a constant.
+    ///
+    /// [`check_against_hash()`]: #method.check_against_hash
+    pub fn check_against(&self, trusted_hash: &Hash) -> Result<u64, ValidationError<Infallible>> {
+        self.check_against_hash(*trusted_hash)
+    }
+}
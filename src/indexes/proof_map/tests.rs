@@ -23,7 +23,17 @@ use rand::{
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::{self, json};
 
-use std::{cmp, collections::HashSet, fmt::Debug, hash::Hash as StdHash, marker::PhantomData};
+use std::{
+    cmp,
+    collections::HashSet,
+    fmt::Debug,
+    hash::Hash as StdHash,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use super::{
     key::{BitsRange, ChildKind, KEY_SIZE},
@@ -31,10 +41,11 @@ use super::{
     MapProof, MapProofError, ProofPath,
 };
 use crate::{
-    access::CopyAccessExt,
+    access::{CopyAccessExt, RawAccess},
     crypto::{hash, Hash, HashStream, HASH_SIZE},
     proof_map::{Hashed, ProofMapIndex, Raw, ToProofPath},
-    BinaryKey, BinaryValue, Database, Fork, HashTag, ObjectHash, TemporaryDB,
+    BinaryKey, BinaryValue, Database, HashTag, Iter, ObjectHash, ResolvedAddress, Snapshot,
+    TemporaryDB,
 };
 
 const IDX_NAME: &str = "idx_name";
@@ -1232,11 +1243,12 @@ fn test_merkle_root_leaf() {
     assert_eq!(HashTag::hash_map_node(merkle_root), index.object_hash());
 }
 
-fn check_map_proof<K, V, S>(
+fn check_map_proof<T, K, V, S>(
     proof: &MapProof<K::Owned, V, S>,
     key: Option<K>,
-    table: &ProofMapIndex<&Fork, K, V, S>,
+    table: &ProofMapIndex<T, K, V, S>,
 ) where
+    T: RawAccess,
     K: BinaryKey,
     K::Owned: Serialize + DeserializeOwned + PartialEq + Debug + Clone,
     V: BinaryValue + ObjectHash + PartialEq + Debug + Serialize + DeserializeOwned,
@@ -1275,11 +1287,12 @@ fn check_map_proof<K, V, S>(
     assert_eq!(deserialized_proof.index_hash(), proof.index_hash());
 }
 
-fn check_map_multiproof<K, V, S>(
+fn check_map_multiproof<T, K, V, S>(
     proof: &MapProof<K, V, S>,
     keys: Vec<K>,
-    table: &ProofMapIndex<&Fork, K, V, S>,
+    table: &ProofMapIndex<T, K, V, S>,
 ) where
+    T: RawAccess,
     K: BinaryKey + ObjectHash + PartialEq + Debug,
     V: BinaryValue + ObjectHash + PartialEq + Debug,
     S: ToProofPath<K>,
@@ -1745,3 +1758,1005 @@ fn unsized_key() {
 
     assert!(proof.check().is_ok());
 }
+
+#[test]
+fn changes_with_proofs_since_checkpoint() {
+    let db = TemporaryDB::new();
+    let mut rng = thread_rng();
+    let data = generate_random_data(200);
+    let (initial, later) = data.split_at(100);
+
+    let fork = db.fork();
+    {
+        let mut table = fork.get_proof_map::<_, [u8; 32], Vec<u8>>(IDX_NAME);
+        for (key, value) in initial {
+            table.put(key, value.clone());
+        }
+    }
+    db.merge(fork.into_patch()).unwrap();
+    let checkpoint = db.snapshot();
+
+    // The follower starts from the checkpoint state.
+    let follower_fork = db.fork();
+
+    let fork = db.fork();
+    let current_root = {
+        let mut table = fork.get_proof_map::<_, [u8; 32], Vec<u8>>(IDX_NAME);
+        for (key, _) in initial.choose_multiple(&mut rng, 20) {
+            table.remove(key);
+        }
+        for (key, _) in initial.choose_multiple(&mut rng, 20) {
+            table.put(key, vec![rng.gen()]);
+        }
+        // Rewriting a value with the same one is not a change.
+        let (same_key, same_value) = &initial[0];
+        if table.contains(same_key) {
+            table.put(same_key, same_value.clone());
+        }
+        for (key, value) in later {
+            table.put(key, value.clone());
+        }
+        table.object_hash()
+    };
+
+    let table = fork.get_proof_map::<_, [u8; 32], Vec<u8>>(IDX_NAME);
+    let old_table = checkpoint.get_proof_map::<_, [u8; 32], Vec<u8>>(IDX_NAME);
+    let (changed_keys, proof) = table.changes_with_proofs_since(&old_table, current_root);
+    let delta = proof.check_against_hash(current_root).unwrap();
+    assert!(delta.entries().count() >= later.len());
+    assert!(delta.all_entries().count() <= later.len() + 40);
+    assert_eq!(changed_keys.len(), delta.all_entries().count());
+    for key in &changed_keys {
+        assert_ne!(table.get(key), old_table.get(key));
+    }
+
+    let mut follower_table = follower_fork.get_proof_map::<_, [u8; 32], Vec<u8>>(IDX_NAME);
+    for key in delta.missing_keys() {
+        follower_table.remove(key);
+    }
+    for (key, value) in delta.entries() {
+        follower_table.put(key, value.clone());
+    }
+    assert_eq!(follower_table.object_hash(), current_root);
+
+    // No changes result in an empty proof.
+    let (changed_keys, proof) = table.changes_with_proofs_since(&table, current_root);
+    assert!(changed_keys.is_empty());
+    let delta = proof.check_against_hash(current_root).unwrap();
+    assert_eq!(delta.all_entries().count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "does not match the hash of the map")]
+fn changes_with_proofs_since_wrong_root() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut table = fork.get_proof_map::<_, u8, u8>(IDX_NAME);
+    table.put(&1, 1);
+    let snapshot = db.snapshot();
+    let old_table = snapshot.get_proof_map::<_, u8, u8>(IDX_NAME);
+    table.changes_with_proofs_since(&old_table, Hash::zero());
+}
+
+#[test]
+fn bounded_multiproofs() {
+    use crate::{ProofError, ProofLimit, ProofLimits};
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, Vec<u8>>(IDX_NAME);
+    for i in 0..100 {
+        map.put(&i, vec![0; 100]);
+    }
+
+    let limits = ProofLimits::new(10, 1_000);
+    let proof = map
+        .get_multiproof_bounded(vec![0, 1, 1_000], &limits)
+        .unwrap();
+    let checked = proof.check_against_hash(map.object_hash()).unwrap();
+    assert_eq!(checked.entries().count(), 2);
+    assert_eq!(checked.missing_keys().count(), 1);
+
+    // The iterator of keys is unbounded, but the request fails nonetheless.
+    let err = map.get_multiproof_bounded(1_000.., &limits).unwrap_err();
+    assert_eq!(
+        err,
+        ProofError::TooLarge {
+            limit: ProofLimit::Entries,
+            max: 10,
+        }
+    );
+
+    // Each existing key accounts for 108 bytes.
+    let err = map.get_multiproof_bounded(0..10, &limits).unwrap_err();
+    assert_eq!(
+        err,
+        ProofError::TooLarge {
+            limit: ProofLimit::Bytes,
+            max: 1_000,
+        }
+    );
+    assert!(map.get_multiproof_bounded(0..9, &limits).is_ok());
+}
+
+#[test]
+fn hash_of_in_memory_map_matches_index() {
+    use std::collections::BTreeMap;
+
+    let db = TemporaryDB::new();
+    for &len in &[0_usize, 1, 2, 3, 5, 8, 13, 64, 100, 257] {
+        let data: BTreeMap<_, _> = generate_random_data(len).into_iter().collect();
+
+        let fork = db.fork();
+        let mut map = fork.get_proof_map(IDX_NAME);
+        let mut raw_map = fork.get_raw_proof_map((IDX_NAME, &1_u8));
+        for (key, value) in &data {
+            map.put(key, value.clone());
+            raw_map.put(key, value.clone());
+        }
+
+        assert_eq!(data.object_hash(), map.object_hash(), "len = {}", len);
+        assert_eq!(
+            HashTag::hash_map::<Hashed, _, _>(&data),
+            map.object_hash(),
+            "len = {}",
+            len
+        );
+        assert_eq!(
+            HashTag::hash_map::<Raw, _, _>(&data),
+            raw_map.object_hash(),
+            "len = {}",
+            len
+        );
+    }
+}
+
+#[test]
+fn verified_keys_in_checked_proofs() {
+    use super::Verified;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, str, u64>(IDX_NAME);
+    map.put("alice", 100);
+    map.put("bob", 50);
+    let root = map.object_hash();
+
+    let proof = map.get_multiproof(vec!["alice".to_owned(), "carol".to_owned()]);
+    let checked_proof = proof.check_against_hash(root).unwrap();
+
+    // The present key with a value different from the expected one.
+    let alice = checked_proof.verify("alice").unwrap();
+    assert_eq!(alice, Verified::Present(&100));
+    assert!(alice.is_present());
+    assert_ne!(alice.into_option(), Some(&50));
+
+    // The absent key.
+    let carol = checked_proof.verify("carol").unwrap();
+    assert_eq!(carol, Verified::Absent);
+    assert!(carol.is_absent());
+    assert_eq!(carol.into_option(), None);
+
+    // The present key not covered by the proof.
+    assert_eq!(checked_proof.verify("bob"), None);
+
+    let proof = map.get_proof("bob".to_owned());
+    let checked_proof = proof.check_against_hash(root).unwrap();
+    assert_eq!(checked_proof.verify("bob"), Some(Verified::Present(&50)));
+}
+
+#[test]
+fn oversized_entries_are_rejected() {
+    use crate::{IndexOptions, SizeError};
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork
+        .get_proof_map::<_, u64, Vec<u8>>(IDX_NAME)
+        .with_options(IndexOptions::new(8, 32));
+    map.try_put(&1, vec![0; 32]).unwrap();
+    let hash = map.object_hash();
+
+    let err = map.try_put(&2, vec![0; 33]).unwrap_err();
+    assert_eq!(err, SizeError::ValueTooLarge { size: 33, max: 32 });
+    // The tree is not changed on error.
+    assert!(!map.contains(&2));
+    assert_eq!(map.object_hash(), hash);
+    assert_eq!(map.options().max_value_bytes, 32);
+
+    // Default limits allow reasonably large values, but reject the oversized ones.
+    let mut other_map = fork.get_proof_map::<_, u64, Vec<u8>>((IDX_NAME, &1_u8));
+    assert_eq!(*other_map.options(), IndexOptions::default());
+    other_map.try_put(&1, vec![0; 1 << 20]).unwrap();
+    let err = other_map.try_put(&2, vec![0; (1 << 26) + 1]).unwrap_err();
+    assert_eq!(
+        err,
+        SizeError::ValueTooLarge {
+            size: (1 << 26) + 1,
+            max: 1 << 26,
+        }
+    );
+    assert!(!other_map.contains(&2));
+}
+
+#[test]
+fn proofs_from_snapshot() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, String>(IDX_NAME);
+    for i in 0..100 {
+        map.put(&i, i.to_string());
+    }
+    let fork_proof = map.get_proof(42);
+    let fork_multiproof = map.get_multiproof(vec![1, 50, 1_000]);
+    let fork_size_proof = map.get_size_proof();
+    drop(map);
+    db.merge(fork.into_patch()).unwrap();
+
+    // Proofs are built purely from the snapshot, without any fork involved.
+    let snapshot = db.snapshot();
+    let mut map = snapshot.get_proof_map::<_, u64, String>(IDX_NAME);
+    let proof = map.get_proof(42);
+    check_map_proof(&proof, Some(42), &map);
+    assert_eq!(proof, fork_proof);
+    let proof = map.get_proof(1_000);
+    check_map_proof(&proof, None, &map);
+
+    let keys = vec![1, 50, 1_000];
+    let multiproof = map.get_multiproof(keys.clone());
+    check_map_multiproof(&multiproof, keys.clone(), &map);
+    assert_eq!(multiproof, fork_multiproof);
+    assert_eq!(map.get_size_proof(), fork_size_proof);
+
+    // The node cache can be used with read-only indexes as well.
+    map.set_node_cache_capacity(16);
+    assert_eq!(map.get_proof(42), fork_proof);
+    assert_eq!(map.get_multiproof(keys), fork_multiproof);
+}
+
+#[test]
+fn size_proofs() {
+    use super::SizeProof;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, String>(IDX_NAME);
+    assert_eq!(map.size(), 0);
+    let proof = map.get_size_proof();
+    assert_eq!(proof.check_against_hash(proof.sized_hash()).unwrap(), 0);
+    assert_eq!(proof.index_hash(), map.object_hash());
+
+    for i in 0..10 {
+        map.put(&i, i.to_string());
+    }
+    map.put(&5, "five".to_owned());
+    map.remove(&7);
+    map.remove(&100);
+    assert_eq!(map.size(), 9);
+
+    let proof = map.get_size_proof();
+    let trusted_hash = HashTag::hash_sized_map_node(9, map.merkle_root());
+    assert_eq!(proof.sized_hash(), trusted_hash);
+    assert_eq!(proof.check_against_hash(trusted_hash).unwrap(), 9);
+    assert_eq!(proof.index_hash(), map.object_hash());
+
+    // A proof with the tampered size does not match the trusted hash.
+    let mut json = serde_json::to_value(&proof).unwrap();
+    json["size"] = json!(10);
+    let tampered_proof: SizeProof = serde_json::from_value(json).unwrap();
+    assert_eq!(tampered_proof.size_unchecked(), 10);
+    assert!(tampered_proof.check_against_hash(trusted_hash).is_err());
+    // ...even though it still restores the `object_hash()` of the map.
+    assert_eq!(tampered_proof.index_hash(), map.object_hash());
+
+    // The size is persisted in the database and is retained by compaction.
+    drop(map);
+    db.merge(fork.into_patch()).unwrap();
+    let snapshot = db.snapshot();
+    let map = snapshot.get_proof_map::<_, u64, String>(IDX_NAME);
+    assert_eq!(map.size(), 9);
+    assert_eq!(map.get_size_proof(), proof);
+
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, String>(IDX_NAME);
+    map.compact_tree();
+    assert_eq!(map.size(), 9);
+    assert_eq!(map.get_size_proof(), proof);
+
+    // Removing all entries resets the size.
+    for i in 0..10 {
+        map.remove(&i);
+    }
+    assert_eq!(map.size(), 0);
+    let empty_map = fork.get_proof_map::<_, u64, String>((IDX_NAME, &1_u8));
+    assert_eq!(map.get_size_proof(), empty_map.get_size_proof());
+}
+
+#[test]
+fn legacy_map_state() {
+    use super::MapState;
+    use crate::SystemSchema;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, String>(IDX_NAME);
+    for i in 0..10 {
+        map.put(&i, i.to_string());
+    }
+    let hash = map.object_hash();
+    // Emulate the state written by older versions of the library.
+    let root_path = map.get_root_path().unwrap();
+    map.state.set(MapState {
+        root_path,
+        summary: None,
+    });
+    drop(map);
+    db.merge(fork.into_patch()).unwrap();
+
+    let snapshot = db.snapshot();
+    let map = snapshot.get_proof_map::<_, u64, String>(IDX_NAME);
+    assert_eq!(map.state.get().unwrap().summary, None);
+    assert_eq!(map.object_hash(), hash);
+    assert_eq!(map.size(), 10);
+    let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+    assert_eq!(aggregator.get(IDX_NAME), Some(hash));
+    map.get_proof(3).check_against_hash(hash).unwrap();
+
+    // The state is upgraded on the next modification.
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, String>(IDX_NAME);
+    map.put(&10, "10".to_owned());
+    assert!(map.state.get().unwrap().summary.is_some());
+    assert_eq!(map.size(), 11);
+
+    let root_path = map.get_root_path().unwrap();
+    map.state.set(MapState {
+        root_path,
+        summary: None,
+    });
+    map.remove(&0);
+    assert!(map.state.get().unwrap().summary.is_some());
+    assert_eq!(map.size(), 10);
+
+    let mut reference = fork.get_proof_map::<_, u64, String>("reference");
+    for i in 1..=10 {
+        reference.put(&i, i.to_string());
+    }
+    assert_eq!(map.object_hash(), reference.object_hash());
+}
+
+#[test]
+fn node_cache_does_not_affect_hashes_and_proofs() {
+    let mut rng = thread_rng();
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+
+    for &capacity in &[1, 2, 8, 1_000] {
+        let mut reference: ProofMapIndex<_, u16, u64> =
+            fork.get_proof_map(("reference", &(capacity as u64)));
+        let mut map: ProofMapIndex<_, u16, u64> =
+            fork.get_proof_map(("cached", &(capacity as u64)));
+        map.set_node_cache_capacity(capacity);
+        assert_eq!(map.node_cache_capacity(), capacity);
+
+        for i in 0..500_u64 {
+            let key = rng.gen_range(0..200_u16);
+            if rng.gen_bool(0.3) {
+                reference.remove(&key);
+                map.remove(&key);
+            } else {
+                reference.put(&key, i);
+                map.put(&key, i);
+            }
+            assert_eq!(map.object_hash(), reference.object_hash());
+            assert!(map.node_cache.borrow().len() <= capacity);
+
+            let proof_key = rng.gen_range(0..200_u16);
+            let proof = map.get_proof(proof_key);
+            let checked_proof = proof.check_against_hash(reference.object_hash()).unwrap();
+            assert_eq!(
+                checked_proof.entries().collect::<Vec<_>>(),
+                reference
+                    .get_proof(proof_key)
+                    .check()
+                    .unwrap()
+                    .entries()
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        // Shrinking the cache evicts nodes, but does not change the map.
+        let hash = map.object_hash();
+        map.set_node_cache_capacity(0);
+        assert_eq!(map.node_cache.borrow().len(), 0);
+        assert_eq!(map.object_hash(), hash);
+
+        map.set_node_cache_capacity(capacity);
+        map.clear();
+        reference.clear();
+        assert_eq!(map.node_cache.borrow().len(), 0);
+        map.put(&1, 1);
+        map.put(&2, 2);
+        assert_ne!(map.object_hash(), reference.object_hash());
+    }
+}
+
+#[test]
+fn display_for_checked_proofs() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u32, u32>(IDX_NAME);
+    for i in 0..10 {
+        map.put(&i, i);
+    }
+
+    let proof = map.get_multiproof(vec![1, 2, 3, 100, 200]);
+    let checked_proof = proof.check_against_hash(map.object_hash()).unwrap();
+    let message = checked_proof.to_string();
+    assert!(
+        message.contains(&map.object_hash().to_string()),
+        "{}",
+        message
+    );
+    assert!(message.contains("entries: 3"), "{}", message);
+    assert!(message.contains("missing keys: 2"), "{}", message);
+}
+
+#[test]
+fn witness_updates() {
+    let db = TemporaryDB::new();
+    let mut rng = thread_rng();
+    let watched_keys = vec![1_u16, 7, 50, 99, 1_000];
+
+    let fork = db.fork();
+    let mut witness = {
+        let mut map = fork.get_proof_map::<_, u16, u32>(IDX_NAME);
+        for key in 0..100 {
+            map.put(&key, u32::from(key));
+        }
+        map.get_multiproof(watched_keys.clone())
+    };
+    db.merge(fork.into_patch()).unwrap();
+
+    for round in 0..20 {
+        let checkpoint = db.snapshot();
+        let fork = db.fork();
+        {
+            let mut map = fork.get_proof_map::<_, u16, u32>(IDX_NAME);
+            let changes_count = if round < 15 { 10 } else { 100 };
+            for _ in 0..changes_count {
+                let key = rng.gen_range(0..120);
+                if round >= 15 || rng.gen_bool(0.4) {
+                    map.remove(&key);
+                } else {
+                    map.put(&key, rng.gen());
+                }
+            }
+            if round % 3 == 0 {
+                map.put(&watched_keys[round % watched_keys.len()], round as u32);
+            }
+        }
+
+        let map = fork.get_proof_map::<_, u16, u32>(IDX_NAME);
+        let old_map = checkpoint.get_proof_map::<_, u16, u32>(IDX_NAME);
+        let (_, changes) = map.changes_with_proofs_since(&old_map, map.object_hash());
+        if old_map.object_hash() != map.object_hash() {
+            assert!(witness.check_against_hash(map.object_hash()).is_err());
+        }
+
+        witness = witness.update_witness(&changes).unwrap();
+        let checked_witness = witness.check_against_hash(map.object_hash()).unwrap();
+        for (key, value) in checked_witness.all_entries() {
+            assert_eq!(value.copied(), map.get(key));
+        }
+        assert_eq!(checked_witness.all_entries().count(), watched_keys.len());
+
+        drop(map);
+        db.merge(fork.into_patch()).unwrap();
+    }
+}
+
+#[test]
+fn projected_proofs() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u32, u32>(IDX_NAME);
+    for i in 0..50 {
+        map.put(&i, i * 10);
+    }
+    let map_hash = map.object_hash();
+
+    // 8 existing and 2 missing keys.
+    let keys = vec![0, 3, 5, 8, 13, 21, 34, 42, 100, 200];
+    let proof = map.get_multiproof(keys.clone());
+
+    let projected_proof = proof.project(&[13, 200]).unwrap();
+    let checked_proof = projected_proof.check_against_hash(map_hash).unwrap();
+    assert_eq!(
+        checked_proof.entries().collect::<Vec<_>>(),
+        vec![(&13, &130)]
+    );
+    assert_eq!(checked_proof.missing_keys().collect::<Vec<_>>(), vec![&200]);
+    // The projected proof is as small as the proof built for the subset of keys.
+    let subset_proof = map.get_multiproof(vec![13, 200]);
+    assert_eq!(
+        projected_proof.proof_unchecked(),
+        subset_proof.proof_unchecked()
+    );
+
+    // Edge cases: projecting onto all keys, no keys and keys not covered by the proof.
+    let full_projection = proof.project(&keys).unwrap();
+    assert_eq!(full_projection.proof_unchecked(), proof.proof_unchecked());
+    for subset in &[vec![], vec![7], vec![200]] {
+        let projected_proof = proof.project(subset).unwrap();
+        let checked_proof = projected_proof.check_against_hash(map_hash).unwrap();
+        assert_eq!(checked_proof.entries().count(), 0);
+        let expected_proof = map.get_multiproof(subset.iter().copied().filter(|&key| key == 200));
+        assert_eq!(
+            projected_proof.proof_unchecked(),
+            expected_proof.proof_unchecked()
+        );
+    }
+
+    // Single-entry maps.
+    let mut map = fork.get_proof_map::<_, u32, u32>((IDX_NAME, &1_u8));
+    map.put(&1, 1);
+    let proof = map.get_multiproof(vec![1, 2]);
+    for subset in &[vec![], vec![1], vec![2], vec![1, 2]] {
+        let projected_proof = proof.project(subset).unwrap();
+        projected_proof
+            .check_against_hash(map.object_hash())
+            .unwrap();
+    }
+}
+
+#[test]
+fn compact_tree_preserves_root_and_proofs() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map: ProofMapIndex<_, u16, u64> = fork.get_proof_map(IDX_NAME);
+    for i in 0..500_u16 {
+        map.put(&i, u64::from(i));
+    }
+    for i in (0..500_u16).filter(|i| i % 7 != 0) {
+        map.remove(&i);
+    }
+
+    let hash = map.object_hash();
+    // The tree is already canonical after removals.
+    assert_eq!(map.compact_tree(), 0);
+    assert_eq!(map.object_hash(), hash);
+
+    // Add a dangling branch node which is not reachable from the root.
+    let stale_path = Hashed::transform_key(&1_u16).prefix(200);
+    map.base.put(&stale_path, BranchNode::empty());
+    assert_eq!(map.compact_tree(), 1);
+    assert!(!map.base.contains(&stale_path));
+    assert_eq!(map.object_hash(), hash);
+    assert_eq!(map.compact_tree(), 0);
+
+    for key in 0..500_u16 {
+        let proof = map.get_proof(key);
+        if key % 7 == 0 {
+            check_map_proof(&proof, Some(key), &map);
+        } else {
+            check_map_proof(&proof, None, &map);
+        }
+    }
+    let proof = map.get_multiproof(0..50);
+    check_map_multiproof(&proof, (0..50).collect(), &map);
+
+    // The map remains functional after compaction.
+    map.put(&1, 1);
+    map.remove(&0);
+    let mut reference: ProofMapIndex<_, u16, u64> = fork.get_proof_map("reference");
+    for (key, value) in &map {
+        reference.put(&key, value);
+    }
+    assert_eq!(map.object_hash(), reference.object_hash());
+
+    // Compacting a map with a single entry or an empty map works as well.
+    reference.clear();
+    reference.put(&1, 1);
+    let hash = reference.object_hash();
+    assert_eq!(reference.compact_tree(), 0);
+    assert_eq!(reference.object_hash(), hash);
+    reference.remove(&1);
+    assert_eq!(reference.compact_tree(), 0);
+    assert_eq!(reference.object_hash(), HashTag::empty_map_hash());
+}
+
+/// Snapshot counting the number of value bytes read from the wrapped snapshot.
+struct CountingSnapshot {
+    inner: Box<dyn Snapshot>,
+    bytes_read: Arc<AtomicUsize>,
+}
+
+impl Snapshot for CountingSnapshot {
+    fn get(&self, name: &ResolvedAddress, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.inner.get(name, key);
+        let len = value.as_ref().map_or(0, Vec::len);
+        self.bytes_read.fetch_add(len, Ordering::SeqCst);
+        value
+    }
+
+    fn iter(&self, name: &ResolvedAddress, from: &[u8]) -> Iter<'_> {
+        self.inner.iter(name, from)
+    }
+}
+
+#[test]
+fn contains_does_not_read_values() {
+    const VALUE_SIZE: usize = 10_000;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, Vec<u8>>(IDX_NAME);
+    map.put(&1, vec![1; VALUE_SIZE]);
+    map.put(&2, vec![2; VALUE_SIZE]);
+    db.merge(fork.into_patch()).unwrap();
+
+    let bytes_read = Arc::new(AtomicUsize::new(0));
+    let snapshot: Box<dyn Snapshot> = Box::new(CountingSnapshot {
+        inner: db.snapshot(),
+        bytes_read: Arc::clone(&bytes_read),
+    });
+    let map = snapshot.get_proof_map::<_, u64, Vec<u8>>(IDX_NAME);
+
+    let initial_bytes = bytes_read.load(Ordering::SeqCst);
+    assert!(map.contains(&1));
+    assert!(!map.contains(&3));
+    let contains_bytes = bytes_read.load(Ordering::SeqCst) - initial_bytes;
+    assert!(contains_bytes < VALUE_SIZE);
+
+    let initial_bytes = bytes_read.load(Ordering::SeqCst);
+    assert_eq!(map.get(&1), Some(vec![1; VALUE_SIZE]));
+    let get_bytes = bytes_read.load(Ordering::SeqCst) - initial_bytes;
+    assert!(get_bytes >= VALUE_SIZE);
+    assert!(contains_bytes < get_bytes);
+
+    // `contains` is consistent with `get` after changes in a fork.
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, Vec<u8>>(IDX_NAME);
+    map.remove(&1);
+    map.put(&3, vec![]);
+    for key in 0..5 {
+        assert_eq!(map.contains(&key), map.get(&key).is_some());
+    }
+}
+
+#[test]
+fn custom_node_hasher() {
+    use assert_matches::assert_matches;
+
+    use crate::{
+        access::{AccessErrorKind, FromAccess},
+        Blake2bNodeHasher, SystemSchema,
+    };
+
+    type Blake2bMap<T> = ProofMapIndex<T, u32, u64, Hashed, Blake2bNodeHasher>;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let group = fork.get_group::<_, u32, Blake2bMap<_>>("group");
+    let mut map = group.get(&1);
+    let mut default_map = fork.get_proof_map(IDX_NAME);
+    for i in 0_u32..50 {
+        map.put(&i, u64::from(i));
+        default_map.put(&i, u64::from(i));
+    }
+    assert_eq!(map.get(&10), Some(10));
+    assert_ne!(map.object_hash(), default_map.object_hash());
+
+    // The root hash does not depend on the order of operations.
+    let mut other_map = group.get(&2);
+    for i in (0_u32..60).rev() {
+        other_map.put(&i, 0);
+    }
+    for i in 0_u32..50 {
+        other_map.put(&i, u64::from(i));
+    }
+    for i in 50_u32..60 {
+        other_map.remove(&i);
+    }
+    assert_eq!(other_map.object_hash(), map.object_hash());
+    let hash = map.object_hash();
+    other_map.compact_tree();
+    assert_eq!(other_map.object_hash(), hash);
+
+    // Single-entry maps are hashed with the custom hasher as well.
+    let mut single_entry_map = group.get(&3);
+    single_entry_map.put(&1, 1);
+    let mut default_map = fork.get_proof_map("single_entry");
+    default_map.put(&1_u32, 1_u64);
+    assert_ne!(single_entry_map.object_hash(), default_map.object_hash());
+
+    // Proofs are built and checked with the custom hasher.
+    let proof = map.get_multiproof(vec![1, 5, 100]);
+    let checked_proof = proof.check_against_hash(hash).unwrap();
+    let mut entries: Vec<_> = checked_proof.entries().collect();
+    entries.sort_unstable();
+    assert_eq!(entries, vec![(&1, &1), (&5, &5)]);
+    assert_eq!(checked_proof.missing_keys().collect::<Vec<_>>(), vec![&100]);
+    let proof = single_entry_map.get_proof(1);
+    proof
+        .check_against_hash(single_entry_map.object_hash())
+        .unwrap();
+    // The proof cannot be checked with another hasher.
+    let proof: MapProof<u32, u64> =
+        serde_json::from_value(serde_json::to_value(&map.get_proof(1)).unwrap()).unwrap();
+    assert!(proof.check_against_hash(hash).is_err());
+
+    // Maps with custom hashers are aggregated with their own hashes.
+    let fork = db.fork();
+    {
+        let mut map = Blake2bMap::from_access(&fork, "blake2b".into()).unwrap();
+        for i in 0_u32..50 {
+            map.put(&i, u64::from(i));
+        }
+        assert_eq!(map.object_hash(), hash);
+    }
+    db.merge(fork.into_patch()).unwrap();
+    let snapshot = db.snapshot();
+    let aggregator = SystemSchema::new(&snapshot).state_aggregator();
+    assert_eq!(aggregator.get("blake2b"), Some(hash));
+
+    // The map cannot be opened with another hasher.
+    let err =
+        ProofMapIndex::<_, u32, u64>::from_access(snapshot.as_ref(), "blake2b".into()).unwrap_err();
+    assert_matches!(
+        err.kind,
+        AccessErrorKind::WrongNodeHasher {
+            expected: 0,
+            actual: 1
+        }
+    );
+}
+
+#[test]
+fn prefix_proof() {
+    fn key(prefix: &[u8], filler: u8) -> [u8; KEY_SIZE] {
+        let mut key = [filler; KEY_SIZE];
+        key[..prefix.len()].copy_from_slice(prefix);
+        key
+    }
+
+    const PREFIX: &[u8] = &[1, 2];
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_raw_proof_map::<_, [u8; KEY_SIZE], u64>(IDX_NAME);
+    assert_eq!(
+        map.get_prefix_proof(PREFIX)
+            .check_prefix(PREFIX)
+            .unwrap()
+            .entries()
+            .count(),
+        0
+    );
+
+    let mut expected = vec![
+        (key(PREFIX, 0), 0),
+        (key(&[1, 2, 3], 1), 1),
+        (key(&[1, 2, 255], 2), 2),
+    ];
+    for &(key, value) in &expected {
+        map.put(&key, value);
+    }
+    // Keys sharing a part of the prefix.
+    let other_keys = [
+        key(&[1], 3),
+        key(&[1, 3], 4),
+        key(&[0, 2], 5),
+        key(&[255], 6),
+    ];
+    for (value, key) in (100..).zip(&other_keys) {
+        map.put(key, value);
+    }
+    let mut rng = thread_rng();
+    for value in 200..300 {
+        let key: [u8; KEY_SIZE] = rng.gen();
+        if !key.starts_with(PREFIX) {
+            map.put(&key, value);
+        }
+    }
+    let map_hash = map.object_hash();
+
+    let proof = map.get_prefix_proof(PREFIX);
+    let checked_proof = proof.check_prefix_against_hash(PREFIX, map_hash).unwrap();
+    let mut entries: Vec<_> = checked_proof.entries().map(|(k, v)| (*k, *v)).collect();
+    entries.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(entries, expected);
+    assert_eq!(checked_proof.missing_keys().count(), 0);
+
+    // A proof omitting some entries with the prefix is valid, but incomplete.
+    let partial_proof = map.get_multiproof(vec![expected[0].0, expected[1].0]);
+    assert!(partial_proof.check_against_hash(map_hash).is_ok());
+    let err = partial_proof.check_prefix(PREFIX).unwrap_err();
+    assert!(
+        matches!(err, MapProofError::HiddenPrefixEntries { .. }),
+        "{:?}",
+        err
+    );
+
+    // The proof is not complete for shorter prefixes, and contains entries
+    // outside of longer ones.
+    let err = proof.check_prefix(&[1]).unwrap_err();
+    assert!(
+        matches!(err, MapProofError::HiddenPrefixEntries { .. }),
+        "{:?}",
+        err
+    );
+    let err = proof.check_prefix(&[1, 2, 3]).unwrap_err();
+    assert!(
+        matches!(err, MapProofError::OutsidePrefix { .. }),
+        "{:?}",
+        err
+    );
+
+    // Prefixes without entries.
+    let empty_proof = map.get_prefix_proof(&[1, 4]);
+    let checked_proof = empty_proof
+        .check_prefix_against_hash(&[1, 4], map_hash)
+        .unwrap();
+    assert_eq!(checked_proof.entries().count(), 0);
+
+    // Full keys and the empty prefix.
+    let full_key = expected[1].0;
+    let key_proof = map.get_prefix_proof(&full_key);
+    let checked_proof = key_proof
+        .check_prefix_against_hash(&full_key, map_hash)
+        .unwrap();
+    assert_eq!(
+        checked_proof.entries().collect::<Vec<_>>(),
+        vec![(&full_key, &1)]
+    );
+    let all_proof = map.get_prefix_proof(&[]);
+    let checked_proof = all_proof.check_prefix_against_hash(&[], map_hash).unwrap();
+    assert_eq!(checked_proof.entries().count(), map.iter().count());
+
+    // Map with a single entry.
+    map.clear();
+    map.put(&expected[0].0, 0);
+    let map_hash = map.object_hash();
+    let proof = map.get_prefix_proof(PREFIX);
+    assert_eq!(
+        proof
+            .check_prefix_against_hash(PREFIX, map_hash)
+            .unwrap()
+            .entries()
+            .count(),
+        1
+    );
+    let other_proof = map.get_prefix_proof(&[1, 3]);
+    let checked_proof = other_proof
+        .check_prefix_against_hash(&[1, 3], map_hash)
+        .unwrap();
+    assert_eq!(checked_proof.entries().count(), 0);
+}
+
+#[test]
+fn union_of_disjoint_shards() {
+    use super::ValidationError;
+    use assert_matches::assert_matches;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut rng = thread_rng();
+
+    // Shards split by the first bit of raw keys (bits in `ProofPath`s are ordered
+    // from the least significant one).
+    let mut left = fork.get_raw_proof_map::<_, [u8; KEY_SIZE], u64>("left");
+    let mut right = fork.get_raw_proof_map::<_, [u8; KEY_SIZE], u64>("right");
+    let mut reference = fork.get_raw_proof_map::<_, [u8; KEY_SIZE], u64>("reference");
+    for value in 0..200 {
+        let key: [u8; KEY_SIZE] = rng.gen();
+        if key[0] % 2 == 0 {
+            left.put(&key, value);
+        } else {
+            right.put(&key, value);
+        }
+        reference.put(&key, value);
+    }
+    let (left_hash, right_hash) = (left.object_hash(), right.object_hash());
+
+    let (union_hash, proof) = left.union_with_proof(&right).unwrap();
+    assert_eq!(union_hash, reference.object_hash());
+    assert_eq!(union_hash, left.object_hash());
+    let checked_proof = proof
+        .check_against_hashes(left_hash, right_hash, union_hash)
+        .unwrap();
+    assert_eq!(checked_proof.left_hash(), left_hash);
+    // The proof consists of the children of the roots of both shards.
+    let proof_json = serde_json::to_value(&proof).unwrap();
+    assert_eq!(proof_json["left"].as_array().unwrap().len(), 2);
+    assert_eq!(proof_json["right"].as_array().unwrap().len(), 2);
+
+    // The proof is bound to the order of the maps and the hashes.
+    let err = proof
+        .check_against_hashes(right_hash, left_hash, union_hash)
+        .unwrap_err();
+    assert_matches!(err, ValidationError::UnmatchedRootHash);
+    let err = proof
+        .check_against_hashes(left_hash, right_hash, right_hash)
+        .unwrap_err();
+    assert_matches!(err, ValidationError::UnmatchedRootHash);
+
+    // Maps with interleaved keys.
+    let mut even = fork.get_proof_map::<_, u64, String>("even");
+    let mut odd = fork.get_proof_map::<_, u64, String>("odd");
+    let mut reference = fork.get_proof_map::<_, u64, String>("all");
+    for i in 0..100_u64 {
+        let map = if i % 2 == 0 { &mut even } else { &mut odd };
+        map.put(&i, i.to_string());
+        reference.put(&i, i.to_string());
+    }
+    let (even_hash, odd_hash) = (even.object_hash(), odd.object_hash());
+    let (union_hash, proof) = odd.union_with_proof(&even).unwrap();
+    assert_eq!(union_hash, reference.object_hash());
+    proof
+        .check_against_hashes(odd_hash, even_hash, union_hash)
+        .unwrap();
+
+    // Common keys are not allowed.
+    let err = odd.union_with_proof(&even).unwrap_err();
+    assert!((0..100_u64).any(|i| Hashed::transform_key(&i) == err.path));
+    assert_eq!(odd.object_hash(), union_hash);
+
+    // Union with an empty map.
+    let mut empty = fork.get_proof_map::<_, u64, String>("empty");
+    let (hash, proof) = even.union_with_proof(&empty).unwrap();
+    assert_eq!(hash, even_hash);
+    proof
+        .check_against_hashes(even_hash, HashTag::empty_map_hash(), even_hash)
+        .unwrap();
+    let (hash, proof) = empty.union_with_proof(&even).unwrap();
+    assert_eq!(hash, even_hash);
+    proof
+        .check_against_hashes(HashTag::empty_map_hash(), even_hash, even_hash)
+        .unwrap();
+
+    // Maps with single entries.
+    let mut first = fork.get_proof_map::<_, u64, String>("first");
+    first.put(&1, "one".to_owned());
+    let mut second = fork.get_proof_map::<_, u64, String>("second");
+    second.put(&2, "two".to_owned());
+    let mut both = fork.get_proof_map::<_, u64, String>("both");
+    both.put(&1, "one".to_owned());
+    both.put(&2, "two".to_owned());
+    let (first_hash, second_hash) = (first.object_hash(), second.object_hash());
+    let (hash, proof) = first.union_with_proof(&second).unwrap();
+    assert_eq!(hash, both.object_hash());
+    proof
+        .check_against_hashes(first_hash, second_hash, hash)
+        .unwrap();
+}
+
+#[cfg(feature = "rkyv")]
+#[test]
+fn archived_proof_is_validated() {
+    use rkyv::Deserialize;
+
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    let mut map = fork.get_proof_map::<_, u64, String>(IDX_NAME);
+    for i in 0_u64..20 {
+        map.put(&i, i.to_string());
+    }
+    let proof = map.get_multiproof(vec![1, 7, 100]);
+    let bytes = rkyv::to_bytes::<_, 1_024>(&proof).unwrap();
+
+    let archived = rkyv::check_archived_root::<MapProof<u64, String>>(&bytes).unwrap();
+    let restored: MapProof<u64, String> = archived.deserialize(&mut rkyv::Infallible).unwrap();
+    assert_eq!(restored, proof);
+    let checked_proof = restored.check_against_hash(map.object_hash()).unwrap();
+    let mut entries: Vec<_> = checked_proof.entries().collect();
+    entries.sort_unstable();
+    assert_eq!(entries, vec![(&1, &"1".to_owned()), (&7, &"7".to_owned())]);
+    assert_eq!(checked_proof.missing_keys().collect::<Vec<_>>(), vec![&100]);
+
+    // Truncated data is rejected instead of being accessed out of bounds.
+    let truncated = &bytes[..bytes.len() - 4];
+    assert!(rkyv::check_archived_root::<MapProof<u64, String>>(truncated).is_err());
+}
@@ -0,0 +1,267 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proofs that a `ProofMapIndex` is the union of two maps with disjoint keys.
+
+use serde_derive::{Deserialize, Serialize};
+use thiserror::Error;
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use super::{
+    key::{BitsRange, ChildKind, ProofPath},
+    node::Node,
+    proof::{check_ordering, collect, MapProofEntry},
+    proof_builder::MerklePatriciaTree,
+    MapProofError,
+};
+use crate::{
+    crypto::Hash,
+    hash::{DefaultNodeHasher, NodeHasher},
+    HashTag, ValidationError,
+};
+
+/// Error returned by [`ProofMapIndex::union_with_proof()`] if the united maps
+/// have a common key.
+///
+/// [`ProofMapIndex::union_with_proof()`]: struct.ProofMapIndex.html#method.union_with_proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("united maps have a common key")]
+pub struct OverlappingKeysError {
+    /// Path corresponding to the common key.
+    pub path: ProofPath,
+}
+
+/// Proof that a map is the union of two maps with disjoint keys.
+///
+/// The proof consists of two cuts of the Merkle Patricia trees of the united maps,
+/// i.e., sets of subtrees covering all entries of each map. Subtrees are represented
+/// by their paths and hashes. The cuts are chosen so that no subtree of one map may
+/// contain keys of a subtree of the other map. Hence, each subtree is also a subtree
+/// of the Merkle Patricia tree of the union, and the hash of the union can be computed
+/// from both cuts together. The proof is small if keys of the united maps are grouped
+/// in few ranges (e.g., if the maps are shards with raw keys split by prefix), and is
+/// linear in the number of entries in the worst case.
+///
+/// Similar to [`MapProof`], the `H` type parameter specifies the node hasher of the maps.
+///
+/// See [`ProofMapIndex::union_with_proof`] for an example of usage.
+///
+/// [`MapProof`]: struct.GenericMapProof.html#node-hasher
+/// [`ProofMapIndex::union_with_proof`]: struct.ProofMapIndex.html#method.union_with_proof
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnionProof<H = DefaultNodeHasher> {
+    left: Vec<MapProofEntry>,
+    right: Vec<MapProofEntry>,
+    #[serde(skip)]
+    _hasher: PhantomData<H>,
+}
+
+/// Version of [`UnionProof`] obtained after verification.
+///
+/// [`UnionProof`]: struct.UnionProof.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckedUnionProof {
+    left_hash: Hash,
+    right_hash: Hash,
+    union_hash: Hash,
+}
+
+impl CheckedUnionProof {
+    /// Returns the `object_hash()` of the left united map.
+    pub fn left_hash(&self) -> Hash {
+        self.left_hash
+    }
+
+    /// Returns the `object_hash()` of the right united map.
+    pub fn right_hash(&self) -> Hash {
+        self.right_hash
+    }
+
+    /// Returns the `object_hash()` of the union of the maps.
+    pub fn union_hash(&self) -> Hash {
+        self.union_hash
+    }
+}
+
+fn map_hash<H: NodeHasher>(entries: &[MapProofEntry]) -> Result<Hash, MapProofError> {
+    let entries: Vec<_> = entries.iter().map(Cow::Borrowed).collect();
+    collect::<H>(&entries).map(HashTag::hash_map_node)
+}
+
+impl<H: NodeHasher> UnionProof<H> {
+    /// Checks the proof and computes the hashes of the united maps and of their union.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the proof is malformed. In particular, the proof is invalid
+    /// if a subtree of one map may contain keys of a subtree of the other map.
+    pub fn check(&self) -> Result<CheckedUnionProof, MapProofError> {
+        check_ordering(&self.left)?;
+        check_ordering(&self.right)?;
+        let left_hash = map_hash::<H>(&self.left)?;
+        let right_hash = map_hash::<H>(&self.right)?;
+
+        let mut union = self.left.clone();
+        union.extend_from_slice(&self.right);
+        union.sort_unstable_by(|x, y| {
+            x.path
+                .partial_cmp(&y.path)
+                .expect("Incomparable paths in proof")
+        });
+        check_ordering(&union)?;
+
+        Ok(CheckedUnionProof {
+            left_hash,
+            right_hash,
+            union_hash: map_hash::<H>(&union)?,
+        })
+    }
+
+    /// Checks the proof against the trusted hashes of the united maps and of their union.
+    /// Fails if the proof is malformed or any of the hashes does not match the one computed
+    /// from the proof.
+    pub fn check_against_hashes(
+        &self,
+        left_hash: Hash,
+        right_hash: Hash,
+        union_hash: Hash,
+    ) -> Result<CheckedUnionProof, ValidationError<MapProofError>> {
+        let checked = self.check().map_err(ValidationError::Malformed)?;
+        if checked.left_hash == left_hash
+            && checked.right_hash == right_hash
+            && checked.union_hash == union_hash
+        {
+            Ok(checked)
+        } else {
+            Err(ValidationError::UnmatchedRootHash)
+        }
+    }
+}
+
+/// Subtrees in a cut of a Merkle Patricia tree.
+type Cut = Vec<MapProofEntry>;
+
+/// Returns children of a branch node at the specified path.
+fn children<K: ?Sized, V, H: NodeHasher>(
+    tree: &impl MerklePatriciaTree<K, V, H>,
+    path: &ProofPath,
+) -> [MapProofEntry; 2] {
+    match tree.node(path) {
+        Node::Branch(branch) => [ChildKind::Left, ChildKind::Right].map(|kind| MapProofEntry {
+            path: branch.child_path(kind),
+            hash: branch.child_hash(kind),
+        }),
+        Node::Leaf(_) => unreachable!("Leaf nodes have full-length paths"),
+    }
+}
+
+/// Adds subtrees of the left and right trees to the corresponding cuts, expanding
+/// the subtrees until they do not contain each other.
+fn unite_subtrees<K: ?Sized, V, H: NodeHasher>(
+    trees: (
+        &impl MerklePatriciaTree<K, V, H>,
+        &impl MerklePatriciaTree<K, V, H>,
+    ),
+    left: MapProofEntry,
+    right: MapProofEntry,
+    cuts: &mut (Cut, Cut),
+) -> Result<(), OverlappingKeysError> {
+    if left.path == right.path {
+        if left.path.is_leaf() {
+            return Err(OverlappingKeysError { path: left.path });
+        }
+        let left_children = children(trees.0, &left.path);
+        let right_children = children(trees.1, &right.path);
+        for (left, right) in left_children.into_iter().zip(right_children) {
+            unite_subtrees(trees, left, right, cuts)?;
+        }
+    } else if right.path.starts_with(&left.path) {
+        // The right subtree is contained in one of children of the left subtree.
+        let next_bit = right.path.bit(left.path.len());
+        for child in children(trees.0, &left.path) {
+            if child.path.bit(left.path.len()) == next_bit {
+                unite_subtrees(trees, child, right, cuts)?;
+            } else {
+                cuts.0.push(child);
+            }
+        }
+    } else if left.path.starts_with(&right.path) {
+        let next_bit = left.path.bit(right.path.len());
+        for child in children(trees.1, &right.path) {
+            if child.path.bit(right.path.len()) == next_bit {
+                unite_subtrees(trees, left, child, cuts)?;
+            } else {
+                cuts.1.push(child);
+            }
+        }
+    } else {
+        cuts.0.push(left);
+        cuts.1.push(right);
+    }
+    Ok(())
+}
+
+/// Returns the root of a tree as a subtree.
+fn root<K: ?Sized, V, H: NodeHasher>(
+    tree: &impl MerklePatriciaTree<K, V, H>,
+) -> Option<MapProofEntry> {
+    tree.root_node().map(|(path, node)| match node {
+        Node::Leaf(hash) => MapProofEntry { path, hash },
+        Node::Branch(branch) => MapProofEntry {
+            path,
+            hash: branch.hash_with::<H>(),
+        },
+    })
+}
+
+/// Splits a cut consisting of a single branch node into the children of the node,
+/// since a single branch node cannot be used to restore the map hash.
+fn split_root<K: ?Sized, V, H: NodeHasher>(tree: &impl MerklePatriciaTree<K, V, H>, cut: &mut Cut) {
+    if let [root] = cut.as_slice() {
+        if !root.path.is_leaf() {
+            *cut = children(tree, &root.path).to_vec();
+        }
+    }
+}
+
+/// Builds a proof that the union of two Merkelized maps is a map with entries
+/// from both maps.
+pub(super) fn create_union_proof<K: ?Sized, V, H: NodeHasher>(
+    left_tree: &impl MerklePatriciaTree<K, V, H>,
+    right_tree: &impl MerklePatriciaTree<K, V, H>,
+) -> Result<UnionProof<H>, OverlappingKeysError> {
+    let mut cuts = (vec![], vec![]);
+    match (root(left_tree), root(right_tree)) {
+        (Some(left), Some(right)) => {
+            unite_subtrees((left_tree, right_tree), left, right, &mut cuts)?;
+        }
+        (left, right) => {
+            cuts.0.extend(left);
+            cuts.1.extend(right);
+        }
+    }
+
+    let (mut left, mut right) = cuts;
+    split_root(left_tree, &mut left);
+    split_root(right_tree, &mut right);
+    for cut in [&mut left, &mut right] {
+        cut.sort_unstable_by(|x, y| x.path.partial_cmp(&y.path).unwrap());
+    }
+    Ok(UnionProof {
+        left,
+        right,
+        _hasher: PhantomData,
+    })
+}
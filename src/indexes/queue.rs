@@ -0,0 +1,396 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! First-in, first-out queues built on top of lists.
+//!
+//! Both [`Queue`] and [`ProofQueue`] store items in a list and keep the position
+//! of the queue head in a separate [`Entry`]. Enqueuing pushes an item to the end of the list,
+//! and dequeuing advances the head, so both operations take constant time. Dequeued items
+//! remain in the list until the queue becomes empty, at which point the list is cleared.
+//!
+//! [`Queue`]: struct.Queue.html
+//! [`ProofQueue`]: struct.ProofQueue.html
+//! [`Entry`]: ../struct.Entry.html
+
+use crate::{
+    access::{Access, AccessError, FromAccess},
+    crypto::{Hash, HashStream},
+    indexes::{iter::Values, proof_list::ListProof},
+    views::{IndexAddress, RawAccess, RawAccessMut},
+    BinaryValue, Entry, ListIndex, ObjectHash, ProofListIndex,
+};
+
+const ITEMS_NAME: &str = "items";
+const HEAD_NAME: &str = "head";
+
+/// A first-in, first-out queue of items.
+///
+/// The queue consists of two indexes: a [`ListIndex`] with items at the address
+/// `{addr}.items`, and an [`Entry`] with the position of the queue head at the address
+/// `{addr}.head`. The tail of the queue is the length of the list.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::FromAccess, Database, Queue, TemporaryDB};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut queue = Queue::from_root(&fork).unwrap();
+/// queue.enqueue(1_u32);
+/// queue.enqueue(2);
+/// assert_eq!(queue.len(), 2);
+/// assert_eq!(queue.peek(), Some(1));
+/// assert_eq!(queue.dequeue(), Some(1));
+/// assert_eq!(queue.dequeue(), Some(2));
+/// assert_eq!(queue.dequeue(), None);
+/// ```
+///
+/// [`ListIndex`]: struct.ListIndex.html
+/// [`Entry`]: struct.Entry.html
+#[derive(Debug)]
+pub struct Queue<T: RawAccess, V> {
+    items: ListIndex<T, V>,
+    head: Entry<T, u64>,
+}
+
+impl<T, V> FromAccess<T> for Queue<T::Base, V>
+where
+    T: Access,
+    V: BinaryValue,
+{
+    fn from_access(access: T, addr: IndexAddress) -> Result<Self, AccessError> {
+        Ok(Self {
+            items: ListIndex::from_access(access.clone(), addr.clone().append_name(ITEMS_NAME))?,
+            head: Entry::from_access(access, addr.append_name(HEAD_NAME))?,
+        })
+    }
+}
+
+impl<T, V> Queue<T, V>
+where
+    T: RawAccess,
+    V: BinaryValue,
+{
+    fn head(&self) -> u64 {
+        self.head.get().unwrap_or_default()
+    }
+
+    /// Returns the item at the head of the queue without removing it, or `None`
+    /// if the queue is empty.
+    pub fn peek(&self) -> Option<V> {
+        self.items.get(self.head())
+    }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&self) -> u64 {
+        self.items.len() - self.head()
+    }
+
+    /// Returns `true` if the queue has no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the items in the queue, starting from its head.
+    pub fn iter(&self) -> Values<'_, V> {
+        self.items.iter_from(self.head())
+    }
+}
+
+impl<T, V> Queue<T, V>
+where
+    T: RawAccessMut,
+    V: BinaryValue,
+{
+    /// Adds an item to the tail of the queue.
+    pub fn enqueue(&mut self, value: V) {
+        self.items.push(value);
+    }
+
+    /// Removes the item at the head of the queue and returns it, or returns `None`
+    /// if the queue is empty.
+    pub fn dequeue(&mut self) -> Option<V> {
+        let head = self.head();
+        let value = self.items.get(head)?;
+        if head + 1 == self.items.len() {
+            self.clear();
+        } else {
+            self.head.set(head + 1);
+        }
+        Some(value)
+    }
+
+    /// Removes all items from the queue.
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.head.remove();
+    }
+}
+
+impl<'a, T, V> IntoIterator for &'a Queue<T, V>
+where
+    T: RawAccess,
+    V: BinaryValue,
+{
+    type Item = V;
+    type IntoIter = Values<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A first-in, first-out queue of items with a hash committing to its contents.
+///
+/// `ProofQueue` is similar to [`Queue`], but stores items in a [`ProofListIndex`].
+/// The [`object_hash`] of the queue commits to the position of the queue head and
+/// the items list, and thus to the items pending in the queue. Membership of pending items
+/// can be proven with [`get_pending_proof`].
+///
+/// Since dequeued items remain in the list until the queue is emptied, two queues with
+/// the same pending items may have different hashes if their histories differ. An empty queue
+/// always has the same hash.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::FromAccess, Database, ObjectHash, ProofQueue, TemporaryDB};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut queue = ProofQueue::from_root(&fork).unwrap();
+/// let empty_hash = queue.object_hash();
+/// queue.enqueue(1_u32);
+/// assert_ne!(queue.object_hash(), empty_hash);
+/// assert_eq!(queue.dequeue(), Some(1));
+/// assert_eq!(queue.object_hash(), empty_hash);
+/// ```
+///
+/// [`Queue`]: struct.Queue.html
+/// [`ProofListIndex`]: proof_list/struct.ProofListIndex.html
+/// [`object_hash`]: #impl-ObjectHash
+/// [`get_pending_proof`]: #method.get_pending_proof
+#[derive(Debug)]
+pub struct ProofQueue<T: RawAccess, V> {
+    items: ProofListIndex<T, V>,
+    head: Entry<T, u64>,
+}
+
+impl<T, V> FromAccess<T> for ProofQueue<T::Base, V>
+where
+    T: Access,
+    V: BinaryValue,
+{
+    fn from_access(access: T, addr: IndexAddress) -> Result<Self, AccessError> {
+        Ok(Self {
+            items: ProofListIndex::from_access(
+                access.clone(),
+                addr.clone().append_name(ITEMS_NAME),
+            )?,
+            head: Entry::from_access(access, addr.append_name(HEAD_NAME))?,
+        })
+    }
+}
+
+impl<T, V> ProofQueue<T, V>
+where
+    T: RawAccess,
+    V: BinaryValue,
+{
+    fn head(&self) -> u64 {
+        self.head.get().unwrap_or_default()
+    }
+
+    /// Returns the item at the head of the queue without removing it, or `None`
+    /// if the queue is empty.
+    pub fn peek(&self) -> Option<V> {
+        self.items.get(self.head())
+    }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&self) -> u64 {
+        self.items.len() - self.head()
+    }
+
+    /// Returns `true` if the queue has no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the items in the queue, starting from its head.
+    pub fn iter(&self) -> Values<'_, V> {
+        self.items.iter_from(self.head())
+    }
+
+    /// Returns the underlying list of items, which includes dequeued items preceding
+    /// the head of the queue.
+    pub fn items(&self) -> &ProofListIndex<T, V> {
+        &self.items
+    }
+
+    /// Returns the position of the queue head in the [`items`] list.
+    ///
+    /// [`items`]: #method.items
+    pub fn head_position(&self) -> u64 {
+        self.head()
+    }
+
+    /// Returns a proof of the pending items in the underlying list. The proof is checked
+    /// against the hash of the [`items`] list; the queue hash can then be restored
+    /// with [`hash_from_parts`].
+    ///
+    /// [`items`]: #method.items
+    /// [`hash_from_parts`]: #method.hash_from_parts
+    pub fn get_pending_proof(&self) -> ListProof<V> {
+        self.items.get_range_proof(self.head()..)
+    }
+
+    /// Computes the hash of a queue from the position of its head and the hash
+    /// of the items list.
+    pub fn hash_from_parts(head_position: u64, items_hash: Hash) -> Hash {
+        HashStream::new()
+            .update(&head_position.to_le_bytes())
+            .update(items_hash.as_ref())
+            .hash()
+    }
+}
+
+impl<T, V> ProofQueue<T, V>
+where
+    T: RawAccessMut,
+    V: BinaryValue,
+{
+    /// Adds an item to the tail of the queue.
+    pub fn enqueue(&mut self, value: V) {
+        self.items.push(value);
+    }
+
+    /// Removes the item at the head of the queue and returns it, or returns `None`
+    /// if the queue is empty.
+    pub fn dequeue(&mut self) -> Option<V> {
+        let head = self.head();
+        let value = self.items.get(head)?;
+        if head + 1 == self.items.len() {
+            self.clear();
+        } else {
+            self.head.set(head + 1);
+        }
+        Some(value)
+    }
+
+    /// Removes all items from the queue.
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.head.remove();
+    }
+}
+
+impl<T, V> ObjectHash for ProofQueue<T, V>
+where
+    T: RawAccess,
+    V: BinaryValue,
+{
+    fn object_hash(&self) -> Hash {
+        Self::hash_from_parts(self.head(), self.items.object_hash())
+    }
+}
+
+impl<'a, T, V> IntoIterator for &'a ProofQueue<T, V>
+where
+    T: RawAccess,
+    V: BinaryValue,
+{
+    type Item = V;
+    type IntoIter = Values<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProofQueue, Queue};
+    use crate::{access::FromAccess, Database, ObjectHash, TemporaryDB};
+
+    #[test]
+    fn queue_is_fifo() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut queue = Queue::from_access(&fork, "queue".into()).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek(), None);
+        assert_eq!(queue.dequeue(), None);
+
+        for i in 0_u32..5 {
+            queue.enqueue(i);
+        }
+        assert_eq!(queue.dequeue(), Some(0));
+        assert_eq!(queue.dequeue(), Some(1));
+        queue.enqueue(5);
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.peek(), Some(2));
+        assert_eq!(queue.iter().collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+
+        db.merge(fork.into_patch()).unwrap();
+        let fork = db.fork();
+        let mut queue = Queue::<_, u32>::from_access(&fork, "queue".into()).unwrap();
+        let mut dequeued = vec![];
+        while let Some(item) = queue.dequeue() {
+            dequeued.push(item);
+        }
+        assert_eq!(dequeued, vec![2, 3, 4, 5]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.dequeue(), None);
+
+        // The queue is fully reset after being emptied.
+        queue.enqueue(6);
+        assert_eq!(queue.items.len(), 1);
+        assert_eq!(queue.dequeue(), Some(6));
+    }
+
+    #[test]
+    fn proof_queue_hash() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut queue = ProofQueue::from_access(&fork, "queue".into()).unwrap();
+        let empty_hash = queue.object_hash();
+        assert_eq!(queue.dequeue(), None);
+        assert_eq!(queue.object_hash(), empty_hash);
+
+        queue.enqueue(1_u32);
+        queue.enqueue(2);
+        queue.enqueue(3);
+        let full_hash = queue.object_hash();
+        assert_eq!(queue.dequeue(), Some(1));
+        assert_ne!(queue.object_hash(), full_hash);
+        assert_eq!(queue.peek(), Some(2));
+
+        let proof = queue.get_pending_proof();
+        let checked = proof
+            .check_against_hash(queue.items().object_hash())
+            .unwrap();
+        assert_eq!(checked.entries(), [(1, 2), (2, 3)]);
+        assert_eq!(
+            ProofQueue::<&_, u32>::hash_from_parts(queue.head_position(), checked.index_hash()),
+            queue.object_hash()
+        );
+
+        assert_eq!(queue.dequeue(), Some(2));
+        assert_eq!(queue.dequeue(), Some(3));
+        assert_eq!(queue.dequeue(), None);
+        assert_eq!(queue.object_hash(), empty_hash);
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An implementation of a key-value map split into several shards.
+//!
+//! `ShardedMap` distributes its entries among several [`MapIndex`]es based
+//! on the hash of the key, while providing the same interface as a single map.
+//!
+//! [`MapIndex`]: ../struct.MapIndex.html
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use std::{borrow::Borrow, fmt, iter::Peekable};
+
+use crate::{
+    access::{Access, AccessError, FromAccess},
+    crypto,
+    indexes::iter::Entries,
+    views::{IndexAddress, RawAccess, RawAccessMut},
+    BinaryKey, BinaryValue, MapIndex,
+};
+
+fn key_bytes<K: BinaryKey + ?Sized>(key: &K) -> Vec<u8> {
+    let mut buffer = vec![0_u8; key.size()];
+    key.write(&mut buffer);
+    buffer
+}
+
+/// A map of keys and values split into a fixed number of shards.
+///
+/// Each shard is a separate [`MapIndex`] in the group with the address of the sharded map;
+/// the shard for a key is determined by the hash of the serialized key. As a result,
+/// entries are distributed among shards uniformly, and shards may be stored (or processed)
+/// separately, e.g., in different column families. Iteration over the sharded map merges
+/// shards, so that entries are returned in the ascending order of keys, just like for
+/// a single `MapIndex`.
+///
+/// The number of shards is not persisted and must be the same each time the map is accessed;
+/// otherwise, the keys will be routed to wrong shards.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{ShardedMap, TemporaryDB, Database};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut map = ShardedMap::new(&fork, "map", 4).unwrap();
+/// for i in 0_u32..10 {
+///     map.put(&i, i.to_string());
+/// }
+/// assert_eq!(map.get(&5), Some("5".to_owned()));
+/// assert_eq!(map.keys().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+/// ```
+///
+/// [`MapIndex`]: ../struct.MapIndex.html
+#[derive(Debug)]
+pub struct ShardedMap<T: RawAccess, K: ?Sized, V> {
+    shards: Vec<MapIndex<T, K, V>>,
+}
+
+impl<T, K, V> ShardedMap<T, K, V>
+where
+    T: RawAccess,
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+{
+    /// Creates a sharded map with the specified number of shards. Shards are stored as members
+    /// of the index group with the specified address, with `u16` shard indexes as keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the shards cannot be accessed, e.g., if there is an index
+    /// of another type at its address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_count` is zero.
+    #[allow(clippy::needless_pass_by_value)] // for consistency with `FromAccess`
+    pub fn new<A>(
+        access: A,
+        addr: impl Into<IndexAddress>,
+        shard_count: u16,
+    ) -> Result<Self, AccessError>
+    where
+        A: Access<Base = T>,
+    {
+        assert!(
+            shard_count > 0,
+            "Sharded map should have at least one shard"
+        );
+        let addr = addr.into();
+        let shards = (0..shard_count)
+            .map(|i| MapIndex::from_access(access.clone(), addr.clone().append_key(&i)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { shards })
+    }
+
+    /// Returns the number of shards in the map.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the index of the shard storing the specified key. The index is determined
+    /// by the first 8 bytes of the key hash and does not depend on the map contents.
+    pub fn shard_index(&self, key: &K) -> usize {
+        let hash = crypto::hash(&key_bytes(key));
+        let prefix = LittleEndian::read_u64(&hash.as_ref()[..8]);
+        (prefix % self.shards.len() as u64) as usize
+    }
+
+    /// Returns the shard with the specified index, or `None` if the index is out of bounds.
+    pub fn shard(&self, index: usize) -> Option<&MapIndex<T, K, V>> {
+        self.shards.get(index)
+    }
+
+    /// Returns a value corresponding to the key.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.shards[self.shard_index(key)].get(key)
+    }
+
+    /// Returns `true` if the map contains a value corresponding to the specified key.
+    pub fn contains(&self, key: &K) -> bool {
+        self.shards[self.shard_index(key)].contains(key)
+    }
+
+    /// Returns an iterator over the entries of the map in ascending order. The iterator
+    /// merges entries from all shards.
+    pub fn iter(&self) -> ShardedEntries<'_, K, V> {
+        ShardedEntries {
+            shards: self
+                .shards
+                .iter()
+                .map(|shard| shard.iter().peekable())
+                .collect(),
+        }
+    }
+
+    /// Returns an iterator over the keys of the map in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = K::Owned> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values of the map in ascending order of keys.
+    pub fn values(&self) -> impl Iterator<Item = V> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+impl<T, K, V> ShardedMap<T, K, V>
+where
+    T: RawAccessMut,
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+{
+    /// Inserts a key-value pair into the map.
+    pub fn put(&mut self, key: &K, value: V) {
+        let index = self.shard_index(key);
+        self.shards[index].put(key, value);
+    }
+
+    /// Removes a key from the map.
+    pub fn remove(&mut self, key: &K) {
+        let index = self.shard_index(key);
+        self.shards[index].remove(key);
+    }
+
+    /// Clears all shards of the map, removing all entries.
+    pub fn clear(&mut self) {
+        for shard in &mut self.shards {
+            shard.clear();
+        }
+    }
+}
+
+impl<'a, T, K, V> IntoIterator for &'a ShardedMap<T, K, V>
+where
+    T: RawAccess,
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+{
+    type Item = (K::Owned, V);
+    type IntoIter = ShardedEntries<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over entries of a [`ShardedMap`] merging entries of all shards in the ascending
+/// order of keys.
+///
+/// [`ShardedMap`]: struct.ShardedMap.html
+pub struct ShardedEntries<'a, K: BinaryKey + ?Sized, V: BinaryValue> {
+    shards: Vec<Peekable<Entries<'a, K, V>>>,
+}
+
+impl<K, V> fmt::Debug for ShardedEntries<'_, K, V>
+where
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("ShardedEntries")
+            .field("shard_count", &self.shards.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V> Iterator for ShardedEntries<'_, K, V>
+where
+    K: BinaryKey + ?Sized,
+    V: BinaryValue,
+{
+    type Item = (K::Owned, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Since each key is stored in a single shard, the keys of shards never coincide.
+        // Keys are compared in the serialized form, which defines the order of iteration
+        // for each shard.
+        let mut min: Option<(usize, Vec<u8>)> = None;
+        for (i, shard) in self.shards.iter_mut().enumerate() {
+            if let Some((key, _)) = shard.peek() {
+                let key = key_bytes::<K>(key.borrow());
+                if min.as_ref().is_none_or(|(_, min_key)| key < *min_key) {
+                    min = Some((i, key));
+                }
+            }
+        }
+        let (i, _) = min?;
+        self.shards[i].next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedMap;
+    use crate::{access::CopyAccessExt, Database, TemporaryDB};
+
+    #[test]
+    fn routing_is_deterministic() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        {
+            let mut map = ShardedMap::new(&fork, "map", 8).unwrap();
+            for i in 0_u32..100 {
+                map.put(&i, i);
+            }
+        }
+        db.merge(fork.into_patch()).unwrap();
+
+        let snapshot = db.snapshot();
+        let map = ShardedMap::<_, u32, u32>::new(&snapshot, "map", 8).unwrap();
+        let other_map = ShardedMap::<_, u32, u32>::new(&snapshot, "other", 8).unwrap();
+        for i in 0_u32..100 {
+            let shard_index = map.shard_index(&i);
+            assert_eq!(shard_index, other_map.shard_index(&i));
+            assert_eq!(map.shard(shard_index).unwrap().get(&i), Some(i));
+            assert_eq!(map.get(&i), Some(i));
+
+            // Shards are ordinary maps within the group.
+            let shard = snapshot.get_map::<_, u32, u32>(("map", &(shard_index as u16)));
+            assert!(shard.contains(&i));
+        }
+
+        // Keys are distributed among all shards.
+        for shard_index in 0..map.shard_count() {
+            assert!(map.shard(shard_index).unwrap().iter().next().is_some());
+        }
+        assert!(map.shard(map.shard_count()).is_none());
+    }
+
+    #[test]
+    fn iteration_over_shards_is_ordered() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut map = ShardedMap::new(&fork, "map", 5).unwrap();
+        assert_eq!(map.iter().count(), 0);
+
+        for i in (0_u64..200).rev() {
+            map.put(&(i * 3), i.to_string());
+        }
+        map.remove(&3);
+        assert!(!map.contains(&3));
+
+        let expected_keys: Vec<_> = (0..200).filter(|&i| i != 1).map(|i| i * 3).collect();
+        assert_eq!(map.keys().collect::<Vec<_>>(), expected_keys);
+        let expected_values: Vec<_> = (0_u64..200)
+            .filter(|&i| i != 1)
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(map.values().collect::<Vec<_>>(), expected_values);
+        assert_eq!((&map).into_iter().count(), 199);
+
+        map.clear();
+        assert_eq!(map.iter().count(), 0);
+    }
+}
@@ -0,0 +1,646 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An implementation of a Merkle sum tree.
+//!
+//! [`SumTreeIndex`] is an append-only list of numeric values, in which each node of the Merkle
+//! tree commits to both the hash and the sum of values in its subtree. Hence, the root of
+//! the tree authenticates the total of all values, and a [`SumProof`] for an element proves
+//! both the element value and the total.
+//!
+//! [`SumTreeIndex`]: struct.SumTreeIndex.html
+//! [`SumProof`]: struct.SumProof.html
+
+use anyhow::ensure;
+use byteorder::{ByteOrder, LittleEndian};
+use serde_derive::{Deserialize, Serialize};
+use thiserror::Error;
+
+use std::borrow::Cow;
+
+use crate::{
+    access::{Access, AccessError, FromAccess},
+    crypto::{Hash, HASH_SIZE},
+    indexes::iter::Values,
+    views::{IndexAddress, RawAccess, RawAccessMut},
+    BinaryValue, HashTag, ListIndex, MapIndex, ObjectHash, ValidationError,
+};
+
+const VALUES_NAME: &str = "values";
+const NODES_NAME: &str = "nodes";
+
+/// Bit shift of the node height in the storage key of a node.
+const HEIGHT_SHIFT: u64 = 56;
+
+fn node_key(height: u8, index: u64) -> u64 {
+    (u64::from(height) << HEIGHT_SHIFT) | index
+}
+
+/// A node of a Merkle sum tree: the hash of the subtree together with the sum of values
+/// in the subtree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SumNode {
+    /// Hash of the subtree.
+    pub hash: Hash,
+    /// Sum of values in the subtree.
+    pub sum: u128,
+}
+
+impl SumNode {
+    /// Creates a leaf node for the specified value.
+    fn leaf<V>(value: &V) -> Self
+    where
+        V: BinaryValue + Clone + Into<u128>,
+    {
+        Self {
+            hash: HashTag::hash_leaf(&value.to_bytes()),
+            sum: value.clone().into(),
+        }
+    }
+
+    /// Creates a branch node with the specified children. Returns `None` if the sum
+    /// of the children overflows.
+    ///
+    /// ```text
+    /// hash = sha256( HashTag::SumTreeBranchNode || left.hash || u128_LE(left.sum)
+    ///     || right.hash? || u128_LE(right.sum)? )
+    /// ```
+    fn branch(left: &Self, right: Option<&Self>) -> Option<Self> {
+        let mut stream = HashTag::SumTreeBranchNode
+            .hash_stream()
+            .update(left.hash.as_ref())
+            .update(&left.sum.to_le_bytes());
+        let mut sum = left.sum;
+        if let Some(right) = right {
+            stream = stream
+                .update(right.hash.as_ref())
+                .update(&right.sum.to_le_bytes());
+            sum = sum.checked_add(right.sum)?;
+        }
+        Some(Self {
+            hash: stream.hash(),
+            sum,
+        })
+    }
+
+    /// Computes the hash of a sum tree with the specified length and root node.
+    ///
+    /// ```text
+    /// h = sha256( HashTag::SumTreeNode || u64_LE(len) || root.hash || u128_LE(root.sum) )
+    /// ```
+    fn tree_hash(&self, len: u64) -> Hash {
+        HashTag::SumTreeNode
+            .hash_stream()
+            .update(&len.to_le_bytes())
+            .update(self.hash.as_ref())
+            .update(&self.sum.to_le_bytes())
+            .hash()
+    }
+
+    fn empty() -> Self {
+        Self {
+            hash: Hash::zero(),
+            sum: 0,
+        }
+    }
+}
+
+/// The node is serialized as the hash followed by the little-endian sum.
+impl BinaryValue for SumNode {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; HASH_SIZE + 16];
+        bytes[..HASH_SIZE].copy_from_slice(self.hash.as_ref());
+        LittleEndian::write_u128(&mut bytes[HASH_SIZE..], self.sum);
+        bytes
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
+        ensure!(bytes.len() == HASH_SIZE + 16, "Invalid sum tree node size");
+        Ok(Self {
+            hash: Hash::from_slice(&bytes[..HASH_SIZE]).unwrap(),
+            sum: LittleEndian::read_u128(&bytes[HASH_SIZE..]),
+        })
+    }
+}
+
+/// An append-only list of numeric values with a Merkle sum tree built on top of it.
+///
+/// Like in [`ProofListIndex`], values are leaves of a binary Merkle tree. Each node of
+/// the tree additionally stores the sum of values in its subtree, and the hash of a branch
+/// node commits to both the hashes and the sums of its children. Thus, [`object_hash()`]
+/// of the index commits to the values, their sums and the [`total()`] of all values,
+/// and a [`SumProof`] for an element proves both the element and the total
+/// against the index hash.
+///
+/// The index consists of a [`ListIndex`] with values at the address `{addr}.values`,
+/// and a [`MapIndex`] with branch nodes of the tree at the address `{addr}.nodes`.
+///
+/// The total of all values must fit into `u128`; appending a value which would overflow
+/// the total fails with a [`SumOverflowError`].
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{access::FromAccess, Database, ObjectHash, SumTreeIndex, TemporaryDB};
+///
+/// let db = TemporaryDB::new();
+/// let fork = db.fork();
+/// let mut supply = SumTreeIndex::<_, u64>::from_access(&fork, "supply".into()).unwrap();
+/// supply.push(100).unwrap();
+/// supply.push(250).unwrap();
+/// supply.push(50).unwrap();
+/// assert_eq!(supply.total(), 400);
+///
+/// let proof = supply.get_sum_proof(1).unwrap();
+/// let checked_proof = proof.check_against_hash(supply.object_hash()).unwrap();
+/// assert_eq!(*checked_proof.value(), 250);
+/// assert_eq!(checked_proof.total(), 400);
+/// ```
+///
+/// [`ProofListIndex`]: proof_list/struct.ProofListIndex.html
+/// [`ListIndex`]: struct.ListIndex.html
+/// [`MapIndex`]: struct.MapIndex.html
+/// [`SumProof`]: struct.SumProof.html
+/// [`SumOverflowError`]: struct.SumOverflowError.html
+/// [`object_hash()`]: #impl-ObjectHash
+/// [`total()`]: #method.total
+#[derive(Debug)]
+pub struct SumTreeIndex<T: RawAccess, V> {
+    values: ListIndex<T, V>,
+    nodes: MapIndex<T, u64, SumNode>,
+}
+
+impl<T, V> FromAccess<T> for SumTreeIndex<T::Base, V>
+where
+    T: Access,
+    V: BinaryValue + Clone + Into<u128>,
+{
+    fn from_access(access: T, addr: IndexAddress) -> Result<Self, AccessError> {
+        Ok(Self {
+            values: ListIndex::from_access(access.clone(), addr.clone().append_name(VALUES_NAME))?,
+            nodes: MapIndex::from_access(access, addr.append_name(NODES_NAME))?,
+        })
+    }
+}
+
+/// Returns the height of the root of a tree with the specified number of leaves,
+/// where leaves have height 0.
+fn root_height(len: u64) -> u8 {
+    if len <= 1 {
+        0
+    } else {
+        (64 - (len - 1).leading_zeros()) as u8
+    }
+}
+
+impl<T, V> SumTreeIndex<T, V>
+where
+    T: RawAccess,
+    V: BinaryValue + Clone + Into<u128>,
+{
+    /// Returns the number of values in the index.
+    pub fn len(&self) -> u64 {
+        self.values.len()
+    }
+
+    /// Returns `true` if the index contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the value at the specified position, or `None` if it is out of bounds.
+    pub fn get(&self, index: u64) -> Option<V> {
+        self.values.get(index)
+    }
+
+    /// Returns an iterator over the values in the index.
+    pub fn iter(&self) -> Values<'_, V> {
+        self.values.iter()
+    }
+
+    /// Returns the total of all values in the index.
+    pub fn total(&self) -> u128 {
+        self.root_node().sum
+    }
+
+    fn node(&self, height: u8, index: u64) -> SumNode {
+        if height == 0 {
+            let value = self
+                .values
+                .get(index)
+                .expect("Value is missing in sum tree");
+            SumNode::leaf(&value)
+        } else {
+            self.nodes
+                .get(&node_key(height, index))
+                .expect("Node is missing in sum tree")
+        }
+    }
+
+    fn root_node(&self) -> SumNode {
+        let len = self.len();
+        if len == 0 {
+            SumNode::empty()
+        } else {
+            self.node(root_height(len), 0)
+        }
+    }
+
+    /// Returns the proof of the value at the specified position and the total of all values,
+    /// or `None` if the position is out of bounds.
+    pub fn get_sum_proof(&self, index: u64) -> Option<SumProof<V>> {
+        let value = self.values.get(index)?;
+        let length = self.len();
+
+        let mut siblings = vec![];
+        let (mut height, mut node_index, mut level_len) = (0, index, length);
+        while level_len > 1 {
+            if node_index % 2 == 1 {
+                siblings.push(self.node(height, node_index - 1));
+            } else if node_index + 1 < level_len {
+                siblings.push(self.node(height, node_index + 1));
+            }
+            height += 1;
+            node_index /= 2;
+            level_len = (level_len + 1) / 2;
+        }
+
+        Some(SumProof {
+            index,
+            value,
+            length,
+            siblings,
+        })
+    }
+}
+
+impl<T, V> SumTreeIndex<T, V>
+where
+    T: RawAccessMut,
+    V: BinaryValue + Clone + Into<u128>,
+{
+    /// Appends a value to the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the total of values in the index overflows `u128`. In this case,
+    /// the index is not changed.
+    pub fn push(&mut self, value: V) -> Result<(), SumOverflowError> {
+        let index = self.len();
+        let mut node = SumNode::leaf(&value);
+
+        // The pushed value is the last one, so nodes on its path have no right siblings.
+        // All nodes are computed before changing the index, so that an overflow
+        // leaves the index intact.
+        let mut branches = vec![];
+        let (mut height, mut node_index, mut level_len) = (0, index, index + 1);
+        while level_len > 1 {
+            let left = if node_index % 2 == 1 {
+                Some(self.node(height, node_index - 1))
+            } else {
+                None
+            };
+            node = match left {
+                Some(left) => SumNode::branch(&left, Some(&node)),
+                None => SumNode::branch(&node, None),
+            }
+            .ok_or(SumOverflowError)?;
+
+            height += 1;
+            node_index /= 2;
+            level_len = (level_len + 1) / 2;
+            branches.push((node_key(height, node_index), node));
+        }
+
+        self.values.push(value);
+        for (key, node) in branches {
+            self.nodes.put(&key, node);
+        }
+        Ok(())
+    }
+
+    /// Appends values from the iterator to the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the total of values in the index overflows `u128`. In this case,
+    /// the values preceding the overflowing one are appended to the index, and the remaining
+    /// values are not.
+    pub fn extend<I>(&mut self, iter: I) -> Result<(), SumOverflowError>
+    where
+        I: IntoIterator<Item = V>,
+    {
+        for value in iter {
+            self.push(value)?;
+        }
+        Ok(())
+    }
+
+    /// Removes all values from the index.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.nodes.clear();
+    }
+}
+
+/// Returns the hash of the sum tree, which commits to the number of values
+/// and the root node of the tree:
+///
+/// ```text
+/// h = sha256( HashTag::SumTreeNode || u64_LE(len) || root.hash || u128_LE(root.sum) )
+/// ```
+///
+/// The root node of an empty tree has zero hash and sum.
+impl<T, V> ObjectHash for SumTreeIndex<T, V>
+where
+    T: RawAccess,
+    V: BinaryValue + Clone + Into<u128>,
+{
+    fn object_hash(&self) -> Hash {
+        self.root_node().tree_hash(self.len())
+    }
+}
+
+/// Error returned when the total of values in a [`SumTreeIndex`] would overflow `u128`.
+///
+/// [`SumTreeIndex`]: struct.SumTreeIndex.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("total of values in sum tree overflows `u128`")]
+pub struct SumOverflowError;
+
+/// Errors that can occur while checking a [`SumProof`].
+///
+/// [`SumProof`]: struct.SumProof.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum SumProofError {
+    /// The index of the proven value is not less than the length of the tree.
+    #[error("index of the value is out of bounds of the tree")]
+    OutOfBounds,
+    /// The proof does not contain enough sibling nodes.
+    #[error("not enough sibling nodes in the proof")]
+    MissingSibling,
+    /// The proof contains redundant sibling nodes.
+    #[error("redundant sibling nodes in the proof")]
+    RedundantSibling,
+    /// The sum of nodes in the proof overflows `u128`.
+    #[error("sum of nodes in the proof overflows")]
+    SumOverflow,
+}
+
+/// Proof of a value in a [`SumTreeIndex`] together with the total of all values.
+///
+/// The proof contains the value, its position, the number of values in the tree,
+/// and the sibling nodes on the path from the value to the root of the tree, starting
+/// from the sibling of the value.
+///
+/// [`SumTreeIndex`]: struct.SumTreeIndex.html
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SumProof<V> {
+    index: u64,
+    value: V,
+    length: u64,
+    siblings: Vec<SumNode>,
+}
+
+impl<V> SumProof<V>
+where
+    V: BinaryValue + Clone + Into<u128>,
+{
+    /// Returns the proven value without checking the proof.
+    pub fn value_unchecked(&self) -> &V {
+        &self.value
+    }
+
+    /// Checks the proof and computes the hash of the sum tree.
+    pub fn check(&self) -> Result<CheckedSumProof<'_, V>, SumProofError> {
+        if self.index >= self.length {
+            return Err(SumProofError::OutOfBounds);
+        }
+
+        let mut siblings = self.siblings.iter();
+        let mut node = SumNode::leaf(&self.value);
+        let (mut node_index, mut level_len) = (self.index, self.length);
+        while level_len > 1 {
+            let branch = if node_index % 2 == 1 {
+                let left = siblings.next().ok_or(SumProofError::MissingSibling)?;
+                SumNode::branch(left, Some(&node))
+            } else if node_index + 1 < level_len {
+                let right = siblings.next().ok_or(SumProofError::MissingSibling)?;
+                SumNode::branch(&node, Some(right))
+            } else {
+                SumNode::branch(&node, None)
+            };
+            node = branch.ok_or(SumProofError::SumOverflow)?;
+            node_index /= 2;
+            level_len = (level_len + 1) / 2;
+        }
+        if siblings.next().is_some() {
+            return Err(SumProofError::RedundantSibling);
+        }
+
+        Ok(CheckedSumProof {
+            index: self.index,
+            value: &self.value,
+            length: self.length,
+            total: node.sum,
+            hash: node.tree_hash(self.length),
+        })
+    }
+
+    /// Checks the proof against the trusted hash of the sum tree.
+    pub fn check_against_hash(
+        &self,
+        expected_hash: Hash,
+    ) -> Result<CheckedSumProof<'_, V>, ValidationError<SumProofError>> {
+        self.check()
+            .map_err(ValidationError::Malformed)
+            .and_then(|checked_proof| {
+                if checked_proof.index_hash() == expected_hash {
+                    Ok(checked_proof)
+                } else {
+                    Err(ValidationError::UnmatchedRootHash)
+                }
+            })
+    }
+}
+
+/// Version of [`SumProof`] obtained after verification.
+///
+/// [`SumProof`]: struct.SumProof.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CheckedSumProof<'a, V> {
+    index: u64,
+    value: &'a V,
+    length: u64,
+    total: u128,
+    hash: Hash,
+}
+
+impl<'a, V> CheckedSumProof<'a, V> {
+    /// Returns the position of the proven value.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Returns the proven value.
+    pub fn value(&self) -> &'a V {
+        self.value
+    }
+
+    /// Returns the number of values in the sum tree.
+    pub fn list_len(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the total of all values in the sum tree.
+    pub fn total(&self) -> u128 {
+        self.total
+    }
+
+    /// Returns the `object_hash()` of the sum tree.
+    pub fn index_hash(&self) -> Hash {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SumNode, SumOverflowError, SumProofError, SumTreeIndex};
+    use crate::{
+        access::FromAccess, BinaryValue, Database, ObjectHash, TemporaryDB, ValidationError,
+    };
+
+    use std::borrow::Cow;
+
+    #[test]
+    fn sum_tree_proofs() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut tree = SumTreeIndex::<_, u64>::from_access(&fork, "tree".into()).unwrap();
+        assert_eq!(tree.total(), 0);
+        assert!(tree.get_sum_proof(0).is_none());
+        let empty_hash = tree.object_hash();
+
+        let mut total = 0_u128;
+        for i in 0..40_u64 {
+            let value = i * i + 1;
+            tree.push(value).unwrap();
+            total += u128::from(value);
+            assert_eq!(tree.total(), total);
+
+            let tree_hash = tree.object_hash();
+            assert_ne!(tree_hash, empty_hash);
+            for index in 0..=i {
+                let proof = tree.get_sum_proof(index).unwrap();
+                let checked_proof = proof.check_against_hash(tree_hash).unwrap();
+                assert_eq!(checked_proof.index(), index);
+                assert_eq!(*checked_proof.value(), index * index + 1);
+                assert_eq!(checked_proof.list_len(), i + 1);
+                assert_eq!(checked_proof.total(), total);
+            }
+        }
+        assert_eq!(tree.iter().count(), 40);
+
+        // The tree is persisted.
+        let hash = tree.object_hash();
+        db.merge(fork.into_patch()).unwrap();
+        let snapshot = db.snapshot();
+        let tree = SumTreeIndex::<_, u64>::from_access(&snapshot, "tree".into()).unwrap();
+        assert_eq!(tree.object_hash(), hash);
+        assert_eq!(tree.total(), total);
+    }
+
+    #[test]
+    fn tampered_sum_fails_verification() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut tree = SumTreeIndex::<_, u32>::from_access(&fork, "tree".into()).unwrap();
+        tree.extend(vec![10, 20, 30, 40, 50]).unwrap();
+        let tree_hash = tree.object_hash();
+
+        let proof = tree.get_sum_proof(2).unwrap();
+        assert_eq!(proof.siblings.len(), 3);
+        assert_eq!(proof.check_against_hash(tree_hash).unwrap().total(), 150);
+
+        // Inflating the sum of a sibling changes the computed hash.
+        let mut tampered_proof = proof.clone();
+        tampered_proof.siblings[1].sum += 1;
+        assert_eq!(tampered_proof.check().unwrap().total(), 151);
+        assert!(matches!(
+            tampered_proof.check_against_hash(tree_hash).unwrap_err(),
+            ValidationError::UnmatchedRootHash
+        ));
+
+        let mut tampered_proof = proof.clone();
+        tampered_proof.value = 31;
+        assert!(tampered_proof.check_against_hash(tree_hash).is_err());
+
+        let mut tampered_proof = proof.clone();
+        tampered_proof.siblings.pop();
+        assert!(matches!(
+            tampered_proof.check().unwrap_err(),
+            SumProofError::MissingSibling
+        ));
+
+        let mut tampered_proof = proof.clone();
+        tampered_proof.siblings[0].sum = u128::MAX;
+        assert!(matches!(
+            tampered_proof.check().unwrap_err(),
+            SumProofError::SumOverflow
+        ));
+
+        let mut tampered_proof = proof;
+        tampered_proof.index = 5;
+        assert!(matches!(
+            tampered_proof.check().unwrap_err(),
+            SumProofError::OutOfBounds
+        ));
+    }
+
+    #[test]
+    fn overflowing_push_leaves_tree_intact() {
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut tree = SumTreeIndex::<_, u128>::from_access(&fork, "tree".into()).unwrap();
+        tree.extend(vec![1, 2, u128::MAX - 10]).unwrap();
+        let tree_hash = tree.object_hash();
+
+        assert_eq!(tree.push(8), Err(SumOverflowError));
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.total(), u128::MAX - 7);
+        assert_eq!(tree.object_hash(), tree_hash);
+
+        // The index remains usable after the failed push.
+        tree.push(7).unwrap();
+        assert_eq!(tree.total(), u128::MAX);
+        let proof = tree.get_sum_proof(3).unwrap();
+        let checked_proof = proof.check_against_hash(tree.object_hash()).unwrap();
+        assert_eq!(checked_proof.total(), u128::MAX);
+
+        assert_eq!(tree.extend(vec![0, 1, 2]), Err(SumOverflowError));
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.total(), u128::MAX);
+    }
+
+    #[test]
+    fn sum_node_binary_value() {
+        let node = SumNode {
+            hash: crate::crypto::hash(b"node"),
+            sum: u128::MAX - 1,
+        };
+        let bytes = node.to_bytes();
+        assert_eq!(SumNode::from_bytes(Cow::Owned(bytes)).unwrap(), node);
+    }
+}
@@ -19,6 +19,8 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 use rust_decimal::Decimal;
 use uuid::Uuid;
 
+use std::mem;
+
 use crate::crypto::{Hash, HASH_SIZE};
 
 /// A type that can be (de)serialized as a key in the blockchain storage.
@@ -174,6 +176,83 @@ storage_key_for_ints! {u32, i32, 4, read_u32, write_u32}
 storage_key_for_ints! {u64, i64, 8, read_u64, write_u64}
 storage_key_for_ints! {u128, i128, 16, read_u128, write_u128}
 
+/// Integer key serialized with the little-endian encoding.
+///
+/// Unlike the default big-endian encoding of integer keys, the little-endian encoding
+/// does **not** preserve the natural ordering of keys, so range iteration over an index
+/// with such keys will not follow the numeric order. The wrapper is intended for
+/// compatibility with external systems that store keys in the little-endian form.
+///
+/// Signed integers are stored in the two's complement form, i.e., as returned by
+/// `to_le_bytes()`.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{BinaryKey, LeKey};
+///
+/// let key = LeKey(0x0102_u16);
+/// let mut buffer = [0; 2];
+/// key.write(&mut buffer);
+/// assert_eq!(buffer, [2, 1]);
+/// assert_eq!(LeKey::<u16>::read(&buffer), key);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LeKey<T>(pub T);
+
+/// Integer key serialized with the big-endian encoding.
+///
+/// For unsigned integers, the encoding is the same as the default encoding of the integer
+/// types; the wrapper merely makes the intent explicit. Signed integers are stored
+/// in the two's complement form, i.e., as returned by `to_be_bytes()`, while
+/// the default encoding of signed integers additionally maps them to the unsigned range
+/// to preserve the ordering. Thus, `BeKey` with a signed integer does not order negative
+/// values before positive ones.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{BeKey, BinaryKey};
+///
+/// let key = BeKey(-2_i16);
+/// let mut buffer = [0; 2];
+/// key.write(&mut buffer);
+/// assert_eq!(buffer, [0xff, 0xfe]);
+/// assert_eq!(BeKey::<i16>::read(&buffer), key);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BeKey<T>(pub T);
+
+macro_rules! storage_key_for_endian_wrapper {
+    ($wrapper:ident, $to_bytes:ident, $from_bytes:ident, $($type:ident),*) => {
+        $(
+            impl BinaryKey for $wrapper<$type> {
+                fn size(&self) -> usize {
+                    mem::size_of::<$type>()
+                }
+
+                fn write(&self, buffer: &mut [u8]) -> usize {
+                    buffer[..self.size()].copy_from_slice(&self.0.$to_bytes());
+                    self.size()
+                }
+
+                fn read(buffer: &[u8]) -> Self {
+                    let mut bytes = [0; mem::size_of::<$type>()];
+                    bytes.copy_from_slice(&buffer[..bytes.len()]);
+                    Self($type::$from_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+storage_key_for_endian_wrapper! {
+    LeKey, to_le_bytes, from_le_bytes, u8, i8, u16, i16, u32, i32, u64, i64, u128, i128
+}
+storage_key_for_endian_wrapper! {
+    BeKey, to_be_bytes, from_be_bytes, u8, i8, u16, i16, u32, i32, u64, i64, u128, i128
+}
+
 macro_rules! storage_key_for_crypto_types {
     ($type:ident, $size:expr) => {
         impl BinaryKey for $type {
@@ -344,7 +423,9 @@ impl BinaryKey for Decimal {
 
 #[cfg(test)]
 mod tests {
-    use super::{BigEndian, BinaryKey, ByteOrder, DateTime, Decimal, Hash, Utc, Uuid};
+    use super::{
+        BeKey, BigEndian, BinaryKey, ByteOrder, DateTime, Decimal, Hash, LeKey, Utc, Uuid,
+    };
     use crate::access::CopyAccessExt;
 
     use std::{fmt::Debug, str::FromStr};
@@ -663,6 +744,47 @@ mod tests {
         assert_round_trip_eq(&decimals);
     }
 
+    #[test]
+    fn test_endian_wrappers_byte_layout() {
+        fn serialize<T: BinaryKey + ?Sized>(key: &T) -> Vec<u8> {
+            let mut buffer = get_buffer(key);
+            assert_eq!(key.write(&mut buffer), buffer.len());
+            buffer
+        }
+
+        assert_eq!(serialize(&LeKey(0x0102_u16)), [2, 1]);
+        assert_eq!(serialize(&BeKey(0x0102_u16)), [1, 2]);
+        assert_eq!(serialize(&LeKey(0x0102_0304_u32)), [4, 3, 2, 1]);
+        assert_eq!(serialize(&BeKey(0x0102_0304_u32)), [1, 2, 3, 4]);
+        assert_eq!(
+            serialize(&LeKey(0x0102_0304_0506_0708_u64)),
+            [8, 7, 6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(
+            serialize(&BeKey(0x0102_0304_0506_0708_u64)),
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(serialize(&LeKey(1_u128))[0], 1);
+        assert_eq!(serialize(&BeKey(1_u128))[15], 1);
+        assert_eq!(serialize(&LeKey(7_u8)), [7]);
+        assert_eq!(serialize(&BeKey(-1_i8)), [0xff]);
+
+        // Signed integers use the two's complement form.
+        assert_eq!(serialize(&LeKey(-2_i32)), [0xfe, 0xff, 0xff, 0xff]);
+        assert_eq!(serialize(&BeKey(-2_i32)), [0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(serialize(&BeKey(1_i64)), [0, 0, 0, 0, 0, 0, 0, 1]);
+
+        // Big-endian encoding of unsigned integers matches the default one.
+        for &x in &[0_u64, 1, 255, 256, u64::max_value()] {
+            assert_eq!(serialize(&BeKey(x)), serialize(&x));
+        }
+
+        assert_round_trip_eq(&[LeKey(0_i64), LeKey(-1), LeKey(i64::min_value())]);
+        assert_round_trip_eq(&[BeKey(0_i64), BeKey(-1), BeKey(i64::max_value())]);
+        assert_round_trip_eq(&[LeKey(0_u16), LeKey(1), LeKey(u16::max_value())]);
+        assert_round_trip_eq(&[BeKey(0_u128), BeKey(1), BeKey(u128::max_value())]);
+    }
+
     fn assert_round_trip_eq<T>(values: &[T])
     where
         T: BinaryKey + PartialEq<<T as ToOwned>::Owned> + Debug,
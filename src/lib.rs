@@ -168,21 +168,28 @@ extern crate serde_derive;
 #[doc(hidden)]
 pub mod _reexports {
     // Re-export the crypto crate for use in the `ObjectHash` derive macro.
-    pub use crate::crypto::{hash, Hash};
+    pub use crate::crypto::{hash, Hash, HashStream};
     pub use anyhow::Error;
+    // Re-export `serde` for use in the `declare_hash_type` macro.
+    pub use serde;
 }
 
 pub use self::{
-    backends::{rocksdb::RocksDB, temporarydb::TemporaryDB},
+    backends::{batching::BatchingDatabase, rocksdb::RocksDB, temporarydb::TemporaryDB},
     db::{
-        Database, DatabaseExt, Fork, Iter, Iterator, OwnedReadonlyFork, Patch, ReadonlyFork,
-        Snapshot,
+        Change, ChangesSink, Database, DatabaseExt, Fork, Iter, Iterator, OwnedReadonlyFork, Patch,
+        ReadonlyFork, Snapshot,
     },
     error::Error,
-    hash::{root_hash, HashTag, ObjectHash, ValidationError},
-    keys::BinaryKey,
+    fixture::TestDbBuilder,
+    hash::{
+        root_hash, Blake2bNodeHasher, DefaultNodeHasher, HashTag, NodeHasher, ObjectHash,
+        ValidationError,
+    },
+    hashing_buffer::HashingBuffer,
+    keys::{BeKey, BinaryKey, LeKey},
     lazy::Lazy,
-    options::DbOptions,
+    options::{ColumnFamilyOptions, CompactionStyle, CompressionType, DbOptions},
     values::BinaryValue,
     views::{AsReadonly, IndexAddress, IndexType, ResolvedAddress, SystemSchema},
 };
@@ -192,23 +199,29 @@ pub use self::{
 pub use self::indexes::{
     proof_list::{self, ListProof, ProofListIndex},
     proof_map::{self, MapProof, ProofMapIndex, RawProofMapIndex},
-    Entry, Group, KeySetIndex, ListIndex, MapIndex, ProofEntry, SparseListIndex, ValueSetIndex,
+    Entry, GraphIndex, Group, IndexOptions, KeySetIndex, ListIndex, MapIndex, ProofEntry,
+    ProofError, ProofLimit, ProofLimits, ProofQueue, Queue, ShardedMap, SizeError, SparseListIndex,
+    SumTreeIndex, ValueSetIndex,
 };
 
 #[macro_use]
 mod macros;
 pub mod access;
 mod backends;
+pub mod codec;
 pub mod crypto;
 mod db;
 mod error;
+mod fixture;
 pub mod generic;
 mod hash;
+mod hashing_buffer;
 pub mod indexes;
 mod keys;
 mod lazy;
 pub mod migration;
 mod options;
+mod patch_file;
 pub mod validation;
 mod values;
 mod views;
@@ -40,6 +40,51 @@ macro_rules! concat_keys {
     });
 }
 
+/// Evaluates an expression within a `tracing` span with the specified name and fields.
+/// The duration of the evaluation is recorded into the `duration_us` span field.
+/// Fields depending on the result of the expression may be specified after the expression.
+///
+/// If the `tracing` feature is disabled, the macro evaluates only the expression; field values
+/// are not evaluated.
+///
+/// ```ignore
+/// let proof = traced!("list_proof", index = index; self.create_proof(index));
+/// let proof = traced!("map_proof"; self.create_multiproof(keys); |proof| entries = proof.len());
+/// ```
+macro_rules! traced {
+    (
+        $name:literal $(, $field:ident = $value:expr)*;
+        $body:expr
+        $(; |$output:ident| $($output_field:ident = $output_value:expr),+)?
+    ) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            $name,
+            $($field = $value,)*
+            $($($output_field = tracing::field::Empty,)+)?
+            duration_us = tracing::field::Empty
+        );
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        #[cfg(feature = "tracing")]
+        let entered = span.enter();
+
+        let output = $body;
+
+        #[cfg(feature = "tracing")]
+        {
+            drop(entered);
+            let duration_us = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+            span.record("duration_us", &duration_us);
+            $(
+                let $output = &output;
+                $(span.record(stringify!($output_field), &$output_value);)+
+            )?
+        }
+        output
+    }};
+}
+
 /// Implement `ObjectHash` trait for any type that implements `BinaryValue`.
 #[macro_export]
 macro_rules! impl_object_hash_for_binary_value {
@@ -48,7 +93,7 @@ macro_rules! impl_object_hash_for_binary_value {
             #[allow(clippy::use_self)] // false positive
             impl ObjectHash for $type {
                 fn object_hash(&self) -> Hash {
-                    $crate::crypto::hash(&$crate::BinaryValue::to_bytes(self))
+                    $crate::HashingBuffer::hash_thread_local(self)
                 }
             }
         )*
@@ -149,3 +194,153 @@ macro_rules! impl_serde_hex_for_binary_value {
         }
     };
 }
+
+/// Declares a newtype wrapping [`Hash`] to be used for hashes of a specific logical domain,
+/// such as block or transaction hashes.
+///
+/// Hashes of different domains declared with this macro are distinct types, so the compiler
+/// rejects passing e.g. a transaction hash where a block hash is expected. There are no
+/// implicit conversions between declared types or from a plain `Hash`; use `new()`
+/// and `as_hash()` to convert explicitly.
+///
+/// The declared type is stored and serialized in the same way as `Hash`: it implements
+/// `BinaryKey`, `BinaryValue` and `ObjectHash`, and is serialized with `serde` as a hex string.
+/// `Display` and `FromStr` use the hex representation as well.
+///
+/// # Examples
+///
+/// ```
+/// use merkledb::{crypto::hash, declare_hash_type};
+///
+/// declare_hash_type! {
+///     /// Hash of a block.
+///     pub BlockHash
+/// }
+///
+/// let block_hash = BlockHash::new(hash(b"block"));
+/// let parsed: BlockHash = block_hash.to_hex().parse().unwrap();
+/// assert_eq!(parsed, block_hash);
+/// assert_eq!(*block_hash.as_hash(), hash(b"block"));
+/// ```
+///
+/// Types declared with the macro are not interchangeable:
+///
+/// ```compile_fail
+/// use merkledb::{crypto::hash, declare_hash_type};
+///
+/// declare_hash_type!(BlockHash);
+/// declare_hash_type!(TxHash);
+///
+/// fn get_block(_hash: BlockHash) {}
+///
+/// get_block(TxHash::new(hash(b"tx")));
+/// ```
+///
+/// [`Hash`]: crypto/struct.Hash.html
+#[macro_export]
+macro_rules! declare_hash_type {
+    ($(#[$attr:meta])* $vis:vis $name:ident) => {
+        $(#[$attr])*
+        #[derive(
+            Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, ::core::hash::Hash, Debug,
+        )]
+        $vis struct $name($crate::crypto::Hash);
+
+        #[allow(dead_code)]
+        impl $name {
+            /// Wraps the specified hash.
+            pub fn new(hash: $crate::crypto::Hash) -> Self {
+                Self(hash)
+            }
+
+            /// Creates a new instance filled with zeros.
+            pub fn zero() -> Self {
+                Self($crate::crypto::Hash::zero())
+            }
+
+            /// Returns the wrapped hash.
+            pub fn as_hash(&self) -> &$crate::crypto::Hash {
+                &self.0
+            }
+
+            /// Returns a hex representation of the hash.
+            /// Lower case letters are used (e.g. `f9b4ca`).
+            pub fn to_hex(&self) -> String {
+                self.0.to_hex()
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                self.0.as_ref()
+            }
+        }
+
+        impl ::core::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                ::core::fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl ::core::str::FromStr for $name {
+            type Err = <$crate::crypto::Hash as ::core::str::FromStr>::Err;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map(Self)
+            }
+        }
+
+        impl $crate::_reexports::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: $crate::_reexports::serde::Serializer,
+            {
+                $crate::_reexports::serde::Serialize::serialize(&self.0, serializer)
+            }
+        }
+
+        impl<'de> $crate::_reexports::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: $crate::_reexports::serde::Deserializer<'de>,
+            {
+                <$crate::crypto::Hash as $crate::_reexports::serde::Deserialize<'de>>::deserialize(
+                    deserializer,
+                )
+                .map(Self)
+            }
+        }
+
+        impl $crate::BinaryValue for $name {
+            fn to_bytes(&self) -> Vec<u8> {
+                $crate::BinaryValue::to_bytes(&self.0)
+            }
+
+            fn from_bytes(
+                bytes: ::std::borrow::Cow<'_, [u8]>,
+            ) -> Result<Self, $crate::_reexports::Error> {
+                <$crate::crypto::Hash as $crate::BinaryValue>::from_bytes(bytes).map(Self)
+            }
+        }
+
+        impl $crate::BinaryKey for $name {
+            fn size(&self) -> usize {
+                $crate::BinaryKey::size(&self.0)
+            }
+
+            fn write(&self, buffer: &mut [u8]) -> usize {
+                $crate::BinaryKey::write(&self.0, buffer)
+            }
+
+            fn read(buffer: &[u8]) -> Self::Owned {
+                Self(<$crate::crypto::Hash as $crate::BinaryKey>::read(buffer))
+            }
+        }
+
+        impl $crate::ObjectHash for $name {
+            fn object_hash(&self) -> $crate::crypto::Hash {
+                self.0
+            }
+        }
+    };
+}
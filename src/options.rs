@@ -14,7 +14,7 @@
 
 //! Abstract settings for databases.
 
-use rocksdb::DBCompressionType;
+use rocksdb::{DBCompactionStyle, DBCompressionType};
 use serde_derive::{Deserialize, Serialize};
 
 /// Options for the database.
@@ -70,6 +70,74 @@ impl DbOptions {
     }
 }
 
+/// Options for a column family storing a specific index in the database.
+///
+/// Column family options override the corresponding database-wide settings from
+/// [`DbOptions`] for a single index (or a single group of indexes, since indexes
+/// in a group share a column family). Fields set to `None` fall back to the
+/// database-wide settings or the `RocksDB` defaults.
+///
+/// [`DbOptions`]: struct.DbOptions.html
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[non_exhaustive]
+pub struct ColumnFamilyOptions {
+    /// An algorithm used for compression of the column family.
+    ///
+    /// Defaults to `None`, meaning that `compression_type` from `DbOptions` is used.
+    pub compression_type: Option<CompressionType>,
+    /// Size of data blocks in bytes.
+    ///
+    /// Larger blocks reduce the index overhead and improve compression, but increase
+    /// the amount of data read for point lookups.
+    ///
+    /// Defaults to `None`, meaning that the block size is chosen by the rocksdb.
+    pub block_size: Option<usize>,
+    /// Compaction style used for the column family.
+    ///
+    /// Defaults to `None`, meaning that `CompactionStyle::Level` is used.
+    pub compaction_style: Option<CompactionStyle>,
+}
+
+impl ColumnFamilyOptions {
+    /// Creates a new `ColumnFamilyOptions` object.
+    pub fn new(
+        compression_type: Option<CompressionType>,
+        block_size: Option<usize>,
+        compaction_style: Option<CompactionStyle>,
+    ) -> Self {
+        Self {
+            compression_type,
+            block_size,
+            compaction_style,
+        }
+    }
+}
+
+/// Styles of compaction for a column family.
+///
+/// See [`RocksDB` docs] for details on compaction styles. The FIFO compaction style
+/// is not supported, since it deletes the oldest data once the column family grows
+/// over the size limit, which would corrupt the indexes stored in the column family.
+///
+/// [`RocksDB` docs]: https://github.com/facebook/rocksdb/wiki/Compaction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactionStyle {
+    /// Leveled compaction (the `RocksDB` default), which favors reads.
+    Level,
+    /// Universal compaction, which favors writes at the cost of space and read amplification.
+    Universal,
+}
+
+impl From<CompactionStyle> for DBCompactionStyle {
+    fn from(compaction_style: CompactionStyle) -> Self {
+        match compaction_style {
+            CompactionStyle::Level => Self::Level,
+            CompactionStyle::Universal => Self::Universal,
+        }
+    }
+}
+
 /// Algorithms of compression for the database.
 ///
 /// Database contents are stored in a set of blocks, each of which holds a
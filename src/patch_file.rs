@@ -0,0 +1,447 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Binary format of patches exported to files.
+//!
+//! The file consists of a header, a sequence of records and a trailer:
+//!
+//! ```text
+//! header = b"MDBPATCH" || version: u8
+//! record = index | system_view
+//! index = 1_u8 || qualified_name: bytes || metadata || changes
+//! system_view = 2_u8 || name: bytes || changes
+//! metadata = 0_u8 | 1_u8 || bytes | 2_u8
+//! changes = is_cleared: u8 || entries_count: u64 || entry*
+//! entry = key: bytes || (0_u8 | 1_u8 || value: bytes)
+//! bytes = len: u32 || bytes
+//! trailer = 0_u8 || sha256(all preceding bytes)
+//! ```
+//!
+//! Integers are little-endian. Indexes are referenced by their fully qualified names rather
+//! than by numeric identifiers, which are specific to a database instance. The `metadata` tag
+//! of an index record means that the metadata of the index is unchanged (`0`), is set
+//! to the following bytes (`1`) or is removed (`2`); the identifier in the set metadata
+//! is replaced with the identifier of the index in the target database. An entry with
+//! the `0` tag is a deletion, and an entry with the `1` tag puts the value. The trailing hash
+//! allows to detect truncated or corrupted files before any changes are applied.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Read, Write},
+    mem,
+    num::NonZeroU64,
+};
+
+use crate::{
+    crypto::{Hash, HashStream, HASH_SIZE},
+    db::{Change, ChangesSink, ViewChanges},
+    views::{indexes_pool_address, IndexAddress, IndexMetadata, IndexesPool},
+    BinaryValue, Error, ResolvedAddress, Result, Snapshot,
+};
+
+const MAGIC: &[u8] = b"MDBPATCH";
+const VERSION: u8 = 2;
+
+const END_TAG: u8 = 0;
+const INDEX_TAG: u8 = 1;
+const SYSTEM_VIEW_TAG: u8 = 2;
+const METADATA_UNCHANGED_TAG: u8 = 0;
+const METADATA_PUT_TAG: u8 = 1;
+const METADATA_REMOVED_TAG: u8 = 2;
+const DELETE_TAG: u8 = 0;
+const PUT_TAG: u8 = 1;
+
+/// Writer hashing all written bytes.
+struct HashingWriter<W> {
+    inner: W,
+    stream: HashStream,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.stream = mem::take(&mut self.stream).update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader hashing all read bytes.
+struct HashingReader<R> {
+    inner: R,
+    stream: HashStream,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.stream = mem::take(&mut self.stream).update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too long byte sequence"))?;
+    writer.write_u32::<LittleEndian>(len)?;
+    writer.write_all(bytes)
+}
+
+/// Change of the index metadata.
+#[derive(Debug, Default)]
+enum MetadataChange<'a> {
+    #[default]
+    Unchanged,
+    Put(&'a [u8]),
+    Removed,
+}
+
+/// Changes of an index exported to a patch file.
+#[derive(Debug, Default)]
+struct IndexChanges<'a> {
+    metadata: MetadataChange<'a>,
+    view_changes: Option<&'a ViewChanges>,
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Groups changes by the fully qualified names of indexes. Changes of system views
+/// are returned separately, keyed by the view name.
+///
+/// Identifiers of the changed views are resolved using the metadata changed in the patch
+/// and, for the views with unchanged or removed metadata, using the `base` snapshot
+/// the patch was created from.
+#[allow(clippy::type_complexity)]
+fn group_changes<'a>(
+    base: &dyn Snapshot,
+    changes: &'a HashMap<ResolvedAddress, ViewChanges>,
+) -> io::Result<(
+    BTreeMap<Vec<u8>, IndexChanges<'a>>,
+    BTreeMap<&'a str, &'a ViewChanges>,
+)> {
+    let base_pool = IndexesPool::new(base);
+    let pool_address = indexes_pool_address();
+    let mut indexes: BTreeMap<Vec<u8>, IndexChanges<'a>> = BTreeMap::new();
+    let mut names_by_id = HashMap::new();
+
+    if let Some(pool_changes) = changes.get(&pool_address) {
+        for (full_name, change) in &pool_changes.data {
+            // The empty key corresponds to the number of indexes in the pool, which is
+            // computed anew when the patch is applied.
+            if full_name.is_empty() {
+                continue;
+            }
+
+            let metadata = match change {
+                Change::Put(bytes) => {
+                    let id = IndexMetadata::<Vec<u8>>::from_bytes(bytes.into())
+                        .expect("Index metadata is corrupted")
+                        .identifier();
+                    let old_metadata = base_pool.index_metadata(full_name);
+                    if old_metadata.map_or(false, |metadata| metadata.identifier() != id) {
+                        let msg = "indexes changing their identifiers cannot be exported";
+                        return Err(invalid_input(msg));
+                    }
+                    names_by_id.insert(id, full_name.as_slice());
+                    MetadataChange::Put(bytes)
+                }
+                Change::Delete => MetadataChange::Removed,
+            };
+            indexes.entry(full_name.clone()).or_default().metadata = metadata;
+        }
+    }
+
+    let mut system_views = BTreeMap::new();
+    let mut unresolved_views = vec![];
+    for (address, view_changes) in changes {
+        match address.id {
+            None if *address == pool_address => {}
+            None => {
+                system_views.insert(address.name.as_str(), view_changes);
+            }
+            Some(id) => {
+                if let Some(full_name) = names_by_id.get(&id) {
+                    let index_changes = indexes.entry(full_name.to_vec()).or_default();
+                    index_changes.view_changes = Some(view_changes);
+                } else {
+                    unresolved_views.push((address, view_changes));
+                }
+            }
+        }
+    }
+
+    let addresses = base_pool.index_addresses(unresolved_views.iter().map(|(address, _)| *address));
+    for (address, view_changes) in unresolved_views {
+        // Views of indexes both created and removed within the patch cannot be resolved;
+        // such views do not need to be exported.
+        if let Some(index_address) = addresses.get(address) {
+            let full_name = index_address.fully_qualified_name();
+            let index_changes = indexes.entry(full_name).or_default();
+            if index_changes.view_changes.is_some() {
+                let msg = "indexes changing their identifiers cannot be exported";
+                return Err(invalid_input(msg));
+            }
+            index_changes.view_changes = Some(view_changes);
+        }
+    }
+    Ok((indexes, system_views))
+}
+
+fn write_view_changes(
+    writer: &mut impl Write,
+    view_changes: Option<&ViewChanges>,
+) -> io::Result<()> {
+    let is_cleared = view_changes.map_or(false, ViewChanges::is_cleared);
+    writer.write_u8(is_cleared.into())?;
+    let data = view_changes.map(|changes| &changes.data);
+    writer.write_u64::<LittleEndian>(data.map_or(0, BTreeMap::len) as u64)?;
+    for (key, change) in data.into_iter().flatten() {
+        write_bytes(writer, key)?;
+        match change {
+            Change::Put(value) => {
+                writer.write_u8(PUT_TAG)?;
+                write_bytes(writer, value)?;
+            }
+            Change::Delete => writer.write_u8(DELETE_TAG)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes patch `changes` made on top of the `base` snapshot to the `writer`.
+pub fn write_changes<W: Write>(
+    writer: W,
+    base: &dyn Snapshot,
+    changes: &HashMap<ResolvedAddress, ViewChanges>,
+) -> io::Result<()> {
+    let (indexes, system_views) = group_changes(base, changes)?;
+
+    let mut writer = HashingWriter {
+        inner: writer,
+        stream: HashStream::new(),
+    };
+    writer.write_all(MAGIC)?;
+    writer.write_u8(VERSION)?;
+
+    for (full_name, index_changes) in &indexes {
+        writer.write_u8(INDEX_TAG)?;
+        write_bytes(&mut writer, full_name)?;
+        match index_changes.metadata {
+            MetadataChange::Unchanged => writer.write_u8(METADATA_UNCHANGED_TAG)?,
+            MetadataChange::Put(metadata) => {
+                writer.write_u8(METADATA_PUT_TAG)?;
+                write_bytes(&mut writer, metadata)?;
+            }
+            MetadataChange::Removed => writer.write_u8(METADATA_REMOVED_TAG)?,
+        }
+        write_view_changes(&mut writer, index_changes.view_changes)?;
+    }
+    for (name, view_changes) in system_views {
+        writer.write_u8(SYSTEM_VIEW_TAG)?;
+        write_bytes(&mut writer, name.as_bytes())?;
+        write_view_changes(&mut writer, Some(view_changes))?;
+    }
+
+    writer.write_u8(END_TAG)?;
+    let hash = mem::take(&mut writer.stream).hash();
+    writer.inner.write_all(hash.as_ref())?;
+    writer.flush()
+}
+
+#[allow(clippy::needless_pass_by_value)] // used in `map_err`
+fn convert_io_error(err: io::Error) -> Error {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        Error::new("Patch file is truncated")
+    } else {
+        Error::new(format!("Cannot read patch file: {err}"))
+    }
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let len = reader
+        .read_u32::<LittleEndian>()
+        .map_err(convert_io_error)?;
+    // Read via `take` so that a corrupted length does not lead to a huge allocation.
+    let mut bytes = Vec::new();
+    reader
+        .take(u64::from(len))
+        .read_to_end(&mut bytes)
+        .map_err(convert_io_error)?;
+    if bytes.len() == len as usize {
+        Ok(bytes)
+    } else {
+        Err(Error::new("Patch file is truncated"))
+    }
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String> {
+    String::from_utf8(read_bytes(reader)?)
+        .map_err(|_| Error::new("Invalid UTF-8 string in patch file"))
+}
+
+fn read_flag(reader: &mut impl Read) -> Result<bool> {
+    match reader.read_u8().map_err(convert_io_error)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        tag => Err(invalid_tag(tag)),
+    }
+}
+
+fn invalid_tag(tag: u8) -> Error {
+    Error::new(format!("Invalid tag in patch file: {tag}"))
+}
+
+/// Reads changes of a view and passes them to the `sink`. If `address` is `None`,
+/// the view corresponds to an index missing in the database, which can only be cleared.
+fn read_view_changes(
+    reader: &mut impl Read,
+    name: &str,
+    address: Option<&ResolvedAddress>,
+    sink: &mut dyn ChangesSink,
+) -> Result<()> {
+    let is_cleared = read_flag(reader)?;
+    let entries_count = reader
+        .read_u64::<LittleEndian>()
+        .map_err(convert_io_error)?;
+    let address = match address {
+        Some(address) => address,
+        // Clearing a missing index is a no-op.
+        None if entries_count == 0 => return Ok(()),
+        None => {
+            return Err(Error::new(format!(
+                "Index `{name}` changed in patch file is missing in the database"
+            )))
+        }
+    };
+
+    if is_cleared {
+        sink.clear(address)?;
+    }
+    for _ in 0..entries_count {
+        let key = read_bytes(reader)?;
+        let change = if read_flag(reader)? {
+            Change::Put(read_bytes(reader)?)
+        } else {
+            Change::Delete
+        };
+        sink.change(address, &key, &change)?;
+    }
+    Ok(())
+}
+
+/// Reads patch changes written with `write_changes` from the `reader` and passes them
+/// to the `sink`. Indexes are resolved against the `snapshot` of the target database;
+/// new indexes are assigned new identifiers.
+///
+/// The changes are passed to the `sink` as they are read, and the integrity of the patch
+/// is verified only after that. Thus, the caller must discard the changes
+/// if an error is returned.
+pub fn read_changes<R: Read>(
+    reader: R,
+    snapshot: &dyn Snapshot,
+    sink: &mut dyn ChangesSink,
+) -> Result<()> {
+    let mut reader = HashingReader {
+        inner: reader,
+        stream: HashStream::new(),
+    };
+    let mut magic = [0_u8; 8];
+    reader.read_exact(&mut magic).map_err(convert_io_error)?;
+    if magic != MAGIC {
+        return Err(Error::new("Not a patch file"));
+    }
+    let version = reader.read_u8().map_err(convert_io_error)?;
+    if version != VERSION {
+        return Err(Error::new(format!(
+            "Unsupported patch file version: {version}"
+        )));
+    }
+
+    let pool = IndexesPool::new(snapshot);
+    let pool_address = indexes_pool_address();
+    let pool_len = pool.len();
+    let mut next_id = pool_len + 1;
+    loop {
+        match reader.read_u8().map_err(convert_io_error)? {
+            END_TAG => break,
+
+            INDEX_TAG => {
+                let full_name = read_bytes(&mut reader)?;
+                let index_address = IndexAddress::from_fully_qualified_name(&full_name);
+                let id = pool
+                    .index_metadata(&full_name)
+                    .map(|metadata| metadata.identifier());
+                let id = match reader.read_u8().map_err(convert_io_error)? {
+                    METADATA_UNCHANGED_TAG => id,
+                    METADATA_PUT_TAG => {
+                        let metadata = read_bytes(&mut reader)?;
+                        let metadata = IndexMetadata::<Vec<u8>>::from_bytes(metadata.into())
+                            .map_err(|err| {
+                                Error::new(format!("Invalid index metadata in patch file: {err}"))
+                            })?;
+                        let id = id.unwrap_or_else(|| {
+                            next_id += 1;
+                            NonZeroU64::new(next_id - 1).unwrap()
+                        });
+                        let metadata = metadata.with_identifier(id).to_bytes();
+                        sink.change(&pool_address, &full_name, &Change::Put(metadata))?;
+                        Some(id)
+                    }
+                    METADATA_REMOVED_TAG => {
+                        if id.is_some() {
+                            sink.change(&pool_address, &full_name, &Change::Delete)?;
+                        }
+                        id
+                    }
+                    tag => return Err(invalid_tag(tag)),
+                };
+
+                let name = index_address.name();
+                let address = id.map(|id| ResolvedAddress::new(name, Some(id)));
+                read_view_changes(&mut reader, name, address.as_ref(), sink)?;
+            }
+
+            SYSTEM_VIEW_TAG => {
+                let name = read_string(&mut reader)?;
+                let address = ResolvedAddress::system(name.as_str());
+                read_view_changes(&mut reader, &name, Some(&address), sink)?;
+            }
+
+            tag => return Err(invalid_tag(tag)),
+        }
+    }
+    if next_id > pool_len + 1 {
+        let len = (next_id - 1).to_bytes();
+        sink.change(&pool_address, &[], &Change::Put(len))?;
+    }
+
+    let expected_hash = mem::take(&mut reader.stream).hash();
+    let mut hash = [0_u8; HASH_SIZE];
+    reader
+        .inner
+        .read_exact(&mut hash)
+        .map_err(convert_io_error)?;
+    if Hash::new(hash) == expected_hash {
+        Ok(())
+    } else {
+        Err(Error::new("Patch file is corrupted"))
+    }
+}
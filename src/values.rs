@@ -74,6 +74,18 @@ pub trait BinaryValue: Sized {
         self.to_bytes()
     }
 
+    /// Appends the serialized value to the `buffer`. The appended bytes must be equal
+    /// to the output of `to_bytes()`.
+    ///
+    /// The default implementation copies the output of `to_bytes()`. Implementing
+    /// this method directly avoids allocating an intermediate vector, e.g., when hashing
+    /// values with a [`HashingBuffer`].
+    ///
+    /// [`HashingBuffer`]: struct.HashingBuffer.html
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_bytes());
+    }
+
     /// Deserializes the value from the given bytes array.
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self>;
 }
@@ -88,6 +100,10 @@ macro_rules! impl_binary_value_scalar {
                 vec![*self as u8]
             }
 
+            fn write_bytes(&self, buffer: &mut Vec<u8>) {
+                buffer.push(*self as u8);
+            }
+
             fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
                 use byteorder::ReadBytesExt;
                 bytes.as_ref().$read().map_err(From::from)
@@ -105,6 +121,12 @@ macro_rules! impl_binary_value_scalar {
                 v
             }
 
+            fn write_bytes(&self, buffer: &mut Vec<u8>) {
+                let mut bytes = [0; $len];
+                LittleEndian::$write(&mut bytes, *self);
+                buffer.extend_from_slice(&bytes);
+            }
+
             fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
                 use byteorder::ReadBytesExt;
                 bytes.as_ref().$read::<LittleEndian>().map_err(From::from)
@@ -134,6 +156,8 @@ impl BinaryValue for () {
         Vec::default()
     }
 
+    fn write_bytes(&self, _buffer: &mut Vec<u8>) {}
+
     fn from_bytes(_bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         Ok(())
     }
@@ -145,6 +169,10 @@ impl BinaryValue for bool {
         vec![*self as u8]
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.push(*self as u8);
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         let value = bytes.as_ref();
         assert_eq!(value.len(), 1);
@@ -162,6 +190,10 @@ impl BinaryValue for Vec<u8> {
         self.clone()
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self);
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         Ok(bytes.into_owned())
     }
@@ -172,6 +204,10 @@ impl BinaryValue for String {
         self.as_bytes().to_owned()
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self.as_bytes());
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         Self::from_utf8(bytes.into_owned()).map_err(From::from)
     }
@@ -182,6 +218,10 @@ impl BinaryValue for Hash {
         self.as_ref().to_vec()
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self.as_ref());
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         Self::from_slice(bytes.as_ref()).ok_or_else(|| {
             format_err!("Unable to decode Hash from bytes: buffer size does not match")
@@ -202,6 +242,13 @@ impl BinaryValue for DateTime<Utc> {
         buffer
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        let mut bytes = [0; 12];
+        LittleEndian::write_i64(&mut bytes[0..8], self.timestamp());
+        LittleEndian::write_u32(&mut bytes[8..12], self.timestamp_subsec_nanos());
+        buffer.extend_from_slice(&bytes);
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         let mut value = bytes.as_ref();
         let secs = value.read_i64::<LittleEndian>()?;
@@ -218,6 +265,10 @@ impl BinaryValue for Uuid {
         self.as_bytes().to_vec()
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self.as_bytes());
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         Self::from_slice(bytes.as_ref()).map_err(From::from)
     }
@@ -228,6 +279,10 @@ impl BinaryValue for Decimal {
         self.serialize().to_vec()
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.serialize());
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         let mut value = bytes.as_ref();
         let mut buf: [u8; 16] = [0; 16];
@@ -241,6 +296,10 @@ impl BinaryValue for [u8; HASH_SIZE] {
         self.to_vec()
     }
 
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self);
+    }
+
     fn from_bytes(bytes: Cow<'_, [u8]>) -> anyhow::Result<Self> {
         let bytes = bytes.as_ref();
         ensure!(
@@ -265,6 +324,10 @@ mod tests {
     fn assert_round_trip_eq<T: BinaryValue + PartialEq + Debug>(values: &[T]) {
         for value in values {
             let bytes = value.to_bytes();
+            // `write_bytes` appends the same bytes as returned by `to_bytes`.
+            let mut buffer = vec![0xff];
+            value.write_bytes(&mut buffer);
+            assert_eq!(buffer[1..], bytes[..]);
             assert_eq!(
                 *value,
                 <T as BinaryValue>::from_bytes(bytes.into()).unwrap()
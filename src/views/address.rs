@@ -229,6 +229,28 @@ impl IndexAddress {
         (name, is_in_group)
     }
 
+    /// Restores an address from the fully qualified name obtained with `fully_qualified_name`.
+    pub(crate) fn from_fully_qualified_name(qualified_name: &[u8]) -> Self {
+        let in_migration = qualified_name.first() == Some(&MIGRATION_CHAR);
+        let name_start = usize::from(in_migration);
+        let (name, id_in_group) = match qualified_name
+            .iter()
+            .position(|&byte| byte == SEPARATOR_CHAR)
+        {
+            Some(pos) => (
+                &qualified_name[name_start..pos],
+                Some(qualified_name[pos + 1..].to_vec()),
+            ),
+            None => (&qualified_name[name_start..], None),
+        };
+
+        Self {
+            name: String::from_utf8_lossy(name).into_owned(),
+            id_in_group,
+            in_migration,
+        }
+    }
+
     /// Converts a migration namespace into the form that all indexes in the namespace
     /// begin with.
     #[inline]
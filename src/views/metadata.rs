@@ -12,20 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::{ensure, format_err};
+use anyhow::{bail, ensure, format_err};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use enum_primitive_derive::Primitive;
 use num_traits::FromPrimitive;
 use serde_derive::{Deserialize, Serialize};
 
-use std::{borrow::Cow, io::Error, mem, num::NonZeroU64, vec};
+use std::{borrow::Cow, collections::HashMap, io::Error, mem, num::NonZeroU64, vec};
 
 use crate::{
     access::{AccessError, AccessErrorKind},
     crypto::{self, Hash},
+    hash::{DefaultNodeHasher, NodeHasher},
     validation::check_index_valid_full_name,
     views::{IndexAddress, RawAccess, RawAccessMut, ResolvedAddress, View},
-    BinaryKey, BinaryValue,
+    BinaryKey, BinaryValue, Iter as BytesIter, Snapshot,
 };
 
 /// Name of the column family used to store `IndexesPool`.
@@ -72,6 +73,9 @@ impl IndexType {
 
 /// Index state attribute tag.
 const INDEX_STATE_TAG: u32 = 0;
+/// Node hasher attribute tag. The attribute is only present for Merkelized indexes
+/// using a non-default node hasher.
+const NODE_HASHER_TAG: u32 = 1;
 
 /// A type that can be (de)serialized as a metadata value.
 pub trait BinaryAttribute: Sized {
@@ -168,6 +172,9 @@ pub struct IndexMetadata<V = Vec<u8>> {
     // calls `IndexState::unset()`. `None` option does not occupy space in the metadata
     // and can therefore be preferable to explicit "default" option.
     state: Option<V>,
+    // Identifier of the node hasher used by a Merkelized index. Equals to
+    // `DefaultNodeHasher::ID` for non-Merkelized indexes.
+    node_hasher: u32,
 }
 
 impl<V> BinaryValue for IndexMetadata<V>
@@ -179,6 +186,9 @@ where
         if let Some(ref state) = self.state {
             capacity += mem::size_of_val(&INDEX_STATE_TAG) + mem::size_of::<u32>() + state.size();
         }
+        if self.node_hasher != DefaultNodeHasher::ID {
+            capacity += mem::size_of_val(&NODE_HASHER_TAG) + 2 * mem::size_of::<u32>();
+        }
         let mut buf = Vec::with_capacity(capacity);
 
         buf.write_u64::<LittleEndian>(self.identifier.get())
@@ -191,6 +201,12 @@ where
             buf.write_u32::<LittleEndian>(state.size() as u32).unwrap();
             state.write(&mut buf);
         }
+        if self.node_hasher != DefaultNodeHasher::ID {
+            buf.write_u32::<LittleEndian>(NODE_HASHER_TAG).unwrap();
+            buf.write_u32::<LittleEndian>(mem::size_of::<u32>() as u32)
+                .unwrap();
+            buf.write_u32::<LittleEndian>(self.node_hasher).unwrap();
+        }
         buf
     }
 
@@ -203,31 +219,27 @@ where
         let index_type = IndexType::from_u32(index_type)
             .ok_or_else(|| format_err!("Unknown index type: {}", index_type))?;
 
-        if bytes.is_empty() {
-            // There are no tags in the metadata, correspondingly, no index state.
-            return Ok(Self {
-                identifier,
-                index_type,
-                state: None,
-            });
+        let mut state = None;
+        let mut node_hasher = DefaultNodeHasher::ID;
+        // Reads attributes in TLV (tag, length, value) form.
+        while !bytes.is_empty() {
+            let tag = bytes.read_u32::<LittleEndian>()?;
+            let len = bytes.read_u32::<LittleEndian>()? as usize;
+            ensure!(bytes.len() >= len, "Index attribute is too short");
+            let (mut value, rest) = bytes.split_at(len);
+            match tag {
+                INDEX_STATE_TAG => state = Some(V::read(value)?),
+                NODE_HASHER_TAG => node_hasher = value.read_u32::<LittleEndian>()?,
+                _ => bail!("Attribute with unknown tag: {}", tag),
+            }
+            bytes = rest;
         }
 
-        // Reads index state in TLV (tag, length, value) form.
-        let state_tag = bytes.read_u32::<LittleEndian>()?;
-        let state_len = bytes.read_u32::<LittleEndian>()? as usize;
-
-        ensure!(
-            state_tag == INDEX_STATE_TAG,
-            "Attribute with unknown tag: {}",
-            state_tag
-        );
-        ensure!(bytes.len() >= state_len, "Index state is too short");
-
-        let state_bytes = &bytes[0..state_len];
         Ok(Self {
             identifier,
             index_type,
-            state: Some(V::read(state_bytes)?),
+            state,
+            node_hasher,
         })
     }
 }
@@ -246,6 +258,12 @@ impl<V> IndexMetadata<V> {
     pub fn identifier(&self) -> NonZeroU64 {
         self.identifier
     }
+
+    /// Replaces the identifier of the index, e.g., when transferring the metadata
+    /// to another database.
+    pub(crate) fn with_identifier(self, identifier: NonZeroU64) -> Self {
+        Self { identifier, ..self }
+    }
 }
 
 #[allow(clippy::use_self)] // false positive
@@ -264,6 +282,7 @@ impl IndexMetadata {
                     );
                 })
             }),
+            node_hasher: self.node_hasher,
         }
     }
 }
@@ -314,20 +333,73 @@ where
 /// Pool size is used as an identifier of newly created indexes.
 pub struct IndexesPool<T: RawAccess>(View<T>);
 
+/// Returns the resolved address of the view storing `IndexesPool`.
+pub(crate) fn indexes_pool_address() -> ResolvedAddress {
+    ResolvedAddress::system(INDEXES_POOL_NAME)
+}
+
 impl<T: RawAccess> IndexesPool<T> {
     pub(crate) fn new(index_access: T) -> Self {
         let view = View::new(index_access, ResolvedAddress::system(INDEXES_POOL_NAME));
         Self(view)
     }
 
-    pub(super) fn len(&self) -> u64 {
+    pub(crate) fn len(&self) -> u64 {
         self.0.get(&()).unwrap_or_default()
     }
 
-    fn index_metadata(&self, index_name: &[u8]) -> Option<IndexMetadata> {
+    pub(crate) fn index_metadata(&self, index_name: &[u8]) -> Option<IndexMetadata> {
         self.0.get(index_name)
     }
 
+    /// Restores index addresses for the specified resolved addresses. Only the metadata
+    /// of the indexes sharing a name with one of `resolved_addrs` is scanned.
+    ///
+    /// Resolved addresses without a known index (e.g., ones of system views) are not included
+    /// into the returned map.
+    pub(crate) fn index_addresses<'a>(
+        &self,
+        resolved_addrs: impl IntoIterator<Item = &'a ResolvedAddress>,
+    ) -> HashMap<&'a ResolvedAddress, IndexAddress> {
+        let mut ids_by_name: HashMap<&str, HashMap<NonZeroU64, &ResolvedAddress>> = HashMap::new();
+        for resolved in resolved_addrs {
+            if let Some(id) = resolved.id {
+                ids_by_name
+                    .entry(&resolved.name)
+                    .or_default()
+                    .insert(id, resolved);
+            }
+        }
+
+        let mut addresses = HashMap::new();
+        for (name, mut ids) in ids_by_name {
+            let root = IndexAddress::from_root(name);
+            let mut migrated_root = root.clone();
+            migrated_root.set_in_migration();
+
+            for root in &[root, migrated_root] {
+                if let Some(metadata) = self.index_metadata(&root.fully_qualified_name()) {
+                    if let Some(resolved) = ids.remove(&metadata.identifier) {
+                        addresses.insert(resolved, root.clone());
+                    }
+                }
+
+                let group_prefix = root.qualified_prefix();
+                for (full_name, metadata) in self.0.iter::<_, Vec<u8>, IndexMetadata>(&group_prefix)
+                {
+                    if ids.is_empty() {
+                        break;
+                    }
+                    if let Some(resolved) = ids.remove(&metadata.identifier) {
+                        let addr = IndexAddress::from_fully_qualified_name(&full_name);
+                        addresses.insert(resolved, addr);
+                    }
+                }
+            }
+        }
+        addresses
+    }
+
     fn set_len(&mut self, len: u64) {
         self.0.put_or_forget(&(), len);
     }
@@ -349,6 +421,7 @@ impl<T: RawAccess> IndexesPool<T> {
             identifier: NonZeroU64::new(len + 1).unwrap(),
             index_type,
             state: None,
+            node_hasher: DefaultNodeHasher::ID,
         };
         let is_phantom = !self.0.put_or_forget(index_name, metadata.to_bytes());
         self.set_len(len + 1);
@@ -552,14 +625,19 @@ pub fn get_object_hash<T: RawAccess>(
                 metadata,
                 index_full_name,
                 is_phantom: false,
+                is_created: false,
             };
 
             if index_type == IndexType::ProofList {
                 // We don't access list elements, so the element type doesn't matter.
+                // The node hasher doesn't matter either, since the root hash is read
+                // from the storage.
                 let list = ProofListIndex::<_, ()>::new(view_with_metadata);
                 list.object_hash()
             } else {
                 // We don't access map elements, so the key / value types don't matter.
+                // The node hasher doesn't matter either, since the root hash is a part
+                // of the map state.
                 let map = ProofMapIndex::<_, (), ()>::new(view_with_metadata);
                 map.object_hash()
             }
@@ -568,6 +646,63 @@ pub fn get_object_hash<T: RawAccess>(
     }
 }
 
+/// Checks whether two snapshots contain the same indexes with the same metadata state
+/// and contents. Index identifiers are not compared, since they depend on the order
+/// in which indexes were created.
+pub fn indexes_eq(first: &dyn Snapshot, second: &dyn Snapshot) -> bool {
+    let first_pool = IndexesPool::new(first);
+    let second_pool = IndexesPool::new(second);
+    // The entry with the empty key stores the pool length, which is not a part of the state.
+    let mut first_indexes = first_pool
+        .0
+        .iter::<_, Vec<u8>, Vec<u8>>(&())
+        .skip_while(|(name, _)| name.is_empty());
+    let mut second_indexes = second_pool
+        .0
+        .iter::<_, Vec<u8>, Vec<u8>>(&())
+        .skip_while(|(name, _)| name.is_empty());
+
+    loop {
+        let ((first_name, first_metadata), (second_name, second_metadata)) =
+            match (first_indexes.next(), second_indexes.next()) {
+                (None, None) => return true,
+                (Some(first), Some(second)) => (first, second),
+                _ => return false,
+            };
+        if first_name != second_name {
+            return false;
+        }
+
+        let first_metadata = <IndexMetadata>::from_bytes(first_metadata.into())
+            .expect("Index metadata is corrupted");
+        let second_metadata = <IndexMetadata>::from_bytes(second_metadata.into())
+            .expect("Index metadata is corrupted");
+        if first_metadata.index_type != second_metadata.index_type
+            || first_metadata.state != second_metadata.state
+            || first_metadata.node_hasher != second_metadata.node_hasher
+        {
+            return false;
+        }
+
+        let (name, _) = IndexAddress::parse_fully_qualified_name(&first_name, 0);
+        let first_addr = ResolvedAddress::new(name.clone(), Some(first_metadata.identifier));
+        let second_addr = ResolvedAddress::new(name, Some(second_metadata.identifier));
+        if !views_eq(first.iter(&first_addr, &[]), second.iter(&second_addr, &[])) {
+            return false;
+        }
+    }
+}
+
+fn views_eq(mut first: BytesIter<'_>, mut second: BytesIter<'_>) -> bool {
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return true,
+            (Some(first_entry), Some(second_entry)) if first_entry == second_entry => {}
+            _ => return false,
+        }
+    }
+}
+
 /// Wrapper struct to manipulate `IndexMetadata` for an index with provided `index_name`.
 #[derive(Debug)]
 pub struct ViewWithMetadata<T: RawAccess> {
@@ -575,6 +710,8 @@ pub struct ViewWithMetadata<T: RawAccess> {
     metadata: IndexMetadata,
     index_full_name: Vec<u8>,
     is_phantom: bool,
+    // Was the index metadata created when the view was obtained?
+    is_created: bool,
 }
 
 impl<T> ViewWithMetadata<T>
@@ -642,9 +779,13 @@ where
         let index_full_name = index_address.fully_qualified_name();
 
         let mut pool = IndexesPool::new(index_access.clone());
-        let (metadata, is_phantom) = pool.index_metadata(&index_full_name).map_or_else(
-            || pool.create_index_metadata(&index_full_name, index_type),
-            |metadata| (metadata, false),
+        let (metadata, is_phantom, is_created) = pool.index_metadata(&index_full_name).map_or_else(
+            || {
+                let (metadata, is_phantom) =
+                    pool.create_index_metadata(&index_full_name, index_type);
+                (metadata, is_phantom, true)
+            },
+            |metadata| (metadata, false, false),
         );
 
         let real_index_type = metadata.index_type;
@@ -669,6 +810,7 @@ where
             metadata,
             index_full_name,
             is_phantom,
+            is_created,
         };
 
         if real_index_type == index_type {
@@ -692,6 +834,29 @@ where
         self.is_phantom
     }
 
+    /// Checks that the Merkelized index uses the node hasher with the specified identifier.
+    /// If the index has just been created, the identifier is recorded in its metadata instead.
+    pub(crate) fn bind_node_hasher(&mut self, node_hasher: u32) -> Result<(), AccessErrorKind> {
+        if self.metadata.node_hasher == node_hasher {
+            return Ok(());
+        }
+        if !self.is_created {
+            return Err(AccessErrorKind::WrongNodeHasher {
+                expected: node_hasher,
+                actual: self.metadata.node_hasher,
+            });
+        }
+
+        self.metadata.node_hasher = node_hasher;
+        // Metadata of phantom indexes is not persisted, thus `access` is `None` for them.
+        if let Some(access) = self.view.access().cloned() {
+            IndexesPool::new(access)
+                .0
+                .put_or_forget(&self.index_full_name, self.metadata.to_bytes());
+        }
+        Ok(())
+    }
+
     pub(crate) fn into_parts<V>(self) -> (View<T>, IndexState<T, V>)
     where
         V: BinaryAttribute,
@@ -729,6 +894,7 @@ mod tests {
             identifier: NonZeroU64::new(12).unwrap(),
             index_type: IndexType::ProofList,
             state: Some(16_u64),
+            node_hasher: 0,
         };
 
         let bytes = metadata.to_bytes();
@@ -738,9 +904,21 @@ mod tests {
             identifier: NonZeroU64::new(12).unwrap(),
             index_type: IndexType::ProofList,
             state: None::<u64>,
+            node_hasher: 0,
+        };
+
+        let bytes = metadata.to_bytes();
+        assert_eq!(IndexMetadata::from_bytes(bytes.into()).unwrap(), metadata);
+
+        let metadata = IndexMetadata {
+            identifier: NonZeroU64::new(12).unwrap(),
+            index_type: IndexType::ProofMap,
+            state: Some(16_u64),
+            node_hasher: 1,
         };
 
         let bytes = metadata.to_bytes();
+        assert_eq!(bytes.len(), 8 + 4 + (8 + 8) + (8 + 4));
         assert_eq!(IndexMetadata::from_bytes(bytes.into()).unwrap(), metadata);
     }
 
@@ -751,6 +929,7 @@ mod tests {
             identifier: NonZeroU64::new(12).unwrap(),
             index_type: IndexType::ProofList,
             state: Some(16_u64),
+            node_hasher: 0,
         };
 
         let mut bytes = metadata.to_bytes();
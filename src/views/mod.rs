@@ -12,11 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) use self::metadata::indexes_pool_address;
 pub use self::{
     address::{IndexAddress, ResolvedAddress},
     metadata::{
-        get_object_hash, BinaryAttribute, GroupKeys, IndexMetadata, IndexState, IndexType,
-        IndexesPool, ViewWithMetadata,
+        get_object_hash, indexes_eq, BinaryAttribute, GroupKeys, IndexMetadata, IndexState,
+        IndexType, IndexesPool, ViewWithMetadata,
     },
     system_schema::{get_state_aggregator, SystemSchema},
 };
@@ -0,0 +1,236 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests related to `BinaryValue` derivation.
+
+use anyhow::ensure;
+use merkledb_derive::BinaryValue;
+use serde_derive::{Deserialize, Serialize};
+
+use std::borrow::Cow;
+
+use merkledb::{access::CopyAccessExt, BinaryValue, Database, TemporaryDB};
+
+/// The wallet schema before versioning was enabled.
+mod v0 {
+    use merkledb_derive::BinaryValue;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, BinaryValue)]
+    #[binary_value(codec = "bincode")]
+    pub struct Wallet {
+        pub owner: String,
+        pub balance: u64,
+    }
+}
+
+/// The first version of the wallet schema, which has the same layout as the unversioned one.
+mod v1 {
+    use merkledb_derive::BinaryValue;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, BinaryValue)]
+    #[binary_value(codec = "bincode", version = 1, legacy = "bincode_deserialize")]
+    pub struct Wallet {
+        pub owner: String,
+        pub balance: u64,
+    }
+
+    fn bincode_deserialize(bytes: &[u8]) -> anyhow::Result<Wallet> {
+        bincode::deserialize(bytes).map_err(From::from)
+    }
+}
+
+/// The second version of the wallet schema with an added field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, BinaryValue)]
+#[binary_value(
+    codec = "bincode",
+    version = 2,
+    upgrade = "Wallet::upgrade",
+    legacy = "Wallet::from_legacy"
+)]
+struct Wallet {
+    owner: String,
+    balance: u64,
+    frozen: bool,
+}
+
+impl Wallet {
+    fn upgrade(old_version: u8, bytes: &[u8]) -> anyhow::Result<Self> {
+        ensure!(old_version == 1, "Unknown wallet version {}", old_version);
+        let v1::Wallet { owner, balance } = bincode::deserialize(bytes)?;
+        Ok(Self {
+            owner,
+            balance,
+            frozen: false,
+        })
+    }
+
+    fn from_legacy(bytes: &[u8]) -> anyhow::Result<Self> {
+        let v0::Wallet { owner, balance } = bincode::deserialize(bytes)?;
+        Ok(Self {
+            owner,
+            balance,
+            frozen: false,
+        })
+    }
+}
+
+/// A versioned value without the upgrade path.
+#[derive(Debug, Serialize, Deserialize, BinaryValue)]
+#[binary_value(codec = "bincode", version = 3)]
+struct Strict {
+    value: u32,
+}
+
+#[test]
+fn versioned_value_round_trip() {
+    let wallet = Wallet {
+        owner: "Alice".to_owned(),
+        balance: 100,
+        frozen: true,
+    };
+    let bytes = wallet.to_bytes();
+    assert_eq!(bytes[..2], [0xff, 2]);
+    assert_eq!(&bytes[2..], bincode::serialize(&wallet).unwrap().as_slice());
+    let mut buffer = vec![];
+    wallet.write_bytes(&mut buffer);
+    assert_eq!(buffer, bytes);
+    assert_eq!(Wallet::from_bytes(Cow::Owned(bytes)).unwrap(), wallet);
+}
+
+#[test]
+fn old_value_is_upgraded() {
+    let old_wallet = v1::Wallet {
+        owner: "Bob".to_owned(),
+        balance: 42,
+    };
+    let bytes = old_wallet.to_bytes();
+    assert_eq!(bytes[..2], [0xff, 1]);
+
+    let wallet = Wallet::from_bytes(Cow::Borrowed(&bytes)).unwrap();
+    assert_eq!(
+        wallet,
+        Wallet {
+            owner: "Bob".to_owned(),
+            balance: 42,
+            frozen: false,
+        }
+    );
+
+    // Values stored with the old schema are upgraded when read from the database.
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    fork.get_list("wallets").push(old_wallet);
+    db.merge(fork.into_patch()).unwrap();
+
+    let snapshot = db.snapshot();
+    let wallets = snapshot.get_list::<_, Wallet>("wallets");
+    assert_eq!(wallets.get(0), Some(wallet.clone()));
+
+    // Upgraded values are written with the current version.
+    let fork = db.fork();
+    let mut wallets = fork.get_list::<_, Wallet>("wallets");
+    let upgraded = wallets.get(0).unwrap();
+    wallets.set(0, upgraded);
+    assert_eq!(
+        fork.get_list::<_, Vec<u8>>("wallets").get(0).unwrap()[..2],
+        [0xff, 2]
+    );
+    assert_eq!(wallets.get(0), Some(wallet));
+}
+
+#[test]
+fn unversioned_value_is_upgraded() {
+    let legacy_wallet = v0::Wallet {
+        owner: "Dave".to_owned(),
+        balance: 7,
+    };
+    let bytes = legacy_wallet.to_bytes();
+    assert_eq!(bytes, bincode::serialize(&legacy_wallet).unwrap());
+
+    let expected_wallet = Wallet {
+        owner: "Dave".to_owned(),
+        balance: 7,
+        frozen: false,
+    };
+    let wallet = Wallet::from_bytes(Cow::Borrowed(&bytes)).unwrap();
+    assert_eq!(wallet, expected_wallet);
+
+    // Values stored with the pre-versioning derive are readable with the versioned one.
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    fork.get_entry("wallet").set(legacy_wallet);
+    db.merge(fork.into_patch()).unwrap();
+    let snapshot = db.snapshot();
+    assert_eq!(
+        snapshot.get_entry::<_, Wallet>("wallet").get(),
+        Some(expected_wallet)
+    );
+
+    // Values without the legacy decoding are not decoded as unversioned ones.
+    let unversioned = 5_u32.to_le_bytes();
+    assert!(Strict::from_bytes(Cow::Borrowed(&unversioned[..])).is_err());
+}
+
+#[test]
+fn unversioned_value_starting_with_version_is_upgraded() {
+    // The serialized owner length is the first byte of the value, which coincides
+    // with the supported versions.
+    for owner in &["a", "ab"] {
+        let legacy_wallet = v0::Wallet {
+            owner: (*owner).to_owned(),
+            balance: 1,
+        };
+        let bytes = legacy_wallet.to_bytes();
+        assert_eq!(usize::from(bytes[0]), owner.len());
+
+        let wallet = Wallet::from_bytes(Cow::Borrowed(&bytes)).unwrap();
+        assert_eq!(
+            wallet,
+            Wallet {
+                owner: (*owner).to_owned(),
+                balance: 1,
+                frozen: false,
+            }
+        );
+        let wallet = v1::Wallet::from_bytes(Cow::Borrowed(&bytes)).unwrap();
+        assert_eq!(wallet.owner, *owner);
+    }
+}
+
+#[test]
+fn unsupported_versions_are_rejected() {
+    let mut bytes = Strict { value: 1 }.to_bytes();
+    assert_eq!(bytes[0], 3);
+    assert_eq!(Strict::from_bytes(Cow::Borrowed(&bytes)).unwrap().value, 1);
+
+    bytes[0] = 2;
+    let err = Strict::from_bytes(Cow::Borrowed(&bytes)).unwrap_err();
+    assert!(err.to_string().contains("Unsupported version 2"), "{}", err);
+    assert!(Strict::from_bytes(Cow::Borrowed(&[])).is_err());
+
+    // Newer versions are not upgraded.
+    bytes[0] = 3;
+    assert!(Wallet::from_bytes(Cow::Borrowed(&bytes)).is_err());
+    let mut wallet_bytes = Wallet {
+        owner: "Carol".to_owned(),
+        balance: 0,
+        frozen: false,
+    }
+    .to_bytes();
+    wallet_bytes[1] = 3;
+    let err = Wallet::from_bytes(Cow::Borrowed(&wallet_bytes)).unwrap_err();
+    assert!(err.to_string().contains("Unsupported version 3"), "{}", err);
+}
@@ -0,0 +1,137 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests that hashing values with a `HashingBuffer` does not allocate.
+
+use merkledb_derive::{BinaryValue, ObjectHash};
+use serde_derive::{Deserialize, Serialize};
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use merkledb::{
+    crypto::{hash, Hash},
+    BinaryValue, HashingBuffer, ObjectHash,
+};
+
+/// Allocator counting allocations made by each thread, so that tests running
+/// in parallel do not affect each other.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_allocation() {
+    // The counter may be inaccessible while the thread is being destroyed.
+    ALLOCATIONS
+        .try_with(|count| count.set(count.get() + 1))
+        .ok();
+}
+
+#[allow(unsafe_code)]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations(action: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    action();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[derive(Debug, Serialize, Deserialize, BinaryValue, ObjectHash)]
+#[binary_value(codec = "bincode")]
+struct Record {
+    id: u64,
+    name: String,
+}
+
+fn hash_all<V: ObjectHash>(values: &[V], hashes: &mut Vec<Hash>) -> usize {
+    hashes.clear();
+    count_allocations(|| hashes.extend(values.iter().map(ObjectHash::object_hash)))
+}
+
+#[test]
+fn object_hash_does_not_allocate() {
+    let numbers: Vec<u64> = (0..1_000).collect();
+    let strings: Vec<_> = (0..1_000).map(|i| format!("string #{}", i)).collect();
+    let records: Vec<_> = (0..1_000)
+        .map(|id| Record {
+            id,
+            name: format!("record #{}", id),
+        })
+        .collect();
+    let mut hashes = Vec::with_capacity(1_000);
+
+    // The first run may allocate the thread-local buffer.
+    hash_all(&numbers, &mut hashes);
+    assert_eq!(hash_all(&numbers, &mut hashes), 0);
+    let expected: Vec<_> = numbers.iter().map(|x| hash(&x.to_bytes())).collect();
+    assert_eq!(hashes, expected);
+
+    hash_all(&strings, &mut hashes);
+    assert_eq!(hash_all(&strings, &mut hashes), 0);
+    let expected: Vec<_> = strings.iter().map(|s| hash(s.as_bytes())).collect();
+    assert_eq!(hashes, expected);
+
+    hash_all(&records, &mut hashes);
+    assert_eq!(hash_all(&records, &mut hashes), 0);
+    let expected: Vec<_> = records.iter().map(|r| hash(&r.to_bytes())).collect();
+    assert_eq!(hashes, expected);
+}
+
+#[test]
+fn explicit_buffer_does_not_allocate() {
+    let records: Vec<_> = (0..100)
+        .map(|id| Record {
+            id,
+            name: "x".repeat(id as usize),
+        })
+        .collect();
+    let mut buffer = HashingBuffer::with_capacity(256);
+    let mut hashes = Vec::with_capacity(records.len());
+
+    let allocations = count_allocations(|| {
+        hashes.extend(records.iter().map(|record| buffer.hash_value(record)));
+    });
+    assert_eq!(allocations, 0);
+    let expected: Vec<_> = records.iter().map(|r| hash(&r.to_bytes())).collect();
+    assert_eq!(hashes, expected);
+
+    // Hashing via `to_bytes()` allocates a vector for each value.
+    let allocations = count_allocations(|| {
+        for record in &records {
+            hash(&record.to_bytes());
+        }
+    });
+    assert!(allocations >= records.len());
+}
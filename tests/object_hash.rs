@@ -0,0 +1,70 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests related to `ObjectHash` derivation.
+
+use merkledb_derive::ObjectHash;
+
+use merkledb::ObjectHash;
+
+#[derive(ObjectHash)]
+struct Wallet {
+    #[object_hash(tag = 1)]
+    owner: String,
+    #[object_hash(tag = 2)]
+    balance: u64,
+    #[object_hash(tag = 5)]
+    frozen: bool,
+}
+
+/// Same as `Wallet`, but with reordered and renamed fields.
+#[derive(ObjectHash)]
+struct ReorderedWallet {
+    #[object_hash(tag = 5)]
+    is_frozen: bool,
+    #[object_hash(tag = 1)]
+    name: String,
+    #[object_hash(tag = 2)]
+    amount: u64,
+}
+
+#[derive(ObjectHash)]
+struct TupleWallet(
+    #[object_hash(tag = 2)] u64,
+    #[object_hash(tag = 5)] bool,
+    #[object_hash(tag = 1)] String,
+);
+
+#[test]
+fn tagged_fields_hash_is_stable() {
+    let wallet = Wallet {
+        owner: "Alice".to_owned(),
+        balance: 100,
+        frozen: false,
+    };
+    let reordered = ReorderedWallet {
+        is_frozen: false,
+        name: "Alice".to_owned(),
+        amount: 100,
+    };
+    let tuple = TupleWallet(100, false, "Alice".to_owned());
+    assert_eq!(wallet.object_hash(), reordered.object_hash());
+    assert_eq!(wallet.object_hash(), tuple.object_hash());
+
+    let other_wallet = Wallet {
+        balance: 101,
+        ..wallet
+    };
+    assert_ne!(other_wallet.object_hash(), reordered.object_hash());
+}
@@ -0,0 +1,197 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stress tests checking that snapshots are not affected by subsequent merges
+//! and compaction of the database.
+
+use tempfile::TempDir;
+
+use std::{sync::Arc, thread};
+
+use merkledb::{
+    access::CopyAccessExt, crypto::Hash, Database, DbOptions, ObjectHash, RocksDB, Snapshot,
+    SystemSchema, TemporaryDB,
+};
+
+const ROUNDS: u64 = 200;
+
+/// Everything readable from a snapshot in the tests.
+#[derive(Debug, PartialEq)]
+struct SnapshotState {
+    state_hash: Hash,
+    list: Vec<u64>,
+    list_hash: Hash,
+    map: Vec<(u64, String)>,
+    map_hash: Hash,
+    grouped: Vec<Vec<u8>>,
+    entry: Option<String>,
+    missing_index_len: u64,
+}
+
+impl SnapshotState {
+    fn read(snapshot: &dyn Snapshot) -> Self {
+        let list = snapshot.get_proof_list::<_, u64>("list");
+        let map = snapshot.get_proof_map::<_, u64, String>("map");
+        let grouped = (0..4_u32)
+            .map(|i| {
+                let index = snapshot.get_list::<_, u8>(("group", &i));
+                index.iter().collect()
+            })
+            .collect();
+        Self {
+            state_hash: SystemSchema::new(snapshot).state_hash(),
+            list: list.iter().collect(),
+            list_hash: list.object_hash(),
+            map: map.iter().collect(),
+            map_hash: map.object_hash(),
+            grouped,
+            entry: snapshot.get_entry::<_, String>("entry").get(),
+            missing_index_len: snapshot.get_list::<_, u64>("created_later").len(),
+        }
+    }
+}
+
+fn fill_initial_state<D: Database>(db: &D) {
+    let fork = db.fork();
+    fork.get_proof_list("list").extend(0..100_u64);
+    let mut map = fork.get_proof_map("map");
+    for i in 0..100_u64 {
+        map.put(&i, i.to_string());
+    }
+    for i in 0..4_u32 {
+        fork.get_list(("group", &i)).extend(vec![i as u8; 10]);
+    }
+    fork.get_entry("entry").set("initial".to_owned());
+    db.merge(fork.into_patch()).unwrap();
+}
+
+/// Performs a single round of changes affecting all indexes read by `SnapshotState`.
+fn churn<D: Database>(db: &D, round: u64) {
+    let fork = db.fork();
+
+    let mut list = fork.get_proof_list::<_, u64>("list");
+    if round % 10 == 0 {
+        list.clear();
+    } else {
+        list.push(round);
+        list.set(round % list.len(), round * 1_000);
+    }
+
+    let mut map = fork.get_proof_map::<_, u64, String>("map");
+    map.remove(&(round % 100));
+    map.put(&(round + 100), format!("round #{}", round));
+    map.put(&(round % 50), "overwritten".to_owned());
+
+    let mut grouped = fork.get_list::<_, u8>(("group", &((round % 4) as u32)));
+    grouped.clear();
+    grouped.push(round as u8);
+
+    fork.get_entry::<_, String>("entry")
+        .set(format!("round #{}", round));
+    fork.get_list::<_, u64>("created_later").push(round);
+
+    db.merge(fork.into_patch()).unwrap();
+}
+
+fn check_snapshot_isolation<D: Database>(db: &D, compact: impl Fn(&D)) {
+    fill_initial_state(db);
+    let snapshot = db.snapshot();
+    let expected = SnapshotState::read(&*snapshot);
+    assert_eq!(expected.list.len(), 100);
+    assert_eq!(expected.missing_index_len, 0);
+
+    for round in 1..=ROUNDS {
+        churn(db, round);
+        if round % 50 == 0 {
+            compact(db);
+            assert_eq!(SnapshotState::read(&*snapshot), expected);
+        }
+    }
+    compact(db);
+
+    assert_eq!(SnapshotState::read(&*snapshot), expected);
+    // The database itself has changed.
+    let actual = SnapshotState::read(&*db.snapshot());
+    assert_ne!(actual.state_hash, expected.state_hash);
+    assert_eq!(actual.missing_index_len, ROUNDS);
+    assert_eq!(actual.entry, Some(format!("round #{}", ROUNDS)));
+}
+
+fn create_rocksdb(dir: &TempDir) -> RocksDB {
+    RocksDB::open(dir.path(), &DbOptions::default()).unwrap()
+}
+
+#[test]
+fn temporary_db_snapshot_isolation() {
+    let db = TemporaryDB::new();
+    // `TemporaryDB` has no compaction.
+    check_snapshot_isolation(&db, |_| {});
+}
+
+#[test]
+fn temporary_db_snapshot_is_not_affected_by_clear() {
+    let db = TemporaryDB::new();
+    fill_initial_state(&db);
+    let snapshot = db.snapshot();
+    let expected = SnapshotState::read(&*snapshot);
+    db.clear().unwrap();
+    assert_eq!(SnapshotState::read(&*snapshot), expected);
+    assert!(SnapshotState::read(&*db.snapshot()).list.is_empty());
+}
+
+#[test]
+fn rocksdb_snapshot_isolation() {
+    let dir = TempDir::new().unwrap();
+    let db = create_rocksdb(&dir);
+    check_snapshot_isolation(&db, |db| db.compact().unwrap());
+}
+
+#[test]
+fn rocksdb_snapshot_isolation_under_concurrent_writes() {
+    let dir = TempDir::new().unwrap();
+    let db = Arc::new(create_rocksdb(&dir));
+    fill_initial_state(&*db);
+    let snapshot: Arc<dyn Snapshot> = Arc::from(db.snapshot());
+    let expected = SnapshotState::read(&*snapshot);
+
+    let writer = {
+        let db = Arc::clone(&db);
+        thread::spawn(move || {
+            for round in 1..=ROUNDS {
+                churn(&*db, round);
+                if round % 20 == 0 {
+                    db.compact().unwrap();
+                }
+            }
+        })
+    };
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let snapshot = Arc::clone(&snapshot);
+            thread::spawn(move || {
+                (0..20)
+                    .map(|_| SnapshotState::read(&*snapshot))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    writer.join().unwrap();
+    for reader in readers {
+        for state in reader.join().unwrap() {
+            assert_eq!(state, expected);
+        }
+    }
+    assert_eq!(SnapshotState::read(&*snapshot), expected);
+}
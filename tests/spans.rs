@@ -0,0 +1,154 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests `tracing` spans emitted by the database.
+
+#![cfg(feature = "tracing")]
+
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Metadata, Subscriber,
+};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use merkledb::{access::CopyAccessExt, crypto::hash, Database, TemporaryDB};
+
+#[derive(Debug, Default)]
+struct CapturedSpan {
+    name: &'static str,
+    fields: HashMap<&'static str, String>,
+}
+
+impl CapturedSpan {
+    fn field(&self, name: &str) -> u64 {
+        self.fields[name].parse().unwrap()
+    }
+}
+
+impl Visit for CapturedSpan {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+/// Subscriber collecting all created spans together with their fields.
+#[derive(Debug, Clone, Default)]
+struct SpanCollector {
+    spans: Arc<Mutex<Vec<CapturedSpan>>>,
+}
+
+impl SpanCollector {
+    fn collect(&self, action: impl FnOnce()) -> Vec<CapturedSpan> {
+        tracing::subscriber::with_default(self.clone(), action);
+        self.spans.lock().unwrap().drain(..).collect()
+    }
+}
+
+impl Subscriber for SpanCollector {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut captured = CapturedSpan {
+            name: span.metadata().name(),
+            ..CapturedSpan::default()
+        };
+        span.record(&mut captured);
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(captured);
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut spans[span.into_u64() as usize - 1]);
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn merge_emits_span() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    fork.get_list("list").extend(vec![1_u32, 2, 3]);
+    let patch = fork.into_patch();
+
+    let spans = SpanCollector::default().collect(|| db.merge(patch).unwrap());
+    let merge_span = spans.iter().find(|span| span.name == "merge").unwrap();
+    assert!(merge_span.field("changes") >= 3);
+    assert!(merge_span.fields.contains_key("duration_us"));
+}
+
+#[test]
+fn proof_generation_emits_spans() {
+    let db = TemporaryDB::new();
+    let fork = db.fork();
+    fork.get_proof_list("list").extend(0_u64..10);
+    let mut map = fork.get_proof_map::<_, u64, u64>("map");
+    for i in 0..10 {
+        map.put(&i, i);
+    }
+
+    let spans = SpanCollector::default().collect(|| {
+        let list = fork.get_proof_list::<_, u64>("list");
+        list.get_range_proof(2..5);
+        map.get_multiproof(vec![1, 2, 20]);
+    });
+
+    let list_span = spans
+        .iter()
+        .find(|span| span.name == "list_range_proof")
+        .unwrap();
+    assert_eq!(list_span.field("list_len"), 10);
+    assert_eq!(list_span.field("entries"), 3);
+    assert!(list_span.fields.contains_key("duration_us"));
+
+    let map_span = spans
+        .iter()
+        .find(|span| span.name == "map_multiproof")
+        .unwrap();
+    assert_eq!(map_span.field("entries"), 3);
+    assert!(map_span.field("proof_nodes") > 0);
+    assert!(map_span.fields.contains_key("duration_us"));
+}
+
+#[test]
+fn only_large_inputs_are_traced_when_hashing() {
+    let collector = SpanCollector::default();
+    let spans = collector.collect(|| {
+        hash(&[0; 1_024]);
+    });
+    assert!(spans.iter().all(|span| span.name != "hash"));
+
+    let spans = collector.collect(|| {
+        hash(&vec![0; 1 << 20]);
+    });
+    let hash_span = spans.iter().find(|span| span.name == "hash").unwrap();
+    assert_eq!(hash_span.field("size"), 1 << 20);
+    assert!(hash_span.fields.contains_key("duration_us"));
+}